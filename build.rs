@@ -0,0 +1,40 @@
+//! Embed the git commit, build time and compiler version for `--version-full`.
+
+use std::{
+    env,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// First line printed by `command`, `None` when it cannot run or fails,
+/// e.g. without git or outside a checkout as in crates.io builds.
+fn output(command: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(command).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    Some(text.lines().next()?.trim().to_string()).filter(|line| !line.is_empty())
+}
+
+fn main() {
+    let commit = output("git", &["rev-parse", "--short=12", "HEAD"]).unwrap_or_default();
+    // reproducible builds pin the time through SOURCE_DATE_EPOCH
+    let built = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()));
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+
+    println!("cargo:rustc-env=GPUSTAT_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=GPUSTAT_BUILD_EPOCH={}", built);
+    println!("cargo:rustc-env=GPUSTAT_RUSTC_VERSION={}", output(&rustc, &["--version"]).unwrap_or_default());
+    println!("cargo:rerun-if-changed=build.rs");
+    for path in [".git/HEAD", ".git/index"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
@@ -4,6 +4,8 @@ set -ex
 
 # Incorporate TARGET env var to the build and test process
 cargo build --target "$TARGET" --verbose
+# the optional modules, e.g. the async collection
+cargo build --target "$TARGET" --all-features --verbose
 
 # We cannot run arm executables on linux
 if [[ $TARGET != arm-unknown-linux-* ]]; then
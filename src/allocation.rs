@@ -0,0 +1,190 @@
+//! `--allocation-summary`: totals of the GPUs `CUDA_VISIBLE_DEVICES` gives
+//! this job, e.g. a SLURM allocation, and processes of other users on them.
+
+use crate::stats::{self, GpuStat, ProcessStat, Snapshot};
+use nix::unistd::{Uid, User};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// The GPUs of a job and their totals.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Allocation {
+    /// Displayed indices of the allocated GPUs.
+    pub gpus: Vec<u32>,
+    /// Used and total memory in bytes, summed over the allocated GPUs.
+    pub memory_used: u64,
+    pub memory_total: u64,
+    /// Mean utilization in percent.
+    pub utilization: f64,
+    /// Power draw in milliwatts, summed over the allocated GPUs.
+    pub power: u64,
+    /// Owners of the processes of other users on allocated GPUs.
+    pub foreign_users: Vec<String>,
+}
+
+/// `CUDA_VISIBLE_DEVICES` as CUDA reads it: indices or (prefixes of) GPU
+/// UUIDs, up to the first entry that is neither. Unset means every GPU.
+#[derive(Clone, Debug)]
+pub struct Visible(Option<Vec<String>>);
+
+impl Visible {
+    pub fn from_env() -> Self {
+        Visible::parse(env::var("CUDA_VISIBLE_DEVICES").ok().as_deref())
+    }
+
+    pub fn parse(value: Option<&str>) -> Self {
+        Visible(value.map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .take_while(|id| id.parse::<u32>().is_ok() || id.starts_with("GPU-") || id.starts_with("MIG-"))
+                .map(str::to_string)
+                .collect()
+        }))
+    }
+
+    /// Whether the GPU of NVML index `index` and `uuid` is visible. CUDA
+    /// counts in PCI bus order here only with `CUDA_DEVICE_ORDER=PCI_BUS_ID`,
+    /// which job schedulers set.
+    fn contains(&self, index: u32, uuid: &str) -> bool {
+        match &self.0 {
+            None => true,
+            // MIG instances are not traced back to their GPU
+            Some(ids) => ids
+                .iter()
+                .any(|id| id.parse() == Ok(index) || (id.starts_with("GPU-") && uuid.starts_with(id.as_str()))),
+        }
+    }
+}
+
+/// The user running gpustat, whose processes belong to the job.
+pub fn current_user() -> Option<String> {
+    Some(User::from_uid(Uid::current()).ok()??.name)
+}
+
+/// Whether a process belongs to another user than `user`. Processes whose
+/// owner could not be read, or that have exited, are not counted.
+pub fn is_foreign(process: &ProcessStat, user: &str) -> bool {
+    process.resolved && !process.stale && !process.user.is_empty() && process.user != user
+}
+
+/// Sum up the allocated GPUs of `snapshot` and mark the processes of other
+/// users on them.
+pub fn summarize(snapshot: &mut Snapshot, visible: &Visible, user: Option<&str>) {
+    let mut allocation = Allocation::default();
+    let mut utilization = 0;
+    for gpu in snapshot.gpus.iter_mut().filter(|gpu| visible.contains(gpu.index, &gpu.uuid)) {
+        allocation.gpus.push(gpu.order_index.unwrap_or(gpu.index));
+        allocation.memory_used += gpu.memory_used;
+        allocation.memory_total += gpu.memory_total;
+        utilization += u64::from(gpu.utilization);
+        for process in &mut gpu.processes {
+            process.foreign = user.is_some_and(|user| is_foreign(process, user));
+            if process.foreign && !allocation.foreign_users.contains(&process.user) {
+                allocation.foreign_users.push(process.user.clone());
+            }
+        }
+    }
+    let allocated: Vec<&GpuStat> = snapshot
+        .gpus
+        .iter()
+        .filter(|gpu| visible.contains(gpu.index, &gpu.uuid))
+        .collect();
+    allocation.power = stats::total_power(&allocated);
+    if !allocation.gpus.is_empty() {
+        allocation.utilization = utilization as f64 / allocation.gpus.len() as f64;
+    }
+    snapshot.allocation = Some(allocation);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, user: &str) -> ProcessStat {
+        ProcessStat {
+            pid,
+            user: user.to_string(),
+            resolved: true,
+            ..ProcessStat::default()
+        }
+    }
+
+    fn gpu(index: u32, processes: Vec<ProcessStat>) -> GpuStat {
+        GpuStat {
+            index,
+            uuid: format!("GPU-{:08}", index),
+            utilization: 10 * index,
+            memory_used: 1 << 30,
+            memory_total: 8 << 30,
+            processes,
+            ..GpuStat::default()
+        }
+    }
+
+    /// Which processes of each GPU are marked foreign for the job of alice
+    /// on the GPUs `visible`.
+    fn foreign(visible: &str, snapshot: &mut Snapshot) -> Vec<Vec<bool>> {
+        summarize(snapshot, &Visible::parse(Some(visible)), Some("alice"));
+        let gpus = snapshot.gpus.iter();
+        gpus.map(|gpu| gpu.processes.iter().map(|process| process.foreign).collect())
+            .collect()
+    }
+
+    #[test]
+    fn other_users_on_allocated_gpus_are_foreign() {
+        let mut snapshot = Snapshot {
+            gpus: vec![
+                // allocated, only the job's own processes
+                gpu(0, vec![process(10, "alice"), process(11, "alice")]),
+                // allocated, with someone else's process
+                gpu(1, vec![process(20, "alice"), process(21, "mallory")]),
+                // not allocated, so bob is just another user
+                gpu(2, vec![process(30, "bob")]),
+            ],
+            ..Snapshot::default()
+        };
+        assert_eq!(
+            foreign("0,1", &mut snapshot),
+            [vec![false, false], vec![false, true], vec![false]]
+        );
+        let allocation = snapshot.allocation.unwrap();
+        assert_eq!(allocation.gpus, [0, 1]);
+        assert_eq!(allocation.foreign_users, ["mallory"]);
+        assert_eq!((allocation.memory_used, allocation.memory_total), (2 << 30, 16 << 30));
+        assert_eq!(allocation.utilization, 5.0);
+    }
+
+    #[test]
+    fn unknown_and_exited_owners_are_not_foreign() {
+        let unresolved = ProcessStat {
+            resolved: false,
+            ..process(1, "mallory")
+        };
+        let exited = ProcessStat {
+            stale: true,
+            ..process(2, "mallory")
+        };
+        for process in [unresolved, exited, process(3, "")] {
+            assert!(!is_foreign(&process, "alice"), "{:?}", process);
+        }
+        let mut snapshot = Snapshot {
+            gpus: vec![gpu(0, vec![process(4, "mallory")])],
+            ..Snapshot::default()
+        };
+        // without a user to compare with nothing is flagged
+        summarize(&mut snapshot, &Visible::parse(None), None);
+        assert!(!snapshot.gpus[0].processes[0].foreign);
+    }
+
+    #[test]
+    fn cuda_visible_devices_stops_at_the_first_invalid_entry() {
+        let visible = Visible::parse(Some("GPU-00000002, 0,bogus,1"));
+        let shown: Vec<u32> = (0..4)
+            .filter(|&index| visible.contains(index, &format!("GPU-{:08}", index)))
+            .collect();
+        assert_eq!(shown, [0, 2]);
+        assert!(Visible::parse(None).contains(7, "GPU-7"));
+        assert!(!Visible::parse(Some("")).contains(0, "GPU-0"));
+    }
+}
@@ -0,0 +1,91 @@
+//! `gpustat app-clocks`: set or reset the application clocks of one GPU.
+
+use crate::{require_root, StatusError, EXIT_USAGE};
+use clap::Args;
+use nvml_wrapper::{enum_wrappers::device::Clock, error::NvmlError, Device, Nvml};
+
+#[derive(Args, Clone, Debug)]
+pub struct AppClocksOpts {
+    #[arg(long = "id", value_name = "INDEX", help = "GPU to change")]
+    id: u32,
+    #[arg(
+        long,
+        value_name = "MHZ",
+        requires = "mem",
+        required_unless_present = "reset",
+        help = "Graphics (SM) application clock"
+    )]
+    sm: Option<u32>,
+    #[arg(long, value_name = "MHZ", requires = "sm", help = "Memory application clock")]
+    mem: Option<u32>,
+    #[arg(long, conflicts_with_all = ["sm", "mem"], help = "Restore the default application clocks")]
+    reset: bool,
+}
+
+/// `1215/6251 MHz`, graphics first.
+fn current(device: &Device) -> Result<String, NvmlError> {
+    Ok(format!(
+        "{}/{} MHz",
+        device.applications_clock(Clock::Graphics)?,
+        device.applications_clock(Clock::Memory)?
+    ))
+}
+
+/// Why `sm`/`mem` is not a clock pair the GPU supports, listing the ones it does.
+fn unsupported(device: &Device, sm: u32, mem: u32) -> Result<Option<String>, NvmlError> {
+    let memory = device.supported_memory_clocks()?;
+    if !memory.contains(&mem) {
+        let memory: Vec<String> = memory.iter().map(u32::to_string).collect();
+        return Ok(Some(format!(
+            "memory clock {} MHz is not supported, expected one of {}",
+            mem,
+            memory.join(", ")
+        )));
+    }
+    let graphics = device.supported_graphics_clocks(mem)?;
+    if !graphics.contains(&sm) {
+        let graphics: Vec<String> = graphics.iter().map(u32::to_string).collect();
+        return Ok(Some(format!(
+            "graphics clock {} MHz is not supported with memory at {} MHz, expected one of {}",
+            sm,
+            mem,
+            graphics.join(", ")
+        )));
+    }
+    Ok(None)
+}
+
+/// Set the application clocks of one GPU after checking them against its
+/// supported clocks, printing the clocks before and after.
+pub fn run(opts: &AppClocksOpts, nvml: &Nvml) -> Result<u8, StatusError> {
+    require_root("changing the application clocks")?;
+
+    let id = opts.id;
+    let mut device = nvml.device_by_index(id)?;
+    let before = match current(&device) {
+        Err(NvmlError::NotSupported) => {
+            eprintln!("GPU {}: application clocks are not supported, leaving them unchanged", id);
+            return Ok(EXIT_USAGE);
+        }
+        result => result?,
+    };
+    let result = match (opts.sm, opts.mem) {
+        (Some(sm), Some(mem)) if !opts.reset => {
+            if let Some(reason) = unsupported(&device, sm, mem)? {
+                eprintln!("GPU {}: {}", id, reason);
+                return Ok(EXIT_USAGE);
+            }
+            device.set_applications_clocks(mem, sm)
+        }
+        _ => device.reset_applications_clocks(),
+    };
+    match result {
+        Err(NvmlError::NotSupported) => {
+            eprintln!("GPU {}: application clocks cannot be changed on this GPU, leaving them unchanged", id);
+            return Ok(EXIT_USAGE);
+        }
+        result => result?,
+    }
+    println!("GPU {}: application clocks {} -> {}", id, before, current(&device)?);
+    Ok(0)
+}
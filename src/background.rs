@@ -0,0 +1,101 @@
+//! Whether the terminal has a light or dark background, so the default
+//! palette stays readable on both without `--palette`.
+//!
+//! The terminal is asked for its background color with an OSC 11 query, and
+//! `COLORFGBG` is the fallback for terminals that do not answer within
+//! `REPLY_TIMEOUT`.
+
+use nix::{
+    poll::{poll, PollFd, PollFlags},
+    sys::termios::{self, LocalFlags, SetArg},
+};
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    os::unix::io::AsRawFd,
+    time::{Duration, Instant},
+};
+
+/// Longest wait for the terminal's answer, which must not be felt at startup.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Background {
+    Light,
+    Dark,
+}
+
+/// Luminance of an `rgb:RRRR/GGGG/BBBB` color, with 1 to 4 hex digits per channel.
+fn from_rgb(reply: &str) -> Option<Background> {
+    let rgb = &reply[reply.find("rgb:")? + "rgb:".len()..];
+    let channels: Vec<f64> = rgb
+        .split('/')
+        .take(3)
+        .map(|channel| {
+            let digits: String = channel.chars().take_while(char::is_ascii_hexdigit).collect();
+            let max = 16f64.powi(digits.len() as i32) - 1.0;
+            Some(f64::from(u16::from_str_radix(&digits, 16).ok()?) / max)
+        })
+        .collect::<Option<_>>()?;
+    let [r, g, b] = channels[..] else {
+        return None;
+    };
+    Some(if 0.299 * r + 0.587 * g + 0.114 * b > 0.5 {
+        Background::Light
+    } else {
+        Background::Dark
+    })
+}
+
+/// `COLORFGBG` as set by rxvt, Konsole and others, e.g. `15;0`: the last
+/// field is the background's index in the 16-color palette, where white (7)
+/// and bright white (15) are the light ones.
+fn from_colorfgbg(value: &str) -> Option<Background> {
+    let background: u8 = value.rsplit(';').next()?.parse().ok()?;
+    Some(if matches!(background, 7 | 15) {
+        Background::Light
+    } else {
+        Background::Dark
+    })
+}
+
+/// Ask the controlling terminal for its background color, in non-canonical
+/// mode so the answer is neither echoed nor held back until a newline.
+fn query_terminal() -> Option<Background> {
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+    let saved = termios::tcgetattr(tty.as_raw_fd()).ok()?;
+    let mut raw = saved.clone();
+    raw.local_flags.remove(LocalFlags::ICANON | LocalFlags::ECHO);
+    termios::tcsetattr(tty.as_raw_fd(), SetArg::TCSANOW, &raw).ok()?;
+
+    let mut reply = Vec::new();
+    if tty.write_all(b"\x1b]11;?\x1b\\").and_then(|_| tty.flush()).is_ok() {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        // the answer ends with BEL or ST
+        while !reply.ends_with(b"\x07") && !reply.ends_with(b"\x1b\\") {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let mut fds = [PollFd::new(tty.as_raw_fd(), PollFlags::POLLIN)];
+            if left.is_zero() || !matches!(poll(&mut fds, left.as_millis() as i32), Ok(1..)) {
+                break;
+            }
+            let mut buffer = [0u8; 64];
+            match tty.read(&mut buffer) {
+                Ok(n) if n > 0 => reply.extend_from_slice(&buffer[..n]),
+                _ => break,
+            }
+        }
+    }
+    let _ = termios::tcsetattr(tty.as_raw_fd(), SetArg::TCSANOW, &saved);
+    let reply = String::from_utf8_lossy(&reply);
+    log::debug!("OSC 11 reply: {:?}", reply);
+    from_rgb(&reply)
+}
+
+/// The terminal's background, `None` when neither the terminal nor
+/// `COLORFGBG` tells.
+pub fn detect() -> Option<Background> {
+    let started = Instant::now();
+    let background = query_terminal().or_else(|| from_colorfgbg(&std::env::var("COLORFGBG").ok()?));
+    log::debug!("background {:?} detected in {:.2?}", background, started.elapsed());
+    background
+}
@@ -0,0 +1,216 @@
+//! `--header-text`, `--header-file` and `--header-cmd`: lines of the
+//! operator's own printed above the table, such as the reservation of a node
+//! or a maintenance notice, in that order when several are given.
+//!
+//! The file is read and the command run again for every snapshot of watch
+//! mode. A command gets `CMD_TIMEOUT` to finish and a file or command that
+//! fails is replaced by a dim note, so a broken script never stops the
+//! table. Only `MAX_LINES` lines of `MAX_WIDTH` characters are shown. Color
+//! and style sequences are kept when the table is styled and dropped when it
+//! is not; every other escape sequence is dropped and other control
+//! characters are escaped, so the content cannot move the cursor or retitle
+//! the terminal. Machine-readable formats never include the lines.
+
+use crate::{
+    render::{self, RenderOpts},
+    Opts,
+};
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::Pid,
+};
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Longest run of `--header-cmd`, after which it is killed with its children.
+pub const CMD_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub const MAX_LINES: usize = 10;
+
+/// Characters per line, not counting style sequences.
+pub const MAX_WIDTH: usize = 200;
+
+/// Bytes read from a file or command; the rest is discarded.
+const MAX_BYTES: u64 = 64 * 1024;
+
+enum Line {
+    Content(String),
+    /// Why a source could not be read.
+    Note(String),
+}
+
+fn content(bytes: &[u8]) -> Vec<Line> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut lines: Vec<Line> = text
+        .split('\n')
+        .map(|line| Line::Content(line.strip_suffix('\r').unwrap_or(line).to_string()))
+        .collect();
+    while matches!(lines.last(), Some(Line::Content(line)) if line.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn read_file(path: &std::path::Path) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    File::open(path)?.take(MAX_BYTES).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The standard output of `script` run by `sh -c` in a process group of its
+/// own, which is killed when the script does not finish within `CMD_TIMEOUT`.
+fn run(script: &str) -> Result<Vec<u8>, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| format!("cannot run sh: {}", e))?;
+    let deadline = Instant::now() + CMD_TIMEOUT;
+    let kill = |child: &mut std::process::Child| {
+        let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+        let _ = child.wait();
+        log::info!("--header-cmd: killed after {:?}", CMD_TIMEOUT);
+        Err(format!("timed out after {:?}", CMD_TIMEOUT))
+    };
+    let mut stdout = child.stdout.take().ok_or("no standard output")?;
+    let (sender, receiver) = mpsc::channel();
+    // a child left in the background can hold the pipe open, so the reader
+    // is waited for until the deadline only and then left behind
+    thread::spawn(move || {
+        let mut bytes = vec![];
+        let read = stdout.by_ref().take(MAX_BYTES).read_to_end(&mut bytes);
+        let _ = sender.send(read.map(|_| bytes));
+        // drain the rest so the script does not block on a full pipe
+        let _ = io::copy(&mut stdout, &mut io::sink());
+    });
+    let bytes = match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(read) => read.map_err(|e| e.to_string())?,
+        Err(_) => return kill(&mut child),
+    };
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => return kill(&mut child),
+            Ok(None) => thread::sleep(Duration::from_millis(5)),
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+    if !status.success() {
+        return Err(status.to_string());
+    }
+    Ok(bytes)
+}
+
+/// `line` with control characters escaped and escape sequences dropped,
+/// except color and style ones when `styled`, and cut at `MAX_WIDTH`.
+fn sanitize(line: &str, styled: bool) -> String {
+    let mut sanitized = String::with_capacity(line.len());
+    let (mut width, mut styles) = (0, false);
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            match chars.next() {
+                Some('[') => {
+                    let mut sequence = String::from("\x1b[");
+                    for c in chars.by_ref() {
+                        sequence.push(c);
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                    let sgr = sequence.len() > 2
+                        && sequence.ends_with('m')
+                        && sequence[2..sequence.len() - 1].chars().all(|c| c.is_ascii_digit() || c == ';');
+                    if styled && sgr {
+                        sanitized += &sequence;
+                        styles = true;
+                    }
+                }
+                // operating system commands end in BEL or ESC \
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if width == MAX_WIDTH {
+            sanitized.push('…');
+            break;
+        }
+        width += 1;
+        if c.is_control() && c != '\t' {
+            sanitized += &render::escape_control(&c.to_string());
+        } else {
+            sanitized.push(c);
+        }
+    }
+    if styles {
+        // keep the styles from bleeding into the table
+        sanitized += "\x1b[0m";
+    }
+    sanitized
+}
+
+/// Whether any of the header options is set.
+pub fn requested(opts: &Opts) -> bool {
+    opts.header_text.is_some() || opts.header_file.is_some() || opts.header_cmd.is_some()
+}
+
+/// The lines to print above the table, each ending in a newline, empty
+/// without any of the header options.
+pub fn render(opts: &Opts, render_opts: &RenderOpts) -> String {
+    let mut lines = vec![];
+    if let Some(text) = &opts.header_text {
+        lines.extend(content(text.as_bytes()));
+    }
+    if let Some(path) = &opts.header_file {
+        match read_file(path) {
+            Ok(bytes) => lines.extend(content(&bytes)),
+            Err(e) => lines.push(Line::Note(format!("--header-file {}: {}", path.display(), e))),
+        }
+    }
+    if let Some(script) = &opts.header_cmd {
+        match run(script) {
+            Ok(bytes) => lines.extend(content(&bytes)),
+            Err(e) => lines.push(Line::Note(format!("--header-cmd failed: {}", e))),
+        }
+    }
+    let styled = render_opts.styling.enabled;
+    let mut text = String::new();
+    for line in lines.iter().take(MAX_LINES) {
+        let line = match line {
+            Line::Content(line) => sanitize(line, styled),
+            Line::Note(note) => {
+                log::info!("{}", note);
+                render::dim(render_opts, &render::escape_control(note))
+            }
+        };
+        text += &line;
+        text.push('\n');
+    }
+    if lines.len() > MAX_LINES {
+        text += &render::dim(render_opts, &format!("… {} more header lines", lines.len() - MAX_LINES));
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,223 @@
+//! `--bell-on`: ring the terminal bell in watch mode when a GPU starts
+//! meeting a condition such as `temp>90`.
+
+use crate::stats::{GpuStat, Snapshot};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::{Duration, Instant},
+};
+
+/// Comparisons in matching order, two-character ones first.
+const OPERATORS: &[(&str, Operator)] = &[
+    (">=", Operator::AtLeast),
+    ("<=", Operator::AtMost),
+    ("==", Operator::Equal),
+    ("!=", Operator::NotEqual),
+    (">", Operator::Above),
+    ("<", Operator::Below),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Above,
+    AtLeast,
+    Below,
+    AtMost,
+    Equal,
+    NotEqual,
+}
+
+/// A metric of one GPU compared with a threshold, e.g. `temp>90`, `mem>=95`
+/// (percent of total), `power>300` (W), `util<5` or `procs==0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    text: String,
+    metric: Metric,
+    operator: Operator,
+    threshold: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Metric {
+    Temperature,
+    Utilization,
+    Memory,
+    Power,
+    Processes,
+}
+
+impl std::str::FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let (at, symbol, operator) = OPERATORS
+            .iter()
+            .filter_map(|&(symbol, operator)| Some((text.find(symbol)?, symbol, operator)))
+            .min_by_key(|&(at, _, _)| at)
+            .ok_or_else(|| format!("expected METRIC>VALUE, got '{}'", s))?;
+        let (metric, value) = (&text[..at], &text[at + symbol.len()..]);
+        let metric = match metric {
+            "temp" => Metric::Temperature,
+            "util" => Metric::Utilization,
+            "mem" => Metric::Memory,
+            "power" => Metric::Power,
+            "procs" => Metric::Processes,
+            _ => {
+                return Err(format!(
+                    "unknown metric '{}', expected temp, util, mem, power or procs",
+                    metric
+                ))
+            }
+        };
+        let threshold = value
+            .trim_end_matches(['%', 'C', 'W'])
+            .parse::<f64>()
+            .map_err(|e| format!("invalid threshold '{}': {}", value, e))?;
+        Ok(Condition {
+            text,
+            metric,
+            operator,
+            threshold,
+        })
+    }
+}
+
+impl Condition {
+    /// Whether the GPU meets the condition, `None` when it was not measured.
+    pub fn holds(&self, gpu: &GpuStat) -> Option<bool> {
+        if !gpu.measured() {
+            return None;
+        }
+        let value = match self.metric {
+            Metric::Temperature => f64::from(gpu.temperature),
+            Metric::Utilization => f64::from(gpu.utilization),
+            Metric::Memory if gpu.memory_total == 0 => return None,
+            Metric::Memory => gpu.memory_used as f64 * 100.0 / gpu.memory_total as f64,
+            Metric::Power => f64::from(gpu.power_usage) / 1000.0,
+            Metric::Processes => gpu.processes.len() as f64,
+        };
+        Some(match self.operator {
+            Operator::Above => value > self.threshold,
+            Operator::AtLeast => value >= self.threshold,
+            Operator::Below => value < self.threshold,
+            Operator::AtMost => value <= self.threshold,
+            Operator::Equal => value == self.threshold,
+            Operator::NotEqual => value != self.threshold,
+        })
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// What `--state-file` keeps of a bell across restarts.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BellState {
+    /// When each condition last rang, by its text.
+    pub rung: BTreeMap<String, DateTime<Local>>,
+    /// Conditions met on the last frame, as condition text and GPU UUID.
+    pub met: Vec<(String, String)>,
+}
+
+/// Edge detection for `--bell-on`: a condition rings when some GPU goes from
+/// not meeting it to meeting it, at most once per cooldown. The first frame
+/// a GPU appears in only records where it stands.
+#[derive(Debug)]
+pub struct Bell {
+    conditions: Vec<Condition>,
+    cooldown: Duration,
+    /// Conditions each GPU met on the previous frame, by position and UUID.
+    met: HashSet<(usize, String)>,
+    seen: HashSet<String>,
+    rung: Vec<Option<Instant>>,
+}
+
+impl Bell {
+    pub fn new(conditions: Vec<Condition>, cooldown: Duration) -> Self {
+        let rung = vec![None; conditions.len()];
+        Bell {
+            conditions,
+            cooldown,
+            met: HashSet::new(),
+            seen: HashSet::new(),
+            rung,
+        }
+    }
+
+    /// Record the frame and list the conditions that should ring now.
+    pub fn observe(&mut self, snapshot: &Snapshot) -> Vec<&Condition> {
+        let mut rising = vec![false; self.conditions.len()];
+        for gpu in &snapshot.gpus {
+            let known = self.seen.contains(&gpu.uuid);
+            for (i, condition) in self.conditions.iter().enumerate() {
+                let key = (i, gpu.uuid.clone());
+                match condition.holds(gpu) {
+                    Some(true) => rising[i] |= self.met.insert(key) && known,
+                    Some(false) => {
+                        self.met.remove(&key);
+                    }
+                    // an unmeasured GPU keeps its previous state
+                    None => {}
+                }
+            }
+        }
+        // forget unplugged GPUs
+        self.seen = snapshot.gpus.iter().map(|gpu| gpu.uuid.clone()).collect();
+        let seen = &self.seen;
+        self.met.retain(|(_, uuid)| seen.contains(uuid));
+
+        let now = Instant::now();
+        let mut ring = vec![];
+        for (i, condition) in self.conditions.iter().enumerate() {
+            let cooled = self.rung[i].is_none_or(|rung| now.duration_since(rung) >= self.cooldown);
+            if rising[i] && cooled {
+                self.rung[i] = Some(now);
+                ring.push(condition);
+            }
+        }
+        ring
+    }
+
+    /// When each condition last rang and which ones each GPU meets.
+    pub fn state(&self) -> BellState {
+        let (now, wall) = (Instant::now(), Local::now());
+        let rung = self
+            .conditions
+            .iter()
+            .zip(&self.rung)
+            .filter_map(|(condition, rung)| {
+                let ago = chrono::Duration::from_std(now.duration_since((*rung)?)).ok()?;
+                Some((condition.text.clone(), wall - ago))
+            })
+            .collect();
+        let met = self.met.iter().map(|(i, uuid)| (self.conditions[*i].text.clone(), uuid.clone())).collect();
+        BellState { rung, met }
+    }
+
+    /// Continue the cooldowns and edges of an earlier session. Conditions
+    /// that are no longer given are dropped, and the GPUs that met one are
+    /// known from the first frame on.
+    pub fn restore(&mut self, state: &BellState) {
+        let (now, wall) = (Instant::now(), Local::now());
+        let conditions = &self.conditions;
+        let position = |text: &str| conditions.iter().position(|condition| condition.text == text);
+        for (text, at) in &state.rung {
+            let ago = (wall - *at).to_std().unwrap_or_default();
+            if let Some(i) = position(text) {
+                self.rung[i] = now.checked_sub(ago);
+            }
+        }
+        for (text, uuid) in &state.met {
+            if let Some(i) = position(text) {
+                self.met.insert((i, uuid.clone()));
+                self.seen.insert(uuid.clone());
+            }
+        }
+    }
+}
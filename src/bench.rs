@@ -0,0 +1,203 @@
+//! `gpustat bench`: how long each NVML query takes on each GPU, and what a
+//! whole collection pass costs, to choose default collectors and
+//! `--query-timeout` from data. The optional queries are the collector
+//! registry, so a new collector is benchmarked without changes here.
+
+use crate::{
+    render::{self, ASCII_TABLE_PRESET, TABLE_PRESET},
+    stats::{self, COLLECTORS},
+    Opts, StatusError,
+};
+use clap::{Args, Parser};
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use nvml_wrapper::{enum_wrappers::device::TemperatureSensor, error::NvmlError, Device, Nvml};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+#[derive(Args, Clone, Debug)]
+pub struct BenchOpts {
+    #[arg(long, value_name = "N", default_value_t = 50, value_parser = clap::value_parser!(u32).range(1..), help = "Run each query N times")]
+    iterations: u32,
+    #[arg(long, help = "Print one JSON object instead of the table")]
+    json: bool,
+}
+
+/// A query every collection pass runs, whatever the options.
+type BaseQuery = (&'static str, fn(&Device) -> Result<(), NvmlError>);
+
+/// The queries of every pass, as the collection runs them before the collectors.
+const BASE_QUERIES: &[BaseQuery] = &[
+    ("name", |device| device.name().map(drop)),
+    ("uuid", |device| device.uuid().map(drop)),
+    ("memory_info", |device| device.memory_info().map(drop)),
+    ("utilization_rates", |device| device.utilization_rates().map(drop)),
+    ("temperature", |device| device.temperature(TemperatureSensor::Gpu).map(drop)),
+    ("power_usage", |device| device.power_usage().map(drop)),
+    ("power_management_limit", |device| device.power_management_limit().map(drop)),
+    ("running_compute_processes", |device| device.running_compute_processes().map(drop)),
+    ("running_graphics_processes", |device| device.running_graphics_processes().map(drop)),
+    ("current_throttle_reasons", |device| device.current_throttle_reasons().map(drop)),
+];
+
+/// Timings of one query, in microseconds.
+#[derive(Clone, Debug, Serialize)]
+pub struct Timing {
+    pub query: String,
+    /// `base` for the queries of every pass, `collector` for the optional ones.
+    pub kind: &'static str,
+    pub min_us: f64,
+    pub median_us: f64,
+    pub p99_us: f64,
+    /// Iterations that failed, with the last error; a query NVML does not
+    /// support on the GPU fails every time, usually fast.
+    pub failures: u32,
+    pub error: Option<String>,
+}
+
+impl Timing {
+    fn of(query: &str, kind: &'static str, mut samples: Vec<Duration>, failures: u32, error: Option<String>) -> Self {
+        samples.sort();
+        let us = |rank: usize| samples[rank.min(samples.len() - 1)].as_nanos() as f64 / 1000.0;
+        // the nearest-rank percentile, so a few iterations still give a real sample
+        let p99 = (samples.len() * 99).div_ceil(100).max(1) - 1;
+        Timing {
+            query: query.to_string(),
+            kind,
+            min_us: us(0),
+            median_us: us(samples.len() / 2),
+            p99_us: us(p99),
+            failures,
+            error,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DeviceBench {
+    pub index: u32,
+    pub name: Option<String>,
+    pub queries: Vec<Timing>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Bench {
+    pub iterations: u32,
+    pub gpus: Vec<DeviceBench>,
+    /// Whole one-shot collections of every GPU, as a plain `gpustat` and `gpustat -a` run them.
+    pub passes: Vec<Timing>,
+}
+
+fn time<T>(iterations: u32, query: &str, kind: &'static str, mut f: impl FnMut() -> Result<T, String>) -> Timing {
+    let mut samples = Vec::with_capacity(iterations as usize);
+    let (mut failures, mut error) = (0, None);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let result = f();
+        samples.push(start.elapsed());
+        if let Err(e) = result {
+            failures += 1;
+            error = Some(e);
+        }
+    }
+    log::info!("bench: {} timed {} times", query, iterations);
+    Timing::of(query, kind, samples, failures, error)
+}
+
+fn bench_device(opts: &Opts, iterations: u32, nvml: &Nvml, index: u32, device: &Device) -> DeviceBench {
+    let mut queries: Vec<Timing> = BASE_QUERIES
+        .iter()
+        .map(|(name, query)| time(iterations, name, "base", || query(device).map_err(|e| e.to_string())))
+        .collect();
+    for collector in COLLECTORS {
+        queries.push(time(iterations, collector.name, "collector", || {
+            collector.run(opts, nvml, index).map_err(|e| e.to_string())
+        }));
+    }
+    DeviceBench {
+        index,
+        name: device.name().ok(),
+        queries,
+    }
+}
+
+/// Time every query on every GPU, then the default and `-a` collection passes.
+pub fn measure(opts: &Opts, bench: &BenchOpts, nvml: &Nvml, hostname: &str) -> Result<Bench, StatusError> {
+    let mut gpus = vec![];
+    for index in 0..nvml.device_count()? {
+        let device = nvml.device_by_index(index)?;
+        gpus.push(bench_device(opts, bench.iterations, nvml, index, &device));
+    }
+    let passes = [("default", Opts::default()), ("-a", Opts::parse_from(["gpustat", "-a"]))]
+        .iter()
+        .map(|(name, pass)| {
+            time(bench.iterations, name, "pass", || {
+                stats::collect(pass, nvml, hostname).map_err(|e| e.to_string())
+            })
+        })
+        .collect();
+    Ok(Bench {
+        iterations: bench.iterations,
+        gpus,
+        passes,
+    })
+}
+
+/// `0.6µs`, `123µs` or `4.56ms`.
+fn micros(us: f64) -> String {
+    if us < 10.0 {
+        format!("{:.1}µs", us)
+    } else if us < 1000.0 {
+        format!("{:.0}µs", us)
+    } else {
+        format!("{:.2}ms", us / 1000.0)
+    }
+}
+
+fn row(gpu: &str, timing: &Timing, iterations: u32) -> Vec<Cell> {
+    let right = |text: String| Cell::new(text).set_alignment(CellAlignment::Right);
+    let note = match &timing.error {
+        Some(error) => format!("{} of {} failed: {}", timing.failures, iterations, error),
+        None => String::new(),
+    };
+    vec![
+        Cell::new(gpu),
+        Cell::new(&timing.query),
+        right(micros(timing.min_us)),
+        right(micros(timing.median_us)),
+        right(micros(timing.p99_us)),
+        Cell::new(note),
+    ]
+}
+
+pub fn run(opts: &Opts, bench: &BenchOpts, nvml: &Nvml, hostname: &str) -> Result<u8, StatusError> {
+    let measured = measure(opts, bench, nvml, hostname)?;
+    if bench.json {
+        println!("{}", serde_json::to_string_pretty(&measured)?);
+        return Ok(0);
+    }
+    let mut table = Table::new();
+    table
+        .load_preset(if opts.ascii {
+            ASCII_TABLE_PRESET
+        } else {
+            TABLE_PRESET
+        })
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(["GPU", "query", "min", "median", "p99", "failures"]);
+    render::apply_styling(render::styling_enabled(opts), &mut table);
+    for gpu in &measured.gpus {
+        for timing in &gpu.queries {
+            table.add_row(row(&gpu.index.to_string(), timing, measured.iterations));
+        }
+    }
+    for pass in &measured.passes {
+        let pass = Timing {
+            query: format!("{} pass", pass.query),
+            ..pass.clone()
+        };
+        table.add_row(row("all", &pass, measured.iterations));
+    }
+    println!("{}", table);
+    println!("{} iterations per query", measured.iterations);
+    Ok(0)
+}
@@ -0,0 +1,221 @@
+//! `gpustat capabilities`: which optional metrics each GPU can provide, for
+//! schedulers. Every collector is probed once per device on a worker thread,
+//! and the result is cached to a file for `--ttl`.
+
+use crate::{
+    parse_duration,
+    stats::COLLECTORS,
+    write_atomic, Opts, StatusError,
+};
+use chrono::prelude::*;
+use clap::Args;
+use nvml_wrapper::Nvml;
+use nvml_wrapper_sys::bindings::NVML_NVLINK_MAX_LINKS;
+use serde_json::{json, Map, Value};
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Reported for a probe that did not answer within `--probe-timeout`.
+const UNKNOWN: &str = "unknown";
+
+#[derive(Args, Clone, Debug)]
+pub struct CapabilitiesOpts {
+    #[arg(long, help = "Print one JSON object instead of the listing")]
+    json: bool,
+    #[arg(long, value_name = "PATH", help = "Cache file, $XDG_CACHE_HOME/gpustat/capabilities.json by default")]
+    cache: Option<PathBuf>,
+    #[arg(long, value_name = "DURATION", default_value = "1h", value_parser = parse_duration, help = "Reuse a cached probe younger than DURATION")]
+    ttl: Duration,
+    #[arg(long, help = "Probe again even if the cache is fresh")]
+    refresh: bool,
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_duration, help = "Report the probes of a GPU that has not answered after DURATION as unknown")]
+    probe_timeout: Duration,
+}
+
+impl CapabilitiesOpts {
+    fn cache_path(&self) -> Option<PathBuf> {
+        if let Some(path) = &self.cache {
+            return Some(path.clone());
+        }
+        let cache = env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".cache")))?;
+        Some(cache.join("gpustat").join("capabilities.json"))
+    }
+}
+
+/// One answer of a device worker.
+enum Probe {
+    Collector(&'static str, bool),
+    Detail(&'static str, Value),
+    Device(String, String),
+}
+
+/// Probe one device, sending each answer as soon as it is known so a hung
+/// query only loses the ones after it.
+fn probe_device(opts: &Opts, nvml: &Nvml, index: u32, sender: &mpsc::Sender<(u32, Probe)>) {
+    let Ok(device) = nvml.device_by_index(index) else {
+        return;
+    };
+    let send = |probe| {
+        let _ = sender.send((index, probe));
+    };
+    send(Probe::Device(
+        device.name().unwrap_or_default(),
+        device.uuid().unwrap_or_default(),
+    ));
+    for collector in COLLECTORS {
+        send(Probe::Collector(collector.name, collector.probe(opts, nvml, index)));
+    }
+    let links = (0..NVML_NVLINK_MAX_LINKS)
+        .filter(|&link| device.link_wrapper_for(link).is_active().unwrap_or_default())
+        .count();
+    send(Probe::Detail("nvlink_links", json!(links)));
+    send(Probe::Detail("mig_capable", json!(device.mig_mode().is_ok())));
+    send(Probe::Detail("accounting", json!(device.is_accounting_enabled().ok())));
+}
+
+/// Probe every GPU within the timeout, as the JSON object that is cached.
+fn probe(opts: &Arc<Opts>, capabilities: &CapabilitiesOpts, nvml: &Arc<Nvml>) -> Result<(Value, bool), StatusError> {
+    let device_num = nvml.device_count()?;
+    let (sender, receiver) = mpsc::channel();
+    for index in 0..device_num {
+        let (opts, nvml, sender) = (Arc::clone(opts), Arc::clone(nvml), sender.clone());
+        thread::spawn(move || probe_device(&opts, &nvml, index, &sender));
+    }
+    drop(sender);
+
+    let mut gpus: Vec<Map<String, Value>> = (0..device_num)
+        .map(|index| {
+            let mut gpu = Map::new();
+            gpu.insert(String::from("index"), json!(index));
+            gpu
+        })
+        .collect();
+    let mut collectors: Vec<Map<String, Value>> = vec![Map::new(); device_num as usize];
+    let deadline = Instant::now() + capabilities.probe_timeout;
+    // a worker stuck in the driver is left behind, its process exits with this one
+    while let Ok((index, probe)) = receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        let i = index as usize;
+        match probe {
+            Probe::Device(name, uuid) => {
+                gpus[i].insert(String::from("name"), json!(name));
+                gpus[i].insert(String::from("uuid"), json!(uuid));
+            }
+            Probe::Collector(name, supported) => {
+                collectors[i].insert(name.to_string(), json!(supported));
+            }
+            Probe::Detail(name, value) => {
+                gpus[i].insert(name.to_string(), value);
+            }
+        }
+    }
+
+    let mut complete = true;
+    for (gpu, mut collectors) in gpus.iter_mut().zip(collectors) {
+        for collector in COLLECTORS {
+            if !collectors.contains_key(collector.name) {
+                complete = false;
+                log::info!("gpu {}: {} probe timed out", gpu["index"], collector.name);
+                collectors.insert(collector.name.to_string(), json!(UNKNOWN));
+            }
+        }
+        for detail in ["nvlink_links", "mig_capable", "accounting"] {
+            if !gpu.contains_key(detail) {
+                complete = false;
+                gpu.insert(detail.to_string(), json!(UNKNOWN));
+            }
+        }
+        gpu.insert(String::from("collectors"), Value::Object(collectors));
+    }
+    let probed = json!({
+        "driver_version": nvml.sys_driver_version().ok(),
+        "probed_at": Local::now().to_rfc3339(),
+        "gpus": gpus,
+    });
+    Ok((probed, complete))
+}
+
+/// The cached probe if it is younger than `--ttl`.
+fn read_cache(capabilities: &CapabilitiesOpts) -> Option<Value> {
+    if capabilities.refresh {
+        return None;
+    }
+    let path = capabilities.cache_path()?;
+    let age = SystemTime::now().duration_since(fs::metadata(&path).ok()?.modified().ok()?).ok()?;
+    if age >= capabilities.ttl {
+        return None;
+    }
+    log::info!("capabilities cached in {} {:.0?} ago", path.display(), age);
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn print(capabilities: &CapabilitiesOpts, probed: &Value) {
+    if capabilities.json {
+        println!("{}", probed);
+        return;
+    }
+    let empty = vec![];
+    for gpu in probed["gpus"].as_array().unwrap_or(&empty) {
+        println!("[{}] {}", gpu["index"], gpu["name"].as_str().unwrap_or_default());
+        let with = |wanted: &Value| -> Vec<&str> {
+            gpu["collectors"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter(|(_, value)| *value == wanted)
+                .map(|(name, _)| name.as_str())
+                .collect()
+        };
+        for (label, value) in [("supported", json!(true)), ("unsupported", json!(false)), (UNKNOWN, json!(UNKNOWN))] {
+            let names = with(&value);
+            if !names.is_empty() {
+                println!("  {:<12}: {}", label, names.join(", "));
+            }
+        }
+        let yes_no = |value: &Value| match value {
+            Value::Bool(true) => String::from("yes"),
+            Value::Bool(false) => String::from("no"),
+            Value::Null => String::from("n/a"),
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        println!("  {:<12}: {}", "nvlink links", yes_no(&gpu["nvlink_links"]));
+        println!("  {:<12}: {}", "MIG capable", yes_no(&gpu["mig_capable"]));
+        println!("  {:<12}: {}", "accounting", yes_no(&gpu["accounting"]));
+    }
+}
+
+/// Print the cached probe if it is fresh, so NVML need not be initialized.
+pub fn print_cached(capabilities: &CapabilitiesOpts) -> bool {
+    match read_cache(capabilities) {
+        Some(probed) => {
+            print(capabilities, &probed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Probe every GPU, print the result and cache it unless a probe timed out.
+pub fn run(opts: &Arc<Opts>, capabilities: &CapabilitiesOpts, nvml: &Arc<Nvml>) -> Result<u8, StatusError> {
+    let (probed, complete) = probe(opts, capabilities, nvml)?;
+    print(capabilities, &probed);
+    // a partial probe is retried on the next call
+    if let Some(path) = capabilities.cache_path().filter(|_| complete) {
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| write_atomic(&path, &probed.to_string()));
+        if let Err(e) = written {
+            log::warn!("Cannot cache capabilities in {}: {}", path.display(), e);
+        }
+    }
+    Ok(0)
+}
@@ -0,0 +1,66 @@
+//! Kubernetes pods of processes, recognised from their cgroup paths.
+
+use std::fs;
+
+/// Pod UID in a cgroup path, for the cgroup v1 layout
+/// (`/kubepods/burstable/pod1b2c…/<container>`) and the systemd one used by
+/// v2 (`/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod1b2c_….slice/…`).
+pub fn pod_uid(path: &str) -> Option<String> {
+    if !path.contains("kubepods") {
+        return None;
+    }
+    path.split('/').find_map(|component| {
+        let component = component.strip_suffix(".slice").unwrap_or(component);
+        let uid = &component[component.rfind("pod")? + "pod".len()..];
+        // systemd escapes the dashes of the UID as underscores
+        let uid = uid.replace('_', "-");
+        Some(uid).filter(|uid| uid.len() == 36 && uid.chars().all(|c| c.is_ascii_hexdigit() || c == '-'))
+    })
+}
+
+/// Pod UID of a process from `/proc/<pid>/cgroup`, whose lines are
+/// `hierarchy:controllers:path` in either cgroup version.
+fn process_pod_uid(pid: u32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/cgroup", pid))
+        .ok()?
+        .lines()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .find_map(pod_uid)
+}
+
+/// The pod's hostname, which Kubernetes sets to the pod name.
+fn pod_hostname(pid: u32) -> Option<String> {
+    fs::read(format!("/proc/{}/environ", pid))
+        .ok()?
+        .split(|&b| b == 0)
+        .find_map(|var| var.strip_prefix(b"HOSTNAME="))
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+}
+
+/// Pod running a process: its name when the process environment is readable,
+/// otherwise `pod/` and the start of its UID.
+pub fn process_pod(pid: u32) -> Option<String> {
+    let uid = process_pod_uid(pid)?;
+    Some(pod_hostname(pid).unwrap_or_else(|| format!("pod/{}", &uid[..8])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pod_uids_of_both_layouts() {
+        let uid = "1b2c3d4e-5f60-4718-89ab-cdef01234567";
+        let v1 = format!("/kubepods/burstable/pod{}/0123456789abcdef", uid);
+        assert_eq!(pod_uid(&v1).as_deref(), Some(uid));
+        let v2 = format!(
+            "/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod{}.slice/cri-containerd-0123.scope",
+            uid.replace('-', "_")
+        );
+        assert_eq!(pod_uid(&v2).as_deref(), Some(uid));
+        // not a pod, or not a UID
+        assert_eq!(pod_uid("/user.slice/user-1000.slice/session-2.scope"), None);
+        assert_eq!(pod_uid("/kubepods/burstable/podnotauid"), None);
+        assert_eq!(pod_uid(&format!("/system.slice/pod{}", uid)), None);
+    }
+}
@@ -0,0 +1,60 @@
+//! `--chargeback`: the GPU memory of every user summed over the node, as a
+//! share of the installed and of the used GPU memory.
+
+use crate::stats::Snapshot;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Per-user GPU memory of a node.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Chargeback {
+    /// Bytes of GPU memory installed, summed over GPUs.
+    pub installed: u64,
+    /// Bytes of GPU memory in use, summed over GPUs.
+    pub used: u64,
+    /// Users by descending memory.
+    pub users: Vec<UserShare>,
+}
+
+/// A user's GPU memory on the node.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct UserShare {
+    pub user: String,
+    /// Bytes summed over the user's processes on every GPU, `None` when NVML
+    /// reported the memory of none of them.
+    pub memory: Option<u64>,
+    /// Some process of the user has no memory reading, so `memory` is a lower bound.
+    pub unavailable: bool,
+    /// Percent of the installed memory.
+    pub installed_share: Option<f64>,
+    /// Percent of the used memory.
+    pub used_share: Option<f64>,
+}
+
+impl Chargeback {
+    pub fn of(snapshot: &Snapshot) -> Chargeback {
+        let installed = snapshot.gpus.iter().map(|gpu| gpu.memory_total).sum();
+        let used = snapshot.gpus.iter().map(|gpu| gpu.memory_used).sum();
+        let mut users: BTreeMap<String, (Option<u64>, bool)> = BTreeMap::new();
+        for process in snapshot.gpus.iter().flat_map(|gpu| &gpu.processes).filter(|p| !p.stale) {
+            let (memory, unavailable) = users.entry(process.display_user()).or_default();
+            match process.used_memory {
+                Some(used) => *memory = Some(memory.unwrap_or_default() + used),
+                None => *unavailable = true,
+            }
+        }
+        let percent = |memory: Option<u64>, of: u64| Some(memory? as f64 * 100.0 / of as f64).filter(|_| of > 0);
+        let mut users: Vec<UserShare> = users
+            .into_iter()
+            .map(|(user, (memory, unavailable))| UserShare {
+                user,
+                memory,
+                unavailable,
+                installed_share: percent(memory, installed),
+                used_share: percent(memory, used),
+            })
+            .collect();
+        users.sort_by(|a, b| b.memory.cmp(&a.memory).then_with(|| a.user.cmp(&b.user)));
+        Chargeback { installed, used, users }
+    }
+}
@@ -0,0 +1,258 @@
+//! How often each NVML query failed on each GPU over a watch session, for
+//! flaky nodes where one snapshot's N/A says little. The counts are shown
+//! below the table with `-v`, written to stderr on SIGUSR1, carried in the
+//! JSON of every snapshot as `collection_errors` and kept in the state file.
+//!
+//! GPUs are counted by UUID, so a GPU that drops out keeps its counts,
+//! marked as gone, and picks them up again when it comes back. A driver
+//! other than the one the counts were taken with starts them afresh.
+
+use crate::stats::{GpuStat, Snapshot};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Counts named in the summary line; the rest are only counted.
+const SHOWN: usize = 10;
+
+/// The failures of one query on one GPU.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ErrorCount {
+    /// The UUID of the GPU, or `index N` for a GPU that failed before its
+    /// UUID was known.
+    pub uuid: String,
+    /// Displayed index of the GPU when last seen.
+    pub gpu: u32,
+    pub query: String,
+    pub count: u64,
+    /// NVML error name of the latest failure, e.g. `NotSupported`.
+    pub last_code: String,
+    pub last_at: Option<DateTime<Local>>,
+    /// The GPU was missing from the latest snapshot.
+    pub gone: bool,
+}
+
+impl ErrorCount {
+    /// `gpu2.fan_speed ×41 (NotSupported)`.
+    pub fn label(&self, ascii: bool) -> String {
+        let times = if ascii { "x" } else { "×" };
+        let gone = if self.gone { ", gone" } else { "" };
+        format!("gpu{}.{} {}{} ({}{})", self.gpu, self.query, times, self.count, self.last_code, gone)
+    }
+}
+
+/// The failures of a GPU's queries in one snapshot, including the one that
+/// stopped its collection.
+fn failures(gpu: &GpuStat) -> Vec<(String, String)> {
+    let mut failures: Vec<(String, String)> =
+        gpu.query_failures.iter().map(|(query, code)| (query.clone(), code.clone())).collect();
+    if let Some(failure) = &gpu.failure {
+        let query = failure.query.clone().unwrap_or_else(|| String::from("device"));
+        if !gpu.query_failures.contains_key(&query) {
+            failures.push((query, failure.code.clone().unwrap_or_else(|| failure.kind.clone())));
+        }
+    }
+    failures
+}
+
+fn key(gpu: &GpuStat) -> String {
+    let uuid = gpu.failure.as_ref().and_then(|failure| failure.uuid.as_deref()).unwrap_or_default();
+    match (gpu.uuid.as_str(), uuid) {
+        ("", "") => format!("index {}", gpu.index),
+        ("", uuid) | (uuid, _) => uuid.to_string(),
+    }
+}
+
+/// The counts of a session.
+#[derive(Debug, Default)]
+pub struct ErrorCounter {
+    /// The driver the counts were taken with, once a snapshot was observed.
+    driver_version: Option<Option<String>>,
+    counts: BTreeMap<(String, String), ErrorCount>,
+}
+
+impl ErrorCounter {
+    /// Carry on with the counts of a state file saved with `driver_version`.
+    pub fn restore(&mut self, counts: &[ErrorCount], driver_version: Option<&str>) {
+        self.driver_version = Some(driver_version.map(str::to_string));
+        for count in counts {
+            self.counts.insert((count.uuid.clone(), count.query.clone()), count.clone());
+        }
+    }
+
+    /// Count the failed queries of `snapshot`.
+    pub fn observe(&mut self, snapshot: &Snapshot) {
+        if self.driver_version.as_ref() != Some(&snapshot.driver_version) {
+            if self.driver_version.is_some() && !self.counts.is_empty() {
+                log::info!("driver changed, collection error counts start afresh");
+            }
+            self.counts.clear();
+            self.driver_version = Some(snapshot.driver_version.clone());
+        }
+        let mut present = vec![];
+        for gpu in &snapshot.gpus {
+            let uuid = key(gpu);
+            for (query, code) in failures(gpu) {
+                let count = self.counts.entry((uuid.clone(), query.clone())).or_insert_with(|| ErrorCount {
+                    uuid: uuid.clone(),
+                    query,
+                    ..Default::default()
+                });
+                count.count = count.count.saturating_add(1);
+                count.last_code = code;
+                count.last_at = Some(snapshot.timestamp);
+            }
+            present.push((uuid, gpu.order_index.unwrap_or(gpu.index)));
+        }
+        for count in self.counts.values_mut() {
+            match present.iter().find(|(uuid, _)| *uuid == count.uuid) {
+                Some((_, index)) => (count.gpu, count.gone) = (*index, false),
+                None => count.gone = true,
+            }
+        }
+    }
+
+    /// Every count, the most frequent failures first.
+    pub fn counts(&self) -> Vec<ErrorCount> {
+        let mut counts: Vec<ErrorCount> = self.counts.values().cloned().collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then(a.gpu.cmp(&b.gpu)).then(a.query.cmp(&b.query)));
+        counts
+    }
+}
+
+/// `collection errors: gpu2.fan_speed ×41 (NotSupported), gpu5.power_usage ×3
+/// (Unknown)` for `counts` in the order of `ErrorCounter::counts`, `None`
+/// without failures.
+pub fn summary(counts: &[ErrorCount], ascii: bool) -> Option<String> {
+    if counts.is_empty() {
+        return None;
+    }
+    let mut labels: Vec<String> = counts.iter().take(SHOWN).map(|count| count.label(ascii)).collect();
+    if counts.len() > SHOWN {
+        labels.push(format!("{} more", counts.len() - SHOWN));
+    }
+    Some(format!("collection errors: {}", labels.join(", ")))
+}
+
+/// Every count on a line of its own with the time of its latest failure,
+/// as SIGUSR1 writes them to stderr.
+pub fn report(counts: &[ErrorCount], ascii: bool) -> String {
+    if counts.is_empty() {
+        return String::from("collection errors: none\n");
+    }
+    let mut text = String::from("collection errors:\n");
+    for count in counts {
+        let last = count.last_at.map(|at| at.to_rfc3339()).unwrap_or_default();
+        text += &format!("  {}, latest {}\n", count.label(ascii), last);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(driver: &str, gpus: &[(&str, &[&str])]) -> Snapshot {
+        let gpus = gpus
+            .iter()
+            .enumerate()
+            .map(|(index, (uuid, failed))| GpuStat {
+                index: index as u32,
+                uuid: uuid.to_string(),
+                query_failures: failed
+                    .iter()
+                    .map(|query| (query.to_string(), String::from("Unknown")))
+                    .collect(),
+                ..GpuStat::default()
+            })
+            .collect();
+        Snapshot {
+            driver_version: Some(driver.to_string()),
+            gpus,
+            ..Snapshot::default()
+        }
+    }
+
+    fn counts(counter: &ErrorCounter) -> Vec<(String, String, u64, bool)> {
+        let counts = counter.counts().into_iter();
+        counts
+            .map(|count| (count.uuid, count.query, count.count, count.gone))
+            .collect()
+    }
+
+    fn count(uuid: &str, query: &str, count: u64, gone: bool) -> (String, String, u64, bool) {
+        (uuid.to_string(), query.to_string(), count, gone)
+    }
+
+    #[test]
+    fn counts_saturate() {
+        let mut counter = ErrorCounter::default();
+        let saturated = ErrorCount {
+            uuid: String::from("GPU-a"),
+            query: String::from("fan_speed"),
+            count: u64::MAX - 1,
+            ..ErrorCount::default()
+        };
+        counter.restore(&[saturated], Some("550.54.14"));
+        for _ in 0..3 {
+            counter.observe(&snapshot("550.54.14", &[("GPU-a", &["fan_speed"])]));
+        }
+        assert_eq!(counts(&counter), [count("GPU-a", "fan_speed", u64::MAX, false)]);
+    }
+
+    #[test]
+    fn successes_keep_counts_and_a_new_driver_resets_them() {
+        let mut counter = ErrorCounter::default();
+        counter.observe(&snapshot(
+            "550.54.14",
+            &[("GPU-a", &["fan_speed", "power_usage"]), ("GPU-b", &[])],
+        ));
+        counter.observe(&snapshot("550.54.14", &[("GPU-a", &["fan_speed"]), ("GPU-b", &[])]));
+        let latest = counter.counts()[0].last_at;
+        // a clean snapshot leaves the session's counts and their latest failure
+        counter.observe(&snapshot("550.54.14", &[("GPU-a", &[]), ("GPU-b", &[])]));
+        assert_eq!(
+            counts(&counter),
+            [
+                count("GPU-a", "fan_speed", 2, false),
+                count("GPU-a", "power_usage", 1, false)
+            ]
+        );
+        assert_eq!(counter.counts()[0].last_at, latest);
+        // the driver was reloaded
+        counter.observe(&snapshot("555.42.02", &[("GPU-a", &[]), ("GPU-b", &["fan_speed"])]));
+        assert_eq!(counts(&counter), [count("GPU-b", "fan_speed", 1, false)]);
+        // as is a state file of another driver
+        counter.restore(&counter.counts(), Some("550.54.14"));
+        counter.observe(&snapshot("555.42.02", &[]));
+        assert!(counts(&counter).is_empty());
+    }
+
+    #[test]
+    fn gpus_that_drop_out_are_marked_gone() {
+        let mut counter = ErrorCounter::default();
+        counter.observe(&snapshot(
+            "550.54.14",
+            &[("GPU-a", &["fan_speed"]), ("GPU-b", &["fan_speed"])],
+        ));
+        counter.observe(&snapshot("550.54.14", &[("GPU-b", &[])]));
+        assert_eq!(
+            counts(&counter),
+            [
+                count("GPU-a", "fan_speed", 1, true),
+                count("GPU-b", "fan_speed", 1, false)
+            ]
+        );
+        let gone = &counter.counts()[0];
+        assert_eq!(gone.label(true), "gpu0.fan_speed x1 (Unknown, gone)");
+        // it comes back as the second GPU and picks up its count
+        counter.observe(&snapshot("550.54.14", &[("GPU-b", &[]), ("GPU-a", &["fan_speed"])]));
+        let back = counter
+            .counts()
+            .into_iter()
+            .find(|count| count.uuid == "GPU-a")
+            .unwrap();
+        assert_eq!((back.count, back.gone, back.gpu), (2, false, 1));
+    }
+}
@@ -0,0 +1,411 @@
+//! Registry of the optional columns: which flags show each one, whether
+//! `--show-all` does, the collectors filling it and how the table renders
+//! it. Collectors run for the columns that show and the table renders them
+//! in registry order, so a new column only needs an entry here, its
+//! collector in `stats::COLLECTORS` and its cells in `render`.
+
+use crate::{
+    render::{self, RenderOpts, Styling},
+    stats::GpuStat,
+    Opts,
+};
+use comfy_table::Cell;
+
+pub struct Column {
+    pub name: &'static str,
+    /// Flags that show the column, as listed by `--list-columns`.
+    pub flags: &'static str,
+    /// Whether `-a` shows the column; expensive or niche ones need their own flag.
+    pub in_all: bool,
+    pub description: &'static str,
+    /// Whether one of `flags` is given.
+    pub flagged: fn(&Opts) -> bool,
+    /// Collectors of `stats::COLLECTORS` filling it, run whenever it shows.
+    pub collectors: &'static [&'static str],
+    /// Its cells in the table; `None` for those changing an existing cell,
+    /// like the brand on the name.
+    pub cells: Option<Cells>,
+}
+
+/// The cells a column adds to each GPU row.
+pub struct Cells {
+    /// Number of cells, two for the encoder and decoder of `codec`.
+    pub span: usize,
+    /// Whether the cells follow the memory instead of preceding the power.
+    pub after_memory: bool,
+    /// Whether a GPU has a value; a column without any is dropped unless
+    /// `--keep-empty-columns`.
+    pub has_value: fn(&GpuStat) -> bool,
+    pub render: fn(&RenderOpts, &mut Styling, &GpuStat) -> Vec<Cell>,
+}
+
+/// A single cell before the power.
+const fn one(
+    has_value: fn(&GpuStat) -> bool,
+    render: fn(&RenderOpts, &mut Styling, &GpuStat) -> Vec<Cell>,
+) -> Option<Cells> {
+    Some(Cells {
+        span: 1,
+        after_memory: false,
+        has_value,
+        render,
+    })
+}
+
+/// Every optional column, in table order.
+pub const COLUMNS: &[Column] = &[
+    Column {
+        name: "brand",
+        flags: "--show-brand",
+        in_all: true,
+        description: "brand tag next to the GPU name",
+        flagged: |opts| opts.show_brand,
+        collectors: &["brand"],
+        cells: None,
+    },
+    Column {
+        name: "temperatures",
+        flags: "--show-temps-all",
+        in_all: true,
+        description: "every temperature sensor instead of the GPU core only",
+        flagged: |opts| opts.show_temps_all,
+        collectors: &["temperatures"],
+        cells: None,
+    },
+    Column {
+        name: "fan",
+        flags: "--show-fan, --fan-detail",
+        in_all: true,
+        description: "fan speed",
+        flagged: |opts| opts.show_fan || opts.fan_detail,
+        collectors: &["fan"],
+        cells: one(|gpu| gpu.fan_speed.is_some(), render::fan_cells),
+    },
+    Column {
+        name: "cooling",
+        flags: "--cooling",
+        in_all: true,
+        description: "memory temperature, margin to the slowdown temperature and power headroom",
+        flagged: |opts| opts.cooling,
+        collectors: &["cooling"],
+        cells: one(|gpu| gpu.cooling.is_some(), render::cooling_cells),
+    },
+    Column {
+        name: "codec",
+        flags: "--show-codec, --show-codec-detail",
+        in_all: true,
+        description: "encoder and decoder utilization",
+        flagged: |opts| opts.show_codec || opts.show_codec_detail,
+        collectors: &["codec"],
+        cells: Some(Cells {
+            span: 2,
+            after_memory: false,
+            has_value: |gpu| gpu.encoder_utilization.is_some() && gpu.decoder_utilization.is_some(),
+            render: render::codec_cells,
+        }),
+    },
+    Column {
+        name: "encoder_capacity",
+        flags: "--show-enc-capacity",
+        in_all: true,
+        description: "remaining NVENC capacity",
+        flagged: |opts| opts.show_enc_capacity,
+        collectors: &["encoder_capacity"],
+        cells: one(
+            |gpu| gpu.h264_capacity.is_some() || gpu.hevc_capacity.is_some(),
+            render::encoder_capacity_cells,
+        ),
+    },
+    Column {
+        name: "pcie_replays",
+        flags: "--show-pcie-errors",
+        in_all: true,
+        description: "PCIe replay counter",
+        flagged: |opts| opts.show_pcie_errors,
+        collectors: &["pcie_replays"],
+        cells: one(|gpu| gpu.pcie_replays.is_some(), render::pcie_replays_cells),
+    },
+    Column {
+        name: "throttle",
+        flags: "--show-throttle",
+        in_all: true,
+        description: "active clock throttle reasons",
+        flagged: |opts| opts.show_throttle,
+        collectors: &["throttle"],
+        cells: one(|gpu| gpu.throttle_reasons.is_some(), render::throttle_cells),
+    },
+    Column {
+        name: "perf",
+        flags: "--show-perf",
+        in_all: true,
+        description: "SM clock as a share of the maximum boost clock",
+        flagged: |opts| opts.show_perf,
+        collectors: &["perf"],
+        cells: one(|gpu| gpu.perf.is_some(), render::perf_cells),
+    },
+    Column {
+        name: "bandwidth",
+        flags: "--show-bandwidth",
+        in_all: true,
+        description: "estimated memory bandwidth against the board's peak",
+        flagged: |opts| opts.show_bandwidth,
+        collectors: &["bandwidth"],
+        cells: one(|gpu| gpu.bandwidth.is_some(), render::bandwidth_cells),
+    },
+    Column {
+        name: "app_clocks",
+        flags: "--show-app-clocks",
+        in_all: true,
+        description: "current and default application clocks",
+        flagged: |opts| opts.show_app_clocks,
+        collectors: &["app_clocks"],
+        cells: one(|gpu| gpu.app_clocks.is_some(), render::app_clocks_cells),
+    },
+    Column {
+        name: "inforom",
+        flags: "--show-inforom",
+        in_all: false,
+        description: "InfoROM version and checksum, read from flash every refresh",
+        flagged: |opts| opts.show_inforom,
+        collectors: &["inforom"],
+        cells: one(|gpu| gpu.inforom.is_some(), render::inforom_cells),
+    },
+    Column {
+        name: "cc_mode",
+        flags: "--show-cc-mode",
+        in_all: true,
+        description: "confidential computing mode",
+        flagged: |opts| opts.show_cc_mode,
+        collectors: &["cc_mode"],
+        cells: one(|gpu| gpu.cc_mode.is_some(), render::cc_mode_cells),
+    },
+    Column {
+        name: "ecc",
+        flags: "--show-ecc",
+        in_all: true,
+        description: "current and pending ECC mode",
+        flagged: |opts| opts.show_ecc,
+        collectors: &["ecc"],
+        cells: one(|gpu| gpu.ecc_mode.is_some(), render::ecc_cells),
+    },
+    Column {
+        name: "mig_mode",
+        flags: "--show-mig-mode",
+        in_all: true,
+        description: "current and pending MIG mode",
+        flagged: |opts| opts.show_mig_mode,
+        collectors: &["mig_mode"],
+        cells: one(|gpu| gpu.mig_mode.is_some(), render::mig_mode_cells),
+    },
+    Column {
+        name: "display",
+        flags: "--show-display",
+        in_all: false,
+        description: "connected displays",
+        flagged: |opts| opts.show_display,
+        collectors: &["display"],
+        cells: one(
+            |gpu| gpu.displays.is_some() || gpu.display_active == Some(true),
+            render::display_cells,
+        ),
+    },
+    Column {
+        name: "allocations",
+        flags: "--show-allocations",
+        in_all: false,
+        description: "Kubernetes pods the GPU is allocated to",
+        flagged: |opts| opts.show_allocations,
+        collectors: &[],
+        // "unallocated" is a value of its own
+        cells: one(|_| true, render::allocations_cells),
+    },
+    Column {
+        name: "sharing",
+        flags: "--show-sharing",
+        in_all: false,
+        description: "Kubernetes time-slicing replicas",
+        flagged: |opts| opts.show_sharing,
+        collectors: &["sharing"],
+        cells: one(|gpu| gpu.sharing.is_some(), render::sharing_cells),
+    },
+    Column {
+        name: "handles",
+        flags: "--show-handles",
+        in_all: false,
+        description: "descriptors open on the GPU's device node, from /proc",
+        flagged: |opts| opts.show_handles,
+        collectors: &["handles"],
+        cells: one(|gpu| gpu.handles.is_some(), render::handles_cells),
+    },
+    Column {
+        name: "xid",
+        flags: "--show-xid",
+        in_all: false,
+        description: "latest Xid error from the kernel log",
+        flagged: |opts| opts.show_xid,
+        collectors: &["xid"],
+        cells: one(|gpu| matches!(gpu.xid, Some(Ok(_))), render::xid_cells),
+    },
+    Column {
+        name: "virtualization",
+        flags: "--show-virt",
+        in_all: true,
+        description: "virtualization mode and active vGPUs",
+        flagged: |opts| opts.show_virt,
+        collectors: &["virtualization"],
+        cells: one(
+            |gpu| {
+                gpu.virtualization
+                    .as_ref()
+                    .and_then(render::virtualization_label)
+                    .is_some()
+            },
+            render::virtualization_cells,
+        ),
+    },
+    Column {
+        name: "affinity",
+        flags: "--show-affinity",
+        in_all: true,
+        description: "closest CPUs and NUMA nodes",
+        flagged: |opts| opts.show_affinity,
+        collectors: if cfg!(target_os = "linux") { &["affinity"] } else { &[] },
+        cells: one(|gpu| gpu.cpu_affinity.is_some(), render::affinity_cells),
+    },
+    Column {
+        name: "owner",
+        flags: "--show-owner",
+        in_all: true,
+        description: "user holding the most memory on each GPU",
+        flagged: |opts| opts.show_owner,
+        collectors: &[],
+        cells: Some(Cells {
+            span: 1,
+            after_memory: true,
+            has_value: |_| true,
+            render: render::owner_cells,
+        }),
+    },
+    // parts of the process column
+    Column {
+        name: "full_cmd",
+        flags: "-f, --show-full-cmd",
+        in_all: true,
+        description: "full command line of each process",
+        flagged: |opts| opts.show_full_cmd,
+        collectors: &[],
+        cells: None,
+    },
+    Column {
+        name: "pid",
+        flags: "-p, --show-pid",
+        in_all: true,
+        description: "PID of each process",
+        flagged: |opts| opts.show_pid,
+        collectors: &[],
+        cells: None,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixture::FixtureProvider,
+        render::{Renderer, TableRenderer},
+        stats::{self, COLLECTORS},
+    };
+    use clap::Parser;
+    use std::path::Path;
+
+    fn opts(args: &[&str]) -> Opts {
+        Opts::try_parse_from(["gpustat"].iter().chain(args)).unwrap()
+    }
+
+    /// The GPU rows of the table for the MIG fixture, without the header
+    /// carrying the time.
+    fn rows(args: &[&str]) -> String {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/a100-mig.json");
+        let provider = FixtureProvider::load(&fixture).unwrap();
+        // descriptors are read from /proc, which the fixture has no handles in
+        let args: Vec<&str> = ["--no-color", "--disable-collector", "handles"]
+            .iter()
+            .chain(args)
+            .copied()
+            .collect();
+        let opts = opts(&args);
+        let snapshot = stats::collect(&opts, &provider, "node").unwrap();
+        let render_opts = RenderOpts::from(&opts);
+        let table = TableRenderer::new(render_opts.thresholds.hysteresis).render(&snapshot, &render_opts);
+        table.lines().skip(1).collect::<Vec<_>>().join("\n")
+    }
+
+    fn names(columns: &[&Column]) -> String {
+        columns.iter().map(|column| column.name).collect::<Vec<_>>().join(",")
+    }
+
+    #[test]
+    fn names_are_unique() {
+        for (i, column) in COLUMNS.iter().enumerate() {
+            assert!(
+                COLUMNS[i + 1..].iter().all(|other| other.name != column.name),
+                "{} is registered twice",
+                column.name
+            );
+        }
+    }
+
+    #[test]
+    fn flags_all_and_columns_each_show_a_column() {
+        let none = opts(&[]);
+        assert!(COLUMNS.iter().all(|column| !none.column(column.name)));
+        assert!(opts(&["--show-fan"]).column("fan"));
+        assert!(opts(&["--fan-detail"]).column("fan"));
+        let all = opts(&["-a"]);
+        for column in COLUMNS {
+            assert_eq!(all.column(column.name), column.in_all, "{}", column.name);
+        }
+        // --columns only adds to -a
+        let more = opts(&["-a", "--columns", "xid,handles"]);
+        assert!(more.column("xid") && more.column("handles") && more.column("brand"));
+
+        let in_all: Vec<&Column> = COLUMNS.iter().filter(|column| column.in_all).collect();
+        assert_eq!(rows(&["-a"]), rows(&["--columns", &names(&in_all)]));
+        let every: Vec<&Column> = COLUMNS.iter().collect();
+        let full = rows(&["--keep-empty-columns", "--columns", &names(&every)]);
+        for column in COLUMNS {
+            let others: Vec<&Column> = COLUMNS.iter().filter(|other| other.name != column.name).collect();
+            let without = rows(&["--keep-empty-columns", "--columns", &names(&others)]);
+            assert_ne!(full, without, "{} does not show", column.name);
+        }
+    }
+
+    #[test]
+    fn columns_name_registered_collectors() {
+        for column in COLUMNS {
+            for name in column.collectors {
+                assert!(
+                    COLLECTORS.iter().any(|collector| collector.name == *name),
+                    "{} names the unknown collector {}",
+                    column.name,
+                    name
+                );
+            }
+        }
+        // the brand is only collected for its column
+        let brand = COLLECTORS.iter().find(|collector| collector.name == "brand").unwrap();
+        assert!(!brand.wanted(&opts(&[])) && brand.wanted(&opts(&["-a"])));
+    }
+
+    #[test]
+    fn unknown_columns_are_refused() {
+        let error = Opts::try_parse_from(["gpustat", "--columns", "brand,bogus"]).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("unknown column, expected one of brand, temperatures"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! `gpustat completions <shell>`: clap's static completion script, plus hooks
+//! that complete `--id`, `--expand-gpu`, `--expand-user` and `--user` from this
+//! machine through the hidden `gpustat __complete` command.
+
+use crate::{runtime_pm, Opts, StatusError};
+use clap::{Args, CommandFactory, ValueEnum};
+use clap_complete::Shell;
+use nix::unistd::{Uid, User};
+use nvml_wrapper::Nvml;
+use std::{collections::BTreeSet, fs, io};
+
+#[derive(Args, Clone, Debug)]
+pub struct CompletionsOpts {
+    #[arg(value_enum, help = "Shell to write the completion script for")]
+    shell: Shell,
+}
+
+/// Candidates `gpustat __complete` prints, one per line.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Candidates {
+    /// GPU indices
+    Id,
+    /// Owners of the processes on the GPUs
+    User,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CompleteOpts {
+    #[arg(value_enum)]
+    candidates: Candidates,
+}
+
+const BASH_HOOK: &str = r#"
+_gpustat_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}"
+    case "$prev" in
+        --id|--expand-gpu) COMPREPLY=($(compgen -W "$(gpustat __complete id 2>/dev/null)" -- "$cur")); return 0 ;;
+        --expand-user|--user) COMPREPLY=($(compgen -W "$(gpustat __complete user 2>/dev/null)" -- "$cur")); return 0 ;;
+    esac
+    _gpustat "$@"
+}
+complete -F _gpustat_dynamic -o bashdefault -o default gpustat
+"#;
+
+const ZSH_HOOK: &str = r#"
+_gpustat_dynamic() {
+    case "${words[CURRENT-1]}" in
+        --id|--expand-gpu) compadd -- ${(f)"$(gpustat __complete id 2>/dev/null)"}; return ;;
+        --expand-user|--user) compadd -- ${(f)"$(gpustat __complete user 2>/dev/null)"}; return ;;
+    esac
+    _gpustat "$@"
+}
+compdef _gpustat_dynamic gpustat
+"#;
+
+const FISH_HOOK: &str = r#"
+complete -c gpustat -l id -x -a '(gpustat __complete id 2>/dev/null)'
+complete -c gpustat -l expand-gpu -x -a '(gpustat __complete id 2>/dev/null)'
+complete -c gpustat -l expand-user -x -a '(gpustat __complete user 2>/dev/null)'
+complete -c gpustat -l user -x -a '(gpustat __complete user 2>/dev/null)'
+"#;
+
+/// Write the completion script of `shell` to stdout. Shells without a hook
+/// get the static script only.
+pub fn run(opts: &CompletionsOpts) -> Result<u8, StatusError> {
+    let mut command = Opts::command();
+    clap_complete::generate(opts.shell, &mut command, "gpustat", &mut io::stdout());
+    match opts.shell {
+        Shell::Bash => print!("{}", BASH_HOOK),
+        Shell::Zsh => print!("{}", ZSH_HOOK),
+        Shell::Fish => print!("{}", FISH_HOOK),
+        _ => {}
+    }
+    Ok(0)
+}
+
+/// Owner of a process from /proc, without a full sysinfo refresh.
+fn process_user(pid: u32) -> Option<String> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let uid = status.lines().find_map(|line| line.strip_prefix("Uid:"))?.split_whitespace().next()?;
+    Some(User::from_uid(Uid::from_raw(uid.parse().ok()?)).ok()??.name)
+}
+
+fn candidates(candidates: Candidates) -> Option<Vec<String>> {
+    let nvml = Nvml::init().ok()?;
+    let count = nvml.device_count().ok()?;
+    Some(match candidates {
+        Candidates::Id => (0..count).map(|index| index.to_string()).collect(),
+        Candidates::User => {
+            let mut users = BTreeSet::new();
+            // a suspended GPU runs no processes, and listing them would wake it
+            let awake = (0..count).filter(|&index| runtime_pm::suspended(index, None).is_none());
+            for device in awake.filter_map(|index| nvml.device_by_index(index).ok()) {
+                let compute = device.running_compute_processes().unwrap_or_default();
+                let graphics = device.running_graphics_processes().unwrap_or_default();
+                users.extend(compute.iter().chain(&graphics).filter_map(|process| process_user(process.pid)));
+            }
+            users.into_iter().collect()
+        }
+    })
+}
+
+/// Print completion candidates, or nothing when NVML is unavailable, so that
+/// tab completion never fails or hangs on an error.
+pub fn complete(opts: &CompleteOpts) -> Result<u8, StatusError> {
+    for candidate in candidates(opts.candidates).unwrap_or_default() {
+        println!("{}", candidate);
+    }
+    Ok(0)
+}
@@ -0,0 +1,218 @@
+//! `--consistency-check`: configuration that should be the same on every GPU
+//! of one model on a node, such as the power limit or ECC mode. A card set
+//! up differently from its peers is almost always a provisioning mistake, so
+//! each one is marked in the table and the differences are listed below it.
+//!
+//! GPUs are compared with the others of the same model only, and a GPU that
+//! could not be queried or lacks an attribute is left out of its comparison.
+
+use crate::stats::{GpuStat, Snapshot};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A compared attribute, by name, label and value as shown.
+type Attribute = (&'static str, &'static str, fn(&GpuStat) -> Option<String>);
+
+fn on_off(enabled: bool) -> String {
+    String::from(if enabled { "on" } else { "off" })
+}
+
+/// Every compared attribute; the names are those of `GpuStat::inconsistent`.
+pub const ATTRIBUTES: &[Attribute] = &[
+    ("power_limit", "power limit", |gpu| Some(format!("{} W", gpu.power_limit / 1000))),
+    ("persistence", "persistence mode", |gpu| gpu.persistence_mode.map(on_off)),
+    ("ecc", "ECC", |gpu| gpu.ecc_mode.as_ref().map(|mode| on_off(mode.currently_enabled))),
+    ("app_clocks", "application clocks", |gpu| {
+        gpu.app_clocks.map(|clocks| format!("{}/{} MHz", clocks.graphics, clocks.memory))
+    }),
+    ("mig", "MIG mode", |gpu| gpu.mig_mode.map(|mode| on_off(mode.current))),
+];
+
+/// GPUs holding one value of a differing attribute.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Outlier {
+    pub value: String,
+    /// Displayed indices.
+    pub gpus: Vec<u32>,
+}
+
+/// An attribute that differs between GPUs of one model.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Difference {
+    pub model: String,
+    /// A name of `ATTRIBUTES`.
+    pub attribute: String,
+    /// The value most of the GPUs share, `None` when no value is the most
+    /// common one and every GPU counts as an outlier.
+    pub majority: Option<String>,
+    pub outliers: Vec<Outlier>,
+}
+
+impl Difference {
+    pub fn label(&self) -> &'static str {
+        ATTRIBUTES
+            .iter()
+            .find(|(name, _, _)| *name == self.attribute)
+            .map_or("", |(_, label, _)| label)
+    }
+}
+
+/// The differences between GPUs of the same model in `gpus`, by model in
+/// the order they are listed and by attribute in the order of `ATTRIBUTES`.
+pub fn differences(gpus: &[GpuStat]) -> Vec<Difference> {
+    let mut models: Vec<(&str, Vec<&GpuStat>)> = vec![];
+    for gpu in gpus.iter().filter(|gpu| gpu.measured()) {
+        match models.iter_mut().find(|(model, _)| *model == gpu.name) {
+            Some((_, peers)) => peers.push(gpu),
+            None => models.push((&gpu.name, vec![gpu])),
+        }
+    }
+    let mut differences = vec![];
+    for (model, peers) in models {
+        for (attribute, _, value) in ATTRIBUTES {
+            // values in the order they first appear, with their GPUs
+            let mut values: Vec<(String, Vec<u32>)> = vec![];
+            for gpu in &peers {
+                let Some(value) = value(gpu) else {
+                    continue;
+                };
+                let index = gpu.order_index.unwrap_or(gpu.index);
+                match values.iter_mut().find(|(seen, _)| *seen == value) {
+                    Some((_, indices)) => indices.push(index),
+                    None => values.push((value, vec![index])),
+                }
+            }
+            if values.len() < 2 {
+                continue;
+            }
+            let most = values.iter().map(|(_, indices)| indices.len()).max().unwrap_or_default();
+            let majority = match values.iter().filter(|(_, indices)| indices.len() == most).count() {
+                1 => values.iter().position(|(_, indices)| indices.len() == most),
+                _ => None,
+            };
+            let majority = majority.map(|position| values.remove(position).0);
+            differences.push(Difference {
+                model: model.to_string(),
+                attribute: attribute.to_string(),
+                majority,
+                outliers: values.into_iter().map(|(value, gpus)| Outlier { value, gpus }).collect(),
+            });
+        }
+    }
+    differences
+}
+
+/// Compare the GPUs of `snapshot`, marking the outliers' attributes in
+/// `GpuStat::inconsistent`.
+pub fn check(snapshot: &mut Snapshot) {
+    let differences = differences(&snapshot.gpus);
+    let mut marked: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for difference in &differences {
+        for index in difference.outliers.iter().flat_map(|outlier| &outlier.gpus) {
+            marked.entry(*index).or_default().push(difference.attribute.clone());
+        }
+    }
+    for gpu in &mut snapshot.gpus {
+        gpu.inconsistent = marked.remove(&gpu.order_index.unwrap_or(gpu.index)).unwrap_or_default();
+    }
+    snapshot.consistency = Some(differences);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::AppClocks;
+
+    /// Four identical A100s at 400 W with 1410/1593 MHz application clocks.
+    fn a100s() -> Vec<GpuStat> {
+        (0..4)
+            .map(|index| GpuStat {
+                index,
+                name: String::from("NVIDIA A100-SXM4-80GB"),
+                temperature: 40,
+                power_limit: 400_000,
+                app_clocks: Some(AppClocks {
+                    graphics: 1410,
+                    memory: 1593,
+                    default_graphics: 1410,
+                    default_memory: 1593,
+                }),
+                ..GpuStat::default()
+            })
+            .collect()
+    }
+
+    /// `power_limit: 250 W on [2] against 400 W` for each difference.
+    fn outliers(differences: &[Difference]) -> Vec<String> {
+        differences
+            .iter()
+            .map(|difference| {
+                let outliers: Vec<String> = difference
+                    .outliers
+                    .iter()
+                    .map(|outlier| format!("{} on {:?}", outlier.value, outlier.gpus))
+                    .collect();
+                let majority = difference.majority.as_deref().unwrap_or("no majority");
+                format!("{}: {} against {}", difference.attribute, outliers.join(", "), majority)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn uniform_gpus_agree() {
+        let mut gpus = a100s();
+        // readings such as the temperature differ on any node
+        gpus[1].temperature = 80;
+        assert!(differences(&gpus).is_empty());
+    }
+
+    #[test]
+    fn one_gpu_set_up_differently_is_the_outlier() {
+        let mut gpus = a100s();
+        gpus[2].power_limit = 250_000;
+        gpus[3].app_clocks.as_mut().unwrap().graphics = 1095;
+        assert_eq!(
+            outliers(&differences(&gpus)),
+            [
+                "power_limit: 250 W on [2] against 400 W",
+                "app_clocks: 1095/1593 MHz on [3] against 1410/1593 MHz",
+            ]
+        );
+        let mut snapshot = Snapshot {
+            gpus,
+            ..Snapshot::default()
+        };
+        check(&mut snapshot);
+        let marked: Vec<&[String]> = snapshot.gpus.iter().map(|gpu| gpu.inconsistent.as_slice()).collect();
+        assert_eq!(
+            marked,
+            [
+                &[][..],
+                &[],
+                &[String::from("power_limit")],
+                &[String::from("app_clocks")]
+            ]
+        );
+    }
+
+    #[test]
+    fn only_measured_peers_of_one_model_are_compared() {
+        let mut gpus = a100s();
+        gpus[0].power_limit = 300_000;
+        gpus[0].name = String::from("NVIDIA H100 80GB HBM3");
+        gpus[1].power_limit = 300_000;
+        gpus[1].error = Some(String::from("GPU is lost"));
+        gpus[2].app_clocks = None;
+        assert!(differences(&gpus).is_empty());
+        // two against two has no majority
+        let mut gpus = a100s();
+        gpus[0].power_limit = 300_000;
+        gpus[1].power_limit = 300_000;
+        let differences = differences(&gpus);
+        assert_eq!(
+            outliers(&differences),
+            ["power_limit: 300 W on [0, 1], 400 W on [2, 3] against no majority"]
+        );
+        assert_eq!(differences[0].label(), "power limit");
+    }
+}
@@ -0,0 +1,175 @@
+//! `--container`: the host view narrowed to the GPUs a Docker container can
+//! see, read once from the Docker Engine API at `DOCKER_HOST` or the local
+//! socket. Only the `docker` feature talks to Docker, so the host mode never
+//! depends on it.
+
+use crate::stats::Snapshot;
+use bollard::{errors::Error, Docker};
+use serde_json::Value;
+use std::{convert::TryFrom, time::Duration};
+
+/// The API answers inspections right away unless the daemon hangs.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// GPUs exposed to a container.
+#[derive(Debug, PartialEq, Eq)]
+enum Visible {
+    All,
+    /// NVML indices or UUIDs, as given to `--gpus device=...` or `NVIDIA_VISIBLE_DEVICES`.
+    Devices(Vec<String>),
+    /// The first N GPUs, as `--gpus N` requests.
+    First(usize),
+}
+
+#[derive(Debug)]
+pub struct Container {
+    pub name: String,
+    visible: Visible,
+}
+
+/// Devices of a `--gpus` request, `None` when the container made none.
+fn device_requests(inspect: &Value) -> Option<Visible> {
+    let requests = inspect["HostConfig"]["DeviceRequests"].as_array()?;
+    let request = requests.iter().find(|request| {
+        let capabilities = request["Capabilities"].as_array().into_iter().flatten();
+        let mut capabilities = capabilities.flat_map(|set| set.as_array().into_iter().flatten());
+        request["Driver"] == "nvidia" || capabilities.any(|capability| capability == "gpu")
+    })?;
+    let ids: Vec<String> = request["DeviceIDs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|id| Some(id.as_str()?.to_string()))
+        .collect();
+    Some(match request["Count"].as_i64() {
+        _ if !ids.is_empty() => Visible::Devices(ids),
+        Some(-1) => Visible::All,
+        Some(count) => Visible::First(usize::try_from(count).unwrap_or_default()),
+        None => Visible::Devices(vec![]),
+    })
+}
+
+/// Devices of `NVIDIA_VISIBLE_DEVICES` for the NVIDIA runtime; unset means none.
+fn visible_devices_env(inspect: &Value) -> Visible {
+    let env = inspect["Config"]["Env"].as_array().into_iter().flatten().filter_map(Value::as_str);
+    let devices = env.filter_map(|var| var.strip_prefix("NVIDIA_VISIBLE_DEVICES=")).next_back();
+    match devices.map(str::trim) {
+        Some("all") => Visible::All,
+        None | Some("") | Some("none") | Some("void") => Visible::Devices(vec![]),
+        Some(devices) => Visible::Devices(devices.split(',').map(|id| id.trim().to_string()).collect()),
+    }
+}
+
+/// Look up the GPUs a running or stopped container can see.
+pub fn inspect(container: &str) -> Result<Container, String> {
+    let docker = Docker::connect_with_local_defaults()
+        .map_err(|e| format!("cannot reach docker: {}", e))?
+        .with_timeout(TIMEOUT);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("cannot start the runtime: {}", e))?;
+    let response = runtime
+        .block_on(docker.inspect_container(container, None))
+        .map_err(|e| match e {
+            Error::DockerResponseServerError { message, .. } => message,
+            e => format!("cannot reach docker: {}", e),
+        })?;
+    // the API's own field names, as in `docker inspect`
+    let inspect = serde_json::to_value(&response).map_err(|e| format!("unexpected inspect response: {}", e))?;
+    Ok(Container::from_inspect(container, &inspect))
+}
+
+impl Container {
+    fn from_inspect(container: &str, inspect: &Value) -> Container {
+        let name = inspect["Name"]
+            .as_str()
+            .unwrap_or(container)
+            .trim_start_matches('/')
+            .to_string();
+        let visible = device_requests(inspect).unwrap_or_else(|| visible_devices_env(inspect));
+        Container { name, visible }
+    }
+
+    /// Keep the GPUs of `snapshot` the container sees, and record its name.
+    pub fn narrow(&self, snapshot: &mut Snapshot) {
+        match &self.visible {
+            Visible::All => {}
+            Visible::Devices(ids) => snapshot
+                .gpus
+                .retain(|gpu| ids.iter().any(|id| *id == gpu.index.to_string() || *id == gpu.uuid)),
+            Visible::First(count) => {
+                snapshot.gpus.sort_by_key(|gpu| gpu.index);
+                snapshot.gpus.truncate(*count);
+                snapshot.gpus.sort_by_key(|gpu| gpu.order_index);
+            }
+        }
+        snapshot.container = Some(self.name.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::tests::{fixtures, gpu};
+    use clap::Parser;
+    use serde_json::json;
+
+    fn env(devices: &str) -> Value {
+        json!({"Config": {"Env": ["PATH=/usr/bin", format!("NVIDIA_VISIBLE_DEVICES={}", devices)]}})
+    }
+
+    fn ids(ids: &[&str]) -> Visible {
+        Visible::Devices(ids.iter().map(|id| id.to_string()).collect())
+    }
+
+    #[test]
+    fn gpu_requests_name_devices_or_a_count() {
+        let request = |request: Value| json!({"HostConfig": {"DeviceRequests": [request]}});
+        let all = request(json!({"Driver": "", "Count": -1, "Capabilities": [["gpu"]]}));
+        assert_eq!(device_requests(&all), Some(Visible::All));
+        let two = request(json!({"Driver": "nvidia", "Count": 2}));
+        assert_eq!(device_requests(&two), Some(Visible::First(2)));
+        let named = request(json!({"Driver": "", "Count": 0, "DeviceIDs": ["1", "GPU-b"], "Capabilities": [["gpu"]]}));
+        assert_eq!(device_requests(&named), Some(ids(&["1", "GPU-b"])));
+        // another vendor's request, or none at all, leaves it to the environment
+        let other = request(json!({"Driver": "", "Count": -1, "Capabilities": [["tpu"]]}));
+        assert_eq!(device_requests(&other), None);
+        assert_eq!(device_requests(&json!({"HostConfig": {"DeviceRequests": null}})), None);
+    }
+
+    #[test]
+    fn the_nvidia_environment_lists_visible_devices() {
+        assert_eq!(visible_devices_env(&env("all")), Visible::All);
+        assert_eq!(visible_devices_env(&env("none")), ids(&[]));
+        assert_eq!(visible_devices_env(&env("void")), ids(&[]));
+        assert_eq!(
+            visible_devices_env(&json!({"Config": {"Env": ["PATH=/usr/bin"]}})),
+            ids(&[])
+        );
+        assert_eq!(visible_devices_env(&env("GPU-a, GPU-b")), ids(&["GPU-a", "GPU-b"]));
+        assert_eq!(visible_devices_env(&env("0,2")), ids(&["0", "2"]));
+    }
+
+    #[test]
+    fn containers_narrow_the_host_view() {
+        let opts = crate::Opts::try_parse_from(["gpustat"]).unwrap();
+        let provider = fixtures((0..4).map(gpu).collect());
+        let shown = |inspect: Value| {
+            let mut snapshot = crate::stats::collect(&opts, &provider, "node").unwrap();
+            let container = Container::from_inspect("abc123", &inspect);
+            container.narrow(&mut snapshot);
+            assert_eq!(snapshot.container.as_deref(), Some(container.name.as_str()));
+            snapshot.gpus.iter().map(|gpu| gpu.index).collect::<Vec<_>>()
+        };
+        assert!(shown(json!({})).is_empty());
+        assert_eq!(shown(env("GPU-00000002")), vec![2]);
+        let mut inspect = env("3,1");
+        inspect["Name"] = json!("/trainer");
+        assert_eq!(Container::from_inspect("abc123", &inspect).name, "trainer");
+        assert_eq!(shown(inspect), vec![1, 3]);
+        let first = json!({"HostConfig": {"DeviceRequests": [{"Driver": "nvidia", "Count": 2}]}});
+        assert_eq!(shown(first), vec![0, 1]);
+        assert_eq!(shown(env("all")), vec![0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,397 @@
+//! `--format csv`: one row per GPU, or with `--csv-level process` per
+//! process, under a header naming the fields of `--csv-fields`.
+//!
+//! Fields are quoted as RFC 4180 asks when they hold the delimiter, a quote
+//! or a line break, which command lines can hold all of, and rows end in
+//! CRLF. Other control characters are escaped as in the table, so a command
+//! line cannot drive the terminal the output is read on.
+
+use crate::{
+    render::{self, escape_control, RenderOpts, Renderer},
+    stats::{GpuStat, ProcessStat, Snapshot},
+};
+use std::{cell::Cell, io::IsTerminal};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CsvLevel {
+    /// One row per GPU.
+    #[default]
+    Gpu,
+    /// One row per process, with the fields of its GPU.
+    Process,
+}
+
+pub struct CsvField {
+    pub name: &'static str,
+    /// Only has a value in process rows.
+    pub process: bool,
+    pub description: &'static str,
+    value: fn(&Snapshot, &GpuStat, Option<&ProcessStat>) -> String,
+}
+
+/// The value of a GPU metric, empty when the GPU could not be queried.
+fn metric(gpu: &GpuStat, value: impl ToString) -> String {
+    if gpu.measured() {
+        value.to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// Every field `--csv-fields` can select.
+pub const FIELDS: &[CsvField] = &[
+    CsvField {
+        name: "time",
+        process: false,
+        description: "collection time, RFC 3339",
+        value: |snapshot, _, _| snapshot.collected_at.unwrap_or(snapshot.timestamp).to_rfc3339(),
+    },
+    CsvField {
+        name: "host",
+        process: false,
+        description: "hostname",
+        value: |snapshot, _, _| snapshot.hostname.clone(),
+    },
+    CsvField {
+        name: "index",
+        process: false,
+        description: "NVML index of the GPU",
+        value: |_, gpu, _| gpu.index.to_string(),
+    },
+    CsvField {
+        name: "uuid",
+        process: false,
+        description: "UUID of the GPU",
+        value: |_, gpu, _| gpu.uuid.clone(),
+    },
+    CsvField {
+        name: "name",
+        process: false,
+        description: "product name of the GPU",
+        value: |_, gpu, _| gpu.name.clone(),
+    },
+    CsvField {
+        name: "error",
+        process: false,
+        description: "why the GPU could not be queried, empty when it could",
+        value: |_, gpu, _| match &gpu.error {
+            Some(error) => error.clone(),
+            None if gpu.reset_required => String::from("reset required"),
+            None if gpu.suspended => String::from("suspended"),
+            None => String::new(),
+        },
+    },
+    CsvField {
+        name: "temperature_c",
+        process: false,
+        description: "GPU temperature in °C",
+        value: |_, gpu, _| metric(gpu, gpu.temperature),
+    },
+    CsvField {
+        name: "utilization_pct",
+        process: false,
+        description: "GPU utilization in percent",
+        value: |_, gpu, _| metric(gpu, gpu.utilization),
+    },
+    CsvField {
+        name: "memory_used_mib",
+        process: false,
+        description: "used GPU memory in MiB",
+        value: |_, gpu, _| metric(gpu, gpu.memory_used >> 20),
+    },
+    CsvField {
+        name: "memory_total_mib",
+        process: false,
+        description: "installed GPU memory in MiB",
+        value: |_, gpu, _| metric(gpu, gpu.memory_total >> 20),
+    },
+    CsvField {
+        name: "power_draw_w",
+        process: false,
+        description: "power draw in W",
+        value: |_, gpu, _| metric(gpu, gpu.power_usage / 1000),
+    },
+    CsvField {
+        name: "power_limit_w",
+        process: false,
+        description: "power limit in W",
+        value: |_, gpu, _| metric(gpu, gpu.power_limit / 1000),
+    },
+    CsvField {
+        name: "fan_pct",
+        process: false,
+        description: "fan speed in percent, empty without fans",
+        value: |_, gpu, _| gpu.fan_speed.map(|speed| metric(gpu, speed)).unwrap_or_default(),
+    },
+    CsvField {
+        name: "processes",
+        process: false,
+        description: "processes listed on the GPU",
+        value: |_, gpu, _| metric(gpu, gpu.processes.len()),
+    },
+    CsvField {
+        name: "pid",
+        process: true,
+        description: "process ID",
+        value: |_, _, process| process.map(|p| p.pid.to_string()).unwrap_or_default(),
+    },
+    CsvField {
+        name: "user",
+        process: true,
+        description: "owner, ?-prefixed when it could not be fully resolved",
+        value: |_, _, process| process.map(ProcessStat::display_user).unwrap_or_default(),
+    },
+    CsvField {
+        name: "command",
+        process: true,
+        description: "process name",
+        value: |_, _, process| process.map(|p| p.command.clone()).unwrap_or_default(),
+    },
+    CsvField {
+        name: "full_command",
+        process: true,
+        description: "full command line",
+        value: |_, _, process| process.map(|p| p.full_command.clone()).unwrap_or_default(),
+    },
+    CsvField {
+        name: "process_memory_mib",
+        process: true,
+        description: "GPU memory of the process in MiB, empty when unavailable",
+        value: |_, _, process| {
+            process
+                .and_then(|p| p.used_memory)
+                .map(|used| (used >> 20).to_string())
+                .unwrap_or_default()
+        },
+    },
+    CsvField {
+        name: "start_time",
+        process: true,
+        description: "start of the process in seconds since the epoch",
+        value: |_, _, process| {
+            process
+                .filter(|p| p.start_time > 0)
+                .map(|p| p.start_time.to_string())
+                .unwrap_or_default()
+        },
+    },
+    CsvField {
+        name: "runtime_s",
+        process: true,
+        description: "seconds the process has been running",
+        value: |snapshot, _, process| {
+            process
+                .and_then(|p| render::runtime(snapshot, p))
+                .map(|seconds| seconds.to_string())
+                .unwrap_or_default()
+        },
+    },
+];
+
+/// Fields of each level without `--csv-fields`.
+const GPU_DEFAULT: &[&str] = &[
+    "time",
+    "host",
+    "index",
+    "uuid",
+    "name",
+    "temperature_c",
+    "utilization_pct",
+    "memory_used_mib",
+    "memory_total_mib",
+    "power_draw_w",
+    "power_limit_w",
+    "processes",
+];
+const PROCESS_DEFAULT: &[&str] = &[
+    "time",
+    "host",
+    "index",
+    "uuid",
+    "pid",
+    "user",
+    "command",
+    "full_command",
+    "process_memory_mib",
+    "runtime_s",
+];
+
+/// The level of the rows, per process with `--csv-level process` or, as
+/// with the JSON format, `--procs-only`.
+pub fn level(opts: &RenderOpts) -> CsvLevel {
+    if opts.procs_only {
+        CsvLevel::Process
+    } else {
+        opts.csv_level
+    }
+}
+
+/// The selected fields in order, which `Opts` has checked by name.
+fn fields(opts: &RenderOpts) -> Vec<&'static CsvField> {
+    let names: Vec<&str> = match (opts.csv_fields.is_empty(), level(opts)) {
+        (false, _) => opts.csv_fields.iter().map(String::as_str).collect(),
+        (true, CsvLevel::Gpu) => GPU_DEFAULT.to_vec(),
+        (true, CsvLevel::Process) => PROCESS_DEFAULT.to_vec(),
+    };
+    names
+        .iter()
+        .filter_map(|name| FIELDS.iter().find(|field| field.name == *name))
+        .collect()
+}
+
+/// Process fields selected for GPU rows, where they would stay empty.
+pub fn misplaced_fields(opts: &RenderOpts) -> Vec<&'static str> {
+    if level(opts) == CsvLevel::Process {
+        return vec![];
+    }
+    fields(opts).iter().filter(|field| field.process).map(|field| field.name).collect()
+}
+
+/// `value` as one field, quoted when it holds the delimiter, a quote or a line break.
+pub fn escape(value: &str, delimiter: char) -> String {
+    // line breaks and tabs are kept, the quotes protect them
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\r' | '\n' | '\t' => escaped.push(c),
+            c if c.is_control() => escaped += &escape_control(&c.to_string()),
+            c => escaped.push(c),
+        }
+    }
+    if escaped.contains([delimiter, '"', '\r', '\n']) {
+        format!("\"{}\"", escaped.replace('"', "\"\""))
+    } else {
+        escaped
+    }
+}
+
+fn row(values: impl Iterator<Item = String>, delimiter: char) -> String {
+    let values: Vec<String> = values.map(|value| escape(&value, delimiter)).collect();
+    values.join(&delimiter.to_string()) + "\r\n"
+}
+
+/// The CSV format, writing its header once per output: with every snapshot
+/// for a file or a terminal, which each show one snapshot, and once for
+/// redirected watch mode, which appends the rows.
+#[derive(Debug, Default)]
+pub struct CsvRenderer {
+    header_written: Cell<bool>,
+}
+
+impl Renderer for CsvRenderer {
+    fn render(&self, snapshot: &Snapshot, opts: &RenderOpts) -> String {
+        let fields = fields(opts);
+        let delimiter = opts.csv_delimiter;
+        let appended = !opts.to_file && !std::io::stdout().is_terminal();
+        let mut text = String::new();
+        if !(appended && self.header_written.get()) {
+            text += &row(fields.iter().map(|field| field.name.to_string()), delimiter);
+            self.header_written.set(true);
+        }
+        let rows: Vec<(&GpuStat, Option<&ProcessStat>)> = match level(opts) {
+            CsvLevel::Gpu => snapshot.gpus.iter().map(|gpu| (gpu, None)).collect(),
+            CsvLevel::Process => render::process_rows(opts, snapshot),
+        };
+        for (gpu, process) in rows {
+            text += &row(fields.iter().map(|field| (field.value)(snapshot, gpu, process)), delimiter);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Opts;
+    use clap::Parser;
+
+    fn render_opts(args: &[&str]) -> RenderOpts {
+        RenderOpts::from(&Opts::try_parse_from(["gpustat", "--format", "csv"].iter().chain(args)).unwrap())
+    }
+
+    fn snapshot() -> Snapshot {
+        let process = ProcessStat {
+            pid: 42,
+            user: String::from("alice"),
+            command: String::from("python"),
+            full_command: String::from("python -c \"print('a,b')\"\n\x1b[2J"),
+            used_memory: Some(2 << 30),
+            ..Default::default()
+        };
+        Snapshot {
+            hostname: String::from("node"),
+            gpus: vec![
+                GpuStat {
+                    name: String::from("NVIDIA A100"),
+                    utilization: 87,
+                    processes: vec![process],
+                    ..Default::default()
+                },
+                GpuStat {
+                    index: 1,
+                    error: Some(String::from("GpuLost")),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fields_are_quoted_as_rfc_4180_asks() {
+        assert_eq!(escape("plain", ','), "plain");
+        assert_eq!(escape("a,b", ','), "\"a,b\"");
+        assert_eq!(escape("a,b", '\t'), "a,b");
+        assert_eq!(escape("say \"hi\"", ','), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape("two\r\nlines", ','), "\"two\r\nlines\"");
+        assert_eq!(escape("a\tb", '\t'), "\"a\tb\"");
+        assert_eq!(escape("a\tb", ','), "a\tb");
+        // other control characters cannot reach the terminal
+        assert!(!escape("\x1b[2J", ',').contains('\x1b'));
+    }
+
+    #[test]
+    fn gpu_rows_leave_unmeasured_metrics_empty() {
+        let opts = render_opts(&["--csv-fields", "index,name,utilization_pct,error"]);
+        let text = CsvRenderer::default().render(&snapshot(), &opts);
+        assert_eq!(
+            text,
+            "index,name,utilization_pct,error\r\n0,NVIDIA A100,87,\r\n1,,,GpuLost\r\n"
+        );
+    }
+
+    #[test]
+    fn process_rows_carry_their_gpu() {
+        let opts = render_opts(&[
+            "--csv-level",
+            "process",
+            "--csv-fields",
+            "index,pid,full_command",
+            "--csv-delimiter",
+            "tab",
+        ]);
+        let text = CsvRenderer::default().render(&snapshot(), &opts);
+        let rows: Vec<&str> = text.split("\r\n").collect();
+        assert_eq!(rows[0], "index\tpid\tfull_command");
+        assert!(
+            rows[1].starts_with("0\t42\t\"python -c \"\"print('a,b')\"\"\n"),
+            "{:?}",
+            rows[1]
+        );
+        assert_eq!(rows.len(), 3);
+        assert!(misplaced_fields(&opts).is_empty());
+        assert_eq!(misplaced_fields(&render_opts(&["--csv-fields", "index,pid"])), ["pid"]);
+    }
+
+    #[test]
+    fn appended_output_has_one_header() {
+        // the test's stdout is not a terminal
+        let renderer = CsvRenderer::default();
+        let opts = render_opts(&[]);
+        let first = renderer.render(&snapshot(), &opts);
+        let second = renderer.render(&snapshot(), &opts);
+        assert!(first.starts_with("time,host,index,uuid,name,"));
+        assert_eq!(second.lines().count(), 2);
+        assert!(!second.contains("time,host"));
+    }
+}
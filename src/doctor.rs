@@ -0,0 +1,263 @@
+use crate::{ffi, render, stats, Opts, StatusError, EXIT_DEGRADED};
+use nvml_wrapper::{error::NvmlError, Nvml};
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::Path,
+};
+
+/// Result of one check. A failed critical check makes `gpustat doctor` exit non-zero.
+struct Check {
+    name: &'static str,
+    passed: bool,
+    critical: bool,
+    detail: String,
+    hint: &'static str,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: String) -> Self {
+        Check {
+            name,
+            passed: true,
+            critical: false,
+            detail,
+            hint: "",
+        }
+    }
+
+    fn fail(name: &'static str, critical: bool, detail: String, hint: &'static str) -> Self {
+        Check {
+            name,
+            passed: false,
+            critical,
+            detail,
+            hint,
+        }
+    }
+
+    fn print(&self) {
+        let status = match (self.passed, self.critical) {
+            (true, _) => " ok ",
+            (false, true) => "FAIL",
+            (false, false) => "warn",
+        };
+        println!("[{}] {}: {}", status, self.name, self.detail);
+        if !self.passed {
+            println!("       hint: {}", self.hint);
+        }
+    }
+}
+
+fn nvml_checks(nvml: &Nvml) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let versions = nvml
+        .sys_driver_version()
+        .and_then(|driver| Ok((driver, nvml.sys_nvml_version()?)));
+    checks.push(match versions {
+        Ok((driver, library)) => Check::pass("driver", format!("driver {}, NVML {}", driver, library)),
+        Err(e) => Check::fail(
+            "driver",
+            true,
+            e.to_string(),
+            "reinstall the NVIDIA driver so the kernel module and user-space library match",
+        ),
+    });
+
+    let count = match nvml.device_count() {
+        Ok(0) => {
+            checks.push(Check::fail(
+                "devices",
+                true,
+                String::from("no GPU found"),
+                "check `lspci | grep -i nvidia` and that the driver is bound to the GPUs",
+            ));
+            return checks;
+        }
+        Ok(count) => {
+            checks.push(Check::pass("devices", format!("{} GPU(s)", count)));
+            count
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                "devices",
+                true,
+                e.to_string(),
+                "check that the driver is loaded with `nvidia-smi`",
+            ));
+            return checks;
+        }
+    };
+
+    let mut denied = Vec::new();
+    let (mut validated, mut corrupted) = (0, Vec::new());
+    for index in 0..count {
+        let query = nvml.device_by_index(index).and_then(|device| {
+            ffi::memory_info(&device)?;
+            let name = device.name()?;
+            if let Err(e) = device.running_compute_processes() {
+                denied.push(format!("GPU {}: {}", index, e));
+            }
+            // GPUs without an InfoROM, like most GeForce cards, cannot validate it
+            match device.validate_info_rom() {
+                Ok(()) => validated += 1,
+                Err(NvmlError::CorruptedInfoROM) => corrupted.push(index),
+                Err(_) => {}
+            }
+            Ok(name)
+        });
+        checks.push(match query {
+            Ok(name) => Check::pass("device query", format!("GPU {} ({})", index, name)),
+            Err(NvmlError::GpuLost) => Check::fail(
+                "device query",
+                true,
+                format!("GPU {} has fallen off the bus", index),
+                "reset the GPU with `gpustat reset` or reboot the machine",
+            ),
+            Err(e) => Check::fail(
+                "device query",
+                true,
+                format!("GPU {}: {}", index, e),
+                "run `nvidia-smi -q -i <index>` to see whether the driver can talk to it",
+            ),
+        });
+    }
+
+    if corrupted.is_empty() && validated > 0 {
+        checks.push(Check::pass("inforom", format!("InfoROM checksum valid on {} GPU(s)", validated)));
+    } else if !corrupted.is_empty() {
+        let indices: Vec<String> = corrupted.iter().map(u32::to_string).collect();
+        checks.push(Check::fail(
+            "inforom",
+            true,
+            format!("InfoROM checksum fails on GPU {}", indices.join(", ")),
+            "the GPU may lose its ECC and power settings, have the InfoROM reflashed or the board replaced",
+        ));
+    }
+
+    checks.push(if denied.is_empty() {
+        Check::pass("processes", String::from("process enumeration permitted"))
+    } else {
+        Check::fail(
+            "processes",
+            false,
+            denied.join(", "),
+            "run as root, or share the host PID namespace with the container",
+        )
+    });
+    checks
+}
+
+/// `hidepid` mount option of /proc, which hides other users' processes from non-root users.
+fn proc_check() -> Check {
+    let hidden = stats::proc_hidepid();
+    match hidden {
+        Some(option) if !nix::unistd::Uid::effective().is_root() => Check::fail(
+            "/proc",
+            false,
+            format!("mounted with {}, other users' processes are hidden", option),
+            "run as root or remount /proc without hidepid to see every process owner",
+        ),
+        _ => Check::pass("/proc", String::from("other users' processes are visible")),
+    }
+}
+
+/// Container runtime detected from marker files and the cgroup of PID 1.
+pub(crate) fn container_runtime() -> Option<&'static str> {
+    if Path::new("/.dockerenv").exists() {
+        return Some("docker");
+    }
+    if Path::new("/run/.containerenv").exists() {
+        return Some("podman");
+    }
+    let cgroup = fs::read_to_string("/proc/1/cgroup").unwrap_or_default();
+    ["kubepods", "docker", "containerd", "lxc"]
+        .iter()
+        .find(|runtime| cgroup.contains(*runtime))
+        .copied()
+}
+
+fn container_check() -> Check {
+    match container_runtime() {
+        None => Check::pass("container", String::from("not running in a container")),
+        Some(runtime) if Path::new("/dev/nvidiactl").exists() => Check::pass(
+            "container",
+            format!("{} container with the NVIDIA devices exposed", runtime),
+        ),
+        Some(runtime) => Check::fail(
+            "container",
+            true,
+            format!("{} container without /dev/nvidiactl", runtime),
+            "start the container with the NVIDIA runtime, e.g. `docker run --gpus all`",
+        ),
+    }
+}
+
+fn color_check(opts: &Opts) -> Check {
+    let term = std::env::var("TERM").unwrap_or_default();
+    if opts.color {
+        Check::pass("color", String::from("forced on with --color"))
+    } else if opts.no_color {
+        Check::pass("color", String::from("disabled with --no-color"))
+    } else if render::no_color_env() {
+        Check::pass("color", String::from("disabled by NO_COLOR"))
+    } else if !io::stdout().is_terminal() {
+        Check::fail(
+            "color",
+            false,
+            String::from("stdout is not a terminal, colors are off"),
+            "pass --color to keep colors when piping",
+        )
+    } else if term.is_empty() || term == "dumb" {
+        Check::fail(
+            "color",
+            false,
+            format!("TERM={:?} does not support colors", term),
+            "set TERM to your terminal, e.g. xterm-256color",
+        )
+    } else {
+        Check::pass("color", format!("TERM={}", term))
+    }
+}
+
+/// Check the setup gpustat depends on, printing one line per check.
+pub fn run(opts: &Opts) -> Result<u8, StatusError> {
+    let mut checks = Vec::new();
+    match Nvml::init() {
+        Ok(nvml) => {
+            checks.push(Check::pass("NVML", String::from("library loaded")));
+            checks.extend(nvml_checks(&nvml));
+        }
+        Err(NvmlError::LibloadingError(e)) => checks.push(Check::fail(
+            "NVML",
+            true,
+            e.to_string(),
+            "install the NVIDIA driver, or add the directory of libnvidia-ml.so.1 to LD_LIBRARY_PATH",
+        )),
+        Err(NvmlError::LibRmVersionMismatch) => checks.push(Check::fail(
+            "NVML",
+            true,
+            String::from("driver/library version mismatch"),
+            "reboot, or reload the nvidia kernel module after a driver upgrade",
+        )),
+        Err(e) => checks.push(Check::fail(
+            "NVML",
+            true,
+            e.to_string(),
+            "check that the nvidia kernel module is loaded with `lsmod | grep nvidia`",
+        )),
+    }
+    checks.push(proc_check());
+    checks.push(container_check());
+    checks.push(color_check(opts));
+
+    for check in &checks {
+        check.print();
+    }
+    if checks.iter().any(|check| check.critical && !check.passed) {
+        Ok(EXIT_DEGRADED)
+    } else {
+        Ok(0)
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{require_root, StatusError, EXIT_USAGE};
+use clap::Args;
+use nvml_wrapper::{error::NvmlError, Nvml};
+
+#[derive(Args, Clone, Debug)]
+pub struct EccOpts {
+    #[arg(
+        long = "id",
+        value_name = "INDEX",
+        required = true,
+        help = "GPU to change (repeatable)"
+    )]
+    ids: Vec<u32>,
+    #[arg(
+        long,
+        conflicts_with = "disable",
+        required_unless_present = "disable",
+        help = "Enable ECC after the next reboot or GPU reset"
+    )]
+    enable: bool,
+    #[arg(long, help = "Disable ECC after the next reboot or GPU reset")]
+    disable: bool,
+}
+
+/// Set the pending ECC mode of the selected GPUs, reporting each device on its own line.
+pub fn run(opts: &EccOpts, nvml: &Nvml) -> Result<u8, StatusError> {
+    require_root("changing the ECC mode")?;
+
+    let enable = opts.enable;
+    let mode = if enable { "on" } else { "off" };
+    let mut code = 0;
+    for &id in &opts.ids {
+        let mut device = nvml.device_by_index(id)?;
+        match device.is_ecc_enabled() {
+            Err(NvmlError::NotSupported) => {
+                eprintln!("GPU {}: ECC is not supported, leaving it unchanged", id);
+                code = EXIT_USAGE;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+            Ok(state) if state.pending_enabled == enable => {
+                println!("GPU {}: ECC is already pending {}", id, mode);
+                continue;
+            }
+            Ok(_) => {}
+        }
+        device.set_ecc(enable)?;
+        println!(
+            "GPU {}: ECC pending mode set to {}, reboot or reset the GPU to apply it",
+            id, mode
+        );
+    }
+    Ok(code)
+}
@@ -0,0 +1,183 @@
+//! `--explain`: a legend below the table naming the NVML functions and units
+//! behind each column shown, the collectors the options ran and the flags of
+//! the optional columns left out. With `--format json` it is the `legend`
+//! object of the snapshot.
+//!
+//! Collectors and optional columns come from `stats::COLLECTORS` and
+//! `columns::COLUMNS`, so the legend follows new ones; only the columns every
+//! table has are described here.
+
+use crate::{columns::COLUMNS, stats::COLLECTORS, Opts};
+use serde::{Deserialize, Serialize};
+
+/// Name, meaning and source of the columns every table has.
+const BASE: &[(&str, &str, &str)] = &[
+    ("index", "NVML index, or the position in --order pci or cuda", "nvmlDeviceGetHandleByIndex_v2"),
+    ("name", "GPU model", "nvmlDeviceGetName"),
+    ("temperature", "GPU core temperature in °C", "nvmlDeviceGetTemperature (NVML_TEMPERATURE_GPU)"),
+    (
+        "utilization",
+        "% of the last sample period, 1/6 s to 1 s by model, in which a kernel ran; not SM occupancy",
+        "nvmlDeviceGetUtilizationRates .gpu",
+    ),
+    (
+        "memory",
+        "used and total memory in MiB, shown as MB; used leaves out what the driver reserves",
+        "nvmlDeviceGetMemoryInfo_v2 .used and .total",
+    ),
+    (
+        "power",
+        "power draw and limit in W, read in mW",
+        "nvmlDeviceGetPowerUsage, nvmlDeviceGetPowerManagementLimit",
+    ),
+    (
+        "processes",
+        "user, command and used memory in MiB of each process, the user and command from /proc",
+        "nvmlDeviceGetComputeRunningProcesses_v3, nvmlDeviceGetGraphicsRunningProcesses_v3",
+    ),
+];
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub description: String,
+    /// The NVML functions behind it and their units, or the flags that
+    /// would show it.
+    pub source: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Legend {
+    /// The columns every table has.
+    pub columns: Vec<Entry>,
+    /// The collectors of optional metrics the options ran.
+    pub collectors: Vec<Entry>,
+    /// Optional columns not shown, with their flags as `source`.
+    pub more: Vec<Entry>,
+}
+
+fn entry(name: &str, description: &str, source: &str) -> Entry {
+    Entry {
+        name: name.to_string(),
+        description: description.to_string(),
+        source: source.to_string(),
+    }
+}
+
+/// The legend of a run with `opts`.
+pub fn legend(opts: &Opts) -> Legend {
+    Legend {
+        columns: BASE.iter().map(|(name, description, source)| entry(name, description, source)).collect(),
+        collectors: COLLECTORS
+            .iter()
+            .filter(|collector| collector.wanted(opts) && !opts.collector_off(collector.name))
+            .map(|collector| entry(collector.name, collector.description, collector.explain))
+            .collect(),
+        more: COLUMNS
+            .iter()
+            .filter(|column| !opts.column(column.name))
+            .map(|column| entry(column.name, column.description, column.flags))
+            .collect(),
+    }
+}
+
+/// The legend as printed below the table.
+pub fn text(legend: &Legend) -> String {
+    let width = [&legend.columns, &legend.collectors, &legend.more]
+        .iter()
+        .flat_map(|entries| entries.iter())
+        .map(|entry| entry.name.chars().count())
+        .max()
+        .unwrap_or_default();
+    let mut text = String::from("\nlegend\n  columns\n");
+    // the source on a line of its own below the description
+    let explained = |entry: &Entry| {
+        format!("    {:<w$}  {}\n    {:<w$}  {}\n", entry.name, entry.description, "", entry.source, w = width)
+    };
+    text.extend(legend.columns.iter().map(explained));
+    text += "  collectors\n";
+    if legend.collectors.is_empty() {
+        text += "    (none)\n";
+    }
+    text.extend(legend.collectors.iter().map(explained));
+    if !legend.more.is_empty() {
+        text += "  more columns\n";
+    }
+    for entry in &legend.more {
+        text += &format!("    {:<w$}  {} ({})\n", entry.name, entry.description, entry.source, w = width);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn opts(args: &[&str]) -> Opts {
+        Opts::try_parse_from(["gpustat", "--explain"].iter().chain(args)).unwrap()
+    }
+
+    fn names(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn every_collector_names_its_source() {
+        for collector in COLLECTORS {
+            let explain = collector.explain;
+            assert!(
+                explain.starts_with("nvml") || explain.starts_with("no NVML query; "),
+                "{}",
+                collector.name
+            );
+        }
+    }
+
+    #[test]
+    fn the_legend_follows_the_options() {
+        let plain = legend(&opts(&[]));
+        assert_eq!(
+            names(&plain.columns),
+            [
+                "index",
+                "name",
+                "temperature",
+                "utilization",
+                "memory",
+                "power",
+                "processes"
+            ]
+        );
+        assert_eq!(
+            names(&plain.more),
+            COLUMNS.iter().map(|column| column.name).collect::<Vec<_>>()
+        );
+        let fan = legend(&opts(&["-F"]));
+        assert!(names(&fan.collectors).contains(&"fan"));
+        assert!(!names(&fan.more).contains(&"fan"));
+        // the same collectors --dry-run plans
+        let planned: Vec<&str> = crate::plan::plan(&opts(&["-F"]))
+            .collectors
+            .iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names(&fan.collectors), planned);
+        let off = legend(&opts(&["-F", "--disable-collector", "fan"]));
+        assert!(!names(&off.collectors).contains(&"fan"));
+    }
+
+    #[test]
+    fn sources_go_below_their_description() {
+        let legend = Legend {
+            columns: vec![entry("name", "GPU model", "nvmlDeviceGetName")],
+            collectors: vec![],
+            more: vec![entry("fan", "fan speed", "--show-fan")],
+        };
+        assert_eq!(
+            text(&legend),
+            "\nlegend\n  columns\n    name  GPU model\n          nvmlDeviceGetName\n  collectors\n    (none)\n  more columns\n    fan   fan speed (--show-fan)\n"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! NVML queries nvml-wrapper does not bind, made through the raw bindings of
+//! nvml-wrapper-sys on the same library `Nvml::init` loads.
+
+use nvml_wrapper::{
+    error::{nvml_sym, nvml_try, NvmlError},
+    struct_wrappers::device::{FieldValueSample, MemoryInfo},
+    Device,
+};
+use nvml_wrapper_sys::bindings::{
+    field_id::NVML_FI_DEV_POWER_INSTANT, nvmlConfComputeSystemState_t, nvmlFieldValue_t, nvmlMemory_t,
+    nvmlTemperatureThresholds_enum_NVML_TEMPERATURE_THRESHOLD_ACOUSTIC_CURR as THRESHOLD_ACOUSTIC_CURR, NvmlLib,
+    NVML_CC_SYSTEM_DEVTOOLS_MODE_ON, NVML_CC_SYSTEM_FEATURE_ENABLED, NVML_POWER_SCOPE_MODULE,
+    NVML_VGPU_NAME_BUFFER_SIZE,
+};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, ffi::CStr, sync::OnceLock};
+
+#[cfg(not(windows))]
+const LIB_PATH: &str = "libnvidia-ml.so.1";
+#[cfg(windows)]
+const LIB_PATH: &str = "nvml.dll";
+
+/// The NVML library, loaded on first use, or `None` when it cannot be.
+fn lib() -> Option<&'static NvmlLib> {
+    static LIB: OnceLock<Option<NvmlLib>> = OnceLock::new();
+    // SAFETY: nvml-wrapper has already loaded the same library, so no new
+    // initialization code runs
+    LIB.get_or_init(|| unsafe { NvmlLib::new(LIB_PATH) }.ok()).as_ref()
+}
+
+/// Current acoustic threshold: the target temperature GeForce cards can be
+/// set to, which the GPU slows down to stay under.
+pub fn acoustic_target(device: &Device) -> Result<u32, NvmlError> {
+    let lib = lib().ok_or(NvmlError::LibraryNotFound)?;
+    let sym = nvml_sym(lib.nvmlDeviceGetTemperatureThreshold.as_ref())?;
+    let mut temperature = 0;
+    // SAFETY: the handle is valid while `device` borrows its `Nvml`, and
+    // `temperature` outlives the call
+    unsafe { nvml_try(sym(device.handle(), THRESHOLD_ACOUSTIC_CURR, &mut temperature))? };
+    Ok(temperature)
+}
+
+/// Framebuffer memory in bytes, as the v1 `nvmlDeviceGetMemoryInfo` reports
+/// it, without the reserved amount v2 splits off.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Memory {
+    pub total: u64,
+    pub free: u64,
+    pub used: u64,
+}
+
+impl From<&MemoryInfo> for Memory {
+    fn from(memory: &MemoryInfo) -> Self {
+        Memory {
+            total: memory.total,
+            free: memory.free,
+            used: memory.used,
+        }
+    }
+}
+
+/// Framebuffer memory through the v1 query, for drivers older than
+/// `nvmlDeviceGetMemoryInfo_v2`, which `Device::memory_info` calls.
+pub fn memory_info_v1(device: &Device) -> Result<Memory, NvmlError> {
+    let lib = lib().ok_or(NvmlError::LibraryNotFound)?;
+    let sym = nvml_sym(lib.nvmlDeviceGetMemoryInfo.as_ref())?;
+    // SAFETY: the handle is valid while `device` borrows its `Nvml`, and the
+    // all-zero memory is valid and outlives the call
+    let memory = unsafe {
+        let mut memory: nvmlMemory_t = std::mem::zeroed();
+        nvml_try(sym(device.handle(), &mut memory))?;
+        memory
+    };
+    Ok(Memory {
+        total: memory.total,
+        free: memory.free,
+        used: memory.used,
+    })
+}
+
+/// Framebuffer memory and, where v2 is available, the reserved amount.
+pub fn memory_info(device: &Device) -> Result<(Memory, Option<u64>), NvmlError> {
+    match device.memory_info() {
+        Ok(memory) => Ok((Memory::from(&memory), Some(memory.reserved))),
+        Err(NvmlError::FunctionNotFound | NvmlError::NotSupported) => Ok((memory_info_v1(device)?, None)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Instantaneous power of the whole module or board `device` sits on, the
+/// module scope of the power field, which `Device::field_values_for` cannot
+/// ask for.
+pub fn module_power(device: &Device) -> Result<FieldValueSample, NvmlError> {
+    let lib = lib().ok_or(NvmlError::LibraryNotFound)?;
+    let sym = nvml_sym(lib.nvmlDeviceGetFieldValues.as_ref())?;
+    // SAFETY: the handle is valid while `device` borrows its `Nvml`, and the
+    // all-zero field value is valid and outlives the call
+    let value = unsafe {
+        let mut value: nvmlFieldValue_t = std::mem::zeroed();
+        value.fieldId = NVML_FI_DEV_POWER_INSTANT;
+        value.scopeId = NVML_POWER_SCOPE_MODULE;
+        nvml_try(sym(device.handle(), 1, &mut value))?;
+        value
+    };
+    FieldValueSample::try_from(value)
+}
+
+/// Type name, e.g. `GRID A100-4C`, and framebuffer size in bytes of the
+/// active vGPU `instance`, as `Device::active_vgpus` lists it.
+pub fn vgpu_type(instance: u32) -> Result<(String, u64), NvmlError> {
+    let lib = lib().ok_or(NvmlError::LibraryNotFound)?;
+    let get_type = nvml_sym(lib.nvmlVgpuInstanceGetType.as_ref())?;
+    let get_name = nvml_sym(lib.nvmlVgpuTypeGetName.as_ref())?;
+    let get_framebuffer = nvml_sym(lib.nvmlVgpuTypeGetFramebufferSize.as_ref())?;
+    let (mut type_id, mut framebuffer) = (0, 0);
+    let mut name = [0; NVML_VGPU_NAME_BUFFER_SIZE as usize];
+    let mut size = NVML_VGPU_NAME_BUFFER_SIZE;
+    // SAFETY: the outputs outlive the calls, and `size` is the length of
+    // `name`, which NVML nul-terminates
+    let name = unsafe {
+        nvml_try(get_type(instance, &mut type_id))?;
+        nvml_try(get_name(type_id, name.as_mut_ptr(), &mut size))?;
+        nvml_try(get_framebuffer(type_id, &mut framebuffer))?;
+        CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned()
+    };
+    Ok((name, framebuffer))
+}
+
+/// Whether confidential computing is enabled on the system, and whether in
+/// devtools mode, which leaves it open to debugging.
+pub fn conf_compute_state() -> Result<(bool, bool), NvmlError> {
+    let lib = lib().ok_or(NvmlError::LibraryNotFound)?;
+    let sym = nvml_sym(lib.nvmlSystemGetConfComputeState.as_ref())?;
+    // SAFETY: the all-zero state is valid and outlives the call
+    let state = unsafe {
+        let mut state: nvmlConfComputeSystemState_t = std::mem::zeroed();
+        nvml_try(sym(&mut state))?;
+        state
+    };
+    Ok((
+        state.ccFeature == NVML_CC_SYSTEM_FEATURE_ENABLED,
+        state.devToolsMode == NVML_CC_SYSTEM_DEVTOOLS_MODE_ON,
+    ))
+}
@@ -0,0 +1,364 @@
+//! `gpustat record-fixture`: the NVML answers of this machine, call by call,
+//! which `GPUSTAT_FIXTURE` replays through `FixtureProvider` in place of NVML
+//! so the binary runs on machines without a GPU, e.g. in CI.
+//!
+//! A fixture is a JSON object with the `hostname`, the `driver_version`, the
+//! `processes` by PID with their `name` and `user`, and one object per GPU in
+//! `devices` mapping call names, as `-v` logs them, to their answers. An answer is the
+//! value nvml-wrapper returned, or `{"error": "NotSupported"}` with the name of
+//! an `NvmlError`. A call a GPU's object leaves out answers `NotSupported`, so
+//! a hand-written fixture only lists what the card has, and a
+//! `device_by_index` error stands for a GPU whose handle cannot be had, e.g.
+//! `GpuLost`.
+//!
+//! Only NVML is replayed: the sysfs, /proc and kernel log of the replaying
+//! machine say nothing about the fixture's GPUs and processes and are not
+//! read, so processes only have the fixture's name and user.
+
+use crate::{columns::COLUMNS, provider::Provider, stats, write_atomic, Opts, StatusError};
+use clap::Args;
+use nvml_wrapper::{error::NvmlError, Device, Nvml};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, env, fs, path::Path, path::PathBuf, sync::Mutex};
+
+/// Environment variable naming a fixture to replay instead of querying NVML.
+pub const FIXTURE_ENV: &str = "GPUSTAT_FIXTURE";
+
+/// The call whose error a GPU's handle fails with.
+const OPEN_CALL: &str = "device_by_index";
+
+#[derive(Args, Clone, Debug)]
+pub struct RecordFixtureOpts {
+    #[arg(value_name = "PATH", help = "File to write the fixture to")]
+    path: PathBuf,
+}
+
+/// Recorded NVML answers, replayed in place of the driver.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureProvider {
+    pub hostname: String,
+    driver_version: Value,
+    #[serde(default)]
+    processes: BTreeMap<u32, RecordedProcess>,
+    devices: Vec<BTreeMap<String, Value>>,
+}
+
+/// What a fixture knows of a process, whose PID is the key.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RecordedProcess {
+    /// The answer of `sys_process_name`.
+    name: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+}
+
+impl FixtureProvider {
+    /// The fixture in the JSON file at `path`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&json).map_err(|e| e.to_string())
+    }
+
+    fn device(&self, index: u32) -> Result<&BTreeMap<String, Value>, NvmlError> {
+        self.devices.get(index as usize).ok_or(NvmlError::InvalidArg)
+    }
+}
+
+impl Provider for FixtureProvider {
+    fn device_count(&self) -> Result<u32, NvmlError> {
+        Ok(self.devices.len() as u32)
+    }
+
+    fn sys_driver_version(&self) -> Result<String, NvmlError> {
+        replayed(&self.driver_version).and_then(text)
+    }
+
+    fn sys_process_name(&self, pid: u32) -> Result<String, NvmlError> {
+        self.processes
+            .get(&pid)
+            .map_or(Err(NvmlError::NotFound), |process| replayed(&process.name))
+            .and_then(text)
+    }
+
+    fn process_owner(&self, pid: u32) -> Option<String> {
+        self.processes.get(&pid)?.user.clone()
+    }
+
+    fn open(&self, index: u32) -> Result<(), NvmlError> {
+        self.device(index)?
+            .get(OPEN_CALL)
+            .map_or(Ok(()), |answer| replayed(answer).map(drop))
+    }
+
+    fn query(
+        &self,
+        index: u32,
+        call: &str,
+        _: &dyn Fn(&Device) -> Result<Value, NvmlError>,
+    ) -> Result<Value, NvmlError> {
+        self.device(index)?
+            .get(call)
+            .map_or(Err(NvmlError::NotSupported), replayed)
+    }
+
+    fn local(&self) -> bool {
+        false
+    }
+}
+
+/// The answer of a call as a fixture holds it.
+fn answer<T: Serialize>(result: &Result<T, NvmlError>) -> Value {
+    match result {
+        Ok(value) => json!(value),
+        Err(e) => json!({ "error": crate::nvml_code(e) }),
+    }
+}
+
+/// The result a fixture's answer stands for.
+fn replayed(answer: &Value) -> Result<Value, NvmlError> {
+    match answer
+        .as_object()
+        .filter(|object| object.len() == 1)
+        .and_then(|object| object.get("error"))
+    {
+        Some(Value::String(name)) => Err(nvml_error(name)),
+        _ => Ok(answer.clone()),
+    }
+}
+
+fn text(value: Value) -> Result<String, NvmlError> {
+    match value {
+        Value::String(text) => Ok(text),
+        _ => Err(NvmlError::Unknown),
+    }
+}
+
+/// The `NvmlError` named `name`, `Unknown` for names with no unit variant.
+fn nvml_error(name: &str) -> NvmlError {
+    match name {
+        "Uninitialized" => NvmlError::Uninitialized,
+        "InvalidArg" => NvmlError::InvalidArg,
+        "NotSupported" => NvmlError::NotSupported,
+        "NoPermission" => NvmlError::NoPermission,
+        "NotFound" => NvmlError::NotFound,
+        "InsufficientSize" => NvmlError::InsufficientSize(None),
+        "InsufficientPower" => NvmlError::InsufficientPower,
+        "DriverNotLoaded" => NvmlError::DriverNotLoaded,
+        "Timeout" => NvmlError::Timeout,
+        "IrqIssue" => NvmlError::IrqIssue,
+        "LibraryNotFound" => NvmlError::LibraryNotFound,
+        "FunctionNotFound" => NvmlError::FunctionNotFound,
+        "CorruptedInfoROM" => NvmlError::CorruptedInfoROM,
+        "GpuLost" => NvmlError::GpuLost,
+        "ResetRequired" => NvmlError::ResetRequired,
+        "OperatingSystem" => NvmlError::OperatingSystem,
+        "LibRmVersionMismatch" => NvmlError::LibRmVersionMismatch,
+        "InUse" => NvmlError::InUse,
+        "InsufficientMemory" => NvmlError::InsufficientMemory,
+        "NoData" => NvmlError::NoData,
+        "VgpuEccNotSupported" => NvmlError::VgpuEccNotSupported,
+        _ => NvmlError::Unknown,
+    }
+}
+
+/// NVML, noting every answer it gives for the fixture.
+struct Recorder<'a> {
+    nvml: &'a Nvml,
+    fixture: Mutex<FixtureProvider>,
+}
+
+impl Recorder<'_> {
+    fn note<T: Serialize>(&self, index: u32, call: &str, result: &Result<T, NvmlError>) {
+        if let Ok(mut fixture) = self.fixture.lock() {
+            if let Some(device) = fixture.devices.get_mut(index as usize) {
+                device.insert(call.to_string(), answer(result));
+            }
+        }
+    }
+}
+
+impl Provider for Recorder<'_> {
+    fn device_count(&self) -> Result<u32, NvmlError> {
+        let count = self.nvml.device_count()?;
+        if let Ok(mut fixture) = self.fixture.lock() {
+            fixture.devices.resize(count as usize, BTreeMap::new());
+        }
+        Ok(count)
+    }
+
+    fn sys_driver_version(&self) -> Result<String, NvmlError> {
+        let version = self.nvml.sys_driver_version();
+        if let Ok(mut fixture) = self.fixture.lock() {
+            fixture.driver_version = answer(&version);
+        }
+        version
+    }
+
+    fn sys_process_name(&self, pid: u32) -> Result<String, NvmlError> {
+        let name = Provider::sys_process_name(self.nvml, pid);
+        if let Ok(mut fixture) = self.fixture.lock() {
+            fixture.processes.entry(pid).or_default().name = answer(&name);
+        }
+        name
+    }
+
+    fn open(&self, index: u32) -> Result<(), NvmlError> {
+        let opened = self.nvml.open(index);
+        if opened.is_err() {
+            self.note(index, OPEN_CALL, &opened);
+        }
+        opened
+    }
+
+    fn query(
+        &self,
+        index: u32,
+        call: &str,
+        live: &dyn Fn(&Device) -> Result<Value, NvmlError>,
+    ) -> Result<Value, NvmlError> {
+        let result = self.nvml.query(index, call, live);
+        self.note(index, call, &result);
+        result
+    }
+}
+
+/// `opts` with every optional column and process detail requested, so the
+/// fixture holds whatever a later run might display.
+fn recording_opts(opts: &Opts) -> Opts {
+    let mut opts = opts.clone();
+    opts.show_all = true;
+    opts.columns = COLUMNS.iter().map(|column| column.name.to_string()).collect();
+    opts.show_cwd = true;
+    opts.show_env = true;
+    opts.show_host_mem = true;
+    opts.show_driver_uptime = true;
+    opts.show_proc_codec = true;
+    opts.count_all = true;
+    opts
+}
+
+/// Collect once with every collector and save the answers to the fixture path.
+pub fn record(opts: &Opts, record: &RecordFixtureOpts, nvml: &Nvml, hostname: &str) -> Result<u8, StatusError> {
+    let recorder = Recorder {
+        nvml,
+        fixture: Mutex::new(FixtureProvider {
+            hostname: hostname.to_string(),
+            ..Default::default()
+        }),
+    };
+    let snapshot = stats::collect(&recording_opts(opts), &recorder, hostname)?;
+    let mut fixture = recorder.fixture.into_inner().unwrap_or_else(|e| e.into_inner());
+    // the replay sees no process of this machine, keep what sysinfo gave
+    for process in snapshot.gpus.iter().flat_map(|gpu| &gpu.processes) {
+        let recorded = fixture.processes.entry(process.pid).or_default();
+        if process.stale {
+            // replayed as exited, the way NVML answers for a PID that is gone
+            recorded.name = answer::<()>(&Err(NvmlError::NotFound));
+        } else if recorded.name.is_null() {
+            recorded.name = json!(process.command);
+        }
+        recorded.user = Some(process.user.clone()).filter(|user| !user.is_empty());
+    }
+    write_atomic(&record.path, &(serde_json::to_string_pretty(&fixture)? + "\n"))?;
+    println!("recorded {} GPUs in {}", snapshot.gpus.len(), record.path.display());
+    Ok(0)
+}
+
+/// The fixture in the file `GPUSTAT_FIXTURE` names, if it is set.
+pub fn from_env() -> Option<Result<FixtureProvider, String>> {
+    let path = PathBuf::from(env::var_os(FIXTURE_ENV).filter(|path| !path.is_empty())?);
+    log::info!("replaying fixture {}", path.display());
+    Some(FixtureProvider::load(&path).map_err(|e| format!("Cannot replay fixture {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A fixture of `devices` on host `node`.
+    pub(crate) fn fixtures(devices: Vec<Value>) -> FixtureProvider {
+        serde_json::from_value(json!({
+            "hostname": "node",
+            "driver_version": "550.54.14",
+            "devices": devices,
+        }))
+        .unwrap()
+    }
+
+    /// An idle RTX 4090 answering the calls every collection makes, and
+    /// nothing else.
+    pub(crate) fn gpu(index: u32) -> Value {
+        json!({
+            "name": "NVIDIA GeForce RTX 4090",
+            "uuid": format!("GPU-{:08}", index),
+            "temperature": 35,
+            "utilization_rates": { "gpu": 0, "memory": 0 },
+            "memory_info": { "free": 25000000000_u64, "reserved": 0, "total": 25769803776_u64, "used": 769803776, "version": 1 },
+            "power_usage": 20000,
+            "power_management_limit": 450000,
+            "running_compute_processes": [],
+            "running_graphics_processes": [],
+        })
+    }
+
+    fn fixture(device: Value) -> FixtureProvider {
+        fixtures(vec![device])
+    }
+
+    fn ask(fixture: &FixtureProvider, call: &str) -> Result<Value, NvmlError> {
+        fixture.query(0, call, &|_| panic!("a fixture never calls NVML"))
+    }
+
+    #[test]
+    fn recorded_answers_are_replayed() {
+        let fixture = fixture(json!({ "name": "NVIDIA A100-SXM4-80GB" }));
+        assert_eq!(ask(&fixture, "name").unwrap(), json!("NVIDIA A100-SXM4-80GB"));
+        assert_eq!(fixture.sys_driver_version().unwrap(), "550.54.14");
+    }
+
+    #[test]
+    fn not_supported_is_replayed_per_call() {
+        let fixture = fixture(json!({ "fan_speed": 30, "fan_speed(1)": { "error": "NotSupported" } }));
+        assert_eq!(ask(&fixture, "fan_speed").unwrap(), json!(30));
+        assert!(matches!(ask(&fixture, "fan_speed(1)"), Err(NvmlError::NotSupported)));
+        // left out of the fixture
+        assert!(matches!(ask(&fixture, "fan_speed_rpm"), Err(NvmlError::NotSupported)));
+    }
+
+    #[test]
+    fn errors_round_trip_by_name() {
+        for name in ["GpuLost", "NoPermission", "FunctionNotFound"] {
+            let recorded = answer::<()>(&Err(nvml_error(name)));
+            assert_eq!(recorded, json!({ "error": name }));
+            assert_eq!(crate::nvml_code(&replayed(&recorded).unwrap_err()), name);
+        }
+        // a value that merely holds an error field is a value
+        let value = json!({ "error": "GpuLost", "count": 1 });
+        assert_eq!(replayed(&value).unwrap(), value);
+    }
+
+    #[test]
+    fn a_lost_handle_fails_open() {
+        let fixture = fixture(json!({ "device_by_index": { "error": "GpuLost" } }));
+        assert!(matches!(fixture.open(0), Err(NvmlError::GpuLost)));
+        assert!(matches!(fixture.open(1), Err(NvmlError::InvalidArg)));
+    }
+
+    #[test]
+    fn unknown_processes_are_not_found() {
+        let fixture: FixtureProvider = serde_json::from_value(json!({
+            "hostname": "node",
+            "driver_version": "550.54.14",
+            "processes": { "4194305": { "name": "python", "user": "alice" } },
+            "devices": [],
+        }))
+        .unwrap();
+        assert_eq!(fixture.sys_process_name(4194305).unwrap(), "python");
+        assert_eq!(fixture.process_owner(4194305).as_deref(), Some("alice"));
+        assert!(matches!(fixture.sys_process_name(4194306), Err(NvmlError::NotFound)));
+        assert_eq!(fixture.process_owner(4194306), None);
+    }
+}
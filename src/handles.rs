@@ -0,0 +1,139 @@
+//! `--show-handles`: descriptors open on each `/dev/nvidia<minor>`, counted
+//! from `/proc/*/fd`. NVML only lists processes with a context, so a server
+//! leaking device handles shows up here long before the driver's client limit.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io, path::Path};
+
+/// Ticks of watch mode between two walks of /proc, which are slow on busy nodes.
+pub const SCAN_EVERY: u32 = 5;
+
+/// Holders of one GPU's device node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceHandles {
+    /// Open descriptors on the node, summed over processes.
+    pub descriptors: u32,
+    /// Distinct processes holding at least one.
+    pub processes: u32,
+    /// Some processes' descriptors were unreadable, so both counts are lower bounds.
+    pub partial: bool,
+}
+
+/// One walk of /proc.
+#[derive(Clone, Debug, Default)]
+pub struct HandleScan {
+    devices: HashMap<u32, DeviceHandles>,
+    /// Processes whose descriptors could not be read.
+    unreadable: u32,
+}
+
+impl HandleScan {
+    /// Holders of `/dev/nvidia<minor>`, zero when no process holds it.
+    pub fn get(&self, minor: u32) -> DeviceHandles {
+        DeviceHandles {
+            partial: self.unreadable > 0,
+            ..self.devices.get(&minor).copied().unwrap_or_default()
+        }
+    }
+}
+
+/// The minor number of a `/dev/nvidia<minor>` path; nvidiactl, nvidia-uvm
+/// and the like are shared by every GPU and not counted.
+fn device_minor(target: &Path) -> Option<u32> {
+    target.to_str()?.strip_prefix("/dev/nvidia")?.parse().ok()
+}
+
+/// Count descriptors per device minor over the processes under `proc`,
+/// normally `/proc`. Processes that exit or deny access mid-walk are skipped.
+pub fn scan(proc: &Path) -> HandleScan {
+    let mut scan = HandleScan::default();
+    let Ok(entries) = fs::read_dir(proc) else {
+        log::warn!("Cannot read {}, no handles counted", proc.display());
+        return scan;
+    };
+    for entry in entries.flatten() {
+        if entry.file_name().to_str().and_then(|pid| pid.parse::<u32>().ok()).is_none() {
+            continue;
+        }
+        let fds = match fs::read_dir(entry.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                scan.unreadable += 1;
+                continue;
+            }
+            // exited since /proc was listed
+            Err(_) => continue,
+        };
+        let mut held: HashMap<u32, u32> = HashMap::new();
+        for fd in fds.flatten() {
+            if let Some(minor) = fs::read_link(fd.path()).ok().as_deref().and_then(device_minor) {
+                *held.entry(minor).or_default() += 1;
+            }
+        }
+        for (minor, descriptors) in held {
+            let device = scan.devices.entry(minor).or_default();
+            device.descriptors += descriptors;
+            device.processes += 1;
+        }
+    }
+    log::debug!("handles: {} processes in {} unreadable", scan.unreadable, proc.display());
+    scan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{os::unix::fs::symlink, path::PathBuf};
+
+    /// A /proc-like tree of processes holding descriptors on `targets`.
+    fn proc_tree(name: &str, processes: &[(&str, &[&str])]) -> PathBuf {
+        let proc = std::env::temp_dir().join(format!("gpustat-handles-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&proc);
+        for (pid, targets) in processes {
+            let fd = proc.join(pid).join("fd");
+            fs::create_dir_all(&fd).unwrap();
+            for (n, target) in targets.iter().enumerate() {
+                symlink(target, fd.join(n.to_string())).unwrap();
+            }
+        }
+        proc
+    }
+
+    #[test]
+    fn descriptors_are_counted_per_device() {
+        let proc = proc_tree(
+            "count",
+            &[
+                (
+                    "10",
+                    &[
+                        "/dev/nvidia0",
+                        "/dev/nvidia0",
+                        "/dev/nvidiactl",
+                        "/dev/nvidia-uvm",
+                        "/dev/null",
+                    ],
+                ),
+                ("11", &["/dev/nvidia0", "/dev/nvidia1"]),
+                ("12", &["/dev/null"]),
+                // not a process
+                ("self-test", &["/dev/nvidia1"]),
+            ],
+        );
+        let scan = scan(&proc);
+        let counts = |descriptors, processes| DeviceHandles {
+            descriptors,
+            processes,
+            partial: false,
+        };
+        assert_eq!(scan.get(0), counts(3, 2));
+        assert_eq!(scan.get(1), counts(1, 1));
+        assert_eq!(scan.get(2), counts(0, 0));
+    }
+
+    #[test]
+    fn a_missing_proc_counts_nothing() {
+        let scan = scan(Path::new("/nonexistent/proc"));
+        assert_eq!(scan.get(0), DeviceHandles::default());
+    }
+}
@@ -0,0 +1,208 @@
+//! `--hosts-file`: collect snapshots from many machines over ssh or HTTP.
+
+use crate::{
+    render::{self, RenderOpts},
+    stats::Snapshot,
+    Opts, StatusError, EXIT_DEGRADED, EXIT_USAGE,
+};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::Read,
+    path::Path,
+    process::{Command, Stdio},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Run on every host; `-a` collects everything the local flags might display.
+const REMOTE_COMMAND: &str = "gpustat --format json -a";
+
+/// Hosts of a hosts file, one per line, ignoring blank lines and `#` comments.
+pub fn read_hosts(path: &Path) -> Result<Vec<String>, StatusError> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// The snapshot an HTTP endpoint answers with, as `gpustat --format json`
+/// prints it, e.g. `http://node:9099` behind a web server or inetd.
+fn fetch_host(url: &str, deadline: Instant) -> Result<Snapshot, String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(deadline.saturating_duration_since(Instant::now()))
+        .user_agent(concat!("gpustat/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let body = agent
+        .get(url)
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_string()
+        .map_err(|e| e.to_string())?;
+    match body.lines().last() {
+        Some(line) if !line.is_empty() => Snapshot::from_json(line).map_err(|e| e.to_string()),
+        _ => Err(String::from("empty response")),
+    }
+}
+
+/// Query one host, killing ssh once `deadline` has passed.
+fn collect_host(host: &str, deadline: Instant) -> Result<Snapshot, String> {
+    if host.starts_with("http://") || host.starts_with("https://") {
+        return fetch_host(host, deadline);
+    }
+    let mut child = Command::new("ssh")
+        .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=10", host, REMOTE_COMMAND])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot run ssh: {}", e))?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    // drain both pipes so a large snapshot cannot block ssh
+    let output = thread::spawn(move || {
+        let mut json = String::new();
+        let _ = stdout.read_to_string(&mut json);
+        json
+    });
+    let errors = thread::spawn(move || {
+        let mut errors = String::new();
+        let _ = stderr.read_to_string(&mut errors);
+        errors
+    });
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(String::from("timed out"));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(20)),
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+    let json = output.join().unwrap_or_default();
+    let errors = errors.join().unwrap_or_default();
+    // a degraded snapshot still exits non-zero, but is worth showing
+    match json.lines().last() {
+        Some(line) if !line.is_empty() => Snapshot::from_json(line).map_err(|e| e.to_string()),
+        _ => Err(errors
+            .lines()
+            .last()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("ssh exited with {}", status))),
+    }
+}
+
+/// Query every host with at most `max_concurrency` sessions at a time,
+/// keeping the order of the hosts file.
+pub fn collect(hosts: &[String], max_concurrency: usize, timeout: Duration) -> Vec<(String, Result<Snapshot, String>)> {
+    let deadline = Instant::now() + timeout;
+    let queue = Arc::new(Mutex::new(hosts.iter().cloned().enumerate().collect::<VecDeque<_>>()));
+    let (sender, receiver) = mpsc::channel();
+    let workers: Vec<_> = (0..max_concurrency.min(hosts.len()))
+        .map(|_| {
+            let (queue, sender) = (Arc::clone(&queue), sender.clone());
+            thread::spawn(move || loop {
+                let next = queue.lock().map(|mut queue| queue.pop_front());
+                let Ok(Some((i, host))) = next else {
+                    break;
+                };
+                let result = if Instant::now() >= deadline {
+                    Err(String::from("timed out"))
+                } else {
+                    collect_host(&host, deadline)
+                };
+                let _ = sender.send((i, host, result));
+            })
+        })
+        .collect();
+    drop(sender);
+    let mut results: Vec<_> = receiver.iter().collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    results.sort_by_key(|(i, _, _)| *i);
+    results.into_iter().map(|(_, host, result)| (host, result)).collect()
+}
+
+/// `node12: 7/8 GPUs busy, 81% mem`, where a GPU is busy while it runs processes.
+fn summary(host: &str, snapshot: &Snapshot) -> String {
+    let busy = snapshot.gpus.iter().filter(|gpu| !gpu.processes.is_empty()).count();
+    let used: u64 = snapshot.gpus.iter().map(|gpu| gpu.memory_used).sum();
+    let total: u64 = snapshot.gpus.iter().map(|gpu| gpu.memory_total).sum();
+    let memory = (used * 100).checked_div(total).unwrap_or_default();
+    format!("{}: {}/{} GPUs busy, {}% mem", host, busy, snapshot.gpus.len(), memory)
+}
+
+/// Collect and print every host of `--hosts-file`, followed by the hosts that failed.
+pub fn run(opts: &Opts, path: &Path) -> Result<u8, StatusError> {
+    let hosts = read_hosts(path)?;
+    let results = collect(&hosts, usize::from(opts.max_concurrency), opts.timeout);
+
+    let render_opts = RenderOpts::from(opts);
+    let registry = render::Registry::builtin(&render_opts);
+    let Some(renderer) = registry.get(opts.format_name()) else {
+        return Ok(EXIT_USAGE);
+    };
+    let mut text = String::new();
+    for (host, result) in &results {
+        if let Ok(snapshot) = result {
+            if opts.host_summary {
+                text = text + &summary(host, snapshot) + "\n";
+            } else {
+                text += &renderer.render(snapshot, &render_opts);
+            }
+        }
+    }
+    print!("{}", text);
+
+    let failed: Vec<_> = results.iter().filter_map(|(host, result)| Some((host, result.as_ref().err()?))).collect();
+    if failed.is_empty() {
+        return Ok(0);
+    }
+    eprintln!("{} of {} hosts failed:", failed.len(), results.len());
+    for (host, error) in failed {
+        eprintln!("  {}: {}", host, error);
+    }
+    Ok(EXIT_DEGRADED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::tests::{fixtures, gpu};
+    use clap::Parser;
+    use std::{io::Write, net::TcpListener};
+
+    #[test]
+    fn http_hosts_answer_with_their_json() {
+        let opts = Opts::try_parse_from(["gpustat"]).unwrap();
+        let snapshot = crate::stats::collect(&opts, &fixtures(vec![gpu(0), gpu(1)]), "node3").unwrap();
+        let body = serde_json::to_string(&snapshot).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for answer in [
+                format!("200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body),
+                String::from("404 Not Found\r\nContent-Length: 0\r\n\r\n"),
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request);
+                stream.write_all(format!("HTTP/1.1 {}", answer).as_bytes()).unwrap();
+            }
+        });
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let fetched = collect_host(&url, deadline).unwrap();
+        assert_eq!(fetched.hostname, "node3");
+        assert_eq!(fetched.gpus.len(), 2);
+        let error = collect_host(&url, deadline).unwrap_err();
+        assert!(error.contains("404"), "{}", error);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+//! Integrated GPUs from sysfs, for laptops where the NVIDIA GPU sleeps most
+//! of the time and the integrated one does the work. Collected with the
+//! `igpu` feature.
+//!
+//! Intel and AMD GPUs are read from `/sys/class/drm/card*/device`: amdgpu's
+//! `gpu_busy_percent` and `mem_info_vram_*` where the driver has them,
+//! otherwise the engine time DRM clients report in `/proc/*/fdinfo` (i915,
+//! amdgpu), which takes two samples to become a utilization. A card offering
+//! neither is left out.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+const VENDORS: &[(&str, &str)] = &[("0x8086", "Intel"), ("0x1002", "AMD")];
+
+/// One integrated GPU.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IntegratedGpu {
+    /// The DRM card, e.g. `card1`.
+    pub card: String,
+    pub vendor: String,
+    /// Kernel driver, e.g. `i915` or `amdgpu`.
+    pub driver: Option<String>,
+    pub pci_bus_id: String,
+    /// Percent, `None` until an fdinfo-based card has two samples.
+    pub utilization: Option<u32>,
+    pub memory_used: Option<u64>,
+    pub memory_total: Option<u64>,
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|text| text.trim().to_string())
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    read_trimmed(path)?.parse().ok()
+}
+
+/// A DRM client, by the PCI address of its device and its client id.
+type Client = (String, String);
+
+/// Engine time in ns per client and engine.
+type EngineTime = HashMap<Client, HashMap<String, u64>>;
+
+/// Engine time of the DRM clients of `proc`. Descriptors sharing a client
+/// are counted once.
+fn engine_time(proc: &Path) -> EngineTime {
+    let mut clients = EngineTime::new();
+    for process in fs::read_dir(proc).into_iter().flatten().flatten() {
+        if process.file_name().to_str().and_then(|pid| pid.parse::<u32>().ok()).is_none() {
+            continue;
+        }
+        for fd in fs::read_dir(process.path().join("fdinfo")).into_iter().flatten().flatten() {
+            let Ok(info) = fs::read_to_string(fd.path()) else {
+                continue;
+            };
+            let (mut pdev, mut client, mut engines) = (None, None, HashMap::new());
+            for line in info.lines() {
+                let Some((key, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let value = value.trim();
+                match key {
+                    "drm-pdev" => pdev = Some(value.to_string()),
+                    "drm-client-id" => client = Some(value.to_string()),
+                    _ => {
+                        let time = value.strip_suffix(" ns").and_then(|ns| ns.trim().parse().ok());
+                        if let (Some(engine), Some(time)) = (key.strip_prefix("drm-engine-"), time) {
+                            engines.insert(engine.to_string(), time);
+                        }
+                    }
+                }
+            }
+            if let (Some(pdev), Some(client)) = (pdev, client) {
+                clients.insert((pdev, client), engines);
+            }
+        }
+    }
+    log::debug!("igpu: engine time of {} DRM clients from {}", clients.len(), proc.display());
+    clients
+}
+
+/// Busy share of the busiest engine of the device at `pdev` between two
+/// samples. A client that appeared since spent all its time in between; the
+/// time of a client that exited meanwhile is lost.
+fn busy_between(
+    pdev: &str,
+    before: &EngineTime,
+    after: &EngineTime,
+    elapsed_ns: f64,
+) -> Option<u32> {
+    let mut engines: HashMap<&str, u64> = HashMap::new();
+    for (client, times) in after.iter().filter(|((device, _), _)| device == pdev) {
+        for (engine, time) in times {
+            let earlier = before.get(client).and_then(|times| times.get(engine)).copied().unwrap_or_default();
+            *engines.entry(engine).or_default() += time.saturating_sub(earlier);
+        }
+    }
+    let busiest = engines.into_values().max()?;
+    Some((busiest as f64 / elapsed_ns * 100.0).round().min(100.0) as u32).filter(|_| elapsed_ns > 0.0)
+}
+
+/// Samples integrated GPUs; keep one across the ticks of watch mode, so the
+/// fdinfo-based utilizations have a previous sample to compare with.
+#[derive(Debug)]
+pub struct Sampler {
+    sys: PathBuf,
+    proc: PathBuf,
+    previous: Option<(Instant, EngineTime)>,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Sampler::new(Path::new("/sys"), Path::new("/proc"))
+    }
+}
+
+impl Sampler {
+    pub fn new(sys: &Path, proc: &Path) -> Self {
+        Sampler {
+            sys: sys.to_path_buf(),
+            proc: proc.to_path_buf(),
+            previous: None,
+        }
+    }
+
+    /// Every Intel and AMD card with usable data, in card order.
+    pub fn sample(&mut self) -> Vec<IntegratedGpu> {
+        let mut cards: Vec<(String, PathBuf)> = fs::read_dir(self.sys.join("class/drm"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_str()?.to_string();
+                // card1-eDP-1 and the like are connectors
+                name.strip_prefix("card")?.parse::<u32>().ok()?;
+                Some((name, entry.path().join("device")))
+            })
+            .collect();
+        cards.sort_by_key(|(name, _)| name["card".len()..].parse::<u32>().unwrap_or_default());
+
+        let mut gpus = vec![];
+        let mut engines = None;
+        for (card, device) in cards {
+            let vendor = read_trimmed(&device.join("vendor")).unwrap_or_default();
+            let Some(&(_, vendor)) = VENDORS.iter().find(|(id, _)| *id == vendor) else {
+                continue;
+            };
+            let Some(pci_bus_id) = fs::canonicalize(&device)
+                .ok()
+                .and_then(|path| Some(path.file_name()?.to_str()?.to_string()))
+            else {
+                continue;
+            };
+            let driver = fs::read_link(device.join("driver"))
+                .ok()
+                .and_then(|driver| Some(driver.file_name()?.to_str()?.to_string()));
+            let mut gpu = IntegratedGpu {
+                card,
+                vendor: vendor.to_string(),
+                driver,
+                utilization: read_number(&device.join("gpu_busy_percent")).map(|busy| busy.min(100) as u32),
+                memory_used: read_number(&device.join("mem_info_vram_used")),
+                memory_total: read_number(&device.join("mem_info_vram_total")),
+                pci_bus_id,
+            };
+            let mut usable = gpu.utilization.is_some() || gpu.memory_total.is_some();
+            if gpu.utilization.is_none() {
+                // one walk of /proc serves every card
+                let (now, clients) = engines.get_or_insert_with(|| (Instant::now(), engine_time(&self.proc)));
+                if clients.keys().any(|(pdev, _)| *pdev == gpu.pci_bus_id) {
+                    usable = true;
+                    gpu.utilization = self.previous.as_ref().and_then(|(then, before)| {
+                        busy_between(&gpu.pci_bus_id, before, clients, now.duration_since(*then).as_nanos() as f64)
+                    });
+                }
+            }
+            if usable {
+                gpus.push(gpu);
+            } else {
+                log::info!("igpu: {} has no usable busy or memory data", gpu.card);
+            }
+        }
+        if engines.is_some() {
+            self.previous = engines;
+        }
+        gpus
+    }
+}
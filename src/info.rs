@@ -0,0 +1,169 @@
+//! `gpustat info`: everything NVML knows about one GPU, for hardware tickets.
+
+use crate::{ffi, stats::CcMode, StatusError};
+use clap::Args;
+use nvml_wrapper::{
+    enum_wrappers::device::{
+        Clock, EccCounter, InfoRom, MemoryError, RetirementCause, TemperatureSensor, TemperatureThreshold,
+    },
+    error::NvmlError,
+    Device, Nvml,
+};
+use serde_json::{json, Map, Value};
+
+#[derive(Args, Clone, Debug)]
+pub struct InfoOpts {
+    #[arg(long = "id", value_name = "INDEX", help = "GPU to describe")]
+    id: u32,
+    #[arg(long, help = "Print one flat JSON object instead of the listing")]
+    json: bool,
+}
+
+/// Fields in listing order, `None` for whatever the GPU or driver cannot report.
+type Fields = Vec<(String, Option<Value>)>;
+
+fn field<T: Into<Value>, E: std::fmt::Display>(fields: &mut Fields, key: impl Into<String>, value: Result<T, E>) {
+    let key = key.into();
+    if let Err(e) = &value {
+        log::debug!("info {}: {}", key, e);
+    }
+    fields.push((key, value.ok().map(Into::into)));
+}
+
+fn mib(bytes: u64) -> u64 {
+    bytes >> 20
+}
+
+fn watts(milliwatts: u32) -> f64 {
+    f64::from(milliwatts) / 1000.0
+}
+
+fn describe(device: &Device) -> Fields {
+    let mut fields = Fields::new();
+    let f = &mut fields;
+
+    field(f, "name", device.name());
+    field(f, "uuid", device.uuid());
+    field(f, "serial", device.serial());
+    field(f, "board_part_number", device.board_part_number());
+    field(f, "vbios", device.vbios_version());
+    field(f, "inforom_image", device.info_rom_image_version());
+    field(f, "inforom_oem", device.info_rom_version(InfoRom::OEM));
+    field(f, "inforom_ecc", device.info_rom_version(InfoRom::ECC));
+    field(f, "inforom_power", device.info_rom_version(InfoRom::Power));
+    let valid = match device.validate_info_rom() {
+        Ok(()) => Ok(true),
+        Err(NvmlError::CorruptedInfoROM) => Ok(false),
+        Err(e) => Err(e),
+    };
+    field(f, "inforom_valid", valid);
+    let cc_mode = CcMode::of(device).map(|mode| mode.map_or("n/a", CcMode::label));
+    field(f, "cc_mode", cc_mode);
+    field(f, "brand", device.brand().map(|brand| format!("{:?}", brand)));
+    field(f, "architecture", device.architecture().map(|arch| arch.to_string()));
+
+    field(f, "pci_bus_id", device.pci_info().map(|pci| pci.bus_id));
+    field(f, "pcie_link_gen", device.current_pcie_link_gen());
+    field(f, "pcie_link_gen_max", device.max_pcie_link_gen());
+    field(f, "pcie_link_width", device.current_pcie_link_width());
+    field(f, "pcie_link_width_max", device.max_pcie_link_width());
+
+    let memory = ffi::memory_info(device);
+    field(f, "fb_total_mib", memory.as_ref().map(|(m, _)| mib(m.total)));
+    field(f, "fb_used_mib", memory.map(|(m, _)| mib(m.used)));
+    let bar1 = device.bar1_memory_info();
+    field(f, "bar1_total_mib", bar1.as_ref().map(|m| mib(m.total)));
+    field(f, "bar1_used_mib", bar1.map(|m| mib(m.used)));
+
+    let ecc = device.is_ecc_enabled();
+    field(f, "ecc_current", ecc.as_ref().map(|ecc| ecc.currently_enabled));
+    field(f, "ecc_pending", ecc.map(|ecc| ecc.pending_enabled));
+    for (key, error, counter) in [
+        ("ecc_corrected_volatile", MemoryError::Corrected, EccCounter::Volatile),
+        ("ecc_corrected_aggregate", MemoryError::Corrected, EccCounter::Aggregate),
+        ("ecc_uncorrected_volatile", MemoryError::Uncorrected, EccCounter::Volatile),
+        ("ecc_uncorrected_aggregate", MemoryError::Uncorrected, EccCounter::Aggregate),
+    ] {
+        field(f, key, device.total_ecc_errors(error, counter));
+    }
+    field(
+        f,
+        "retired_pages_sbe",
+        device
+            .retired_pages(RetirementCause::MultipleSingleBitEccErrors)
+            .map(|pages| pages.len() as u64),
+    );
+    field(
+        f,
+        "retired_pages_dbe",
+        device.retired_pages(RetirementCause::DoubleBitEccError).map(|pages| pages.len() as u64),
+    );
+    // nvml-wrapper does not bind nvmlDeviceGetRemappedRows
+    f.push((String::from("remapped_rows"), None));
+
+    for (key, max_key, clock) in [
+        ("clock_graphics_mhz", "clock_graphics_max_mhz", Clock::Graphics),
+        ("clock_sm_mhz", "clock_sm_max_mhz", Clock::SM),
+        ("clock_memory_mhz", "clock_memory_max_mhz", Clock::Memory),
+        ("clock_video_mhz", "clock_video_max_mhz", Clock::Video),
+    ] {
+        field(f, key, device.clock_info(clock));
+        field(f, max_key, device.max_clock_info(clock));
+    }
+
+    field(f, "power_draw_w", device.power_usage().map(watts));
+    field(f, "power_limit_w", device.power_management_limit().map(watts));
+    field(f, "power_limit_default_w", device.power_management_limit_default().map(watts));
+    field(f, "power_limit_enforced_w", device.enforced_power_limit().map(watts));
+    let constraints = device.power_management_limit_constraints();
+    field(f, "power_limit_min_w", constraints.as_ref().map(|c| watts(c.min_limit)));
+    field(f, "power_limit_max_w", constraints.map(|c| watts(c.max_limit)));
+
+    field(f, "temperature_c", device.temperature(TemperatureSensor::Gpu));
+    for (key, threshold) in [
+        ("temperature_slowdown_c", TemperatureThreshold::Slowdown),
+        ("temperature_shutdown_c", TemperatureThreshold::Shutdown),
+        ("temperature_gpu_max_c", TemperatureThreshold::GpuMax),
+        ("temperature_memory_max_c", TemperatureThreshold::MemoryMax),
+    ] {
+        field(f, key, device.temperature_threshold(threshold));
+    }
+    let fans = device.num_fans();
+    let count = *fans.as_ref().unwrap_or(&0);
+    field(f, "fans", fans);
+    for fan in 0..count {
+        field(f, format!("fan{}_percent", fan), device.fan_speed(fan));
+    }
+
+    field(f, "persistence_mode", device.is_in_persistent_mode());
+    field(f, "compute_mode", device.compute_mode().map(|mode| format!("{:?}", mode)));
+    field(f, "mig_mode", device.mig_mode().map(|mode| mode.current == 1));
+    field(f, "mig_mode_pending", device.mig_mode().map(|mode| mode.pending == 1));
+    fields
+}
+
+/// Print every field of one GPU as `key: value` lines or as one JSON object.
+pub fn run(opts: &InfoOpts, nvml: &Nvml) -> Result<u8, StatusError> {
+    let device = nvml.device_by_index(opts.id)?;
+    let fields = describe(&device);
+    if opts.json {
+        let object: Map<String, Value> = fields
+            .into_iter()
+            .map(|(key, value)| (key, value.unwrap_or(Value::Null)))
+            .chain([(String::from("index"), json!(opts.id))])
+            .collect();
+        println!("{}", Value::Object(object));
+        return Ok(0);
+    }
+    let width = fields.iter().map(|(key, _)| key.len()).max().unwrap_or_default();
+    println!("{:width$}: {}", "index", opts.id, width = width);
+    for (key, value) in fields {
+        let value = match value {
+            None => String::from("n/a"),
+            Some(Value::String(s)) => s,
+            Some(value) => value.to_string(),
+        };
+        println!("{:width$}: {}", key, value, width = width);
+    }
+    Ok(0)
+}
@@ -0,0 +1,937 @@
+pub mod allocation;
+pub mod app_clocks;
+pub mod background;
+pub mod banner;
+pub mod bench;
+pub mod bell;
+pub mod capabilities;
+pub mod cgroup;
+pub mod chargeback;
+pub mod collection_errors;
+pub mod columns;
+pub mod completions;
+pub mod consistency;
+#[cfg(feature = "docker")]
+pub mod container;
+pub mod csv;
+pub mod doctor;
+pub mod ecc;
+pub mod explain;
+mod ffi;
+pub mod fixture;
+pub mod handles;
+pub mod hosts;
+pub mod igpu;
+pub mod info;
+pub mod lock;
+pub mod logfile;
+pub mod man;
+pub mod minimal;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod pcie;
+pub mod pid_list;
+pub mod plan;
+pub mod provider;
+pub mod render;
+pub mod ready;
+pub mod reset;
+pub mod runtime_pm;
+pub mod sharing;
+pub mod snapshot_cache;
+pub mod sorting;
+pub mod state;
+pub mod stats;
+pub mod template;
+#[cfg(feature = "check-update")]
+pub mod update;
+pub mod version;
+pub mod xid;
+
+#[cfg(feature = "async")]
+pub use nonblocking::{collect_snapshot_async, SnapshotStream};
+
+use chrono::format::StrftimeItems;
+use clap::{Parser, Subcommand};
+use csv::CsvLevel;
+use logfile::{LogCompress, LogRotate};
+use sorting::SortKey;
+use render::{Layout, Palette, RenderOpts};
+use serde::{Deserialize, Serialize};
+use stats::{AdaptiveInterval, ChangeThreshold, DeviceOrder, Distinguish, FailCondition, MemoryDisplay, Redact};
+use std::{
+    env, fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum StatusError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Failed to convert string: {0}")]
+    Utf8Error(#[from] std::str::Utf8Error),
+    #[error("Failed to initialize nvml: {0}")]
+    NvmlInitError(nvml_wrapper::error::NvmlError),
+    #[error("NVML call failed: {0}")]
+    NvmlError(#[from] nvml_wrapper::error::NvmlError),
+    #[error("Querying {query} of GPU {gpu} failed: {source}")]
+    NvmlQuery {
+        gpu: u32,
+        query: String,
+        source: nvml_wrapper::error::NvmlError,
+    },
+    #[error("Failed to call nix call: {0}")]
+    NixError(#[from] nix::Error),
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("Failed to convert json: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Unsupported snapshot schema version {0}")]
+    SchemaVersion(u32),
+    #[error("{0}")]
+    Locked(String),
+}
+
+impl StatusError {
+    /// Stable name of the variant, the `kind` of its `Failure`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StatusError::IoError(_) => "Io",
+            StatusError::Utf8Error(_) => "Utf8",
+            StatusError::NvmlInitError(_) => "NvmlInit",
+            StatusError::NvmlError(_) => "Nvml",
+            StatusError::NvmlQuery { .. } => "NvmlQuery",
+            StatusError::NixError(_) => "Nix",
+            StatusError::PermissionDenied(_) => "PermissionDenied",
+            StatusError::JsonError(_) => "Json",
+            StatusError::SchemaVersion(_) => "SchemaVersion",
+            StatusError::Locked(_) => "Locked",
+        }
+    }
+
+    /// The error as `--format json` prints it.
+    pub fn failure(&self) -> Failure {
+        let (gpu, query, nvml) = match self {
+            StatusError::NvmlQuery { gpu, query, source } => (Some(*gpu), Some(query.clone()), Some(source)),
+            StatusError::NvmlInitError(e) | StatusError::NvmlError(e) => (None, None, Some(e)),
+            _ => (None, None, None),
+        };
+        Failure {
+            kind: self.kind().to_string(),
+            gpu,
+            uuid: None,
+            query,
+            code: nvml.map(nvml_code),
+            message: self.to_string(),
+        }
+    }
+}
+
+/// Name of an NVML error without its payload, e.g. `NotSupported`.
+pub(crate) fn nvml_code(error: &nvml_wrapper::error::NvmlError) -> String {
+    let debug = format!("{:?}", error);
+    debug.split(['(', ' ', '{']).next().unwrap_or_default().to_string()
+}
+
+/// A failure in a form automation can classify: the `failure` of a GPU that
+/// could not be queried, or the `error` object `--format json` prints when the
+/// whole run fails, e.g. `{"kind": "NvmlQuery", "gpu": 3, "query": "fan",
+/// "code": "NotSupported", "message": "..."}`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Failure {
+    /// `StatusError::kind`, or `Timeout` for a GPU past `--query-timeout`.
+    pub kind: String,
+    pub gpu: Option<u32>,
+    pub uuid: Option<String>,
+    /// The NVML query or collector that failed.
+    pub query: Option<String>,
+    /// NVML error name, e.g. `NotSupported` or `GpuLost`.
+    pub code: Option<String>,
+    /// The same as one readable sentence.
+    pub message: String,
+}
+
+pub const EXIT_CODES_HELP: &str = "\
+Exit status:
+  0  success
+  1  usage or unexpected error
+  2  NVML could not be initialized
+  3  degraded: version mismatch, GPU reset required, a GPU could not be queried, or a required `ready` check failed
+  4  a GPU matches a --fail-if condition
+  5  none of the --pid processes is running on any GPU
+  6  --pids found no process
+  7  the driver lists no GPU
+  10 check-update --quiet: a newer release exists
+  11 check-update: GitHub could not be reached or its answer not understood
+  12 --otlp-endpoint: watch mode stopped while OTLP exports were failing";
+
+const SHOW_ALL_LONG_HELP: &str = "\
+Display every column --list-columns marks as part of -a.
+
+Columns that are slow or need extra permissions, such as xid (the kernel log) and handles (/proc), are left out; name them with --columns to add them. A column shows when its own flag, -a or --columns asks for it, so `-a --columns xid` is every -a column plus xid.";
+
+const COLUMNS_LONG_HELP: &str = "\
+Display these optional columns by name, in addition to their flags (see --list-columns).
+
+--columns only adds: it composes with -a and with the --show-* flags, and no combination hides a column another one asked for. A collector switched off with --disable-collector still shows its column, with 'off' cells.";
+
+const BELL_ON_LONG_HELP: &str = "\
+Ring the terminal bell when a GPU starts meeting CONDITION (repeatable).
+
+A condition is METRIC OPERATOR VALUE, e.g. 'temp>90':
+  METRIC    temp (°C), util (%), mem (% of total), power (W) or procs (count)
+  OPERATOR  >, >=, <, <=, == or !=
+  VALUE     a number, optionally followed by its unit %, C or W
+
+The bell rings when a GPU starts meeting a condition, not on every frame while it does, and at most once per --bell-cooldown for each condition. A GPU whose metric was not measured never meets it.";
+
+const CHANGE_THRESHOLD_LONG_HELP: &str = "\
+Margins for --changes-only, --sort and the --baseline regressions.
+
+A comma-separated list of KEY=VALUE, where omitted keys keep their default:
+  util   utilization in percentage points
+  mem    used memory in MiB, or with a K, M or G suffix
+  temp   temperature in °C
+  power  power draw in W
+
+A GPU counts as changed when any metric moved by at least its margin, and
+with --sort it moves ahead of another by leading it by more than the margin
+of the sort metric.";
+
+const MATCH_LONG_HELP: &str = "\
+Highlight processes whose full command line matches REGEX, case-insensitively unless --case-sensitive.
+
+The syntax is that of the regex crate, which matches in time linear in the command line: classes such as [a-z0-9_] or [^/], \\d, \\w, \\s and \\b, the anchors ^ and $, alternation with |, groups and the greedy or lazy quantifiers *, +, ?, {m,n}, but no backreferences or lookaround. Matched processes are prefixed with *; --match-only hides the others.";
+
+#[derive(Parser, Clone, Debug)]
+#[command(author, version, about, after_help = EXIT_CODES_HELP, after_long_help = man::long_help_footer())]
+// the status table needs no command, which `[COMMAND]` alone does not tell
+#[command(override_usage = "gpustat [OPTIONS]            print the GPU status table\n       gpustat [OPTIONS] <COMMAND>  run a command, see below")]
+#[command(group(clap::ArgGroup::new("compare").multiple(true).args(["changes_only", "baseline", "sort"])))]
+pub struct Opts {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[arg(long, help = "Force colored output (even when stdout is not a tty)")]
+    pub color: bool,
+    #[arg(long, help = "Suppress colored output")]
+    pub no_color: bool,
+    #[arg(long, conflicts_with = "compact", help = "Indent JSON output (the default when stdout is a tty)")]
+    pub pretty: bool,
+    #[arg(long, help = "Print JSON output on one line (the default when piped)")]
+    pub compact: bool,
+    #[arg(long, visible_alias = "theme", value_enum, default_value_t = Palette::Auto, help = "Color palette for the table; auto picks default or light after the terminal background")]
+    pub palette: Palette,
+    #[arg(long, value_enum, default_value_t = Layout::Single, help = "Table layout: the processes on each GPU's line, or on a dim line of their own below it")]
+    pub layout: Layout,
+    #[arg(long, help = "Draw the table with ASCII characters only (default for non-UTF-8 locales)")]
+    pub ascii: bool,
+    #[arg(long, conflicts_with = "ascii", help = "Keep Unicode output even in a non-UTF-8 locale")]
+    pub no_ascii: bool,
+    #[arg(long, help = "Do not print hints such as the limited-permissions note")]
+    pub no_hints: bool,
+    #[arg(long, help = "Group GPUs sharing a board under a row with the board's totals")]
+    pub group_by_board: bool,
+    #[arg(long, help = "Size the columns for the largest realistic values so rows never shift, always on in watch mode")]
+    pub fixed_width: bool,
+    #[arg(long, help = "Keep requested columns even when no GPU reports a value for them")]
+    pub keep_empty_columns: bool,
+    // #[clap(short = 'u', long, about = "Display username of the process owner")]
+    // show_user: bool,
+    #[arg(short = 'c', long, help = "Display the process name")]
+    pub show_cmd: bool,
+    #[arg(short = 'f', long, help = "Display full command and cpu stats of running process")]
+    pub show_full_cmd: bool,
+    #[arg(long, value_enum, value_name = "WHOSE", num_args = 0..=1, require_equals = true, default_missing_value = "others", help = "Show only the executable name of other users' processes and their arguments as [redacted], in every format; =all also for your own processes and for root")]
+    pub redact_args: Option<Redact>,
+    #[arg(long, value_name = "BYTES", default_value = "4K", value_parser = parse_bytes, help = "Cut each command line at BYTES, e.g. 512 or 16K, marking the rest as [+1.9MB truncated], in every format")]
+    pub max_cmd_length: usize,
+    #[arg(short = 'p', long, help = "Display PID of the process")]
+    pub show_pid: bool,
+    #[arg(long, value_name = "TEMPLATE", value_parser = parse_proc_format, help = "Template for each process, e.g. '{user}:{cmd}/{pid}({mem})', replacing -c, -f and -p")]
+    pub proc_format: Option<String>,
+    #[arg(long, help = "Display every temperature sensor the GPU exposes")]
+    pub show_temps_all: bool,
+    #[arg(long, help = "Display the adjustable target temperature next to the temperature, flagged when it is close")]
+    pub temp_detail: bool,
+    #[arg(long, help = "Display the last two components of each process's working directory")]
+    pub show_cwd: bool,
+    #[arg(long, help = "Display the virtualenv or conda env of Python processes")]
+    pub show_env: bool,
+    #[arg(long, help = "Display lifetime peak memory and average utilization of processes from accounting stats")]
+    pub show_accounting: bool,
+    #[arg(long, help = "Display the number of CUDA contexts of processes holding more than one, highlighting GPUs with a process above 4")]
+    pub show_contexts: bool,
+    #[arg(long, help = "Display the encoder sessions and frame rate of each process, listing processes that only encode")]
+    pub show_proc_codec: bool,
+    #[arg(long, help = "Display host RAM and swap in the header, and the host memory of processes with -f")]
+    pub show_host_mem: bool,
+    #[arg(long, help = "Display in the header how long the driver has been loaded, highlighted under an hour")]
+    pub show_driver_uptime: bool,
+    #[arg(long, help = "Flag GPUs whose processes requested more memory than the GPU has (Unified Memory oversubscription); NVML has no UVM fault or migration counters to show")]
+    pub show_uvm: bool,
+    #[arg(long, help = "Show each process's lifetime peak memory from accounting stats next to the current one, and in watch mode flag GPUs with much free memory where a process's memory fell and did not regrow, as after an OOM from fragmentation (advisory)")]
+    pub oom_hints: bool,
+    #[arg(long, help = "Display the number of processes of each GPU before the process list, e.g. '3 procs' or '2C+1G'")]
+    pub show_proc_count: bool,
+    #[arg(long, requires = "show_proc_count", help = "Count every process of the GPU, not only those matching --pid")]
+    pub count_all: bool,
+    #[arg(long, help = "Display the most recent Xid error of each GPU from the kernel log")]
+    pub show_xid: bool,
+    #[arg(long, help = "Display the descriptors open on each GPU's /dev/nvidia<N> and the processes holding them, from /proc")]
+    pub show_handles: bool,
+    #[arg(long, value_name = "N", default_value_t = 1024, requires = "show_handles", help = "Descriptors on one GPU above which --show-handles turns red, a sign of leaked handles")]
+    pub handles_threshold: u32,
+    #[arg(long, help = "Display the Kubernetes pods holding each GPU, or 'unallocated'")]
+    pub show_allocations: bool,
+    #[arg(long, help = "Display the number of connected displays and mark GPUs driving one")]
+    pub show_display: bool,
+    #[arg(long, help = "Display 'timeslice ×N' on GPUs the Kubernetes device plugin time-slices, from its config or the pods running on them")]
+    pub show_sharing: bool,
+    #[arg(long, value_name = "PATH", requires = "show_sharing", help = "Device plugin config to read the time-slicing replicas from, instead of /config/config.yaml or /etc/nvidia-device-plugin/config.yaml")]
+    pub sharing_config: Option<PathBuf>,
+    #[arg(short = 'F', long, help = "Display GPU fan speed and control policy")]
+    pub show_fan: bool,
+    #[arg(long, help = "Display the memory temperature, degrees to the slowdown temperature and power headroom, for liquid-cooled boards without fans")]
+    pub cooling: bool,
+    #[arg(long, help = "Display the RPM of every fan next to the fan speed, in red when a driven fan does not turn")]
+    pub fan_detail: bool,
+    #[arg(short = 'e', long, help = "Display encoder and/or decoder utilization")]
+    pub show_codec: bool,
+    #[arg(long, help = "Display the sampling window of the encoder and decoder utilization")]
+    pub show_codec_detail: bool,
+    #[arg(long, help = "Display remaining NVENC capacity for H.264 and HEVC")]
+    pub show_enc_capacity: bool,
+    #[arg(long, help = "Display the PCIe replay counter (and its growth in watch mode)")]
+    pub show_pcie_errors: bool,
+    #[arg(long, help = "Display the current and pending ECC mode")]
+    pub show_ecc: bool,
+    #[arg(long, help = "Display the current and pending MIG mode")]
+    pub show_mig_mode: bool,
+    #[arg(long, help = "Display the active clock throttle reasons, as a share of the recent ticks in watch mode")]
+    pub show_throttle: bool,
+    #[arg(long, value_name = "TICKS", default_value_t = 60, value_parser = clap::value_parser!(u16).range(1..), help = "Ticks over which --show-throttle shares are computed in watch mode")]
+    pub throttle_window: u16,
+    #[arg(long, help = "Display the SM clock as a share of the maximum boost clock, e.g. 'perf: 86%', with the dominant throttle reason")]
+    pub show_perf: bool,
+    #[arg(long, help = "Display the estimated memory bandwidth against the board's peak, e.g. '~1.2/2.0 TB/s': memory controller utilization times the peak")]
+    pub show_bandwidth: bool,
+    #[arg(long, value_name = "PERCENT", default_value_t = 90, value_parser = clap::value_parser!(u32).range(1..=100), help = "Bold --show-perf below PERCENT on busy GPUs")]
+    pub perf_threshold: u32,
+    #[arg(long, help = "Display the current and default application clocks, highlighted when below the default")]
+    pub show_app_clocks: bool,
+    #[arg(long, help = "Display the InfoROM image version and checksum, in red when corrupted (reads the InfoROM every refresh)")]
+    pub show_inforom: bool,
+    #[arg(long, help = "Display the confidential computing mode: enabled, disabled or devtools, and n/a on GPUs not capable of it")]
+    pub show_cc_mode: bool,
+    #[arg(long, help = "Display GPU brand tag (GF, DC, Q, ...) next to its name")]
+    pub show_brand: bool,
+    #[arg(long, help = "Display GPU virtualization mode and active vGPU instances")]
+    pub show_virt: bool,
+    #[arg(long, help = "Display the CPUs and NUMA node closest to each GPU")]
+    pub show_affinity: bool,
+    #[arg(long, help = "Display the user holding the most GPU memory on each GPU, e.g. 'alice (+2)' with two other users, in a color that stays the same for the user everywhere")]
+    pub show_owner: bool,
+    #[arg(short = 'a', long, help = "Display every column --list-columns marks as part of -a", long_help = SHOW_ALL_LONG_HELP)]
+    pub show_all: bool,
+    #[arg(long, value_name = "NAME,...", value_delimiter = ',', value_parser = parse_column, help = "Display these optional columns by name, in addition to their flags (see --list-columns)", long_help = COLUMNS_LONG_HELP)]
+    pub columns: Vec<String>,
+    #[arg(long, help = "List the optional columns, the flags that show them and whether -a does")]
+    pub list_columns: bool,
+    #[arg(long, value_enum, default_value_t = DeviceOrder::Nvml, help = "GPU enumeration order and index labels")]
+    pub order: DeviceOrder,
+    #[arg(long, value_enum, value_name = "KEY", help = "Append the index, UUID or PCI bus id to GPU names, telling identical cards apart")]
+    pub distinguish: Option<Distinguish>,
+    #[arg(long, value_name = "INDEX,...", value_delimiter = ',', conflicts_with = "reverse_devices", help = "Print GPUs in this order of their displayed index, e.g. 7,6,5,4, the unlisted ones following in their usual order")]
+    pub device_order: Vec<u32>,
+    #[arg(long, help = "Print GPUs in reverse order")]
+    pub reverse_devices: bool,
+    #[arg(long, value_enum, value_name = "KEY", conflicts_with_all = ["device_order", "reverse_devices"], help = "Print GPUs by this metric, the highest first and ties by index; in watch mode two GPUs only trade places once they differ by more than its --change-threshold margin")]
+    pub sort: Option<SortKey>,
+    #[arg(long, help = "Highlight the whole row of GPUs with a critical metric")]
+    pub highlight_rows: bool,
+    #[arg(long, value_name = "CONDITION", conflicts_with = "interval", help = "Exit with 4 when a GPU matches CONDITION, e.g. 'mig!=on' or 'temp>85' (repeatable)")]
+    pub fail_if: Vec<FailCondition>,
+    #[arg(long, value_parser = parse_time_format, help = "Header timestamp format (chrono strftime syntax)")]
+    pub time_format: Option<String>,
+    #[arg(long, conflicts_with = "epoch", help = "Display the header timestamp in UTC")]
+    pub utc: bool,
+    #[arg(long, conflicts_with = "time_format", help = "Display the header timestamp as seconds since the Unix epoch")]
+    pub epoch: bool,
+    #[arg(long, help = "Hide the hostname from the header")]
+    pub no_hostname: bool,
+    #[arg(long, value_name = "LABEL", help = "Show and record LABEL as the hostname, overriding GPUSTAT_HOSTNAME")]
+    pub hostname_label: Option<String>,
+    #[arg(long, conflicts_with = "hostname_label", help = "Fully qualify the hostname through DNS, keeping the short name if that takes over 200ms")]
+    pub fqdn: bool,
+    #[arg(long, help = "Hide the timestamp from the header")]
+    pub no_timestamp: bool,
+    #[arg(long, help = "Hide the driver version from the header")]
+    pub no_driver: bool,
+    #[arg(long, help = "Hide the header line entirely")]
+    pub no_header: bool,
+    #[arg(long, value_name = "TEXT", help = "Print TEXT above the header, e.g. a reservation or maintenance notice")]
+    pub header_text: Option<String>,
+    #[arg(long, value_name = "PATH", help = "Print the first lines of PATH above the header, read again at every refresh")]
+    pub header_file: Option<PathBuf>,
+    #[arg(long, value_name = "SCRIPT", help = "Print the output of the shell command SCRIPT above the header, run again at every refresh and killed after 2s")]
+    pub header_cmd: Option<String>,
+    #[arg(short = 'i', long, value_name = "SECONDS", group = "watch", value_parser = parse_interval, help = "Refresh the output every SECONDS (watch mode)")]
+    pub interval: Option<f64>,
+    #[arg(long, value_name = "MIN..MAX", group = "watch", help = "Watch mode whose period doubles from MIN up to MAX seconds while nothing changes, e.g. 1..30")]
+    pub adaptive_interval: Option<AdaptiveInterval>,
+    #[arg(long, value_name = "PERCENT", default_value_t = 5.0, value_parser = parse_percent, help = "In watch mode, keep a cell bold until it drops PERCENT of its limit below it")]
+    pub hysteresis: f64,
+    #[arg(long, requires = "watch", help = "In watch mode, only print GPUs that changed since they were last printed")]
+    pub changes_only: bool,
+    #[arg(long, value_name = "MARGINS", default_value = "util=5,mem=100M,temp=2,power=10", requires = "compare", help = "Margins for --changes-only, --sort and the --baseline regressions, e.g. util=5,mem=100M,temp=2,power=10", long_help = CHANGE_THRESHOLD_LONG_HELP)]
+    pub change_threshold: ChangeThreshold,
+    #[arg(long, value_name = "FILE", help = "Annotate temperature, power and memory with their change since a --format json snapshot, matching GPUs by UUID")]
+    pub baseline: Option<PathBuf>,
+    #[arg(short = 'o', long, value_name = "PATH", help = "Atomically write each frame to PATH instead of stdout")]
+    pub output: Option<PathBuf>,
+    #[arg(long, value_name = "PATH", help = "Also append every snapshot as a JSON line to PATH, gzip-compressed when it ends in .gz")]
+    pub log: Option<PathBuf>,
+    #[arg(long, value_enum, value_name = "METHOD", requires = "log", help = "Compression of --log, instead of choosing by the extension")]
+    pub log_compress: Option<LogCompress>,
+    #[arg(long, value_enum, value_name = "PERIOD", requires = "log", help = "Start a new --log file every hour or day, named with the date before the extension, e.g. gpu-2026-10-14.jsonl.gz")]
+    pub log_rotate: Option<LogRotate>,
+    #[arg(short = 'v', long, action = clap::ArgAction::Count, help = "Log NVML queries to stderr (-vv for more detail); in watch mode, also count failed queries below the table")]
+    pub verbose: u8,
+    #[arg(long, help = "Print the version with build metadata and the NVML, driver and CUDA versions, for bug reports")]
+    pub version_full: bool,
+    #[arg(long, value_name = "NAME,...", value_delimiter = ',', value_parser = parse_collector, help = "Skip the queries of these collectors, showing their cells as 'off' (see --list-collectors)")]
+    pub disable_collector: Vec<String>,
+    #[arg(long, help = "List the collectors of optional metrics and whether they run by default")]
+    pub list_collectors: bool,
+    #[arg(long, help = "Print the NVML queries the collection would make for these options, per GPU and collector, without initializing NVML; JSON with --format json")]
+    pub dry_run: bool,
+    #[arg(long, conflicts_with_all = ["watch", "hosts_file", "minimal"], help = "After the table, print the NVML function and units behind each column shown, the collectors that ran and the flags of the columns left out; a legend object with --format json")]
+    pub explain: bool,
+    #[arg(long, help = "Print collection and rendering time to stderr")]
+    pub timing: bool,
+    #[arg(long, conflicts_with_all = ["watch", "hosts_file", "procs_only"], help = "Only query the utilization and memory of each GPU, skipping processes, the hostname and every optional column, for status bars; prints a small table or --format oneline")]
+    pub minimal: bool,
+    #[arg(short = 'q', long, help = "Suppress normal output, keeping diagnostics and the exit status")]
+    pub quiet: bool,
+    #[arg(long, value_enum, value_name = "WHAT", default_value_t = MemoryDisplay::Used, help = "Memory figures to display")]
+    pub mem_display: MemoryDisplay,
+    #[arg(long, help = "Display the rolling average and session peak of the power draw")]
+    pub power_detail: bool,
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, requires = "power_detail", help = "Reset the power average and peak every DURATION, e.g. 30s, 15m or 1h")]
+    pub reset_stats_every: Option<Duration>,
+    #[arg(long, requires = "interval", help = "Bold power and utilization on their maximum sampled by the driver since the last refresh")]
+    pub window_max: bool,
+    #[arg(long, help = "Display the memory reserved by the driver next to the used memory, e.g. '30720 + 512r / 81920 MB'")]
+    pub show_reserved: bool,
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, requires = "watch", help = "Reset the session peak of the used memory every DURATION in watch mode")]
+    pub reset_peaks_every: Option<Duration>,
+    #[arg(long, requires = "watch", help = "Display the GPU-hours spent above --busy-threshold utilization since the start of watch mode")]
+    pub show_busy: bool,
+    #[arg(long, value_name = "PERCENT", default_value_t = 10, value_parser = clap::value_parser!(u32).range(0..100), help = "Utilization above which a GPU counts as busy, for --show-busy and the note on busy GPUs without processes")]
+    pub busy_threshold: u32,
+    #[arg(long, value_name = "FILE", requires = "show_busy", help = "Continue the --show-busy counter from the last snapshot of a --format json recording, if FILE exists")]
+    pub resume_state: Option<PathBuf>,
+    #[arg(long, value_name = "PATH", help = "Keep the session peaks, --show-busy total, --bell-on cooldowns, GPU idle times and collection error counts in PATH across restarts, saved every minute and on SIGINT or SIGTERM; a one-shot run reads the idle times from it")]
+    pub state_file: Option<PathBuf>,
+    #[arg(long, requires = "watch", help = "Take over the lock on --output, --state-file or --log from a gpustat watcher that is no longer running")]
+    pub force: bool,
+    #[cfg(feature = "otlp")]
+    #[arg(long, value_name = "URL", requires = "watch", value_parser = otlp::parse_endpoint, help = "Export GPU and process gauges to this OTLP gRPC collector every tick in watch mode, e.g. http://collector:4317 (OTLP/HTTP is not supported)")]
+    pub otlp_endpoint: Option<String>,
+    #[arg(long, value_name = "CONDITION", requires = "watch", help = "Ring the terminal bell when a GPU starts meeting CONDITION, e.g. 'temp>90', 'mem>=95' or 'procs==0' (repeatable)", long_help = BELL_ON_LONG_HELP)]
+    pub bell_on: Vec<bell::Condition>,
+    #[arg(long, value_name = "DURATION", default_value = "60s", value_parser = parse_duration, requires = "bell_on", help = "Ring at most once per DURATION for each --bell-on condition")]
+    pub bell_cooldown: Duration,
+    #[arg(long, value_name = "MB", default_value_t = 1024, requires = "watch", help = "MB per minute of steady growth past which a process turns the process cell red; slower growth shows it in yellow")]
+    pub mem_growth_alert: u64,
+    #[arg(long, help = "Query static device properties such as the name and UUID on every tick instead of caching them, for debugging")]
+    pub no_cache: bool,
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with_all = ["watch", "minimal", "hosts_file"], help = "Show a snapshot another gpustat of this user collected less than DURATION ago, e.g. 2s, instead of querying NVML; otherwise collect and cache one")]
+    pub cache: Option<Duration>,
+    #[arg(long, requires = "cache", help = "When the cache is stale and NVML fails, show the cached snapshot with its age instead of failing")]
+    pub allow_stale: bool,
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, requires = "allow_stale", help = "Oldest cached snapshot --allow-stale shows, e.g. 10m")]
+    pub max_age: Option<Duration>,
+    #[arg(long, help = "Leave the process list empty when NVML refuses it, instead of listing the processes that hold the device in /proc")]
+    pub no_proc_scan: bool,
+    #[arg(long, help = "Query GPUs that runtime power management suspended, waking them up, instead of showing them as suspended")]
+    pub wake: bool,
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, help = "Give up on a GPU that does not answer within DURATION, e.g. 500ms, and show it as timed out")]
+    pub query_timeout: Option<Duration>,
+    #[arg(long, help = "Insert thousands separators in memory and power figures")]
+    pub group_digits: bool,
+    #[arg(long, value_name = "SEP", default_value = ",", requires = "group_digits", value_parser = parse_digit_separator, help = "Thousands separator for --group-digits: ',', '.', '_' or 'space'")]
+    pub digit_separator: char,
+    #[arg(long, conflicts_with = "group_digits", help = "Write table figures with the separators of LC_NUMERIC, e.g. 61 210 and 21,2 in French; other formats are unaffected")]
+    pub locale_numbers: bool,
+    /// Decimal separator of the table, set from the locale by `--locale-numbers`.
+    #[arg(skip = '.')]
+    pub decimal_separator: char,
+    #[arg(long, value_name = "NAME", default_value = "table", value_parser = format_parser(), help = "Output format (see --list-formats)")]
+    pub format: String,
+    #[arg(long, help = "List the output formats accepted by --format")]
+    pub list_formats: bool,
+    /// Deprecated alias of `--format json`.
+    #[arg(long, hide = true, conflicts_with = "format")]
+    pub json: bool,
+    /// Deprecated alias of `--format oneline`.
+    #[arg(long, hide = true, conflicts_with = "format")]
+    pub oneline: bool,
+    #[arg(long, value_name = "TEMPLATE", value_parser = parse_oneline_format, help = "Template for each GPU with the oneline format, e.g. '{index}:{util}%/{mem}G'")]
+    pub oneline_format: Option<String>,
+    #[arg(long, value_name = "SEP", default_value = " ", help = "Separator between GPUs with the oneline format")]
+    pub oneline_separator: String,
+    #[arg(long, value_name = "NAME,...", value_delimiter = ',', value_parser = parse_csv_field, help = "Fields and their order with the csv format (see --list-csv-fields)")]
+    pub csv_fields: Vec<String>,
+    #[arg(long, value_name = "CHAR", default_value = ",", value_parser = parse_csv_delimiter, help = "Field delimiter with the csv format, e.g. 'tab' for TSV")]
+    pub csv_delimiter: char,
+    #[arg(long, value_enum, default_value_t = CsvLevel::Gpu, help = "One csv row per GPU or per process")]
+    pub csv_level: CsvLevel,
+    #[arg(long, help = "List the fields of the csv format and whether they need --csv-level process")]
+    pub list_csv_fields: bool,
+    #[arg(long, value_name = "PATH", conflicts_with = "watch", help = "Collect from every host listed in PATH, one per line: a hostname for ssh, or an http:// URL answering with the output of gpustat --format json")]
+    pub hosts_file: Option<PathBuf>,
+    #[arg(long, value_name = "N", default_value_t = 16, value_parser = clap::value_parser!(u16).range(1..), requires = "hosts_file", help = "Hosts queried at the same time with --hosts-file")]
+    pub max_concurrency: u16,
+    #[arg(long, value_name = "DURATION", default_value = "60s", value_parser = parse_duration, requires = "hosts_file", help = "Give up on hosts that have not answered after DURATION with --hosts-file")]
+    pub timeout: Duration,
+    #[arg(long, requires = "hosts_file", help = "Print one summary line per host instead of their tables")]
+    pub host_summary: bool,
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..), help = "Only show page N of the GPUs, see --page-size")]
+    pub page: Option<u16>,
+    #[arg(long, value_name = "K", value_parser = clap::value_parser!(u16).range(1..), help = "GPUs per page with --page (default: 8)")]
+    pub page_size: Option<u16>,
+    #[arg(long, help = "Group processes spanning multiple GPUs below the table")]
+    pub group_procs: bool,
+    #[arg(long = "pid", value_name = "PID", help = "Only show GPUs running the given process (repeatable)")]
+    pub pids: Vec<u32>,
+    #[arg(long, requires = "pids", help = "Also match descendants of the --pid processes")]
+    pub pid_children: bool,
+    #[arg(long = "match", value_name = "REGEX", value_parser = parse_pattern, help = "Highlight processes whose full command line matches REGEX, case-insensitively, e.g. 'train\\.py|finetune'", long_help = MATCH_LONG_HELP)]
+    pub match_pattern: Option<regex::Regex>,
+    #[arg(long, requires = "match_pattern", help = "Hide the processes --match does not match, keeping every GPU")]
+    pub match_only: bool,
+    #[arg(long, requires = "match_pattern", help = "Match --match case-sensitively")]
+    pub case_sensitive: bool,
+    #[arg(long = "id", value_name = "INDEX,...", value_delimiter = ',', conflicts_with = "hosts_file", help = "Only query the GPUs of these NVML indices, e.g. 0,2")]
+    pub ids: Vec<u32>,
+    #[cfg(feature = "docker")]
+    #[arg(long, value_name = "NAME", conflicts_with = "hosts_file", help = "Only show the GPUs visible to a Docker container, read from the Docker Engine API")]
+    pub container: Option<String>,
+    #[arg(long, conflicts_with = "hosts_file", help = "Summarize the GPUs of CUDA_VISIBLE_DEVICES above the table and flag other users' processes on them")]
+    pub allocation_summary: bool,
+    #[arg(long, help = "Group the processes of each GPU per user, with counts and total memory")]
+    pub procs_by_user: bool,
+    #[arg(long = "expand-user", value_name = "USER", requires = "procs_by_user", help = "List the processes of USER individually with --procs-by-user (repeatable)")]
+    pub expand_users: Vec<String>,
+    #[arg(long, help = "Condense each process on a GPU and the descendants it started there into one entry, e.g. torchrun→python ×8 (total 64.2G)")]
+    pub collapse_tree: bool,
+    #[arg(long = "expand-gpu", value_name = "INDEX", requires = "collapse_tree", help = "List the processes of GPU INDEX individually with --collapse-tree (repeatable)")]
+    pub expand_gpus: Vec<u32>,
+    #[arg(long, help = "Print each user's GPU memory summed over all GPUs below the table, as a share of the installed and of the used GPU memory")]
+    pub chargeback: bool,
+    #[arg(long, help = "Compare the power limit, persistence, ECC, application clocks and MIG mode of the GPUs of each model and mark the outliers with ≠, listing the differences below the table")]
+    pub consistency_check: bool,
+    #[arg(long, help = "Display process memory as a percentage of the GPU memory too")]
+    pub proc_percent: bool,
+    #[arg(long, help = "Print one row per process (time, host, GPU, PID, user, command, memory, runtime) instead of the GPUs")]
+    pub procs_only: bool,
+    #[arg(long, requires = "procs_only", help = "Print an idle row for each GPU without processes with --procs-only")]
+    pub include_idle_marker: bool,
+    #[arg(long = "pids", visible_alias = "show-pids-only", conflicts_with_all = ["watch", "hosts_file", "minimal", "output", "format", "json", "oneline"], help = "Print only the PIDs of the compute and graphics processes, e.g. for kill $(gpustat --id 3 --pids); one line per GPU starting with its index unless there is one GPU or --flat. Exits with 6 when there are none")]
+    pub pid_list: bool,
+    #[arg(long = "user", value_name = "USER", requires = "pid_list", help = "Only print the PIDs of USER's processes with --pids (repeatable)")]
+    pub users: Vec<String>,
+    #[arg(long, requires = "pid_list", help = "Print the PIDs of every GPU on one line with --pids")]
+    pub flat: bool,
+}
+
+impl Opts {
+    /// Selected output format, with the deprecated `--json` and `--oneline` as shorthands for `--format`.
+    pub fn format_name(&self) -> &str {
+        if self.json {
+            "json"
+        } else if self.oneline {
+            "oneline"
+        } else {
+            &self.format
+        }
+    }
+
+    /// Deprecated flags and options the selected format ignores, to warn about.
+    pub fn format_warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        for (flag, set) in [("json", self.json), ("oneline", self.oneline)] {
+            if set {
+                warnings.push(format!("--{} is deprecated, use --format {}", flag, flag));
+            }
+        }
+        let format = self.format_name();
+        let ignored = [
+            (
+                "--color",
+                self.color
+                    && (format == "oneline" || format == "csv" || (format == "json" && !render::pretty_json(self))),
+            ),
+            ("--pretty", self.pretty && format != "json"),
+            ("--compact", self.compact && format != "json"),
+            ("--palette", !matches!(self.palette, Palette::Auto | Palette::Default) && format != "table"),
+            ("--layout", self.layout != Layout::Single && format != "table"),
+            ("--proc-format", self.proc_format.is_some() && (format == "json" || format == "csv")),
+            ("--oneline-format", self.oneline_format.is_some() && format != "oneline"),
+            ("--procs-only", self.procs_only && format == "oneline"),
+            ("--csv-fields", !self.csv_fields.is_empty() && format != "csv"),
+            ("--csv-delimiter", self.csv_delimiter != ',' && format != "csv"),
+            ("--csv-level", self.csv_level != CsvLevel::Gpu && format != "csv"),
+            ("--header-text", self.header_text.is_some() && format != "table"),
+            ("--header-file", self.header_file.is_some() && format != "table"),
+            ("--header-cmd", self.header_cmd.is_some() && format != "table"),
+            ("--explain", self.explain && format != "table" && format != "json"),
+        ];
+        for (option, _) in ignored.iter().filter(|(_, ignored)| *ignored) {
+            warnings.push(format!("{} has no effect with --format {}", option, format));
+        }
+        warnings
+    }
+
+    /// Whether this run is `gpustat ready`, which needs the readiness collectors.
+    pub fn ready_check(&self) -> bool {
+        matches!(self.command, Some(Command::Ready(_)))
+    }
+
+    /// Whether the optional column `name` of `columns::COLUMNS` is shown, by its
+    /// own flags, `--columns` or `-a`.
+    pub fn column(&self, name: &str) -> bool {
+        let Some(column) = columns::COLUMNS.iter().find(|column| column.name == name) else {
+            debug_assert!(false, "unregistered column {}", name);
+            return false;
+        };
+        (column.flagged)(self) || (column.in_all && self.show_all) || self.columns.iter().any(|shown| shown == name)
+    }
+
+    /// Whether `--disable-collector` switched off the collector `name`.
+    pub fn collector_off(&self, name: &str) -> bool {
+        self.disable_collector.iter().any(|off| off == name)
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Compare this build with the latest GitHub release; the only command that uses the network
+    #[cfg(feature = "check-update")]
+    CheckUpdate(update::CheckUpdateOpts),
+    /// Set or reset the application clocks of a GPU (requires root)
+    AppClocks(app_clocks::AppClocksOpts),
+    /// Time each NVML query on every GPU, and whole collection passes
+    Bench(bench::BenchOpts),
+    /// Probe which optional metrics every GPU supports, cached for schedulers
+    Capabilities(capabilities::CapabilitiesOpts),
+    /// Print a shell completion script that also completes GPU indices and users
+    Completions(completions::CompletionsOpts),
+    /// Print completion candidates for the completion scripts
+    #[command(name = "__complete", hide = true)]
+    Complete(completions::CompleteOpts),
+    /// Check the NVML setup, permissions and terminal, with a hint for each problem
+    Doctor,
+    /// Set the pending ECC mode of a GPU (requires root)
+    Ecc(ecc::EccOpts),
+    /// Print everything NVML reports about one GPU, one field per line
+    Info(info::InfoOpts),
+    /// Print the PCIe link and payload configuration of every GPU, exiting non-zero on a downgraded link
+    Pcie,
+    /// Check that a GPU is ready for a job, exiting non-zero when a required check fails
+    Ready(ready::ReadyOpts),
+    /// Save the NVML answers of one collection to a file that GPUSTAT_FIXTURE replays without a GPU
+    RecordFixture(fixture::RecordFixtureOpts),
+    /// Reset an idle GPU (requires root)
+    Reset(reset::ResetOpts),
+    /// Print the JSON schema of the --format json snapshot
+    Schema,
+    /// Print the gpustat(1) man page in roff, e.g. for `gpustat man | man -l -`
+    Man,
+}
+
+/// Replace `path` with `contents` through a rename, so readers never see a partial file.
+pub fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+impl Default for Opts {
+    /// The options of a plain `gpustat` run.
+    fn default() -> Self {
+        Opts::parse_from(["gpustat"])
+    }
+}
+
+/// Environment variable naming the node, between `--hostname-label` and the system hostname.
+pub const HOSTNAME_ENV: &str = "GPUSTAT_HOSTNAME";
+
+/// Longest wait for the DNS lookup of `--fqdn`.
+const FQDN_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Name of this node in the header and in snapshots: `--hostname-label`, then
+/// `GPUSTAT_HOSTNAME`, then the system hostname, fully qualified with `--fqdn`.
+pub fn hostname(opts: &Opts) -> Result<String, StatusError> {
+    if let Some(label) = &opts.hostname_label {
+        return Ok(label.clone());
+    }
+    if let Some(label) = env::var(HOSTNAME_ENV).ok().filter(|label| !label.is_empty()) {
+        return Ok(label);
+    }
+    let hostname = hostname::get()?.to_string_lossy().into_owned();
+    if opts.fqdn {
+        return Ok(fqdn().unwrap_or(hostname));
+    }
+    Ok(hostname)
+}
+
+/// `hostname --fqdn`, killed after `FQDN_TIMEOUT` so a dead resolver cannot stall the run.
+fn fqdn() -> Option<String> {
+    let mut child = process::Command::new("hostname")
+        .arg("--fqdn")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .inspect_err(|e| log::info!("--fqdn: cannot run hostname: {}", e))
+        .ok()?;
+    let deadline = Instant::now() + FQDN_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                log::info!("--fqdn: DNS lookup timed out");
+                return None;
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(5)),
+            Err(_) => return None,
+        }
+    };
+    let mut name = String::new();
+    child.stdout.take()?.read_to_string(&mut name).ok()?;
+    let name = name.trim();
+    Some(name.to_string()).filter(|name| status.success() && !name.is_empty())
+}
+
+/// Collect one snapshot of this machine, initializing NVML for the call.
+pub fn snapshot(opts: &Opts) -> Result<stats::Snapshot, StatusError> {
+    let nvml = nvml_wrapper::Nvml::init().map_err(StatusError::NvmlInitError)?;
+    stats::collect(opts, &nvml, &hostname(opts)?)
+}
+
+/// Collect one snapshot and render it as the table, e.g. to show inline in a
+/// notebook. Colors are off unless `opts.color` is set.
+pub fn render_table(opts: &Opts) -> Result<String, StatusError> {
+    use render::Renderer;
+
+    let snapshot = snapshot(opts)?;
+    let mut opts = opts.clone();
+    opts.no_color |= !opts.color;
+    Ok(render::TableRenderer::new(opts.hysteresis).render(&snapshot, &RenderOpts::from(&opts)))
+}
+
+/// JSON schema of the snapshot written by `--format json`, see `stats::SCHEMA_VERSION`.
+pub const SNAPSHOT_SCHEMA: &str = include_str!("schema.json");
+
+/// Refuse privileged subcommands early with a clear message.
+fn require_root(action: &str) -> Result<(), StatusError> {
+    if nix::unistd::Uid::effective().is_root() {
+        Ok(())
+    } else {
+        Err(StatusError::PermissionDenied(format!("{} requires root", action)))
+    }
+}
+
+fn parse_interval(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(interval) if interval > 0.0 && interval.is_finite() => Ok(interval),
+        Ok(_) => Err(String::from("interval must be a positive number of seconds")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let seconds = match unit {
+        "ms" => 0.001,
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        _ => return Err(format!("unknown unit '{}', expected ms, s, m, h or d", unit)),
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(Duration::from_secs_f64(n * seconds)),
+        Ok(_) => Err(String::from("duration must be positive")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_bytes(value: &str) -> Result<usize, String> {
+    let (digits, shift) = match value.chars().last() {
+        Some('K') => (&value[..value.len() - 1], 10),
+        Some('M') => (&value[..value.len() - 1], 20),
+        _ => (value, 0),
+    };
+    match digits.parse::<usize>() {
+        Ok(0) => Err(String::from("length must be positive")),
+        Ok(bytes) => bytes.checked_mul(1 << shift).ok_or_else(|| String::from("length too large")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        Ok(_) => Err(String::from("percentage must be between 0 and 100")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn format_parser() -> clap::builder::PossibleValuesParser {
+    clap::builder::PossibleValuesParser::new(
+        render::FORMATS.map(|(name, description)| clap::builder::PossibleValue::new(name).help(description)),
+    )
+}
+
+fn parse_column(name: &str) -> Result<String, String> {
+    if columns::COLUMNS.iter().any(|column| column.name == name) {
+        Ok(name.to_string())
+    } else {
+        let names: Vec<&str> = columns::COLUMNS.iter().map(|column| column.name).collect();
+        Err(format!("unknown column, expected one of {}", names.join(", ")))
+    }
+}
+
+fn parse_collector(name: &str) -> Result<String, String> {
+    if stats::COLLECTORS.iter().any(|collector| collector.name == name) {
+        Ok(name.to_string())
+    } else {
+        let names: Vec<&str> = stats::COLLECTORS.iter().map(|collector| collector.name).collect();
+        Err(format!("unknown collector, expected one of {}", names.join(", ")))
+    }
+}
+
+fn parse_time_format(format: &str) -> Result<String, String> {
+    StrftimeItems::new(format)
+        .parse()
+        .map(|_| format.to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn parse_oneline_format(format: &str) -> Result<String, String> {
+    template::validate(format, render::ONELINE_FIELDS)
+}
+
+fn parse_proc_format(format: &str) -> Result<String, String> {
+    template::validate(format, render::PROC_FIELDS)
+}
+
+fn parse_pattern(pattern: &str) -> Result<regex::Regex, String> {
+    regex::Regex::new(pattern).map_err(|e| e.to_string())
+}
+
+fn parse_csv_field(name: &str) -> Result<String, String> {
+    if csv::FIELDS.iter().any(|field| field.name == name) {
+        Ok(name.to_string())
+    } else {
+        let names: Vec<&str> = csv::FIELDS.iter().map(|field| field.name).collect();
+        Err(format!("unknown field, expected one of {}", names.join(", ")))
+    }
+}
+
+fn parse_csv_delimiter(value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+    match (value, chars.next(), chars.next()) {
+        ("tab" | "\\t", _, _) => Ok('\t'),
+        (_, Some(c), None) if !matches!(c, '"' | '\r' | '\n') => Ok(c),
+        _ => Err(String::from("delimiter must be one character other than a quote or line break, or 'tab'")),
+    }
+}
+
+fn parse_digit_separator(value: &str) -> Result<char, String> {
+    match value {
+        "," => Ok(','),
+        "." => Ok('.'),
+        "_" => Ok('_'),
+        "space" | " " => Ok(' '),
+        _ => Err(String::from("separator must be one of ',', '.', '_' or 'space'")),
+    }
+}
+
+/// Whether the effective locale is explicitly set to a non-UTF-8 charset.
+pub fn non_utf8_locale() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty());
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_ascii_lowercase();
+            !locale.contains("utf-8") && !locale.contains("utf8")
+        }
+        None => false,
+    }
+}
+
+/// Thousands and decimal separators of the numeric locale, by language, for
+/// `--locale-numbers`. Languages not listed write numbers the C way.
+pub fn locale_separators() -> (char, char) {
+    let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    separators_of(&locale)
+}
+
+/// The separators of `locale`, e.g. `de_DE.UTF-8`.
+pub(crate) fn separators_of(locale: &str) -> (char, char) {
+    let language = locale.split(['_', '.', '@']).next().unwrap_or_default();
+    match language {
+        "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "uk" | "hu" | "bg" => (' ', ','),
+        "de" | "it" | "es" | "nl" | "pt" | "da" | "id" | "tr" | "el" | "ro" | "hr" | "sl" => ('.', ','),
+        _ => (',', '.'),
+    }
+}
+
+pub const EXIT_USAGE: u8 = 1;
+pub const EXIT_NVML_INIT: u8 = 2;
+/// Exit code used when the driver needs attention or some GPU could not be queried.
+pub const EXIT_DEGRADED: u8 = 3;
+/// Exit code used when a GPU matches a `--fail-if` condition.
+pub const EXIT_FAIL_IF: u8 = 4;
+/// Exit code used when none of the `--pid` processes is running on any GPU.
+pub const EXIT_PID_NOT_FOUND: u8 = 5;
+/// Exit code used when `--pids` finds no process to print.
+pub const EXIT_NO_PROCESSES: u8 = 6;
+/// Exit code used when the driver is loaded but lists no GPU.
+pub const EXIT_NO_DEVICES: u8 = 7;
+
+/// Exit code of a run that failed with `error`, by its kind.
+pub fn exit_code(error: &StatusError) -> u8 {
+    match error {
+        StatusError::NvmlInitError(_) => EXIT_NVML_INIT,
+        StatusError::NvmlQuery { .. } => EXIT_DEGRADED,
+        _ => EXIT_USAGE,
+    }
+}
@@ -0,0 +1,171 @@
+//! Advisory locks on the files watch mode keeps rewriting, `--output` and
+//! `--state-file`, so a second watcher pointed at the same path is refused at
+//! startup instead of interleaving its writes with the first one.
+//!
+//! The lock is a `flock` on `PATH.lock` holding the owner's pid, not on PATH
+//! itself, which `write_atomic` replaces on every write. The kernel drops the
+//! lock when its owner dies, so a lock file left behind by a crash is simply
+//! taken over.
+
+use crate::StatusError;
+use nix::{
+    errno::Errno,
+    fcntl::{flock, FlockArg},
+    sys::signal::kill,
+    unistd::Pid,
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, Write},
+    os::unix::{fs::MetadataExt, io::AsRawFd},
+    path::{Path, PathBuf},
+};
+
+/// Held until dropped, which removes the lock file.
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+    file: File,
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+/// Whether `file` is still the one at `path`, rather than a lock file that
+/// was removed or taken over since it was opened.
+fn is_current(file: &File, path: &Path) -> bool {
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.dev() == current.dev() && open.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+fn holder(file: &mut File) -> Option<i32> {
+    let mut pid = String::new();
+    file.read_to_string(&mut pid).ok()?;
+    pid.trim().parse().ok()
+}
+
+/// `kill(pid, 0)` succeeds, or fails only for lack of permission.
+fn running(pid: i32) -> bool {
+    !matches!(kill(Pid::from_raw(pid), None), Err(Errno::ESRCH))
+}
+
+/// Lock `path` for this process. With `force`, a lock still held by a pid
+/// that is not running, e.g. one from another pid namespace that is gone, is
+/// taken over.
+pub fn acquire(path: &Path, force: bool) -> Result<FileLock, StatusError> {
+    let lock = lock_path(path);
+    loop {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&lock)?;
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => {}
+            Err(Errno::EWOULDBLOCK) => {
+                let holder = holder(&mut file);
+                let by = holder.map_or_else(|| String::from("another gpustat"), |pid| format!("gpustat pid {}", pid));
+                if !force {
+                    return Err(StatusError::Locked(format!(
+                        "{} is in use by {} (lock {}); stop it, or pass --force if it is no longer running",
+                        path.display(),
+                        by,
+                        lock.display()
+                    )));
+                }
+                if holder.is_none_or(running) {
+                    return Err(StatusError::Locked(format!(
+                        "{} is in use by {}, which is still running",
+                        path.display(),
+                        by
+                    )));
+                }
+                log::warn!("Taking over {} from {}, which is no longer running", lock.display(), by);
+                fs::remove_file(&lock)?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        // the previous owner may have removed the file between open and flock
+        if !is_current(&file, &lock) {
+            continue;
+        }
+        if let Some(pid) = holder(&mut file) {
+            log::info!("lock {} of pid {} was left behind, taking it over", lock.display(), pid);
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        log::debug!("locked {}", lock.display());
+        return Ok(FileLock { path: lock, file });
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // after a --force takeover the file belongs to the new owner
+        if is_current(&self.file, &self.path) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gpustat-lock-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A pid that no process has, for a lock whose owner is gone.
+    const GONE: i32 = 0x3fff_fff0;
+
+    #[test]
+    fn a_second_lock_is_refused_until_the_first_is_dropped() {
+        let path = scratch("second").join("gpus.json");
+        let lock = acquire(&path, false).unwrap();
+        let lock_file = path.with_file_name("gpus.json.lock");
+        assert_eq!(
+            fs::read_to_string(&lock_file).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+        let error = acquire(&path, false).unwrap_err().to_string();
+        assert!(
+            error.contains(&format!("in use by gpustat pid {}", std::process::id())),
+            "{}",
+            error
+        );
+        // a running holder is never taken over
+        let error = acquire(&path, true).unwrap_err().to_string();
+        assert!(error.ends_with("which is still running"), "{}", error);
+        drop(lock);
+        assert!(!lock_file.exists());
+        drop(acquire(&path, false).unwrap());
+    }
+
+    #[test]
+    fn locks_of_gone_owners_are_taken_over() {
+        let path = scratch("gone").join("state.json");
+        // left behind by a crash, unlocked
+        fs::write(lock_path(&path), format!("{}\n", GONE)).unwrap();
+        let first = acquire(&path, false).unwrap();
+        // still held, but by a pid that is not running here
+        fs::write(lock_path(&path), format!("{}\n", GONE)).unwrap();
+        assert!(acquire(&path, false).is_err());
+        let second = acquire(&path, true).unwrap();
+        // the first owner leaves the new lock file alone
+        drop(first);
+        assert_eq!(
+            fs::read_to_string(lock_path(&path)).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+        drop(second);
+        assert!(!lock_path(&path).exists());
+    }
+}
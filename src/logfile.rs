@@ -0,0 +1,312 @@
+//! `--log`: every snapshot appended to a JSON Lines file, gzip-compressed
+//! for `.gz` paths or with `--log-compress gzip`, and with `--log-rotate`
+//! split into one file per hour or day, e.g. `gpu-2026-10-14.jsonl.gz`.
+
+use crate::stats::Snapshot;
+use chrono::{DateTime, Local};
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogCompress {
+    None,
+    Gzip,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogRotate {
+    Hourly,
+    Daily,
+}
+
+/// Longest time compressed lines are held back before they reach the file,
+/// which is all a killed watcher loses.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What the end of a gzip file looks like after a sync flush while its
+/// stream is unfinished: an empty stored block.
+const SYNC_MARKER: [u8; 4] = [0, 0, 0xff, 0xff];
+
+enum Output {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+}
+
+pub struct LogSink {
+    path: PathBuf,
+    gzip: bool,
+    rotate: Option<LogRotate>,
+    /// The open file and the period it holds.
+    open: Option<(String, Output)>,
+    flushed: Instant,
+}
+
+/// `gpu.jsonl.gz` as `gpu-<suffix>.jsonl.gz`, the suffix before the extensions.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match name.find('.').filter(|&dot| dot > 0) {
+        Some(dot) => format!("{}-{}{}", &name[..dot], suffix, &name[dot..]),
+        None => format!("{}-{}", name, suffix),
+    };
+    path.with_file_name(name)
+}
+
+/// Whether a new gzip member can follow the end of `path`. A stream cut short
+/// by a kill ends at a flush, and decompressors would read the next member's
+/// header as more of it.
+fn member_complete(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let mut magic = [0; 2];
+    file.read_exact(&mut magic)?;
+    let mut end = [0; 4];
+    file.seek(SeekFrom::End(-4))?;
+    file.read_exact(&mut end)?;
+    Ok(magic == [0x1f, 0x8b] && end != SYNC_MARKER)
+}
+
+/// Whether a plain log ends in the middle of a line, as after a full disk.
+fn cut_line(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(false);
+    }
+    let mut last = [0];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last != *b"\n")
+}
+
+impl LogSink {
+    /// A sink for `path`, compressed when `compress` says so or, without it,
+    /// when the path ends in `.gz`. Nothing is opened before the first write.
+    pub fn new(path: &Path, compress: Option<LogCompress>, rotate: Option<LogRotate>) -> Self {
+        let gzip = match compress {
+            Some(compress) => compress == LogCompress::Gzip,
+            None => path.extension().is_some_and(|extension| extension == "gz"),
+        };
+        LogSink {
+            path: path.to_path_buf(),
+            gzip,
+            rotate,
+            open: None,
+            flushed: Instant::now(),
+        }
+    }
+
+    fn period(&self, timestamp: DateTime<Local>) -> String {
+        match self.rotate {
+            None => String::new(),
+            Some(LogRotate::Hourly) => timestamp.format("%Y-%m-%dT%H").to_string(),
+            Some(LogRotate::Daily) => timestamp.format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    fn open_output(&self, period: &str) -> io::Result<Output> {
+        let path = match period {
+            "" => self.path.clone(),
+            period => with_suffix(&self.path, period),
+        };
+        if !self.gzip {
+            let cut = cut_line(&path).unwrap_or(false);
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            if cut {
+                log::warn!("{} ends in the middle of a line, starting a new one", path.display());
+                file.write_all(b"\n")?;
+            }
+            return Ok(Output::Plain(file));
+        }
+        // a file that is unreadable or cut short is left as it is for zcat to
+        // read what it can, and the log goes on in gpu-1.jsonl.gz, gpu-2.jsonl.gz, ...
+        let (mut candidate, mut attempt) = (path.clone(), 0);
+        loop {
+            let file = match fs::metadata(&candidate) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    OpenOptions::new().write(true).create_new(true).open(&candidate)?
+                }
+                Ok(_) if member_complete(&candidate).unwrap_or(false) => {
+                    OpenOptions::new().append(true).open(&candidate)?
+                }
+                _ => {
+                    log::warn!("{} is incomplete, logging to the next file", candidate.display());
+                    attempt += 1;
+                    candidate = with_suffix(&path, &attempt.to_string());
+                    continue;
+                }
+            };
+            log::info!("logging to {}", candidate.display());
+            return Ok(Output::Gzip(GzEncoder::new(file, Compression::default())));
+        }
+    }
+
+    /// Append `snapshot` as one line, switching files when its timestamp
+    /// starts a new period.
+    pub fn write(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        let period = self.period(snapshot.timestamp);
+        if self.open.as_ref().map(|(open, _)| open) != Some(&period) {
+            self.close()?;
+            let output = self.open_output(&period)?;
+            self.open = Some((period, output));
+            self.flushed = Instant::now();
+        }
+        let line = serde_json::to_string(snapshot)? + "\n";
+        match &mut self.open {
+            Some((_, Output::Plain(file))) => file.write_all(line.as_bytes()),
+            Some((_, Output::Gzip(gzip))) => {
+                gzip.write_all(line.as_bytes())?;
+                if self.flushed.elapsed() >= FLUSH_INTERVAL {
+                    gzip.flush()?;
+                    self.flushed = Instant::now();
+                }
+                Ok(())
+            }
+            None => unreachable!("opened above"),
+        }
+    }
+
+    /// Finish the open file, ending a gzip stream with its trailer.
+    pub fn close(&mut self) -> io::Result<()> {
+        match self.open.take() {
+            Some((_, Output::Plain(file))) => file.sync_all(),
+            Some((_, Output::Gzip(gzip))) => gzip.finish()?.sync_all(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::prelude::*;
+    use flate2::read::MultiGzDecoder;
+
+    /// An empty directory for one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gpustat-log-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn snapshot(day: u32, hour: u32) -> Snapshot {
+        Snapshot {
+            hostname: String::from("node"),
+            timestamp: Local.with_ymd_and_hms(2026, 10, day, hour, 0, 0).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    /// The snapshots in a log, decompressing every member of a gzip one.
+    fn read(path: &Path) -> Vec<Snapshot> {
+        let file = File::open(path).unwrap();
+        let mut reader: Box<dyn Read> = match path.extension() {
+            Some(extension) if extension == "gz" => Box::new(MultiGzDecoder::new(file)),
+            _ => Box::new(file),
+        };
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn suffixes_go_before_the_extensions() {
+        assert_eq!(
+            with_suffix(Path::new("/var/log/gpu.jsonl.gz"), "2026-10-14"),
+            Path::new("/var/log/gpu-2026-10-14.jsonl.gz")
+        );
+        assert_eq!(with_suffix(Path::new("gpu"), "1"), Path::new("gpu-1"));
+        assert_eq!(with_suffix(Path::new(".log"), "1"), Path::new(".log-1"));
+    }
+
+    #[test]
+    fn daily_gzip_files_round_trip() {
+        let dir = scratch("daily");
+        let mut sink = LogSink::new(&dir.join("gpu.jsonl.gz"), None, Some(LogRotate::Daily));
+        for (day, hour) in [(13, 22), (13, 23), (14, 0)] {
+            sink.write(&snapshot(day, hour)).unwrap();
+        }
+        sink.close().unwrap();
+        // a restarted watcher appends a member to the finished file
+        let mut sink = LogSink::new(&dir.join("gpu.jsonl.gz"), None, Some(LogRotate::Daily));
+        sink.write(&snapshot(14, 1)).unwrap();
+        sink.close().unwrap();
+
+        let hours = |name: &str| {
+            read(&dir.join(name))
+                .iter()
+                .map(|s| s.timestamp.hour())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hours("gpu-2026-10-13.jsonl.gz"), [22, 23]);
+        assert_eq!(hours("gpu-2026-10-14.jsonl.gz"), [0, 1]);
+        assert_eq!(read(&dir.join("gpu-2026-10-14.jsonl.gz"))[0].hostname, "node");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn damaged_files_never_stop_the_log() {
+        let dir = scratch("damaged");
+        let path = dir.join("gpu.jsonl.gz");
+        fs::write(&path, b"not gzip at all").unwrap();
+        let mut sink = LogSink::new(&path, None, None);
+        sink.write(&snapshot(14, 1)).unwrap();
+        sink.close().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"not gzip at all");
+        assert_eq!(read(&dir.join("gpu-1.jsonl.gz")).len(), 1);
+
+        // a plain log cut in the middle of a line goes on with a new line
+        let path = dir.join("gpu.jsonl");
+        fs::write(&path, b"{\"hostname\":").unwrap();
+        let mut sink = LogSink::new(&path, None, None);
+        sink.write(&snapshot(14, 2)).unwrap();
+        sink.close().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let (cut, line) = text.split_once('\n').unwrap();
+        assert_eq!(cut, "{\"hostname\":");
+        assert_eq!(
+            serde_json::from_str::<Snapshot>(line.trim_end())
+                .unwrap()
+                .timestamp
+                .hour(),
+            2
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn flushed_streams_read_back_and_take_no_member() {
+        let dir = scratch("flushed");
+        let path = dir.join("gpu.jsonl.gz");
+        let mut gzip = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        gzip.write_all(b"{\"hostname\":\"node\"}\n").unwrap();
+        gzip.flush().unwrap();
+        // unfinished, as a killed watcher leaves it
+        let mut text = String::new();
+        let _ = MultiGzDecoder::new(File::open(&path).unwrap()).read_to_string(&mut text);
+        assert_eq!(text, "{\"hostname\":\"node\"}\n");
+        assert!(!member_complete(&path).unwrap());
+        gzip.finish().unwrap();
+        assert!(member_complete(&path).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn gzip_can_be_forced_or_switched_off() {
+        let dir = scratch("compress");
+        let path = dir.join("gpu.log");
+        let mut sink = LogSink::new(&path, Some(LogCompress::Gzip), None);
+        sink.write(&snapshot(14, 3)).unwrap();
+        sink.close().unwrap();
+        assert_eq!(fs::read(&path).unwrap()[..2], [0x1f, 0x8b]);
+        let plain = dir.join("gpu.jsonl.gz");
+        let mut sink = LogSink::new(&plain, Some(LogCompress::None), None);
+        sink.write(&snapshot(14, 3)).unwrap();
+        sink.close().unwrap();
+        assert!(fs::read_to_string(&plain).unwrap().starts_with('{'));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,188 +1,673 @@
 use chrono::prelude::*;
 use clap::Parser;
-use comfy_table::{Attribute, Cell, Color, ContentArrangement, Table};
-use nix::{unistd::{Uid, User}};
-use nvml_wrapper::{enum_wrappers::device::TemperatureSensor, enums::device::UsedGpuMemory, Nvml};
-use sysinfo::{Pid, PidExt, ProcessExt, ProcessRefreshKind, RefreshKind, System, SystemExt};
-use thiserror::Error;
-
-#[non_exhaustive]
-#[derive(Error, Debug)]
-pub enum StatusError {
-    #[error("Failed to parse hostname: {0}")]
-    IoError(#[from] std::io::Error),
-    #[error("Failed to convert string: {0}")]
-    Utf8Error(#[from] std::str::Utf8Error),
-    #[error("Failed to load nvml library: {0}")]
-    NvmlError(#[from] nvml_wrapper::error::NvmlError),
-    #[error("Failed to call nix call: {0}")]
-    NixError(#[from] nix::Error),
+use gpustat::{
+    allocation, app_clocks,
+    background::{self, Background},
+    banner, bell, bench, capabilities, chargeback, collection_errors, columns, completions, consistency, csv, doctor,
+    ecc, exit_code, explain, fixture, hostname, hosts, info, locale_separators, lock, logfile, minimal,
+    non_utf8_locale, pcie, pid_list, plan,
+    provider::Provider,
+    ready,
+    render::{self, format_timestamp, header_line, Palette, RenderOpts, VERSION_MISMATCH_HINT},
+    reset, snapshot_cache, sorting,
+    state::{self, SessionState},
+    stats,
+    version, write_atomic,
+    Command, Opts, StatusError, SNAPSHOT_SCHEMA, EXIT_DEGRADED, EXIT_FAIL_IF, EXIT_NO_DEVICES, EXIT_PID_NOT_FOUND,
+    EXIT_USAGE,
+};
+use nvml_wrapper::{error::NvmlError, Nvml};
+use std::{
+    env, fs,
+    io::{IsTerminal, Write},
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// GPUs per page when only `--page` is given.
+const DEFAULT_PAGE_SIZE: u16 = 8;
+
+/// Minimal logger writing `-v` diagnostics to stderr.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
 }
 
-#[derive(Parser, Debug)]
-#[command(author, version, about)]
-struct Opts {
-    #[arg(long, help = "Force colored output (even when stdout is not a tty)")]
-    color: bool,
-    #[arg(long, help = "Suppress colored output")]
-    no_color: bool,
-    // #[clap(short = 'u', long, about = "Display username of the process owner")]
-    // show_user: bool,
-    #[arg(short = 'c', long, help = "Display the process name")]
-    show_cmd: bool,
-    #[arg(short = 'f', long, help = "Display full command and cpu stats of running process")]
-    show_full_cmd: bool,
-    #[arg(short = 'p', long, help = "Display PID of the process")]
-    show_pid: bool,
-    #[arg(short = 'F', long, help = "Display GPU fan speed")]
-    show_fan: bool,
-    #[arg(short = 'e', long, help = "Display encoder and/or decoder utilization")]
-    show_codec: bool,
-    #[arg(short = 'a', long, help = "Display all gpu properties above")]
-    show_all: bool,
+/// Where snapshots come from: the GPUs, a `GPUSTAT_FIXTURE` recording, or
+/// the snapshot cache.
+enum Source {
+    Nvml(Arc<Nvml>),
+    Fixture(Arc<fixture::FixtureProvider>),
+    Cached(Box<stats::Snapshot>),
 }
 
-macro_rules! bold_limit {
-    ($value:ident, $limit:expr, $color:expr, $($arg:tt)*) => {{
-        let cell = Cell::new(format!($($arg)*)).fg($color);
-        if $value > $limit {
-            cell.add_attribute(Attribute::Bold)
-        } else {
-            cell
+impl Source {
+    /// What to collect from, `None` for a snapshot that is already there.
+    fn provider(&self) -> Option<Arc<dyn Provider>> {
+        match self {
+            Source::Nvml(nvml) => Some(Arc::clone(nvml) as Arc<dyn Provider>),
+            Source::Fixture(fixture) => Some(Arc::clone(fixture) as Arc<dyn Provider>),
+            Source::Cached(_) => None,
         }
-    }};
+    }
 }
 
-fn main() -> Result<(), StatusError> {
-    let opts: Opts = Opts::parse();
-    let localtime: DateTime<Local> = Local::now();
-
-    let mut table = Table::new();
+fn run(opts: &Arc<Opts>) -> Result<u8, StatusError> {
+    if opts.version_full {
+        for line in version::report(Nvml::init().as_ref()) {
+            println!("{}", line);
+        }
+        return Ok(0);
+    }
+    if opts.list_formats {
+        let width = render::FORMATS.iter().map(|(name, _)| name.len()).max().unwrap_or_default();
+        for (name, description) in render::FORMATS {
+            println!("{:width$}  {}", name, description, width = width);
+        }
+        return Ok(0);
+    }
+    if opts.list_csv_fields {
+        let width = csv::FIELDS.iter().map(|field| field.name.len()).max().unwrap_or_default();
+        println!("{:width$}  level    description", "field", width = width);
+        for field in csv::FIELDS {
+            let level = if field.process { "process" } else { "any" };
+            println!("{:width$}  {:7}  {}", field.name, level, field.description, width = width);
+        }
+        return Ok(0);
+    }
+    if opts.list_columns {
+        let width = columns::COLUMNS.iter().map(|column| column.name.len()).max().unwrap_or_default();
+        let flags = columns::COLUMNS.iter().map(|column| column.flags.len()).max().unwrap_or_default();
+        println!("{:width$}  {:flags$}  -a   description", "column", "flags", width = width, flags = flags);
+        for column in columns::COLUMNS {
+            let all = if column.in_all { "yes" } else { "no" };
+            println!(
+                "{:width$}  {:flags$}  {:3}  {}",
+                column.name,
+                column.flags,
+                all,
+                column.description,
+                width = width,
+                flags = flags
+            );
+        }
+        return Ok(0);
+    }
+    if opts.list_collectors {
+        let width = stats::COLLECTORS.iter().map(|collector| collector.name.len()).max().unwrap_or_default();
+        println!("{:width$}  default  description", "collector", width = width);
+        for collector in stats::COLLECTORS {
+            let default = if collector.by_default() { "on" } else { "off" };
+            println!("{:width$}  {:7}  {}", collector.name, default, collector.description, width = width);
+        }
+        return Ok(0);
+    }
+    if opts.dry_run {
+        if opts.command.is_some() {
+            eprintln!("--dry-run only plans the GPU table, leave out the subcommand");
+            return Ok(EXIT_USAGE);
+        }
+        return plan::run(opts);
+    }
+    match &opts.command {
+        Some(Command::Schema) => {
+            print!("{}", SNAPSHOT_SCHEMA);
+            return Ok(0);
+        }
+        Some(Command::Doctor) => return doctor::run(opts),
+        Some(Command::Man) => return gpustat::man::run(),
+        // a fresh cache answers without initializing NVML
+        Some(Command::Capabilities(capabilities)) if capabilities::print_cached(capabilities) => return Ok(0),
+        Some(Command::Completions(completions)) => return completions::run(completions),
+        Some(Command::Complete(complete)) => return completions::complete(complete),
+        #[cfg(feature = "check-update")]
+        Some(Command::CheckUpdate(check)) => return gpustat::update::run(check),
+        _ => {}
+    }
+    if let Some(path) = &opts.hosts_file {
+        return hosts::run(opts, path);
+    }
+    // before the hostname lookup, which --minimal skips
+    if opts.minimal && opts.command.is_none() {
+        return minimal::run(opts);
+    }
 
-    table
-        .load_preset("     ═  |          ")
-        .set_content_arrangement(ContentArrangement::Dynamic);
+    let fixture = match fixture::from_env() {
+        Some(Ok(fixture)) => Some(Arc::new(fixture)),
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            return Ok(EXIT_USAGE);
+        }
+        None => None,
+    };
+    let args: Vec<_> = env::args_os().skip(1).collect();
+    let cache = match (&fixture, opts.cache) {
+        (None, Some(_)) if opts.command.is_some() => {
+            eprintln!("--cache only caches the GPU table, leave out the subcommand");
+            return Ok(EXIT_USAGE);
+        }
+        (None, Some(ttl)) => match snapshot_cache::SnapshotCache::new(&args, ttl) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                log::warn!("Not using the cache: {}", e);
+                None
+            }
+        },
+        _ => None,
+    };
+    // a stale cache is refreshed by one run at a time, the others wait and read it
+    let (cached, mut cache_lock) = match cache.as_ref().map(snapshot_cache::SnapshotCache::fresh_or_lock) {
+        Some(Ok(cached)) => (Some(cached), None),
+        Some(Err(lock)) => (None, lock),
+        None => (None, None),
+    };
+    // a replayed fixture renders the same on any machine
+    let hostname = match &fixture {
+        Some(fixture) => fixture.hostname.clone(),
+        None => hostname(opts)?,
+    };
+    let hostname = hostname.as_str();
+    // with --allow-stale, a collection that fails shows the stale cache instead
+    let stale = |e: StatusError| {
+        let cached = cache.as_ref().filter(|_| opts.allow_stale).and_then(|cache| cache.stale(opts.max_age));
+        match cached {
+            Some(cached) => {
+                log::warn!("{}; showing the cached snapshot", e);
+                Ok(stats::Snapshot {
+                    hostname: hostname.to_string(),
+                    ..cached
+                })
+            }
+            None => Err(e),
+        }
+    };
+    // a fresh cache is shown without initializing NVML
+    let cached = cached.map(|cached| stats::Snapshot {
+        hostname: hostname.to_string(),
+        ..cached
+    });
+    let render_opts = RenderOpts::from(opts.as_ref());
+    let source = match (fixture, cached) {
+        (Some(_), _) if opts.command.is_some() => {
+            eprintln!("{} only replays the GPU table, unset it for subcommands", fixture::FIXTURE_ENV);
+            return Ok(EXIT_USAGE);
+        }
+        (Some(fixture), _) => Source::Fixture(fixture),
+        (None, Some(cached)) => Source::Cached(Box::new(cached)),
+        (None, None) => match Nvml::init() {
+            Err(NvmlError::LibRmVersionMismatch) => {
+                let timestamp = format_timestamp(&render_opts, Local::now());
+                if opts.quiet {
+                    eprintln!("{}", VERSION_MISMATCH_HINT);
+                } else {
+                    if let Some(header) = header_line(&render_opts, hostname, &timestamp, None) {
+                        println!("{}", header);
+                    }
+                    println!("{}", VERSION_MISMATCH_HINT);
+                }
+                return Ok(EXIT_DEGRADED);
+            }
+            Ok(nvml) => Source::Nvml(Arc::new(nvml)),
+            Err(e) => Source::Cached(Box::new(stale(StatusError::NvmlInitError(e))?)),
+        },
+    };
+    if let Source::Nvml(nvml) = &source {
+        if log::log_enabled!(log::Level::Debug) {
+            for line in version::report(Ok(nvml)) {
+                log::debug!("{}", line);
+            }
+        }
 
-    if opts.no_color {
-        table.force_no_tty();
-    } else if opts.color {
-        table.enforce_styling();
+        match &opts.command {
+            Some(Command::AppClocks(app_clocks)) => return app_clocks::run(app_clocks, nvml),
+            Some(Command::Bench(bench)) => return bench::run(opts, bench, nvml, hostname),
+            Some(Command::Capabilities(capabilities)) => return capabilities::run(opts, capabilities, nvml),
+            Some(Command::Ecc(ecc)) => return ecc::run(ecc, nvml),
+            Some(Command::Info(info)) => return info::run(info, nvml),
+            Some(Command::Pcie) => return pcie::run(opts, nvml),
+            Some(Command::Ready(ready)) => return ready::run(opts, ready, nvml),
+            Some(Command::RecordFixture(record)) => return fixture::record(opts, record, nvml, hostname),
+            Some(Command::Reset(reset)) => return reset::run(reset, nvml),
+            Some(Command::Schema) | Some(Command::Doctor) | Some(Command::Man) | None => {}
+            Some(Command::Completions(_)) | Some(Command::Complete(_)) => {}
+            #[cfg(feature = "check-update")]
+            Some(Command::CheckUpdate(_)) => {}
+        }
     }
+    let source = match (source, &cache) {
+        (Source::Nvml(nvml), Some(cache)) => {
+            match cache.refresh(opts, &(nvml as Arc<dyn Provider>), hostname, cache_lock.take()) {
+                Ok(snapshot) => Source::Cached(Box::new(snapshot)),
+                Err(e) => Source::Cached(Box::new(stale(e)?)),
+            }
+        }
+        (source, _) => source,
+    };
 
-    let nvml = Nvml::init()?;
-    let device_num = nvml.device_count()?;
+    let visible = opts.allocation_summary.then(allocation::Visible::from_env);
+    let user = allocation::current_user();
+    #[cfg(feature = "docker")]
+    let container = match &opts.container {
+        Some(name) => match gpustat::container::inspect(name) {
+            Ok(container) => Some(container),
+            Err(e) => {
+                eprintln!("Cannot inspect container {}: {}", name, e);
+                return Ok(EXIT_USAGE);
+            }
+        },
+        None => None,
+    };
 
-    let system = System::new_with_specifics(RefreshKind::new()
-        .with_processes(ProcessRefreshKind::new().with_user())
-        .with_users_list()
+    let mut previous: Option<stats::Snapshot> = None;
+    let registry = render::Registry::builtin(&render_opts);
+    let renderer = match registry.get(opts.format_name()) {
+        Some(renderer) => renderer,
+        None => {
+            let names: Vec<&str> = registry.names().collect();
+            eprintln!("Unknown format {}, expected one of {}", opts.format_name(), names.join(", "));
+            return Ok(EXIT_USAGE);
+        }
+    };
+    let misplaced = csv::misplaced_fields(&render_opts);
+    if opts.format_name() == "csv" && !misplaced.is_empty() {
+        eprintln!("--csv-fields {} need --csv-level process, they have values per process only", misplaced.join(","));
+        return Ok(EXIT_USAGE);
+    }
+    let baseline = match &opts.baseline {
+        // a recorded watch session holds one snapshot per line, compare against the last
+        Some(path) => match fs::read_to_string(path) {
+            Ok(jsonl) => Some(stats::Snapshot::from_jsonl(&jsonl)?),
+            Err(e) => {
+                eprintln!("Cannot read baseline {}: {}", path.display(), e);
+                return Ok(EXIT_USAGE);
+            }
+        },
+        None => None,
+    };
+    let resumed = match &opts.resume_state {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(jsonl) => stats::Snapshot::from_jsonl(&jsonl)?.busy,
+            // the first run starts the file
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                eprintln!("Cannot read state {}: {}", path.display(), e);
+                return Ok(EXIT_USAGE);
+            }
+        },
+        None => None,
+    };
+    let driver_version = match &source {
+        Source::Nvml(nvml) => nvml.sys_driver_version().ok(),
+        Source::Fixture(fixture) => fixture.sys_driver_version().ok(),
+        Source::Cached(cached) => cached.driver_version.clone(),
+    };
+    // a second watcher writing to the same files would interleave with this one
+    let _locks = match opts.interval {
+        Some(_) => opts
+            .output
+            .iter()
+            .chain(&opts.state_file)
+            .chain(&opts.log)
+            .map(|path| lock::acquire(path, opts.force))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+    let restored = opts
+        .state_file
+        .as_ref()
+        .and_then(|path| SessionState::load(path, hostname, driver_version.as_deref()));
+    let mut idle = opts.state_file.as_ref().map(|_| {
+        let activity = restored.as_ref().map(SessionState::fresh_activity).unwrap_or_default();
+        stats::IdleTracker::new(opts.busy_threshold, activity)
+    });
+    // a one-shot run only reads the idle times, the rest belongs to the watcher
+    let restored = restored.filter(|_| opts.interval.is_some());
+    // these are finished on the way out
+    let finished = opts.state_file.is_some() || opts.log.is_some();
+    // and the exit status tells whether exports were failing
+    #[cfg(feature = "otlp")]
+    let finished = finished || opts.otlp_endpoint.is_some();
+    if finished && opts.interval.is_some() {
+        state::catch_stop_signals();
+    }
+    let mut log = opts
+        .log
+        .as_ref()
+        .map(|path| logfile::LogSink::new(path, opts.log_compress, opts.log_rotate));
+    let resumed = restored.as_ref().and_then(|restored| restored.busy).or(resumed);
+    let mut busy = stats::BusyTracker::new(opts.busy_threshold, resumed);
+    let mut history = stats::HistoryStore::new(
+        usize::from(opts.throttle_window),
+        opts.reset_stats_every,
+        opts.reset_peaks_every,
     );
+    let mut period = opts.interval.unwrap_or_default();
+    let mut timed = opts.query_timeout.map(stats::TimedCollector::new);
+    let mut cache = stats::DeviceCache::default();
+    // the bell would only corrupt redirected output
+    let mut bell = Some(&opts.bell_on)
+        .filter(|conditions| !conditions.is_empty() && std::io::stdout().is_terminal())
+        .map(|conditions| bell::Bell::new(conditions.clone(), opts.bell_cooldown));
+    let mut collection_errors = collection_errors::ErrorCounter::default();
+    // NVML indices in the order of the previous refresh, for --sort
+    let mut sorted: Vec<u32> = vec![];
+    if opts.interval.is_some() {
+        state::catch_dump_signal();
+    }
+    if let Some(restored) = &restored {
+        history.restore_peaks(&restored.gpus);
+        collection_errors.restore(&restored.collection_errors, driver_version.as_deref());
+        if let Some(bell) = &mut bell {
+            bell.restore(&restored.bell);
+        }
+    }
+    #[cfg(feature = "otlp")]
+    let exporter = opts.otlp_endpoint.as_deref().map(gpustat::otlp::Exporter::new);
+    #[cfg(feature = "igpu")]
+    let mut igpus = gpustat::igpu::Sampler::default();
+    let provider = source.provider();
+    let mut saved = Instant::now();
+    loop {
+        let collect_start = Instant::now();
+        let mut snapshot = match (&source, provider.as_ref(), &mut timed) {
+            (Source::Cached(cached), _, _) => (**cached).clone(),
+            (_, Some(provider), Some(timed)) => timed.collect(opts, provider, hostname)?,
+            (_, Some(provider), None) => stats::collect_cached(opts, provider.as_ref(), &mut cache, hostname)?,
+            (_, None, _) => unreachable!("only a cached snapshot has no provider"),
+        };
+        #[cfg(feature = "igpu")]
+        if let Source::Nvml(_) = &source {
+            snapshot.igpus = igpus.sample();
+        }
+        let collect_time = collect_start.elapsed();
+        if opts.interval.is_some() {
+            collection_errors.observe(&snapshot);
+            snapshot.collection_errors = Some(collection_errors.counts());
+        }
+        // shares of the whole node, before any narrowing
+        if opts.chargeback {
+            snapshot.chargeback = Some(chargeback::Chargeback::of(&snapshot));
+        }
+        if opts.consistency_check {
+            consistency::check(&mut snapshot);
+        }
+        if opts.explain {
+            snapshot.legend = Some(explain::legend(opts));
+        }
+        #[cfg(feature = "otlp")]
+        if let Some(exporter) = &exporter {
+            exporter.export(&snapshot);
+        }
+        if opts.reverse_devices {
+            snapshot.gpus.reverse();
+        } else if !opts.device_order.is_empty() {
+            snapshot.reorder(&opts.device_order);
+        } else if let Some(key) = opts.sort {
+            sorting::sort(&mut snapshot.gpus, key, &opts.change_threshold, &sorted);
+            sorted = snapshot.gpus.iter().map(|gpu| gpu.index).collect();
+        }
+        #[cfg(feature = "docker")]
+        if let Some(container) = &container {
+            container.narrow(&mut snapshot);
+            if snapshot.gpus.is_empty() {
+                eprintln!("No GPUs visible to container {}", container.name);
+                return Ok(EXIT_USAGE);
+            }
+        }
+        if let Some(visible) = &visible {
+            allocation::summarize(&mut snapshot, visible, user.as_deref());
+        }
+        if let Some(previous) = &previous {
+            snapshot.track_deltas(previous);
+        }
+        if let Some(baseline) = &baseline {
+            snapshot.compare_with(baseline);
+        }
+        history.tick(&snapshot);
+        if opts.power_detail {
+            history.observe_power(&mut snapshot);
+        }
+        if opts.interval.is_some() {
+            history.observe_memory(&mut snapshot);
+            history.observe_process_memory(&mut snapshot);
+            if opts.oom_hints {
+                history.observe_oom(&mut snapshot);
+            }
+        }
+        if opts.show_busy {
+            busy.observe(&mut snapshot);
+        }
+        if opts.interval.is_some() && opts.column("throttle") {
+            history.observe_throttle(&mut snapshot);
+        }
+        match &mut idle {
+            Some(idle) if opts.interval.is_some() => idle.observe(&mut snapshot),
+            Some(idle) => idle.recall(&mut snapshot),
+            None => {}
+        }
 
-    for index in 0..device_num {
-        let device = nvml.device_by_index(index)?;
-        let device_name = device.name()?;
-        let device_memory = device.memory_info()?;
-        let device_processes = device.running_compute_processes()?;
-
-        let mut process_info = vec![];
-        for device_process in device_processes {
-            let process = system.process(Pid::from_u32(device_process.pid)).unwrap();
-            let user_id = process.user_id().expect("Unable to get UID!");
-            let user = User::from_uid(Uid::from(*user_id.to_owned()))?.unwrap();
-            let used = match device_process.used_gpu_memory {
-                UsedGpuMemory::Unavailable => String::from("Unavailable"),
-                UsedGpuMemory::Used(m) => {
-                    format!("{}M", m >> 20)
-                }
+        if !opts.pids.is_empty() && snapshot.gpus.iter().all(|gpu| gpu.processes.is_empty()) {
+            let pids: Vec<String> = opts.pids.iter().map(u32::to_string).collect();
+            eprintln!("No GPU is running process {}", pids.join(", "));
+            return Ok(EXIT_PID_NOT_FOUND);
+        }
+        if opts.pid_list {
+            return Ok(pid_list::print(opts, &snapshot));
+        }
+
+        if let Some(interval) = opts.interval {
+            period = match &opts.adaptive_interval {
+                Some(adaptive) => adaptive.next(period, &snapshot, previous.as_ref(), &opts.change_threshold),
+                None => interval,
             };
+            snapshot.interval = Some(period);
+        }
 
-            let info = {
-                let mut s = user.name;
-                if opts.show_full_cmd || opts.show_all {
-                    s = s + ":" + &process.cmd().join(" ");
-                } else if opts.show_cmd {
-                    s = s + ":" + process.name();
+        let mut shown = snapshot.clone();
+        if opts.page.is_some() || opts.page_size.is_some() {
+            let page = usize::from(opts.page.unwrap_or(1));
+            shown.paginate(page, usize::from(opts.page_size.unwrap_or(DEFAULT_PAGE_SIZE)));
+        }
+        if opts.changes_only {
+            shown.gpus.retain(|gpu| history.changed_since_printed(gpu, &opts.change_threshold));
+        }
+
+        let render_start = Instant::now();
+        let text = if opts.changes_only && shown.gpus.is_empty() {
+            String::new()
+        } else {
+            renderer.render(&shown, &render_opts)
+        };
+        let text = if opts.format_name() == "table" && banner::requested(opts) && !text.is_empty() {
+            banner::render(opts, &render_opts) + &text
+        } else {
+            text
+        };
+        let text = match &shown.legend {
+            Some(legend) if opts.format_name() == "table" => text + &explain::text(legend),
+            _ => text,
+        };
+        if opts.timing {
+            eprintln!(
+                "timing: collection {:.2?}, rendering {:.2?}",
+                collect_time,
+                render_start.elapsed()
+            );
+        }
+
+        match &opts.output {
+            Some(path) => {
+                if let Err(e) = write_atomic(path, &text) {
+                    if opts.interval.is_none() {
+                        return Err(e.into());
+                    }
+                    eprintln!("Failed to write {}: {}", path.display(), e);
                 }
-                if opts.show_pid || opts.show_all {
-                    s = s + "/" + &device_process.pid.to_string();
+            }
+            None if opts.quiet => {}
+            None => {
+                if opts.interval.is_some() && !opts.changes_only && std::io::stdout().is_terminal() {
+                    print!("\x1b[2J\x1b[H");
                 }
-                s
-            };
-            process_info.push(format!("{}({})", info, used));
+                print!("{}", text);
+                if let Some(bell) = &mut bell {
+                    let ring = bell.observe(&snapshot);
+                    for condition in &ring {
+                        log::info!("bell: {}", condition);
+                    }
+                    if !ring.is_empty() {
+                        print!("\x07");
+                    }
+                }
+                std::io::stdout().flush()?;
+            }
         }
 
-        let temperature = device.temperature(TemperatureSensor::Gpu)?; // 50
-        let util_rates = device.utilization_rates()?.gpu; // 30
+        if let (Some(log), Some(path)) = (&mut log, &opts.log) {
+            let logged = log.write(&snapshot).and_then(|_| match opts.interval {
+                Some(_) => Ok(()),
+                None => log.close(),
+            });
+            if let Err(e) = logged {
+                if opts.interval.is_none() {
+                    return Err(e.into());
+                }
+                eprintln!("Failed to log to {}: {}", path.display(), e);
+            }
+        }
 
-        let device_memory_rates = device_memory.used as f64 / device_memory.total as f64; // 50
+        match opts.interval {
+            Some(_) => {
+                previous = Some(snapshot);
+                let save = |path: &std::path::Path| {
+                    let driver_version = driver_version.as_deref();
+                    let state = SessionState::capture(
+                        hostname,
+                        driver_version,
+                        &history,
+                        busy.total(),
+                        bell.as_ref(),
+                        idle.as_ref(),
+                        collection_errors.counts(),
+                    );
+                    if let Err(e) = state.save(path) {
+                        log::warn!("Cannot save state {}: {}", path.display(), e);
+                    }
+                };
+                if let Some(path) = opts.state_file.as_deref().filter(|_| saved.elapsed() >= state::STATE_SAVE_INTERVAL) {
+                    save(path);
+                    saved = Instant::now();
+                }
+                state::sleep_unless_stopped(Duration::from_secs_f64(period), || {
+                    eprint!("{}", collection_errors::report(&collection_errors.counts(), opts.ascii));
+                });
+                if state::stop_requested() {
+                    if let Some(path) = &opts.state_file {
+                        save(path);
+                    }
+                    if let (Some(log), Some(path)) = (&mut log, &opts.log) {
+                        if let Err(e) = log.close() {
+                            eprintln!("Failed to finish {}: {}", path.display(), e);
+                        }
+                    }
+                    #[cfg(feature = "otlp")]
+                    if exporter.as_ref().is_some_and(|exporter| exporter.failing()) {
+                        return Ok(gpustat::otlp::EXIT_EXPORT_FAILED);
+                    }
+                    return Ok(0);
+                }
+            }
+            None if snapshot.reason.as_deref() == Some(stats::NO_DEVICES) => {
+                // the table and JSON carry it already
+                let shown = matches!(opts.format_name(), "table" | "json");
+                if let Some(line) = render::no_devices_line(&render_opts, &snapshot).filter(|_| !shown) {
+                    eprintln!("{}", line);
+                }
+                return Ok(EXIT_NO_DEVICES);
+            }
+            None if snapshot.degraded() => return Ok(EXIT_DEGRADED),
+            None => {
+                let mut failed = false;
+                for gpu in &snapshot.gpus {
+                    for condition in opts.fail_if.iter().filter(|condition| condition.matches(gpu)) {
+                        eprintln!("GPU {} matches --fail-if {}", gpu.order_index.unwrap_or(gpu.index), condition);
+                        failed = true;
+                    }
+                }
+                return Ok(if failed { EXIT_FAIL_IF } else { 0 });
+            }
+        }
+    }
+}
 
-        let temperature_cell = bold_limit!(temperature, 50, Color::Red, "{}°C", temperature);
-        let utilization_cell = bold_limit!(util_rates, 30, Color::Green, "{} %", util_rates);
+fn main() -> ExitCode {
+    let mut opts = match Opts::try_parse() {
+        Ok(opts) => opts,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() {
+                ExitCode::from(EXIT_USAGE)
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
 
-        let mut row = vec![
-            Cell::new(format!("[{}]", index)).fg(Color::DarkCyan), // index
-            Cell::new(device_name).fg(Color::DarkBlue),            // gpu type name
-            temperature_cell,
-            utilization_cell,
-        ];
+    opts.ascii = opts.ascii || (!opts.no_ascii && non_utf8_locale());
+    if opts.locale_numbers {
+        (opts.digit_separator, opts.decimal_separator) = locale_separators();
+        opts.group_digits = true;
+    }
+    if let Some(adaptive) = opts.adaptive_interval {
+        opts.interval = Some(adaptive.min);
+    }
 
-        if opts.show_fan || opts.show_all {
-            let fan_color = Color::Rgb {
-                r: 255,
-                g: 0,
-                b: 255,
-            };
-            let fan_rates = device.fan_speed(0)?; // 50
-            let fan_cell = bold_limit!(fan_rates, 50, fan_color, "F: {} %", fan_rates);
-            row.push(fan_cell);
-        }
-
-        if opts.show_codec || opts.show_all {
-            let en_util_rates = device.encoder_utilization()?.utilization; // 30
-            let de_util_rates = device.decoder_utilization()?.utilization; // 30
-
-            let encoder_cell =
-                bold_limit!(en_util_rates, 30, Color::Cyan, "E: {} %", en_util_rates);
-            let decoder_cell =
-                bold_limit!(de_util_rates, 30, Color::Cyan, "D: {} %", de_util_rates);
-
-            row.push(encoder_cell);
-            row.push(decoder_cell);
-        }
-
-        let pow_usage = device.power_usage()?;
-        let pow_limit = device.power_management_limit()?;
-        let pow_rates = pow_usage as f32 / pow_limit as f32; // 50
-        let pow_cell = bold_limit!(
-            pow_rates,
-            0.5,
-            Color::DarkMagenta,
-            "{} / {} W",
-            pow_usage / 1000,
-            pow_limit / 1000
-        );
-        let memory_cell = bold_limit!(
-            device_memory_rates,
-            0.5,
-            Color::Yellow,
-            "{} / {} MB",
-            device_memory.used >> 20,
-            device_memory.total >> 20
-        );
-
-        row.push(pow_cell);
-        row.push(memory_cell);
-        row.push(Cell::new(process_info.join(",")).fg(Color::DarkYellow));
-
-        table.add_row(row);
-    }
-
-    println!(
-        "{}\t{}\t{}",
-        hostname::get()?.to_str().unwrap_or_default(),
-        localtime.format("%Y-%m-%d %H:%M:%S"),
-        nvml.sys_driver_version()?
-    );
-    println!("{}", table);
+    log::set_logger(&StderrLogger).expect("logger is only set once");
+    log::set_max_level(match opts.verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        _ => log::LevelFilter::Debug,
+    });
+    log::debug!("{:?}", opts);
+    for warning in opts.format_warnings() {
+        log::warn!("{}", warning);
+    }
+    // asked once, so a watch session keeps its colors
+    let dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+    if opts.palette == Palette::Auto
+        && opts.command.is_none()
+        && opts.format_name() == "table"
+        && std::io::stdout().is_terminal()
+        && render::styling_enabled(&opts)
+        && !dumb
+        && background::detect() == Some(Background::Light)
+    {
+        opts.palette = Palette::Light;
+    }
 
-    Ok(())
+    let opts = Arc::new(opts);
+    match run(&opts) {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            if opts.format_name() == "json" {
+                // scripts parsing stdout get the failure in the same format
+                println!("{}", serde_json::json!({ "error": e.failure() }));
+            } else {
+                eprintln!("gpustat: {}", e);
+            }
+            ExitCode::from(exit_code(&e))
+        }
+    }
 }
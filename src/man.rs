@@ -0,0 +1,175 @@
+//! `gpustat man`: a gpustat(1) man page in roff, rendered by clap_mangen
+//! from the clap definition so it lists exactly the options `--help` does,
+//! plus the EXAMPLES and EXIT STATUS sections kept here.
+
+use crate::{Opts, EXIT_CODES_HELP};
+use clap::CommandFactory;
+
+/// Worked examples for the man page and `--help`, as a command line and what it does.
+pub const EXAMPLES: &[(&str, &str)] = &[
+    (
+        "gpustat -i 2 -a",
+        "Refresh every 2 seconds with every column -a covers; --list-columns tells which those are.",
+    ),
+    (
+        "gpustat -a --columns xid,handles",
+        "Every -a column plus the Xid and device handle columns, which -a leaves out because they read the kernel log and /proc.",
+    ),
+    (
+        "gpustat -i 5 --bell-on 'temp>90' --bell-on procs==0",
+        "Ring the terminal bell when a GPU gets hotter than 90°C or its last process exits, at most once a minute per condition.",
+    ),
+    (
+        "gpustat -i 1 --changes-only --change-threshold util=10,temp=5",
+        "Print a GPU only when its utilization moved 10 points or its temperature 5°C since it was last printed, for a scrolling log.",
+    ),
+    (
+        "gpustat --match 'train\\.py|finetune' --match-only",
+        "Show only the processes whose command line matches, marked with *, to find one job on a shared node.",
+    ),
+    (
+        "gpustat -i 10 --format json -o /run/gpustat.json",
+        "Keep a JSON snapshot for other tools, replaced atomically every 10 seconds; `gpustat schema` describes it.",
+    ),
+    (
+        "gpustat -i 60 --show-busy --state-file /var/lib/gpustat/state.json",
+        "A long-running watcher whose peaks, GPU-hours and idle times survive restarts; a one-shot `gpustat --state-file` on the same path shows the idle times.",
+    ),
+    (
+        "gpustat ready --id 0 --require healthy,idle",
+        "Exit non-zero unless GPU 0 is healthy and runs no process, before a scheduler hands it out.",
+    ),
+];
+
+/// The examples as the end of `--help`, followed by the exit status.
+pub fn long_help_footer() -> String {
+    let mut text = String::from("Examples:\n");
+    for (command, description) in EXAMPLES {
+        text += &format!("  {}\n      {}\n", command, description);
+    }
+    text + "\n" + EXIT_CODES_HELP
+}
+
+/// Escape text for roff, which treats backslashes specially and a leading
+/// `.` or `'` as a request.
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e");
+    match text.chars().next() {
+        Some('.' | '\'') => format!("\\&{}", text),
+        _ => text,
+    }
+}
+
+/// What the DESCRIPTION section says besides the one-line about.
+const DESCRIPTION: &str = "Shows the temperature, utilization, power and memory of every NVIDIA GPU \
+                           and the processes using it, once or in watch mode with -i.";
+
+/// A section clap_mangen renders.
+type Section = fn(&clap_mangen::Man, &mut dyn std::io::Write) -> std::io::Result<()>;
+
+/// The whole page: the title, name, synopsis, description, options and
+/// commands rendered by clap_mangen from the clap definition, then the
+/// sections kept here.
+pub fn page() -> String {
+    let command = Opts::command();
+    let about = command.get_about().map(|about| about.to_string()).unwrap_or_default();
+    let command = command.long_about(format!("{}\n\n{}", about, DESCRIPTION));
+    let man = clap_mangen::Man::new(command).title("GPUSTAT").manual("User Commands");
+    let sections: [Section; 6] = [
+        clap_mangen::Man::render_title,
+        clap_mangen::Man::render_name_section,
+        clap_mangen::Man::render_synopsis_section,
+        clap_mangen::Man::render_description_section,
+        clap_mangen::Man::render_options_section,
+        clap_mangen::Man::render_subcommands_section,
+    ];
+    let mut roff = String::new();
+    for section in sections {
+        let mut rendered = vec![];
+        section(&man, &mut rendered).expect("writing to a Vec cannot fail");
+        let rendered = String::from_utf8_lossy(&rendered);
+        // every section starts with the same preamble, which the page needs once
+        match rendered.find(".TH ").or_else(|| rendered.find(".SH ")) {
+            Some(start) if !roff.is_empty() => roff += &rendered[start..],
+            _ => roff += &rendered,
+        }
+    }
+    roff += "See \\fBgpustat \\fICOMMAND\\fB \\-\\-help\\fR for the options of each.\n";
+
+    roff += ".SH EXAMPLES\n";
+    for (line, description) in EXAMPLES {
+        roff += &format!(".PP\n{}\n.RS\n.nf\n\\fB{}\\fR\n.fi\n.RE\n", escape(description), escape(line).replace('-', "\\-"));
+    }
+
+    roff += ".SH EXIT STATUS\n";
+    for line in EXIT_CODES_HELP.lines().skip(1) {
+        if let Some((code, meaning)) = line.trim().split_once(' ') {
+            roff += &format!(".TP\n\\fB{}\\fR\n{}\n", code, escape(meaning.trim()));
+        }
+    }
+
+    roff += ".SH SEE ALSO\n\\fBnvidia\\-smi\\fR(1)\n";
+    roff
+}
+
+/// Write the page to stdout, e.g. for `gpustat man | man -l -`.
+pub fn run() -> Result<u8, crate::StatusError> {
+    print!("{}", page());
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    /// Split a command line as a shell does, for the single quotes the examples use.
+    fn words(line: &str) -> Vec<String> {
+        let mut words = vec![];
+        let mut word: Option<String> = None;
+        let mut quoted = false;
+        for c in line.chars() {
+            match c {
+                '\'' => {
+                    quoted = !quoted;
+                    word.get_or_insert_with(String::new);
+                }
+                ' ' if !quoted => words.extend(word.take()),
+                c => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        assert!(!quoted, "unbalanced quotes in {}", line);
+        words.extend(word);
+        words
+    }
+
+    #[test]
+    fn examples_parse() {
+        for (line, _) in EXAMPLES {
+            let words = words(line);
+            assert_eq!(words[0], "gpustat");
+            if let Err(e) = Opts::try_parse_from(&words) {
+                panic!("{} does not parse: {}", line, e);
+            }
+        }
+    }
+
+    #[test]
+    fn page_has_every_section() {
+        let page = page();
+        assert!(page.starts_with(".ie") && page.matches(".TH ").count() == 1);
+        for section in [
+            "NAME",
+            "SYNOPSIS",
+            "DESCRIPTION",
+            "OPTIONS",
+            "SUBCOMMANDS",
+            "EXAMPLES",
+            "EXIT STATUS",
+        ] {
+            assert!(page.contains(&format!(".SH {}\n", section)), "no {} section", section);
+        }
+        assert!(page.contains("\\fB\\-a\\fR, \\fB\\-\\-show\\-all\\fR"));
+        assert!(page.contains(".TP\n\\fB12\\fR\n"));
+    }
+}
@@ -0,0 +1,71 @@
+//! `--minimal`: the utilization and memory of every GPU and nothing else, for
+//! status bars that run gpustat every few seconds. The hostname, the sysinfo
+//! process table and every per-device query but two are skipped.
+
+use crate::{
+    render::{self, RenderOpts},
+    stats::{self, Snapshot},
+    template, write_atomic, Opts, StatusError, EXIT_DEGRADED, EXIT_NO_DEVICES, EXIT_USAGE,
+};
+use nvml_wrapper::Nvml;
+use std::time::Instant;
+
+/// The `--oneline-format` placeholders `--minimal` fills in.
+pub const MINIMAL_FIELDS: &[&str] = &["index", "util", "mem", "mem_total"];
+
+/// Whether a minimal snapshot has a GPU that could not be queried. Without a
+/// driver version it cannot tell a version mismatch.
+fn degraded(snapshot: &Snapshot) -> bool {
+    snapshot.gpus.iter().any(|gpu| gpu.reset_required || gpu.error.is_some())
+}
+
+pub fn run(opts: &Opts) -> Result<u8, StatusError> {
+    let format = opts.format_name();
+    if format != "table" && format != "oneline" {
+        eprintln!("--minimal prints the table and oneline formats, not {}", format);
+        return Ok(EXIT_USAGE);
+    }
+    if let Some(oneline_format) = opts.oneline_format.as_deref().filter(|_| format == "oneline") {
+        if let Err(e) = template::validate(oneline_format, MINIMAL_FIELDS) {
+            eprintln!("--minimal: {}", e);
+            return Ok(EXIT_USAGE);
+        }
+    }
+
+    let init_start = Instant::now();
+    let nvml = Nvml::init().map_err(StatusError::NvmlInitError)?;
+    let init_time = init_start.elapsed();
+    let collect_start = Instant::now();
+    let snapshot = stats::collect_minimal(opts, &nvml)?;
+    let collect_time = collect_start.elapsed();
+    let render_start = Instant::now();
+    let render_opts = RenderOpts::from(opts);
+    let text = if format == "oneline" {
+        render::render_oneline(&render_opts, &snapshot)
+    } else {
+        render::render_minimal(&render_opts, &snapshot)
+    };
+    if opts.timing {
+        eprintln!(
+            "timing: nvml init {:.2?}, collection {:.2?}, rendering {:.2?}",
+            init_time,
+            collect_time,
+            render_start.elapsed()
+        );
+    }
+
+    match &opts.output {
+        Some(path) => write_atomic(path, &text)?,
+        None if opts.quiet => {}
+        None => print!("{}", text),
+    }
+    if snapshot.reason.as_deref() == Some(stats::NO_DEVICES) {
+        if format == "oneline" {
+            if let Some(line) = render::no_devices_line(&render_opts, &snapshot) {
+                eprintln!("{}", line);
+            }
+        }
+        return Ok(EXIT_NO_DEVICES);
+    }
+    Ok(if degraded(&snapshot) { EXIT_DEGRADED } else { 0 })
+}
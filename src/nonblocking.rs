@@ -0,0 +1,109 @@
+//! Collection for async runtimes, behind the `async` feature.
+//!
+//! NVML and sysinfo calls block, so they run on tokio's blocking thread pool.
+
+use crate::{
+    stats::{self, DeviceCache, Snapshot},
+    Opts, StatusError,
+};
+use futures_core::Stream;
+use nvml_wrapper::Nvml;
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    task::{JoinError, JoinHandle},
+    time::{self, Interval, MissedTickBehavior},
+};
+
+fn join_error(e: JoinError) -> StatusError {
+    StatusError::IoError(io::Error::other(e))
+}
+
+/// Collect one snapshot without blocking the runtime threads.
+pub async fn collect_snapshot_async(
+    opts: Arc<Opts>,
+    nvml: Arc<Nvml>,
+    hostname: String,
+) -> Result<Snapshot, StatusError> {
+    tokio::task::spawn_blocking(move || stats::collect(&opts, &*nvml, &hostname))
+        .await
+        .map_err(join_error)?
+}
+
+/// NVML with the device cache that belongs to it.
+type Session = (Nvml, DeviceCache);
+
+type Collection = (Option<Session>, Result<Snapshot, StatusError>);
+
+/// Snapshots collected every `period`.
+///
+/// A failed collection is yielded as an error and NVML is initialized again,
+/// with an empty device cache, on the next tick, so the stream recovers from
+/// a driver restart.
+pub struct SnapshotStream {
+    opts: Arc<Opts>,
+    hostname: String,
+    session: Option<Session>,
+    interval: Interval,
+    task: Option<JoinHandle<Collection>>,
+}
+
+impl SnapshotStream {
+    /// Must be called from within a tokio runtime.
+    pub fn new(opts: Arc<Opts>, hostname: String, period: Duration) -> Self {
+        let mut interval = time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        SnapshotStream {
+            opts,
+            hostname,
+            session: None,
+            interval,
+            task: None,
+        }
+    }
+}
+
+impl Stream for SnapshotStream {
+    type Item = Result<Snapshot, StatusError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(task) = &mut this.task {
+                let (session, result) = match Pin::new(task).poll(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Ok(collection)) => collection,
+                    Poll::Ready(Err(e)) => (None, Err(join_error(e))),
+                };
+                this.task = None;
+                this.session = session;
+                return Poll::Ready(Some(result));
+            }
+
+            if this.interval.poll_tick(cx).is_pending() {
+                return Poll::Pending;
+            }
+            let opts = Arc::clone(&this.opts);
+            let hostname = this.hostname.clone();
+            let session = this.session.take();
+            this.task = Some(tokio::task::spawn_blocking(move || {
+                let (nvml, mut cache) = match session {
+                    Some(session) => session,
+                    None => match Nvml::init() {
+                        Ok(nvml) => (nvml, DeviceCache::default()),
+                        Err(e) => return (None, Err(StatusError::NvmlInitError(e))),
+                    },
+                };
+                let result = stats::collect_cached(&opts, &nvml, &mut cache, &hostname);
+                // drop the handle after a failure so the next tick starts afresh
+                (Some((nvml, cache)).filter(|_| result.is_ok()), result)
+            }));
+        }
+    }
+}
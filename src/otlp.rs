@@ -0,0 +1,368 @@
+//! `--otlp-endpoint`: export every watch mode tick as OTLP gauges over gRPC,
+//! behind the `otlp` feature.
+//!
+//! Each tick becomes an `ExportMetricsServiceRequest`, sent with the tonic
+//! client of the OTLP metrics service. A worker thread runs the client on
+//! its own runtime, so a slow or unreachable collector never holds up
+//! collection.
+
+use crate::stats::{GpuStat, ProcessStat, Snapshot};
+use opentelemetry_proto::tonic::{
+    collector::metrics::v1::{metrics_service_client::MetricsServiceClient, ExportMetricsServiceRequest},
+    common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue},
+    metrics::v1::{metric::Data, number_data_point, Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics},
+    resource::v1::Resource,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use tokio::runtime::Runtime;
+use tonic::transport::{Channel, ClientTlsConfig};
+
+/// Watch mode ended while the latest export was failing.
+pub const EXIT_EXPORT_FAILED: u8 = 12;
+
+/// First wait after a failed export, doubled on each failure in a row.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Longest a single export may take.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Check that an `--otlp-endpoint` is an http or https URL, without a trailing slash.
+pub fn parse_endpoint(endpoint: &str) -> Result<String, String> {
+    if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+        return Err(String::from(
+            "expected an http:// or https:// URL, e.g. http://collector:4317",
+        ));
+    }
+    let endpoint = endpoint.trim_end_matches('/');
+    Channel::from_shared(endpoint.to_string()).map_err(|e| e.to_string())?;
+    Ok(endpoint.to_string())
+}
+
+fn attribute(key: &str, value: any_value::Value) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue { value: Some(value) }),
+        ..Default::default()
+    }
+}
+
+fn string(value: &str) -> any_value::Value {
+    any_value::Value::StringValue(value.to_string())
+}
+
+/// A `Metric` holding one gauge, with a data point per value.
+fn gauge(name: &str, description: &str, unit: &str, time: u64, points: Vec<(f64, Vec<KeyValue>)>) -> Metric {
+    let data_points = points
+        .into_iter()
+        .map(|(value, attributes)| NumberDataPoint {
+            attributes,
+            time_unix_nano: time,
+            value: Some(number_data_point::Value::AsDouble(value)),
+            ..Default::default()
+        })
+        .collect();
+    Metric {
+        name: name.to_string(),
+        description: description.to_string(),
+        unit: unit.to_string(),
+        data: Some(Data::Gauge(Gauge { data_points })),
+        ..Default::default()
+    }
+}
+
+fn process_attributes(process: &ProcessStat) -> Vec<KeyValue> {
+    vec![
+        attribute("process.pid", any_value::Value::IntValue(i64::from(process.pid))),
+        attribute("process.owner", string(&process.user)),
+        attribute("process.executable.name", string(&process.command)),
+    ]
+}
+
+/// The `ResourceMetrics` of one GPU.
+fn gpu_metrics(hostname: &str, gpu: &GpuStat, time: u64) -> ResourceMetrics {
+    let one = |name, description, unit, value| gauge(name, description, unit, time, vec![(value, vec![])]);
+    let mut metrics = vec![
+        one(
+            "gpu.utilization",
+            "GPU utilization",
+            "1",
+            f64::from(gpu.utilization) / 100.0,
+        ),
+        one("gpu.memory.used", "Used GPU memory", "By", gpu.memory_used as f64),
+        one(
+            "gpu.memory.total",
+            "Installed GPU memory",
+            "By",
+            gpu.memory_total as f64,
+        ),
+        one(
+            "gpu.temperature",
+            "GPU core temperature",
+            "Cel",
+            f64::from(gpu.temperature),
+        ),
+        one(
+            "gpu.power.usage",
+            "Power draw",
+            "W",
+            f64::from(gpu.power_usage) / 1000.0,
+        ),
+        one(
+            "gpu.power.limit",
+            "Power management limit",
+            "W",
+            f64::from(gpu.power_limit) / 1000.0,
+        ),
+    ];
+    if let Some(fan_speed) = gpu.fan_speed {
+        metrics.push(one("gpu.fan.speed", "Fan speed", "1", f64::from(fan_speed) / 100.0));
+    }
+    let processes: Vec<(f64, Vec<KeyValue>)> = gpu
+        .processes
+        .iter()
+        .filter(|process| !process.stale)
+        .filter_map(|process| Some((process.used_memory? as f64, process_attributes(process))))
+        .collect();
+    if !processes.is_empty() {
+        metrics.push(gauge(
+            "gpu.process.memory.used",
+            "GPU memory used by a process",
+            "By",
+            time,
+            processes,
+        ));
+    }
+    let index = gpu.order_index.unwrap_or(gpu.index);
+    ResourceMetrics {
+        resource: Some(Resource {
+            attributes: vec![
+                attribute("host.name", string(hostname)),
+                attribute("gpu.uuid", string(&gpu.uuid)),
+                attribute("gpu.index", any_value::Value::IntValue(i64::from(index))),
+            ],
+            ..Default::default()
+        }),
+        scope_metrics: vec![ScopeMetrics {
+            scope: Some(InstrumentationScope {
+                name: String::from("gpustat"),
+                version: String::from(env!("CARGO_PKG_VERSION")),
+                ..Default::default()
+            }),
+            metrics,
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+/// The `ExportMetricsServiceRequest` of a snapshot, one resource per measured GPU.
+fn export_request(snapshot: &Snapshot) -> ExportMetricsServiceRequest {
+    let collected = snapshot.collected_at.unwrap_or(snapshot.timestamp);
+    let time = collected.timestamp_nanos_opt().unwrap_or_default() as u64;
+    ExportMetricsServiceRequest {
+        resource_metrics: snapshot
+            .gpus
+            .iter()
+            .filter(|gpu| gpu.measured())
+            .map(|gpu| gpu_metrics(&snapshot.hostname, gpu, time))
+            .collect(),
+    }
+}
+
+/// A client of the collector at `endpoint`, which connects on the first
+/// export and again after the connection drops.
+fn client(endpoint: &str, runtime: &Runtime) -> Result<MetricsServiceClient<Channel>, String> {
+    let _entered = runtime.enter();
+    let mut channel = Channel::from_shared(endpoint.to_string())
+        .map_err(|e| e.to_string())?
+        .timeout(EXPORT_TIMEOUT)
+        .connect_timeout(EXPORT_TIMEOUT)
+        .user_agent(concat!("gpustat/", env!("CARGO_PKG_VERSION")))
+        .map_err(|e| e.to_string())?;
+    if endpoint.starts_with("https://") {
+        channel = channel
+            .tls_config(ClientTlsConfig::new().with_webpki_roots())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(MetricsServiceClient::new(channel.connect_lazy()))
+}
+
+/// Send one request, failing on a transport error or a gRPC error status.
+async fn export(
+    client: &mut MetricsServiceClient<Channel>,
+    request: &ExportMetricsServiceRequest,
+) -> Result<(), String> {
+    let response = client.export(request.clone()).await.map_err(|status| {
+        // the innermost cause names what failed, e.g. a refused connection
+        let mut cause: Option<&dyn std::error::Error> = None;
+        let mut next = std::error::Error::source(&status);
+        while let Some(source) = next {
+            cause = Some(source);
+            next = source.source();
+        }
+        let cause = cause.map(|cause| format!(" ({})", cause)).unwrap_or_default();
+        format!("{:?}: {}{}", status.code(), status.message(), cause)
+    })?;
+    if let Some(partial) = response.into_inner().partial_success {
+        if partial.rejected_data_points > 0 {
+            log::warn!(
+                "otlp: the collector rejected {} data points: {}",
+                partial.rejected_data_points,
+                partial.error_message
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Send requests until the exporter is dropped. A request that fails is
+/// retried with backoff, unless a newer tick supersedes it since gauges only
+/// keep their latest value. The first failure in a row and the recovery are
+/// reported on stderr; `failing` tells whether the latest attempt failed.
+fn export_loop(endpoint: String, requests: Receiver<ExportMetricsServiceRequest>, failing: Arc<AtomicBool>) {
+    let started = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())
+        .and_then(|runtime| {
+            // the lazy channel spawns its worker on the runtime
+            let client = client(&endpoint, &runtime)?;
+            Ok((runtime, client))
+        });
+    let (runtime, mut client) = match started {
+        Ok(started) => started,
+        Err(e) => {
+            eprintln!("OTLP exporter to {} failed to start: {}", endpoint, e);
+            failing.store(true, Ordering::Relaxed);
+            return;
+        }
+    };
+    let mut backoff = MIN_BACKOFF;
+    while let Ok(mut request) = requests.recv() {
+        loop {
+            let mut skipped = 0;
+            while let Ok(newer) = requests.try_recv() {
+                request = newer;
+                skipped += 1;
+            }
+            if skipped > 0 {
+                log::info!("otlp: skipped {} ticks that were not exported in time", skipped);
+            }
+            match runtime.block_on(export(&mut client, &request)) {
+                Ok(()) => {
+                    log::debug!(
+                        "otlp: exported {} resources to {}",
+                        request.resource_metrics.len(),
+                        endpoint
+                    );
+                    if failing.swap(false, Ordering::Relaxed) {
+                        eprintln!("OTLP export to {} recovered", endpoint);
+                    }
+                    backoff = MIN_BACKOFF;
+                    break;
+                }
+                Err(e) => {
+                    if !failing.swap(true, Ordering::Relaxed) {
+                        eprintln!("OTLP export to {} failed, retrying with backoff: {}", endpoint, e);
+                    }
+                    log::info!("otlp: export failed, retrying in {:?}: {}", backoff, e);
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+}
+
+/// Exports snapshots to an OTLP collector in the background.
+pub struct Exporter {
+    requests: Sender<ExportMetricsServiceRequest>,
+    failing: Arc<AtomicBool>,
+}
+
+impl Exporter {
+    pub fn new(endpoint: &str) -> Self {
+        let (requests, receiver) = mpsc::channel();
+        let endpoint = endpoint.to_string();
+        let failing = Arc::new(AtomicBool::new(false));
+        let worker = Arc::clone(&failing);
+        thread::spawn(move || export_loop(endpoint, receiver, worker));
+        Exporter { requests, failing }
+    }
+
+    /// Queue the gauges of `snapshot`, one resource per GPU, without waiting.
+    pub fn export(&self, snapshot: &Snapshot) {
+        if self.requests.send(export_request(snapshot)).is_err() {
+            eprintln!("OTLP exporter stopped");
+            self.failing.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the latest export failed, for the exit status of watch mode.
+    pub fn failing(&self) -> bool {
+        self.failing.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::tests::{fixtures, gpu};
+    use crate::{stats, Opts};
+    use clap::Parser;
+
+    #[test]
+    fn endpoints_are_http_urls() {
+        assert_eq!(
+            parse_endpoint("http://collector:4317/").unwrap(),
+            "http://collector:4317"
+        );
+        assert!(parse_endpoint("collector:4317").is_err());
+        assert!(parse_endpoint("http://collector 4317").is_err());
+    }
+
+    #[test]
+    fn requests_carry_a_resource_per_gpu() {
+        let provider = fixtures(vec![gpu(0), gpu(1)]);
+        let opts = Opts::try_parse_from(["gpustat"]).unwrap();
+        let snapshot = stats::collect(&opts, &provider, "host").unwrap();
+        let request = export_request(&snapshot);
+        assert_eq!(request.resource_metrics.len(), 2);
+        let resource = &request.resource_metrics[1];
+        let attributes = &resource.resource.as_ref().unwrap().attributes;
+        assert_eq!(attributes[0], attribute("host.name", string("host")));
+        assert_eq!(attributes[2], attribute("gpu.index", any_value::Value::IntValue(1)));
+        let metrics = &resource.scope_metrics[0].metrics;
+        let power = metrics.iter().find(|metric| metric.name == "gpu.power.usage").unwrap();
+        assert_eq!(power.unit, "W");
+        let Some(Data::Gauge(gauge)) = &power.data else {
+            panic!("{:?}", power.data);
+        };
+        assert_eq!(
+            gauge.data_points[0].value,
+            Some(number_data_point::Value::AsDouble(20.0))
+        );
+        assert!(gauge.data_points[0].time_unix_nano > 0);
+    }
+
+    #[test]
+    fn unreachable_collectors_fail_the_export() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // nothing listens on port 1
+        let mut client = client("http://127.0.0.1:1", &runtime).unwrap();
+        let result = runtime.block_on(export(&mut client, &ExportMetricsServiceRequest::default()));
+        assert!(result.unwrap_err().starts_with("Unavailable"));
+    }
+}
@@ -0,0 +1,100 @@
+//! `gpustat pcie`: PCIe link and device configuration of every GPU.
+
+use crate::{
+    render::{self, ASCII_TABLE_PRESET, TABLE_PRESET},
+    reset::sysfs_device,
+    Opts, StatusError, EXIT_DEGRADED,
+};
+use comfy_table::{Cell, ContentArrangement, Table};
+use nvml_wrapper::{Device, Nvml};
+use std::fs;
+
+/// Capability ID of the PCI Express capability in the config space.
+const PCI_CAP_ID_EXP: u8 = 0x10;
+
+/// Max payload size in bytes and whether relaxed ordering is enabled, from
+/// the Device Control register of the PCIe capability. The kernel only lets
+/// root read past the standard 64-byte header, so this is `None` otherwise.
+fn device_control(bus_id: &str) -> Option<(u32, bool)> {
+    let config = fs::read(sysfs_device(bus_id).join("config")).ok()?;
+    let mut offset = usize::from(*config.get(0x34)?) & !3;
+    // bound the walk in case the list loops
+    for _ in 0..48 {
+        if offset == 0 {
+            return None;
+        }
+        if *config.get(offset)? == PCI_CAP_ID_EXP {
+            let control = u16::from_le_bytes([*config.get(offset + 8)?, *config.get(offset + 9)?]);
+            let payload = 128 << ((control >> 5) & 0x7);
+            return Some((payload, control & 0x10 != 0));
+        }
+        offset = usize::from(*config.get(offset + 1)?) & !3;
+    }
+    None
+}
+
+/// `Gen4 x16`, with `?` for whichever half NVML cannot report.
+fn link(generation: Option<u32>, width: Option<u32>) -> String {
+    let show = |value: Option<u32>| value.map_or_else(|| String::from("?"), |value| value.to_string());
+    format!("Gen{} x{}", show(generation), show(width))
+}
+
+fn or_na(value: Option<String>) -> String {
+    value.unwrap_or_else(|| String::from("n/a"))
+}
+
+/// One row of the table, and whether the link runs below its maximum.
+fn device_row(index: u32, device: &Device, opts: &Opts) -> (Vec<Cell>, bool) {
+    let max_gen = device.max_pcie_link_gen().ok();
+    let max_width = device.max_pcie_link_width().ok();
+    let gen = device.current_pcie_link_gen().ok();
+    let width = device.current_pcie_link_width().ok();
+    // an idle GPU lowers its link generation to save power, so only a busy
+    // one is held to its maximum generation
+    let busy = device.utilization_rates().map_or(true, |rates| rates.gpu > 0);
+    let downgraded = matches!((width, max_width), (Some(now), Some(max)) if now < max)
+        || busy && matches!((gen, max_gen), (Some(now), Some(max)) if now < max);
+
+    let max_link = (max_gen.is_some() || max_width.is_some()).then(|| link(max_gen, max_width));
+    let current = (gen.is_some() || width.is_some()).then(|| link(gen, width));
+    let current = match (current, downgraded) {
+        (Some(current), true) => Cell::new(current + " (downgraded)").fg(opts.palette.theme().critical),
+        (current, _) => Cell::new(or_na(current)),
+    };
+    let control = device.pci_info().ok().and_then(|pci| device_control(&pci.bus_id));
+
+    let row = vec![
+        Cell::new(format!("[{}]", index)).fg(opts.palette.theme().index),
+        Cell::new(or_na(max_link)),
+        current,
+        Cell::new(or_na(device.pcie_replay_counter().ok().map(|replays| replays.to_string()))),
+        Cell::new(or_na(control.map(|(payload, _)| format!("{} B", payload)))),
+        Cell::new(or_na(control.map(|(_, relaxed)| String::from(if relaxed { "on" } else { "off" })))),
+    ];
+    (row, downgraded)
+}
+
+/// Print the PCIe configuration of every GPU, exiting non-zero when any link
+/// runs below its maximum width, or a busy one below its maximum generation.
+pub fn run(opts: &Opts, nvml: &Nvml) -> Result<u8, StatusError> {
+    let mut table = Table::new();
+    table
+        .load_preset(if opts.ascii {
+            ASCII_TABLE_PRESET
+        } else {
+            TABLE_PRESET
+        })
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(["GPU", "max link", "current link", "replays", "max payload", "relaxed ordering"]);
+    render::apply_styling(render::styling_enabled(opts), &mut table);
+
+    let mut downgraded = false;
+    for index in 0..nvml.device_count()? {
+        let device = nvml.device_by_index(index)?;
+        let (row, slow) = device_row(index, &device, opts);
+        downgraded |= slow;
+        table.add_row(row);
+    }
+    println!("{}", table);
+    Ok(if downgraded { EXIT_DEGRADED } else { 0 })
+}
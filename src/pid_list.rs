@@ -0,0 +1,57 @@
+//! `--pids`: nothing but the PIDs of the compute and graphics processes on
+//! the GPUs, for scripts such as `kill $(gpustat --id 3 --pids)`.
+//!
+//! One GPU, or `--flat`, gives one line of PIDs, each listed once; more GPUs
+//! give a line per GPU starting with its index and a tab. Processes gpustat
+//! could not find in `/proc`, such as those of another PID namespace, are
+//! left out, since their NVML PID may name an unrelated process here.
+
+use crate::{
+    stats::{ProcessKind, Snapshot},
+    Opts, EXIT_NO_PROCESSES,
+};
+
+/// The PIDs on each GPU by displayed index, of `--user` when given.
+fn pids(opts: &Opts, snapshot: &Snapshot) -> Vec<(u32, Vec<u32>)> {
+    snapshot
+        .gpus
+        .iter()
+        .map(|gpu| {
+            let mut pids: Vec<u32> = vec![];
+            for process in &gpu.processes {
+                let listed = matches!(process.kind, ProcessKind::Compute | ProcessKind::Graphics | ProcessKind::Mixed);
+                let user = opts.users.is_empty() || opts.users.contains(&process.user);
+                if listed && user && !process.stale && !pids.contains(&process.pid) {
+                    pids.push(process.pid);
+                }
+            }
+            (gpu.order_index.unwrap_or(gpu.index), pids)
+        })
+        .collect()
+}
+
+/// Print the PIDs, returning the exit code: `EXIT_NO_PROCESSES` without any.
+pub fn print(opts: &Opts, snapshot: &Snapshot) -> u8 {
+    let gpus = pids(opts, snapshot);
+    let join = |pids: &[u32]| pids.iter().map(u32::to_string).collect::<Vec<_>>().join(" ");
+    if opts.flat || gpus.len() == 1 {
+        let mut all: Vec<u32> = vec![];
+        for pid in gpus.iter().flat_map(|(_, pids)| pids) {
+            if !all.contains(pid) {
+                all.push(*pid);
+            }
+        }
+        if all.is_empty() {
+            return EXIT_NO_PROCESSES;
+        }
+        println!("{}", join(&all));
+    } else {
+        if gpus.iter().all(|(_, pids)| pids.is_empty()) {
+            return EXIT_NO_PROCESSES;
+        }
+        for (index, pids) in &gpus {
+            println!("{}\t{}", index, join(pids));
+        }
+    }
+    0
+}
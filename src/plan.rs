@@ -0,0 +1,284 @@
+//! `--dry-run`: the NVML queries a collection with the given options would
+//! make, printed without initializing NVML, for reports of slow runs or
+//! missing columns. The optional queries come from `stats::COLLECTORS`, the
+//! registry the collection runs, so the plan follows new collectors.
+
+use crate::{
+    stats::{DeviceOrder, COLLECTORS},
+    Opts, StatusError,
+};
+use serde::Serialize;
+
+/// One query of the plan, with the condition it depends on at run time.
+#[derive(Clone, Debug, Serialize)]
+pub struct Query {
+    pub call: &'static str,
+    pub when: Option<&'static str>,
+}
+
+impl Query {
+    fn always(call: &'static str) -> Self {
+        Query { call, when: None }
+    }
+
+    fn when(call: &'static str, when: &'static str) -> Self {
+        Query { call, when: Some(when) }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PlannedCollector {
+    pub name: &'static str,
+    pub calls: &'static [&'static str],
+    /// Runs on the first pass only and is then served from the device cache.
+    pub cached: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Plan {
+    /// NVML indices of `--id`, `None` for every GPU.
+    pub gpus: Option<Vec<u32>>,
+    /// Seconds between collections in watch mode.
+    pub interval: Option<f64>,
+    /// Queries of each collection before any device's.
+    pub setup: Vec<Query>,
+    /// Queries of each GPU on every pass, in the order they are made.
+    pub base: Vec<Query>,
+    pub collectors: Vec<PlannedCollector>,
+    /// Collectors the options ask for but `--disable-collector` switched off.
+    pub disabled: Vec<&'static str>,
+    /// Collectors no option asks for.
+    pub not_requested: Vec<&'static str>,
+}
+
+/// The plan of a collection with `opts`, following `stats::collect_gpu`.
+pub fn plan(opts: &Opts) -> Plan {
+    let cached = if opts.no_cache { None } else { Some("not cached yet") };
+    let mut setup = vec![Query::always("device_count"), Query::always("sys_driver_version")];
+    if opts.order != DeviceOrder::Nvml {
+        setup.push(Query::when("pci_info", "the GPUs are not sorted yet"));
+    }
+    if opts.order == DeviceOrder::Cuda {
+        setup.push(Query::when("cuda_compute_capability", "the GPUs are not sorted yet"));
+        setup.push(Query::when("memory_info", "the GPUs are not sorted yet"));
+    }
+
+    let mut base = vec![Query::always("device_by_index")];
+    base.push(cached.map_or(Query::always("name"), |when| Query::when("name", when)));
+    base.push(Query::always("running_compute_processes"));
+    base.push(Query::always("running_graphics_processes"));
+    if !opts.no_proc_scan {
+        base.push(Query::when("minor_number", "the process list is denied"));
+    }
+    base.push(Query::always("memory_info"));
+    base.push(Query::when("memory_info(v1)", "the driver has no memory_info v2"));
+    base.push(cached.map_or(Query::always("uuid"), |when| Query::when("uuid", when)));
+    for call in ["temperature", "utilization_rates", "power_usage", "power_management_limit"] {
+        base.push(Query::always(call));
+    }
+
+    let (mut collectors, mut disabled, mut not_requested) = (vec![], vec![], vec![]);
+    for collector in COLLECTORS {
+        if !collector.wanted(opts) {
+            not_requested.push(collector.name);
+        } else if opts.collector_off(collector.name) {
+            disabled.push(collector.name);
+        } else {
+            collectors.push(PlannedCollector {
+                name: collector.name,
+                calls: collector.calls,
+                cached: collector.fixed && !opts.no_cache,
+            });
+        }
+    }
+    // the power brake is checked even without the throttle column
+    if !opts.collector_off("throttle") && !collectors.iter().any(|collector| collector.name == "throttle") {
+        base.push(Query::always("current_throttle_reasons"));
+    }
+
+    Plan {
+        gpus: Some(opts.ids.clone()).filter(|ids| !ids.is_empty()),
+        interval: opts.interval,
+        setup,
+        base,
+        collectors,
+        disabled,
+        not_requested,
+    }
+}
+
+fn query_line(query: &Query) -> String {
+    match query.when {
+        Some(when) => format!("    {} (when {})", query.call, when),
+        None => format!("    {}", query.call),
+    }
+}
+
+/// The plan as an indented tree.
+pub fn tree(plan: &Plan) -> String {
+    let gpus = match &plan.gpus {
+        Some(ids) => format!("GPUs {}", ids.iter().map(u32::to_string).collect::<Vec<_>>().join(", ")),
+        None => String::from("every GPU"),
+    };
+    let every = plan.interval.map(|interval| format!(", every {}s", interval)).unwrap_or_default();
+    let mut lines = vec![format!("collection plan for {}{} (NVML not initialized)", gpus, every)];
+    lines.push(String::from("  setup"));
+    lines.extend(plan.setup.iter().map(query_line));
+    lines.push(String::from("  each GPU"));
+    lines.extend(plan.base.iter().map(query_line));
+    lines.push(String::from("  collectors"));
+    if plan.collectors.is_empty() {
+        lines.push(String::from("    (none)"));
+    }
+    for collector in &plan.collectors {
+        let calls = match collector.calls {
+            [] => String::from("no NVML queries"),
+            calls => calls.join(", "),
+        };
+        let cached = if collector.cached { " (first pass only)" } else { "" };
+        lines.push(format!("    {}: {}{}", collector.name, calls, cached));
+    }
+    if !plan.disabled.is_empty() {
+        lines.push(String::from("  disabled by --disable-collector"));
+        lines.push(format!("    {}", plan.disabled.join(", ")));
+    }
+    if !plan.not_requested.is_empty() {
+        lines.push(String::from("  not requested"));
+        lines.push(format!("    {}", plan.not_requested.join(", ")));
+    }
+    lines.join("\n")
+}
+
+/// Print the plan, as JSON with `--format json` and as a tree otherwise.
+pub fn run(opts: &Opts) -> Result<u8, StatusError> {
+    let plan = plan(opts);
+    if opts.format_name() == "json" {
+        println!("{}", serde_json::to_string_pretty(&plan)?);
+    } else {
+        println!("{}", tree(&plan));
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixture::FixtureProvider, provider::Provider, stats};
+    use clap::Parser;
+    use nvml_wrapper::{error::NvmlError, Device};
+    use serde_json::Value;
+    use std::{collections::BTreeSet, path::Path, sync::Mutex};
+
+    fn opts(args: &[&str]) -> Opts {
+        Opts::try_parse_from(["gpustat"].iter().chain(args)).unwrap()
+    }
+
+    /// A fixture noting the queries a collection makes, without their arguments.
+    struct Recording {
+        fixture: FixtureProvider,
+        calls: Mutex<BTreeSet<String>>,
+    }
+
+    impl Provider for Recording {
+        fn device_count(&self) -> Result<u32, NvmlError> {
+            self.fixture.device_count()
+        }
+
+        fn sys_driver_version(&self) -> Result<String, NvmlError> {
+            self.fixture.sys_driver_version()
+        }
+
+        fn sys_process_name(&self, pid: u32) -> Result<String, NvmlError> {
+            self.fixture.sys_process_name(pid)
+        }
+
+        fn process_owner(&self, pid: u32) -> Option<String> {
+            self.fixture.process_owner(pid)
+        }
+
+        fn open(&self, index: u32) -> Result<(), NvmlError> {
+            self.fixture.open(index)
+        }
+
+        fn query(
+            &self,
+            index: u32,
+            call: &str,
+            live: &dyn Fn(&Device) -> Result<Value, NvmlError>,
+        ) -> Result<Value, NvmlError> {
+            let name = call.split('(').next().unwrap_or(call);
+            self.calls.lock().unwrap().insert(name.to_string());
+            self.fixture.query(index, call, live)
+        }
+
+        fn local(&self) -> bool {
+            false
+        }
+    }
+
+    /// Every call of the plan, without arguments.
+    fn planned(plan: &Plan) -> BTreeSet<String> {
+        let queries = plan.setup.iter().chain(&plan.base).map(|query| query.call);
+        let calls = plan
+            .collectors
+            .iter()
+            .flat_map(|collector| collector.calls.iter().copied());
+        queries
+            .chain(calls)
+            .map(|call| call.split('(').next().unwrap_or(call).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn the_plan_covers_every_query_collection_makes() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let runs: [&[&str]; 4] = [
+            &[],
+            &["-a"],
+            &["-a", "--columns", "inforom,display"],
+            &["-a", "--order", "cuda"],
+        ];
+        for (fixture, args) in ["consumer", "a100-mig", "wsl"]
+            .iter()
+            .flat_map(|f| runs.map(|args| (f, args)))
+        {
+            let opts = opts(args);
+            let recording = Recording {
+                fixture: FixtureProvider::load(&fixtures.join(format!("{}.json", fixture))).unwrap(),
+                calls: Mutex::default(),
+            };
+            stats::collect(&opts, &recording, "node").unwrap();
+            let made = recording.calls.into_inner().unwrap();
+            let planned = planned(&plan(&opts));
+            let unplanned: Vec<&String> = made.difference(&planned).collect();
+            assert!(
+                unplanned.is_empty(),
+                "{} {:?} made {:?} outside the plan",
+                fixture,
+                args,
+                unplanned
+            );
+        }
+    }
+
+    #[test]
+    fn options_change_the_plan() {
+        let default = plan(&opts(&[]));
+        assert!(!default.collectors.iter().any(|c| c.name == "fan"));
+        assert!(default.not_requested.contains(&"fan"));
+        // the power brake check alone reads the throttle reasons
+        assert!(default
+            .base
+            .iter()
+            .any(|query| query.call == "current_throttle_reasons"));
+        let all = plan(&opts(&["-a", "--disable-collector", "fan", "--id", "1", "--no-cache"]));
+        assert!(all.disabled.contains(&"fan"));
+        assert!(all.collectors.iter().any(|c| c.name == "throttle"));
+        assert!(all.collectors.iter().all(|c| !c.cached));
+        assert!(!all.base.iter().any(|query| query.call == "current_throttle_reasons"));
+        assert_eq!(all.base[1].when, None);
+        let tree = tree(&all);
+        assert!(tree.starts_with("collection plan for GPUs 1 (NVML not initialized)\n  setup\n    device_count"));
+        assert!(tree.contains("  disabled by --disable-collector\n    fan"));
+    }
+}
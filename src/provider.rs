@@ -0,0 +1,68 @@
+//! Where collection gets its NVML answers: the driver through nvml-wrapper,
+//! or `fixture::FixtureProvider` replaying recorded answers call by call.
+
+use nvml_wrapper::{error::NvmlError, Device, Nvml};
+use serde_json::Value;
+
+/// Answers to the NVML queries of a collection. Each query of a GPU is one
+/// call, named as `-v` logs it, e.g. `memory_info` or `fan_speed(1)`, whose
+/// answer travels as JSON so that it can be recorded and replayed.
+pub trait Provider: Send + Sync {
+    fn device_count(&self) -> Result<u32, NvmlError>;
+
+    fn sys_driver_version(&self) -> Result<String, NvmlError>;
+
+    /// Name of a process NVML knows of, for processes sysinfo cannot see.
+    fn sys_process_name(&self, pid: u32) -> Result<String, NvmlError>;
+
+    /// Owner of a process sysinfo cannot see, by default from `/proc/<pid>`.
+    fn process_owner(&self, pid: u32) -> Option<String> {
+        crate::stats::proc_owner(pid)
+    }
+
+    /// Whether the GPU at `index` can be queried at all, as getting its
+    /// handle tells, e.g. `GpuLost`.
+    fn open(&self, index: u32) -> Result<(), NvmlError>;
+
+    /// Answer `call` for the GPU at `index`. `live` makes the call on the
+    /// NVML handle of the GPU, if there is one.
+    fn query(
+        &self,
+        index: u32,
+        call: &str,
+        live: &dyn Fn(&Device) -> Result<Value, NvmlError>,
+    ) -> Result<Value, NvmlError>;
+
+    /// Whether the answers are about this machine's GPUs, so that its sysfs
+    /// and kernel log describe them too.
+    fn local(&self) -> bool {
+        true
+    }
+}
+
+impl Provider for Nvml {
+    fn device_count(&self) -> Result<u32, NvmlError> {
+        Nvml::device_count(self)
+    }
+
+    fn sys_driver_version(&self) -> Result<String, NvmlError> {
+        Nvml::sys_driver_version(self)
+    }
+
+    fn sys_process_name(&self, pid: u32) -> Result<String, NvmlError> {
+        Nvml::sys_process_name(self, pid, 64)
+    }
+
+    fn open(&self, index: u32) -> Result<(), NvmlError> {
+        self.device_by_index(index).map(drop)
+    }
+
+    fn query(
+        &self,
+        index: u32,
+        _: &str,
+        live: &dyn Fn(&Device) -> Result<Value, NvmlError>,
+    ) -> Result<Value, NvmlError> {
+        live(&self.device_by_index(index)?)
+    }
+}
@@ -0,0 +1,239 @@
+//! `gpustat ready`: whether a GPU can take a job, for scheduler prologs.
+
+use crate::{
+    render::{self, ASCII_TABLE_PRESET, TABLE_PRESET},
+    stats::{self, GpuStat},
+    Opts, StatusError, EXIT_DEGRADED, EXIT_USAGE,
+};
+use clap::Args;
+use comfy_table::{Cell, ContentArrangement, Table};
+use nvml_wrapper::Nvml;
+use serde_json::json;
+
+#[derive(Args, Clone, Debug)]
+pub struct ReadyOpts {
+    #[arg(long = "id", value_name = "INDEX", help = "GPU to check")]
+    id: u32,
+    #[arg(long, value_name = "CHECK,...", value_delimiter = ',', value_parser = parse_check, help = "Checks that must pass, all of them by default")]
+    require: Vec<String>,
+    #[arg(long, help = "Print the checks as one JSON object")]
+    json: bool,
+}
+
+fn parse_check(name: &str) -> Result<String, String> {
+    if CHECKS.iter().any(|(check, _)| *check == name) {
+        Ok(name.to_string())
+    } else {
+        let names: Vec<&str> = CHECKS.iter().map(|(check, _)| *check).collect();
+        Err(format!("unknown check, expected one of {}", names.join(", ")))
+    }
+}
+
+/// Whether a GPU passes a check, and why.
+type Predicate = fn(&GpuStat) -> (bool, String);
+
+/// Every check in listing order.
+pub const CHECKS: &[(&str, Predicate)] = &[
+    ("healthy", healthy),
+    ("idle", idle),
+    ("ecc", ecc),
+    ("reset", reset),
+    ("mig", mig),
+    ("persistence", persistence),
+];
+
+fn healthy(gpu: &GpuStat) -> (bool, String) {
+    match &gpu.error {
+        Some(e) => (false, e.clone()),
+        None if gpu.critical() => (false, String::from("critical temperature, memory, ECC or slowdown")),
+        None => (true, String::from("no critical metric")),
+    }
+}
+
+fn idle(gpu: &GpuStat) -> (bool, String) {
+    match gpu.processes.len() {
+        0 => (true, String::from("no processes")),
+        1 => (false, String::from("1 process")),
+        n => (false, format!("{} processes", n)),
+    }
+}
+
+fn ecc(gpu: &GpuStat) -> (bool, String) {
+    match gpu.ecc_uncorrected {
+        None => (true, String::from("no ECC")),
+        Some(0) => (true, String::from("no uncorrected errors")),
+        Some(count) => (false, format!("{} uncorrected errors", count)),
+    }
+}
+
+fn reset(gpu: &GpuStat) -> (bool, String) {
+    if gpu.reset_required {
+        (false, String::from("reset required"))
+    } else {
+        (true, String::from("no reset pending"))
+    }
+}
+
+fn mig(gpu: &GpuStat) -> (bool, String) {
+    match gpu.mig_mode {
+        None => (true, String::from("no MIG")),
+        Some(mode) if mode.current == mode.pending => (true, String::from("no MIG change pending")),
+        Some(_) => (false, String::from("MIG mode change waits for a reset")),
+    }
+}
+
+fn persistence(gpu: &GpuStat) -> (bool, String) {
+    match gpu.persistence_mode {
+        None => (false, String::from("unknown")),
+        Some(false) => (false, String::from("off")),
+        Some(true) => (true, String::from("on")),
+    }
+}
+
+/// Outcome of one check.
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// Run every check against a collected GPU.
+pub fn evaluate(gpu: &GpuStat) -> Vec<Check> {
+    CHECKS
+        .iter()
+        .map(|(name, predicate)| {
+            let (passed, detail) = predicate(gpu);
+            Check { name, passed, detail }
+        })
+        .collect()
+}
+
+/// Check one GPU, printing every check as a table or JSON, and exit
+/// non-zero unless all required checks pass.
+pub fn run(opts: &Opts, ready: &ReadyOpts, nvml: &Nvml) -> Result<u8, StatusError> {
+    // the hostname is not shown
+    let snapshot = stats::collect(opts, nvml, "")?;
+    let Some(gpu) = snapshot.gpus.iter().find(|gpu| gpu.index == ready.id) else {
+        eprintln!("No GPU {}", ready.id);
+        return Ok(EXIT_USAGE);
+    };
+    let required = |name: &str| ready.require.is_empty() || ready.require.iter().any(|check| check == name);
+    let checks = evaluate(gpu);
+    let passed = checks.iter().all(|check| check.passed || !required(check.name));
+
+    if ready.json {
+        let checks: Vec<_> = checks
+            .iter()
+            .map(|check| {
+                json!({
+                    "name": check.name,
+                    "required": required(check.name),
+                    "passed": check.passed,
+                    "detail": check.detail,
+                })
+            })
+            .collect();
+        println!(
+            "{}",
+            json!({ "index": gpu.index, "uuid": gpu.uuid, "ready": passed, "checks": checks })
+        );
+    } else {
+        let mut table = Table::new();
+        table
+            .load_preset(if opts.ascii {
+                ASCII_TABLE_PRESET
+            } else {
+                TABLE_PRESET
+            })
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(["check", "required", "result", "detail"]);
+        render::apply_styling(render::styling_enabled(opts), &mut table);
+        for check in &checks {
+            let result = match (check.passed, required(check.name)) {
+                (true, _) => Cell::new("pass"),
+                (false, true) => Cell::new("FAIL").fg(opts.palette.theme().critical),
+                (false, false) => Cell::new("fail"),
+            };
+            table.add_row(vec![
+                Cell::new(check.name),
+                Cell::new(if required(check.name) { "yes" } else { "no" }),
+                result,
+                Cell::new(&check.detail),
+            ]);
+        }
+        println!("{}", table);
+        println!("GPU {}: {}", gpu.index, if passed { "ready" } else { "not ready" });
+    }
+    Ok(if passed { 0 } else { EXIT_DEGRADED })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::{MigModeState, ProcessStat};
+
+    fn outcome(gpu: &GpuStat) -> Vec<(&'static str, bool, String)> {
+        evaluate(gpu)
+            .into_iter()
+            .map(|check| (check.name, check.passed, check.detail))
+            .collect()
+    }
+
+    #[test]
+    fn a_clean_gpu_is_ready() {
+        let gpu = GpuStat {
+            persistence_mode: Some(true),
+            ..GpuStat::default()
+        };
+        let checks = outcome(&gpu);
+        assert_eq!(checks.len(), CHECKS.len());
+        assert!(checks.iter().all(|(_, passed, _)| *passed), "{:?}", checks);
+        assert_eq!(checks[1].2, "no processes");
+        assert_eq!(checks[2].2, "no ECC");
+    }
+
+    #[test]
+    fn failures_name_their_reason() {
+        let gpu = GpuStat {
+            processes: vec![ProcessStat::default(), ProcessStat::default()],
+            ecc_uncorrected: Some(2),
+            reset_required: true,
+            mig_mode: Some(MigModeState {
+                current: false,
+                pending: true,
+            }),
+            ..GpuStat::default()
+        };
+        assert_eq!(
+            outcome(&gpu),
+            [
+                (
+                    "healthy",
+                    false,
+                    String::from("critical temperature, memory, ECC or slowdown")
+                ),
+                ("idle", false, String::from("2 processes")),
+                ("ecc", false, String::from("2 uncorrected errors")),
+                ("reset", false, String::from("reset required")),
+                ("mig", false, String::from("MIG mode change waits for a reset")),
+                ("persistence", false, String::from("unknown")),
+            ]
+        );
+        let failed = GpuStat {
+            error: Some(String::from("GpuLost")),
+            ..gpu
+        };
+        assert_eq!(outcome(&failed)[0], ("healthy", false, String::from("GpuLost")));
+    }
+
+    #[test]
+    fn required_checks_must_exist() {
+        assert_eq!(parse_check("idle").unwrap(), "idle");
+        let error = parse_check("bogus").unwrap_err();
+        assert!(
+            error.contains("healthy, idle, ecc, reset, mig, persistence"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,3515 @@
+use crate::{
+    allocation::Allocation,
+    chargeback::Chargeback,
+    collection_errors,
+    columns::COLUMNS,
+    consistency::Difference,
+    csv::CsvLevel,
+    igpu::IntegratedGpu,
+    snapshot_cache::Staleness,
+    stats::{
+        self, Bandwidth, BusyTime, CcMode, ChangeThreshold, Cooling, Distinguish, EncoderUse, FanStat, GpuStat,
+        HostMemory, MemoryDisplay, MissingProcesses, Perf, PermissionLimit, ProcessCount, ProcessKind, ProcessStat,
+        Snapshot, VgpuStat, WindowMax, THROTTLE_REASONS,
+    },
+    template, Opts,
+};
+use chrono::prelude::*;
+use comfy_table::{Attribute, Cell, CellAlignment, Color, ColumnConstraint, ContentArrangement, Table, Width};
+use nvml_wrapper::{
+    enum_wrappers::device::{Brand, GpuVirtualizationMode},
+    enums::device::FanControlPolicy,
+};
+use serde_json::json;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+    io::{self, IsTerminal},
+    time::Duration,
+};
+
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub const VERSION_MISMATCH_HINT: &str =
+    "warning: driver/library version mismatch - reboot or reload the nvidia modules";
+
+pub const CLOCK_SUSPECT_HINT: &str =
+    "warning: the system clock is set before this gpustat was built - timestamps are wrong until NTP syncs";
+
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+/// Colors used for each column of the GPU table.
+pub struct Theme {
+    pub index: Color,
+    pub name: Color,
+    pub temperature: Color,
+    pub utilization: Color,
+    pub fan: Color,
+    pub codec: Color,
+    pub virtualization: Color,
+    pub affinity: Color,
+    pub pcie: Color,
+    pub ecc: Color,
+    pub mig: Color,
+    pub throttle: Color,
+    pub display: Color,
+    pub power: Color,
+    pub memory: Color,
+    pub processes: Color,
+    /// Processes matching `--match`.
+    pub matched: Color,
+    pub critical: Color,
+    pub warning: Color,
+    /// Outliers of `--consistency-check`.
+    pub inconsistent: Color,
+    /// Colors of `--show-owner`, one picked per user name.
+    pub owners: &'static [Color],
+}
+
+const DEFAULT_THEME: Theme = Theme {
+    index: Color::DarkCyan,
+    name: Color::DarkBlue,
+    temperature: Color::Red,
+    utilization: Color::Green,
+    fan: rgb(255, 0, 255),
+    codec: Color::Cyan,
+    virtualization: Color::DarkBlue,
+    affinity: Color::DarkGreen,
+    pcie: Color::DarkGreen,
+    ecc: Color::DarkGreen,
+    mig: Color::DarkGreen,
+    throttle: Color::DarkRed,
+    display: Color::Magenta,
+    power: Color::DarkMagenta,
+    memory: Color::Yellow,
+    processes: Color::DarkYellow,
+    matched: Color::White,
+    critical: Color::Red,
+    warning: Color::Yellow,
+    inconsistent: Color::Magenta,
+    owners: &[
+        Color::Cyan,
+        Color::Green,
+        Color::Magenta,
+        Color::Blue,
+        Color::DarkCyan,
+        Color::DarkGreen,
+        Color::DarkMagenta,
+        rgb(255, 135, 0),
+    ],
+};
+
+/// Blue/orange/purple scheme that stays distinguishable with deuteranopia.
+const COLORBLIND_THEME: Theme = Theme {
+    index: rgb(86, 180, 233),
+    name: rgb(0, 114, 178),
+    temperature: rgb(230, 159, 0),
+    utilization: rgb(0, 114, 178),
+    fan: rgb(204, 121, 167),
+    codec: rgb(86, 180, 233),
+    virtualization: rgb(0, 114, 178),
+    affinity: rgb(204, 121, 167),
+    pcie: rgb(0, 114, 178),
+    ecc: rgb(0, 114, 178),
+    mig: rgb(0, 114, 178),
+    throttle: rgb(213, 94, 0),
+    display: rgb(204, 121, 167),
+    power: rgb(136, 34, 85),
+    memory: rgb(240, 228, 66),
+    processes: rgb(230, 159, 0),
+    matched: rgb(86, 180, 233),
+    critical: rgb(213, 94, 0),
+    warning: rgb(240, 228, 66),
+    inconsistent: rgb(204, 121, 167),
+    owners: &[
+        rgb(86, 180, 233),
+        rgb(0, 114, 178),
+        rgb(0, 158, 115),
+        rgb(204, 121, 167),
+        rgb(230, 159, 0),
+        rgb(240, 228, 66),
+    ],
+};
+
+/// Darker colors that stay readable on a white or light gray background.
+const LIGHT_THEME: Theme = Theme {
+    index: Color::DarkCyan,
+    name: Color::DarkBlue,
+    temperature: Color::DarkRed,
+    utilization: Color::DarkGreen,
+    fan: Color::DarkMagenta,
+    codec: Color::DarkCyan,
+    virtualization: Color::DarkBlue,
+    affinity: Color::DarkGreen,
+    pcie: Color::DarkGreen,
+    ecc: Color::DarkGreen,
+    mig: Color::DarkGreen,
+    throttle: Color::DarkRed,
+    display: Color::DarkMagenta,
+    power: Color::DarkMagenta,
+    memory: rgb(135, 95, 0),
+    processes: rgb(135, 95, 0),
+    matched: Color::Black,
+    critical: Color::DarkRed,
+    warning: rgb(175, 95, 0),
+    inconsistent: Color::DarkMagenta,
+    owners: &[
+        Color::DarkBlue,
+        Color::DarkGreen,
+        Color::DarkMagenta,
+        Color::DarkCyan,
+        rgb(135, 95, 0),
+        rgb(95, 0, 175),
+    ],
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Palette {
+    /// `default` or `light` after the terminal background, detected at startup
+    #[default]
+    Auto,
+    Default,
+    Light,
+    Colorblind,
+}
+
+/// How the table lays out the row of a GPU.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Metrics and processes on one line
+    #[default]
+    Single,
+    /// Metrics on one line, then the processes at full width on a dim line below
+    TwoLine,
+}
+
+impl Palette {
+    pub fn theme(self) -> &'static Theme {
+        match self {
+            Palette::Auto | Palette::Default => &DEFAULT_THEME,
+            Palette::Light => &LIGHT_THEME,
+            Palette::Colorblind => &COLORBLIND_THEME,
+        }
+    }
+}
+
+/// Box-drawing preset for the GPU table and its pure-ASCII fallback for `--ascii`.
+pub(crate) const TABLE_PRESET: &str = "     ═  |          ";
+pub(crate) const ASCII_TABLE_PRESET: &str = "     =  |          ";
+
+/// Bold styling of threshold cells, carried across watch mode frames by GPU UUID
+/// so that a value hovering around its limit doesn't flicker.
+#[derive(Debug, Default)]
+pub struct Styling {
+    /// Fraction of the limit a value must drop below it before the cell reverts.
+    margin: f64,
+    bold: HashSet<(String, &'static str)>,
+    /// Widest content seen per column under `--fixed-width`, so columns only ever grow.
+    widths: Vec<usize>,
+}
+
+impl Styling {
+    pub fn new(margin_percent: f64) -> Self {
+        Styling {
+            margin: margin_percent / 100.0,
+            bold: HashSet::new(),
+            widths: vec![],
+        }
+    }
+
+    /// Fold this frame's column widths into the widest seen so far, starting
+    /// over when the set of columns changes.
+    fn widen(&mut self, widths: Vec<usize>) -> &[usize] {
+        if self.widths.len() != widths.len() {
+            self.widths = widths;
+        } else {
+            for (seen, width) in self.widths.iter_mut().zip(widths) {
+                *seen = (*seen).max(width);
+            }
+        }
+        &self.widths
+    }
+
+    /// Whether `value` is above `limit`, or still within the margin of a cell bolded last frame.
+    fn over_limit(&mut self, key: (&str, &'static str), value: f64, limit: f64) -> bool {
+        let key = (key.0.to_string(), key.1);
+        let was_bold = self.bold.contains(&key);
+        let bold = value > limit || (was_bold && value >= limit * (1.0 - self.margin));
+        if bold {
+            self.bold.insert(key);
+        } else if was_bold {
+            self.bold.remove(&key);
+        }
+        bold
+    }
+}
+
+/// Degrees below the target temperature at which `--temp-detail` flags the temperature.
+const TARGET_MARGIN: u32 = 3;
+
+/// Degrees below the slowdown temperature at which the `--cooling` cell is bold red.
+const SLOWDOWN_MARGIN: i64 = 5;
+
+macro_rules! bold_limit {
+    ($styling:ident[$key:expr], $value:expr, $limit:expr, $color:expr, $($arg:tt)*) => {{
+        // every limited cell holds a figure, right-aligned so columns line up
+        let cell = Cell::new(format!($($arg)*)).fg($color).set_alignment(CellAlignment::Right);
+        if $styling.over_limit($key, $value as f64, $limit as f64) {
+            cell.add_attribute(Attribute::Bold)
+        } else {
+            cell
+        }
+    }};
+}
+
+/// Render the header timestamp according to `--time-format`, `--utc` and `--epoch`.
+pub fn format_timestamp(opts: &RenderOpts, time: DateTime<Local>) -> String {
+    let format = opts.units.time_format.as_deref().unwrap_or(DEFAULT_TIME_FORMAT);
+    if opts.units.epoch {
+        time.timestamp().to_string()
+    } else if opts.units.utc {
+        format!("{}Z", time.with_timezone(&Utc).format(format))
+    } else {
+        time.format(format).to_string()
+    }
+}
+
+/// Join the header fields that are not hidden, or `None` when all of them are.
+pub fn header_line(opts: &RenderOpts, hostname: &str, timestamp: &str, driver: Option<&str>) -> Option<String> {
+    let mut fields = vec![];
+    if !opts.no_hostname {
+        fields.push(hostname);
+    }
+    if !opts.no_timestamp {
+        fields.push(timestamp);
+    }
+    if let Some(driver) = driver.filter(|_| !opts.no_driver) {
+        fields.push(driver);
+    }
+    if opts.no_header || fields.is_empty() {
+        None
+    } else {
+        Some(fields.join("\t"))
+    }
+}
+
+/// Whether `NO_COLOR` asks for no colors, see <https://no-color.org>.
+pub fn no_color_env() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+/// Whether ANSI styling is written, by tables and JSON alike: `--no-color`
+/// wins, then `--color`, then `--output` and `NO_COLOR` turn it off and
+/// otherwise stdout must be a terminal.
+pub fn styling_enabled(opts: &Opts) -> bool {
+    if opts.no_color || (!opts.color && (opts.output.is_some() || no_color_env())) {
+        false
+    } else {
+        opts.color || io::stdout().is_terminal()
+    }
+}
+
+/// Style a table exactly when `styled`, as `styling_enabled` decides.
+pub fn apply_styling(styled: bool, table: &mut Table) {
+    if styled {
+        table.enforce_styling();
+    } else {
+        table.force_no_tty();
+    }
+}
+
+/// Whether `--format json` is indented: `--pretty` or `--compact` decide,
+/// otherwise only a terminal gets it.
+pub fn pretty_json(opts: &Opts) -> bool {
+    if opts.pretty || opts.compact {
+        opts.pretty
+    } else {
+        opts.output.is_none() && io::stdout().is_terminal()
+    }
+}
+
+/// Color the keys, strings, numbers and literals of serialized JSON.
+fn colorize_json(json: &str) -> String {
+    const KEY: &str = "\x1b[1;34m";
+    const STRING: &str = "\x1b[32m";
+    const NUMBER: &str = "\x1b[33m";
+    const LITERAL: &str = "\x1b[35m";
+    const RESET: &str = "\x1b[0m";
+    let mut text = String::with_capacity(json.len() * 2);
+    let mut chars = json.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut string = String::from('"');
+                while let Some(c) = chars.next() {
+                    string.push(c);
+                    match c {
+                        '\\' => string.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                let color = if chars.peek() == Some(&':') { KEY } else { STRING };
+                text += color;
+                text += &string;
+                text += RESET;
+            }
+            '-' | '0'..='9' | 't' | 'f' | 'n' => {
+                let mut token = String::from(c);
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || "+-.".contains(**c)) {
+                    token.push(c);
+                    chars.next();
+                }
+                text += if c.is_ascii_alphabetic() { LITERAL } else { NUMBER };
+                text += &token;
+                text += RESET;
+            }
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+/// Share of memory above which the `--show-host-mem` header item is bold.
+const HOST_MEMORY_PRESSURE: f64 = 0.9;
+
+/// Host RAM and swap for the header, e.g. `RAM 421/512G swap 3.0/8.0G`.
+fn host_memory_label(opts: &RenderOpts, memory: &HostMemory) -> String {
+    let label = format!(
+        "RAM {}/{}G swap {}/{}G",
+        gigabytes(memory.used),
+        gigabytes(memory.total),
+        gigabytes(memory.swap_used),
+        gigabytes(memory.swap_total)
+    );
+    let pressure = |used: u64, total: u64| total > 0 && used as f64 / total as f64 > HOST_MEMORY_PRESSURE;
+    if opts.styling.enabled && (pressure(memory.used, memory.total) || pressure(memory.swap_used, memory.swap_total)) {
+        format!("\x1b[1m{}\x1b[0m", label)
+    } else {
+        label
+    }
+}
+
+/// `data age: 0.4s` of a cached snapshot, dim within the `--cache`
+/// duration, and yellow past it and red past three times it when
+/// `--allow-stale` shows a stale one.
+fn data_age_label(opts: &RenderOpts, age_ms: u64) -> String {
+    // rounded down, a fresh cache is never shown as old as its duration
+    let label = match age_ms {
+        0..=59_999 => format!("data age: {}.{}s", age_ms / 1000, age_ms % 1000 / 100),
+        _ => format!("data age: {}", idle_duration(age_ms / 1000)),
+    };
+    if !opts.styling.enabled {
+        return label;
+    }
+    match opts.cache.map_or(Staleness::Fresh, |ttl| Staleness::of(age_ms, ttl)) {
+        Staleness::VeryStale => format!("\x1b[1;31m{}\x1b[0m", label),
+        Staleness::Stale => format!("\x1b[33m{}\x1b[0m", label),
+        Staleness::Fresh => format!("\x1b[2m{}\x1b[0m", label),
+    }
+}
+
+/// Driver uptime below which the `--show-driver-uptime` header item is bold.
+const DRIVER_RECENT_SECONDS: u64 = 3600;
+
+/// `driver up 20m`, bold while the driver was loaded in the last hour.
+fn driver_uptime_label(opts: &RenderOpts, uptime: u64) -> String {
+    let label = format!("driver up {}", short_age(chrono::Duration::seconds(uptime as i64)));
+    if opts.styling.enabled && uptime < DRIVER_RECENT_SECONDS {
+        format!("\x1b[1m{}\x1b[0m", label)
+    } else {
+        label
+    }
+}
+
+/// ` (1650 rpm)` with `--fan-detail`, or ` (1650 / 1600 rpm)` for several fans.
+fn fan_rpm(gpu: &GpuStat) -> String {
+    let rpms: Vec<String> = gpu
+        .fans
+        .iter()
+        .map(|fan| fan.rpm.map_or_else(|| String::from("?"), |rpm| rpm.to_string()))
+        .collect();
+    if gpu.fans.iter().all(|fan| fan.rpm.is_none()) {
+        String::new()
+    } else {
+        format!(" ({} rpm)", rpms.join(" / "))
+    }
+}
+
+/// A cell in the critical color once its value grew by `margin` or more since `--baseline`.
+fn regression(cell: Cell, delta: Option<i64>, margin: u64, theme: &Theme) -> Cell {
+    match delta {
+        Some(delta) if delta > 0 && delta.unsigned_abs() >= margin => cell.fg(theme.critical),
+        _ => cell,
+    }
+}
+
+/// `!` after a reading that was clamped because NVML returned it out of range.
+fn clamped(gpu: &GpuStat, field: &str) -> &'static str {
+    if gpu.raw.contains_key(field) {
+        "!"
+    } else {
+        ""
+    }
+}
+
+/// Encoder/decoder sampling periods above this make the utilization too coarse to trust.
+const COARSE_CODEC_PERIOD_US: u32 = 1_000_000;
+
+/// `~` before a coarse codec utilization, and ` (167ms window)` after it with `detail`.
+fn codec_period(detail: bool, period_us: Option<u32>) -> (&'static str, String) {
+    let Some(period_us) = period_us else {
+        return ("", String::new());
+    };
+    let coarse = if period_us > COARSE_CODEC_PERIOD_US { "~" } else { "" };
+    let window = if detail {
+        format!(" ({}ms window)", period_us / 1000)
+    } else {
+        String::new()
+    };
+    (coarse, window)
+}
+
+/// Xids younger than this are highlighted.
+const XID_RECENT_HOURS: i64 = 24;
+
+/// Largest whole unit of an age, e.g. `2h` or `3d`.
+fn short_age(age: chrono::Duration) -> String {
+    let seconds = age.num_seconds().max(0);
+    match seconds {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
+
+/// The two largest units of an idle time, e.g. `6d4h` or `12m`.
+fn idle_duration(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours) {
+        (0, 0) if minutes == 0 => format!("{}s", seconds),
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h{}m", hours, minutes),
+        _ => format!("{}d{}h", days, hours),
+    }
+}
+
+/// Short tag for a GPU brand, falling back to the raw NVML value for unknown brands.
+fn brand_tag(brand: Result<Brand, u32>) -> String {
+    let tag = match brand {
+        Ok(Brand::GeForce) => "GF",
+        Ok(Brand::GeForceRTX) => "GF RTX",
+        Ok(Brand::Tesla) => "DC",
+        Ok(Brand::Quadro) => "Q",
+        Ok(Brand::QuadroRTX) => "Q RTX",
+        Ok(Brand::NvidiaRTX) => "RTX",
+        Ok(Brand::NVS) => "NVS",
+        Ok(Brand::GRID) => "GRID",
+        Ok(Brand::Titan) => "TTN",
+        Ok(Brand::TitanRTX) => "TTN RTX",
+        Ok(Brand::VApps) => "vApps",
+        Ok(Brand::VPC) => "vPC",
+        Ok(Brand::VCS) => "vCS",
+        Ok(Brand::VWS) => "vWS",
+        Ok(Brand::CloudGaming) | Ok(Brand::VGaming) => "CG",
+        Ok(Brand::Nvidia) => "NV",
+        Ok(Brand::Unknown) => "?",
+        Err(value) => return format!("#{}", value),
+    };
+    tag.to_string()
+}
+
+pub(crate) fn virtualization_label(mode: &GpuVirtualizationMode) -> Option<&'static str> {
+    match mode {
+        GpuVirtualizationMode::PassThrough => Some("passthrough"),
+        GpuVirtualizationMode::Vgpu => Some("vGPU guest"),
+        GpuVirtualizationMode::HostVgpu => Some("vGPU host"),
+        GpuVirtualizationMode::HostVsga => Some("vSGA host"),
+        GpuVirtualizationMode::Bare => None,
+    }
+}
+
+/// The sub-row of an active vGPU, e.g. `vGPU 3: GRID A100-4C, 4096M`.
+fn vgpu_label(vgpu: &VgpuStat) -> String {
+    let mut label = format!("vGPU {}", vgpu.id);
+    if let Some(name) = &vgpu.type_name {
+        label = label + ": " + name;
+    }
+    if let Some(framebuffer) = vgpu.framebuffer {
+        label = label + &format!(", {}M", framebuffer >> 20);
+    }
+    label
+}
+
+/// Format `used` as a whole percentage of `total`, never exceeding 100%.
+fn memory_percent(used: u64, total: u64) -> String {
+    if total == 0 {
+        return String::from("?%");
+    }
+    let percent = (used as f64 / total as f64 * 100.0).round().min(100.0);
+    format!("{}%", percent)
+}
+
+/// Compress a sorted list of indices into ranges, e.g. `0-3,6`.
+fn index_ranges(indices: &[u32]) -> String {
+    let mut ranges: Vec<String> = vec![];
+    let mut iter = indices.iter().peekable();
+    while let Some(&start) = iter.next() {
+        let mut end = start;
+        while iter.peek() == Some(&&(end + 1)) {
+            end = *iter.next().unwrap();
+        }
+        if end == start {
+            ranges.push(start.to_string());
+        } else {
+            ranges.push(format!("{}-{}", start, end));
+        }
+    }
+    ranges.join(",")
+}
+
+/// Insert `separator` between groups of three digits, e.g. `61,210`.
+fn group_digits(value: u64, separator: char) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::with_capacity(digits.len() * 4 / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// A memory or power figure, grouped with `--group-digits`. Like `decimal`,
+/// this is for the table only: machine formats keep plain C-locale numbers.
+fn figure(opts: &RenderOpts, value: u64) -> String {
+    if opts.units.group_digits {
+        group_digits(value, opts.units.digit_separator)
+    } else {
+        value.to_string()
+    }
+}
+
+/// `value` with `precision` decimals, written with the locale's decimal
+/// separator under `--locale-numbers`, e.g. `21,2`.
+fn decimal(opts: &RenderOpts, value: f64, precision: usize) -> String {
+    let s = format!("{:.*}", precision, value);
+    if opts.units.decimal_separator == '.' {
+        s
+    } else {
+        s.replace('.', &opts.units.decimal_separator.to_string())
+    }
+}
+
+/// Bytes in GiB with one decimal, e.g. `31.5`.
+fn gibibytes(opts: &RenderOpts, bytes: u64) -> String {
+    decimal(opts, bytes as f64 / (1u64 << 30) as f64, 1)
+}
+
+/// Memory used by one process, e.g. `1234M` or `1234M, 15%` with `--proc-percent`.
+fn process_memory(opts: &RenderOpts, process: &ProcessStat, memory_total: u64) -> String {
+    match process.used_memory {
+        None if process.scanned => String::from("~"),
+        None => String::from("Unavailable"),
+        Some(m) if opts.columns.proc_percent => {
+            format!("{}M, {}", m >> 20, memory_percent(m, memory_total))
+        }
+        Some(m) => format!("{}M", m >> 20),
+    }
+}
+
+/// `2 enc sess @ 60fps` for `--show-proc-codec`.
+fn encoder_sessions(encoder: EncoderUse) -> String {
+    format!("{} enc sess @ {}fps", encoder.sessions, encoder.average_fps)
+}
+
+/// Processes with more contexts than this on one GPU are highlighted by `--show-contexts`.
+const MANY_CONTEXTS: u32 = 4;
+
+/// Memory with accounting stats, e.g. `cur 8192M, max 12595M, 64% avg`, or plain memory without them.
+fn accounted_memory(opts: &RenderOpts, process: &ProcessStat, memory_total: u64) -> String {
+    let used = process_memory(opts, process, memory_total);
+    match (process.max_memory, process.average_utilization) {
+        (None, None) => used,
+        (max, utilization) => {
+            let mut s = format!("cur {}", used);
+            if let Some(max) = max {
+                s = s + &format!(", max {}M", max >> 20);
+            }
+            if let Some(utilization) = utilization {
+                s = s + &format!(", {}% avg", utilization);
+            }
+            s
+        }
+    }
+}
+
+/// The last two components of a path, e.g. `exp/run1`.
+fn short_path(path: &str) -> &str {
+    let trimmed = path.trim_end_matches('/');
+    match trimmed.rmatch_indices('/').nth(1) {
+        Some((i, _)) => &trimmed[i + 1..],
+        None => trimmed,
+    }
+}
+
+/// Placeholders understood by `--proc-format`.
+pub const PROC_FIELDS: &[&str] = &[
+    "user", "cmd", "full_cmd", "pid", "mem", "mem_pct", "runtime", "container", "location",
+];
+
+/// The process template selected by `-c`, `-f` and `-p` when `--proc-format` is not given.
+fn proc_format(opts: &RenderOpts) -> String {
+    if let Some(format) = &opts.proc_format {
+        return format.clone();
+    }
+    let mut format = String::from("{user}");
+    if opts.column("full_cmd") {
+        format += ":{full_cmd}";
+    } else if opts.columns.show_cmd {
+        format += ":{cmd}";
+    }
+    if opts.column("pid") {
+        format += "/{pid}";
+    }
+    format + "{location}({mem})"
+}
+
+/// Control characters as visible `\x1b`-style escapes, since process names
+/// and command lines are chosen by any local user and could otherwise drive
+/// the terminal.
+pub fn escape_control(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_control() {
+            escaped += &format!("\\x{:02x}", u32::from(c));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// One entry of the process cell, e.g. `user:cmd/pid(mem)` depending on the flags.
+pub fn process_entry(opts: &RenderOpts, process: &ProcessStat, memory_total: u64) -> String {
+    if process.stale {
+        let used = process_memory(opts, process, memory_total);
+        return format!("<exited pid {}>({})", process.pid, used);
+    }
+    if opts.group_procs {
+        let used = process_memory(opts, process, memory_total);
+        return format!("{}({})", process.pid, used);
+    }
+    // cells are colored whole, so a star tells the matches apart within one
+    let marker = if process.matched && !opts.match_only { "*" } else { "" };
+    let entry = template::expand(&proc_format(opts), |field| match field {
+        "user" => process.display_user(),
+        "cmd" => process.command.clone(),
+        "full_cmd" => process.full_command.clone(),
+        "pid" => process.pid.to_string(),
+        "mem" => {
+            let mut memory = accounted_memory(opts, process, memory_total);
+            if opts.columns.show_contexts && process.contexts > 1 {
+                let times = if opts.styling.ascii { "x" } else { "×" };
+                let each = process
+                    .used_memory
+                    .map(|m| format!("{}M", (m / u64::from(process.contexts)) >> 20))
+                    .unwrap_or_else(|| String::from("?"));
+                memory = format!("{}{}{} ctxs, {}", process.contexts, times, each, memory);
+            }
+            if let Some(growth) = process.memory_growth {
+                memory = memory + &format!(", +{}M/min", growth >> 20);
+            }
+            if let Some(host) = process.host_memory {
+                memory = memory + &format!(", RSS {}M", host >> 20);
+            }
+            match process.encoder.filter(|_| opts.columns.show_proc_codec) {
+                // processes known only from their sessions report no memory
+                Some(encoder) if process.kind == ProcessKind::Encoder && process.used_memory.is_none() => {
+                    encoder_sessions(encoder)
+                }
+                Some(encoder) => memory + ", " + &encoder_sessions(encoder),
+                None => memory,
+            }
+        }
+        "mem_pct" => process
+            .used_memory
+            .map(|m| memory_percent(m, memory_total))
+            .unwrap_or_default(),
+        "runtime" => {
+            let started = Local.timestamp_opt(process.start_time as i64, 0).single();
+            started
+                .filter(|_| process.start_time > 0)
+                .map(|started| short_age(Local::now().signed_duration_since(started)))
+                .unwrap_or_default()
+        }
+        "container" => process.pod.clone().unwrap_or_default(),
+        "location" => process
+            .env
+            .as_deref()
+            .or(process.cwd.as_deref().map(short_path))
+            .map(|location| format!("@{}", location))
+            .unwrap_or_default(),
+        _ => String::new(),
+    });
+    String::from(marker) + &escape_control(&entry)
+}
+
+/// Processes of one user on a GPU, for `--procs-by-user`.
+struct UserProcesses<'a> {
+    user: &'a str,
+    processes: Vec<&'a ProcessStat>,
+    /// Memory of the processes that report it, and whether any doesn't.
+    used: u64,
+    unavailable: bool,
+}
+
+/// Group the processes of a GPU per user, most memory first and by name on ties.
+fn processes_by_user(processes: &[ProcessStat]) -> Vec<UserProcesses<'_>> {
+    let mut users: BTreeMap<&str, UserProcesses> = BTreeMap::new();
+    for process in processes {
+        let entry = users
+            .entry(process.user.as_str())
+            .or_insert_with(|| UserProcesses {
+                user: &process.user,
+                processes: vec![],
+                used: 0,
+                unavailable: false,
+            });
+        entry.processes.push(process);
+        match process.used_memory {
+            Some(m) => entry.used += m,
+            None => entry.unavailable = true,
+        }
+    }
+    let mut users: Vec<UserProcesses> = users.into_values().collect();
+    users.sort_by(|a, b| b.used.cmp(&a.used).then_with(|| a.user.cmp(b.user)));
+    users
+}
+
+/// `--show-owner`: the user holding the most memory on the GPU and how many
+/// other users have processes there, `None` without processes or when the
+/// top two hold the same. Unresolved and stale processes are not counted.
+fn owner(gpu: &GpuStat) -> Option<(String, usize)> {
+    let counted: Vec<ProcessStat> = gpu
+        .processes
+        .iter()
+        .filter(|p| p.resolved && !p.stale && !p.user.is_empty())
+        .cloned()
+        .collect();
+    let users = processes_by_user(&counted);
+    match &users[..] {
+        [] => None,
+        [first, second, ..] if first.used == second.used => None,
+        [first, ..] => Some((first.user.to_string(), users.len() - 1)),
+    }
+}
+
+/// The color of `user` in `palette`, from an FNV-1a hash of the name so it
+/// is the same on every row and every run, unlike the std hasher.
+fn user_color(user: &str, palette: &[Color]) -> Color {
+    let hash = user
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3));
+    palette[(hash % palette.len() as u64) as usize]
+}
+
+impl UserProcesses<'_> {
+    /// e.g. `alice(3 procs, 21.2G)`, with `+?` when some memory is unavailable.
+    fn summary(&self, opts: &RenderOpts) -> String {
+        let count = self.processes.len();
+        let used = if self.unavailable && self.used == 0 {
+            String::from("Unavailable")
+        } else {
+            let used = gibibytes(opts, self.used) + "G";
+            if self.unavailable {
+                used + "+?"
+            } else {
+                used
+            }
+        };
+        let procs = if count == 1 { "proc" } else { "procs" };
+        format!("{}({} {}, {})", escape_control(self.user), count, procs, used)
+    }
+}
+
+/// A process on a GPU with the descendants it started there, for `--collapse-tree`.
+struct ProcessTree<'a> {
+    root: &'a ProcessStat,
+    descendants: Vec<&'a ProcessStat>,
+}
+
+/// Whether `child` was started by `parent`. A parent that started later
+/// only holds a reused PID, and exited processes are never related.
+fn started_by(child: &ProcessStat, parent: &ProcessStat) -> bool {
+    child.parent == Some(parent.pid)
+        && child.pid != parent.pid
+        && !child.stale
+        && !parent.stale
+        && parent.start_time > 0
+        && parent.start_time <= child.start_time
+}
+
+/// Group `processes` into trees under their topmost ancestor on the same GPU,
+/// in the order their first member is listed in. A process whose parent is not on the GPU is a
+/// root of its own, even if a grandparent is.
+fn process_trees<'a>(processes: &[&'a ProcessStat]) -> Vec<ProcessTree<'a>> {
+    let parent = |child: usize| processes.iter().position(|p| started_by(processes[child], p));
+    let mut trees: Vec<ProcessTree> = vec![];
+    let mut roots: Vec<usize> = vec![];
+    for (i, process) in processes.iter().enumerate() {
+        let (mut root, mut steps) = (i, 0);
+        while let Some(up) = parent(root) {
+            // parents listed in a cycle, as only reused PIDs started in the same second can be
+            steps += 1;
+            if up == i || steps > processes.len() {
+                root = i;
+                break;
+            }
+            root = up;
+        }
+        let tree = roots.iter().position(|&r| r == root).unwrap_or_else(|| {
+            roots.push(root);
+            trees.push(ProcessTree {
+                root: processes[root],
+                descendants: vec![],
+            });
+            trees.len() - 1
+        });
+        if root != i {
+            trees[tree].descendants.push(process);
+        }
+    }
+    trees
+}
+
+impl ProcessTree<'_> {
+    /// e.g. `alice:torchrun→python ×8 (total 64.2G)`, the workers being the descendants.
+    fn summary(&self, opts: &RenderOpts) -> String {
+        let (arrow, times) = if opts.styling.ascii { ("->", "x") } else { ("→", "×") };
+        let mut commands: Vec<&str> = vec![];
+        for process in &self.descendants {
+            if !commands.contains(&process.command.as_str()) {
+                commands.push(&process.command);
+            }
+        }
+        let members = || std::iter::once(self.root).chain(self.descendants.iter().copied());
+        let used: u64 = members().filter_map(|p| p.used_memory).sum();
+        let unavailable = members().any(|p| p.used_memory.is_none());
+        let used = match (unavailable, used) {
+            (true, 0) => String::from("Unavailable"),
+            (true, used) => gibibytes(opts, used) + "G+?",
+            (false, used) => gibibytes(opts, used) + "G",
+        };
+        let marker = if members().any(|p| p.matched) && !opts.match_only { "*" } else { "" };
+        let entry = format!(
+            "{}:{}{}{} {}{} (total {})",
+            self.root.display_user(),
+            self.root.command,
+            arrow,
+            commands.join("+"),
+            times,
+            self.descendants.len(),
+            used
+        );
+        String::from(marker) + &escape_control(&entry)
+    }
+}
+
+/// The process cell, grouped per user with `--procs-by-user` when several users share the GPU.
+fn process_cell(opts: &RenderOpts, gpu: &GpuStat) -> String {
+    let collapse = opts.collapse_tree && !opts.expand_gpus.contains(&gpu.order_index.unwrap_or(gpu.index));
+    let entries = |processes: &[&ProcessStat]| -> Vec<String> {
+        if !collapse {
+            return processes
+                .iter()
+                .map(|p| process_entry(opts, p, gpu.memory_total))
+                .collect();
+        }
+        process_trees(processes)
+            .iter()
+            .map(|tree| match tree.descendants[..] {
+                [] => process_entry(opts, tree.root, gpu.memory_total),
+                _ => tree.summary(opts),
+            })
+            .collect()
+    };
+    // once per GPU rather than on every process
+    let accounting_off = if gpu.accounting_enabled == Some(false) && !gpu.processes.is_empty() {
+        "accounting off "
+    } else {
+        ""
+    };
+    let oom = match gpu.oom_hint {
+        Some(hint) => format!(
+            "oom? pid {} {}{}{} GB (advisory) ",
+            hint.pid,
+            gibibytes(opts, hint.before),
+            if opts.styling.ascii { "->" } else { "→" },
+            gibibytes(opts, hint.after)
+        ),
+        None => String::new(),
+    };
+    let prefix = String::from(accounting_off) + &oom;
+    let users = processes_by_user(&gpu.processes);
+    if !opts.procs_by_user || users.len() < 2 {
+        let processes: Vec<&ProcessStat> = gpu.processes.iter().collect();
+        return prefix + &entries(&processes).join(",");
+    }
+    let groups: Vec<String> = users
+        .iter()
+        .map(|user| {
+            if opts.expand_users.iter().any(|u| u == user.user) {
+                entries(&user.processes).join(",")
+            } else {
+                user.summary(opts)
+            }
+        })
+        .collect();
+    prefix + &groups.join(" ")
+}
+
+/// A process seen on one or more GPUs, keyed by PID and start time.
+struct ProcessGroup {
+    label: String,
+    gpus: Vec<u32>,
+    used: Vec<Option<u64>>,
+}
+
+impl ProcessGroup {
+    fn summary(&self, opts: &RenderOpts) -> String {
+        let times = if opts.styling.ascii { "x" } else { "×" };
+        let used = if self.used.iter().any(Option::is_none) {
+            String::from("Unavailable")
+        } else {
+            let used: Vec<u64> = self.used.iter().flatten().map(|m| m >> 20).collect();
+            let total: u64 = used.iter().sum();
+            if used.len() == 1 {
+                format!("{}M", total)
+            } else if used.iter().all(|m| *m == used[0]) {
+                format!("{}{}{}M = {}M total", used.len(), times, used[0], total)
+            } else {
+                let parts: Vec<String> = used.iter().map(|m| format!("{}M", m)).collect();
+                format!("{} = {}M total", parts.join(" + "), total)
+            }
+        };
+        format!(
+            "{} on GPUs {}, {}",
+            self.label,
+            index_ranges(&self.gpus),
+            used
+        )
+    }
+}
+
+fn process_groups(gpus: &[GpuStat]) -> Vec<ProcessGroup> {
+    let mut groups: BTreeMap<(u32, u64), ProcessGroup> = BTreeMap::new();
+    for gpu in gpus {
+        for process in &gpu.processes {
+            let group = groups
+                .entry((process.pid, process.start_time))
+                .or_insert_with(|| {
+                    let cmd = if process.full_command.is_empty() {
+                        &process.command
+                    } else {
+                        &process.full_command
+                    };
+                    ProcessGroup {
+                        label: if process.stale {
+                            format!("<exited pid {}>", process.pid)
+                        } else {
+                            escape_control(&format!("{}:{}/{}", process.display_user(), cmd, process.pid))
+                        },
+                        gpus: vec![],
+                        used: vec![],
+                    }
+                });
+            group.gpus.push(gpu.index);
+            group.used.push(process.used_memory);
+        }
+    }
+    groups.into_values().collect()
+}
+
+/// `cool: mem 71°C, 22°C to slowdown, 115 W headroom`, leaving out what the
+/// GPU does not report, down to `cool: n/a`.
+fn cooling_label(opts: &RenderOpts, cooling: &Cooling) -> String {
+    let degree = if opts.styling.ascii { "" } else { "°" };
+    let mut parts = vec![];
+    if let Some(memory) = cooling.memory_temperature {
+        parts.push(format!("mem {}{}C", memory, degree));
+    }
+    match cooling.slowdown_margin {
+        Some(margin) if margin < 0 => parts.push(format!("{}{}C above slowdown", -margin, degree)),
+        Some(margin) => parts.push(format!("{}{}C to slowdown", margin, degree)),
+        None => {}
+    }
+    if let Some(headroom) = cooling.power_headroom {
+        parts.push(format!("{} W headroom", headroom / 1000));
+    }
+    if parts.is_empty() {
+        String::from("cool: n/a")
+    } else {
+        format!("cool: {}", parts.join(", "))
+    }
+}
+
+/// `~1.2/2.0 TB/s`, or `~420/936 GB/s` for boards below 1 TB/s; the tilde
+/// marks the used figure as an estimate.
+fn bandwidth_label(opts: &RenderOpts, bandwidth: &Bandwidth) -> String {
+    if bandwidth.peak >= 1000.0 {
+        let tb = |gb: f64| decimal(opts, gb / 1000.0, 1);
+        format!("~{}/{} TB/s", tb(bandwidth.used), tb(bandwidth.peak))
+    } else {
+        format!("~{:.0}/{:.0} GB/s", bandwidth.used, bandwidth.peak)
+    }
+}
+
+/// The GPU name with the `--distinguish` suffix, e.g. `NVIDIA A100-SXM4-80GB #3`.
+pub fn display_name(opts: &RenderOpts, gpu: &GpuStat) -> String {
+    match opts.distinguish {
+        None => gpu.name.clone(),
+        Some(Distinguish::Index) => format!("{} #{}", gpu.name, gpu.order_index.unwrap_or(gpu.index)),
+        Some(Distinguish::Uuid) => {
+            let digits: String = gpu.uuid.trim_start_matches("GPU-").chars().take(8).collect();
+            format!("{} #{}", gpu.name, digits)
+        }
+        Some(Distinguish::Bus) => format!("{} @{}", gpu.name, gpu.pci_bus_id.as_deref().unwrap_or("?")),
+    }
+}
+
+/// The index cell label, e.g. `[0]`, or `[2 (nvml 0)]` under `--order`.
+fn index_label(gpu: &GpuStat) -> String {
+    match gpu.order_index {
+        Some(position) => format!("[{} (nvml {})]", position, gpu.index),
+        None => format!("[{}]", gpu.index),
+    }
+}
+
+/// A copy of `gpu` with every fluctuating metric at its largest realistic
+/// value: full utilization and memory, power at the limit, 100°C and 5-digit clocks.
+fn widest(gpu: &GpuStat) -> GpuStat {
+    let full = |value: Option<u32>| value.map(|_| 100);
+    let mut gpu = gpu.clone();
+    gpu.temperature = 100;
+    gpu.temperatures.values_mut().for_each(|value| *value = 100);
+    gpu.utilization = 100;
+    gpu.memory_used = gpu.memory_total;
+    gpu.memory_free = gpu.memory_total;
+    gpu.memory_peak = gpu.memory_peak.map(|_| gpu.memory_total);
+    gpu.power_usage = gpu.power_usage.max(gpu.power_limit);
+    gpu.power_average = gpu.power_average.map(|_| gpu.power_usage);
+    gpu.power_peak = gpu.power_peak.map(|_| gpu.power_usage);
+    gpu.power_instant = gpu.power_instant.map(|_| gpu.power_usage);
+    gpu.power_driver_average = gpu.power_driver_average.map(|_| gpu.power_usage);
+    // as for a board of two modules at their limit
+    gpu.board_power = gpu.board_power.map(|board| board.max(gpu.power_usage.saturating_mul(2)));
+    gpu.window_max = gpu.window_max.map(|window| WindowMax {
+        power: window.power.map(|_| gpu.power_usage),
+        utilization: full(window.utilization),
+    });
+    gpu.fan_speed = full(gpu.fan_speed);
+    for fan in &mut gpu.fans {
+        fan.speed = full(fan.speed);
+        fan.rpm = fan.rpm.map(|rpm| rpm.max(9999));
+    }
+    gpu.encoder_utilization = full(gpu.encoder_utilization);
+    gpu.decoder_utilization = full(gpu.decoder_utilization);
+    gpu.perf = gpu.perf.map(|perf| Perf { percent: 100, ..perf });
+    gpu.cooling = gpu.cooling.map(|cooling| Cooling {
+        memory_temperature: cooling.memory_temperature.map(|_| 100),
+        slowdown_margin: cooling.slowdown_margin.map(|margin| margin.max(100)),
+        power_headroom: cooling.power_headroom.map(|_| gpu.power_limit),
+    });
+    gpu.bandwidth = gpu.bandwidth.map(|bandwidth| Bandwidth {
+        used: bandwidth.peak,
+        ..bandwidth
+    });
+    if let Some(clocks) = &mut gpu.app_clocks {
+        clocks.graphics = clocks.graphics.max(10000);
+        clocks.memory = clocks.memory.max(10000);
+    }
+    gpu
+}
+
+/// Content width of every column but the last, the processes, wide enough
+/// for the largest realistic value of each GPU.
+fn stable_widths(opts: &RenderOpts, gpus: &[GpuStat], empty: &[&str]) -> Vec<usize> {
+    let mut widths: Vec<usize> = vec![];
+    // bolding of the made-up values must not leak into the real styling
+    let mut scratch = Styling::new(0.0);
+    let indent = opts.group_by_board && board_groups(gpus).is_some();
+    for gpu in gpus.iter().filter(|gpu| gpu.measured()) {
+        let rows = [
+            gpu_row(opts, &mut scratch, gpu, empty, indent),
+            gpu_row(opts, &mut scratch, &widest(gpu), empty, indent),
+        ];
+        for row in rows {
+            for (i, cell) in row.iter().take(row.len() - 1).enumerate() {
+                let width = cell.content().lines().map(|line| line.chars().count()).max().unwrap_or_default();
+                match widths.get_mut(i) {
+                    Some(widest) => *widest = (*widest).max(width),
+                    None => widths.push(width),
+                }
+            }
+        }
+    }
+    widths
+}
+
+// Cells of the optional columns, which `columns::COLUMNS` registers in
+// table order. Each returns the column's cells of one GPU, empty ones when
+// the GPU has no value for it.
+
+pub(crate) fn fan_cells(opts: &RenderOpts, styling: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    let Some(fan_rates) = gpu.fan_speed else {
+        return vec![Cell::new("")];
+    };
+    let policy = match gpu.fan_policy {
+        Some(FanControlPolicy::TemperatureContinousSw) => " (auto)",
+        Some(FanControlPolicy::Manual) => " (manual)",
+        None => "",
+    };
+    let fan_cell = bold_limit!(
+        styling[(&gpu.uuid, "fan")],
+        fan_rates,
+        50,
+        theme.fan,
+        "F: {} %{}{}{}",
+        fan_rates,
+        clamped(gpu, "fan_speed"),
+        fan_rpm(gpu),
+        policy
+    );
+    if gpu.fans.iter().any(FanStat::stalled) {
+        vec![fan_cell.fg(theme.critical).add_attribute(Attribute::Bold)]
+    } else {
+        vec![fan_cell]
+    }
+}
+
+pub(crate) fn cooling_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    vec![match &gpu.cooling {
+        None => Cell::new(""),
+        Some(cooling) if cooling.slowdown_margin.is_some_and(|margin| margin < SLOWDOWN_MARGIN) => {
+            Cell::new(cooling_label(opts, cooling))
+                .fg(theme.critical)
+                .add_attribute(Attribute::Bold)
+        }
+        Some(cooling) => Cell::new(cooling_label(opts, cooling)).fg(theme.temperature),
+    }]
+}
+
+pub(crate) fn codec_cells(opts: &RenderOpts, styling: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    let (Some(en_util_rates), Some(de_util_rates)) = (gpu.encoder_utilization, gpu.decoder_utilization) else {
+        return vec![Cell::new(""), Cell::new("")];
+    };
+    let detail = opts.columns.show_codec_detail || opts.verbose > 0;
+    let en_period = codec_period(detail, gpu.encoder_sampling_period);
+    let de_period = codec_period(detail, gpu.decoder_sampling_period);
+    let encoder_cell = bold_limit!(
+        styling[(&gpu.uuid, "encoder")],
+        en_util_rates,
+        30,
+        theme.codec,
+        "E: {}{} %{}{}",
+        en_period.0,
+        en_util_rates,
+        clamped(gpu, "encoder_utilization"),
+        en_period.1
+    );
+    let decoder_cell = bold_limit!(
+        styling[(&gpu.uuid, "decoder")],
+        de_util_rates,
+        30,
+        theme.codec,
+        "D: {}{} %{}{}",
+        de_period.0,
+        de_util_rates,
+        clamped(gpu, "decoder_utilization"),
+        de_period.1
+    );
+    vec![encoder_cell, decoder_cell]
+}
+
+pub(crate) fn encoder_capacity_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    let capacities = [("H264", gpu.h264_capacity), ("HEVC", gpu.hevc_capacity)];
+    let capacities: Vec<(&str, u32)> = capacities
+        .iter()
+        .filter_map(|(codec, capacity)| capacity.map(|capacity| (*codec, capacity)))
+        .collect();
+    // cards without NVENC keep an empty cell so the columns stay aligned
+    let text: Vec<String> = capacities
+        .iter()
+        .map(|(codec, capacity)| format!("{} {}%", codec, capacity))
+        .collect();
+    let text = if text.is_empty() {
+        String::new()
+    } else {
+        format!("NVENC {}", text.join(" "))
+    };
+    let cell = Cell::new(text).fg(theme.codec);
+    if capacities.iter().any(|(_, capacity)| *capacity < 20) {
+        vec![cell.add_attribute(Attribute::Bold)]
+    } else {
+        vec![cell]
+    }
+}
+
+pub(crate) fn pcie_replays_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    let cell = match (gpu.pcie_replays, gpu.pcie_replays_delta) {
+        (Some(replays), Some(delta)) => Cell::new(format!("replays: {} (+{})", replays, delta)),
+        (Some(replays), None) => Cell::new(format!("replays: {}", replays)),
+        (None, _) => Cell::new(""),
+    };
+    let replays = gpu.pcie_replays.unwrap_or_default();
+    let cell = cell.fg(if replays > 0 { theme.critical } else { theme.pcie });
+    // a climbing counter matters more than an old, stable one
+    if gpu.pcie_replays_delta.unwrap_or(replays) > 0 {
+        vec![cell.add_attribute(Attribute::Bold)]
+    } else {
+        vec![cell]
+    }
+}
+
+pub(crate) fn throttle_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    let cell = match gpu.throttle_reasons {
+        None => Cell::new(""),
+        // watch mode: how much of the window each reason was active
+        Some(_) if !gpu.throttle_percent.is_empty() => {
+            let reasons: Vec<String> = THROTTLE_REASONS
+                .iter()
+                .filter_map(|(_, name)| Some(format!("{} {}%", name, gpu.throttle_percent.get(*name)?)))
+                .collect();
+            Cell::new(reasons.join(" / "))
+        }
+        Some(reasons) => {
+            let active: Vec<&str> = THROTTLE_REASONS
+                .iter()
+                .filter(|(reason, _)| reasons.contains(*reason))
+                .map(|(_, name)| *name)
+                .collect();
+            if active.is_empty() {
+                Cell::new("not throttled")
+            } else {
+                Cell::new(active.join(" / "))
+            }
+        }
+    };
+    vec![cell.fg(theme.throttle)]
+}
+
+pub(crate) fn perf_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    vec![match gpu.perf {
+        None => Cell::new(""),
+        Some(perf) => {
+            let reason = gpu
+                .dominant_throttle()
+                .map(|reason| format!(" ({})", reason))
+                .unwrap_or_default();
+            let cell = Cell::new(format!("perf: {}%{}", perf.percent, reason)).fg(theme.throttle);
+            // idle GPUs lower their clocks without losing anything
+            if perf.percent < opts.thresholds.perf && gpu.utilization > 0 {
+                cell.add_attribute(Attribute::Bold)
+            } else {
+                cell
+            }
+        }
+    }]
+}
+
+pub(crate) fn bandwidth_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    vec![match &gpu.bandwidth {
+        None => Cell::new(""),
+        Some(bandwidth) => Cell::new(bandwidth_label(opts, bandwidth)),
+    }]
+}
+
+pub(crate) fn app_clocks_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    let cell = match gpu.app_clocks {
+        None => Cell::new(""),
+        Some(clocks) => {
+            let cell = Cell::new(format!(
+                "app: {}/{} MHz (def {}/{})",
+                clocks.graphics, clocks.memory, clocks.default_graphics, clocks.default_memory
+            ));
+            if clocks.below_default() {
+                cell.fg(theme.critical).add_attribute(Attribute::Bold)
+            } else {
+                cell
+            }
+        }
+    };
+    vec![consistency_mark(
+        opts,
+        gpu.inconsistent.iter().any(|a| a == "app_clocks"),
+        cell,
+    )]
+}
+
+pub(crate) fn inforom_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    vec![match &gpu.inforom {
+        None => Cell::new(""),
+        Some(inforom) if inforom.corrupted() => Cell::new("inforom: CORRUPTED")
+            .fg(theme.critical)
+            .add_attribute(Attribute::Bold),
+        Some(inforom) if inforom.valid == Some(true) => Cell::new(format!("inforom: ok ({})", inforom.image)),
+        Some(inforom) => Cell::new(format!("inforom: {}", inforom.image)),
+    }]
+}
+
+pub(crate) fn cc_mode_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    vec![match gpu.cc_mode {
+        None => Cell::new("CC: n/a"),
+        Some(CcMode::DevTools) => Cell::new("CC: devtools").fg(theme.warning),
+        Some(mode) => Cell::new(format!("CC: {}", mode.label())),
+    }]
+}
+
+pub(crate) fn ecc_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+    let cell = match &gpu.ecc_mode {
+        None => Cell::new(""),
+        Some(mode) if mode.currently_enabled == mode.pending_enabled => {
+            Cell::new(format!("ECC {}", on_off(mode.currently_enabled))).fg(theme.ecc)
+        }
+        Some(mode) => Cell::new(format!(
+            "ECC {}{}{} (pending)",
+            on_off(mode.currently_enabled),
+            if opts.styling.ascii { "->" } else { "→" },
+            on_off(mode.pending_enabled)
+        ))
+        .fg(theme.critical)
+        .add_attribute(Attribute::Bold),
+    };
+    vec![consistency_mark(
+        opts,
+        gpu.inconsistent.iter().any(|a| a == "ecc"),
+        cell,
+    )]
+}
+
+pub(crate) fn mig_mode_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+    vec![match &gpu.mig_mode {
+        None => Cell::new(""),
+        Some(mode) if mode.current == mode.pending => Cell::new(format!("MIG: {}", on_off(mode.current))).fg(theme.mig),
+        Some(mode) => Cell::new(format!(
+            "MIG: {}{}{} (pending reset)",
+            on_off(mode.current),
+            if opts.styling.ascii { "->" } else { "→" },
+            on_off(mode.pending)
+        ))
+        .fg(theme.critical)
+        .add_attribute(Attribute::Bold),
+    }]
+}
+
+pub(crate) fn display_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    vec![match (gpu.displays, gpu.display_active) {
+        (Some(1), _) => Cell::new("1 display").fg(theme.display),
+        (Some(n), _) if n > 1 => Cell::new(format!("{} displays", n)).fg(theme.display),
+        (None, Some(true)) => Cell::new("display").fg(theme.display),
+        _ => Cell::new(""),
+    }]
+}
+
+pub(crate) fn allocations_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let pods = gpu.pods();
+    vec![if pods.is_empty() {
+        Cell::new("unallocated").add_attribute(Attribute::Dim)
+    } else {
+        Cell::new(escape_control(&pods.join(", "))).fg(opts.styling.palette.theme().affinity)
+    }]
+}
+
+pub(crate) fn sharing_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    vec![match gpu.sharing {
+        Some(sharing) => {
+            let times = if opts.styling.ascii { "x" } else { "×" };
+            let at_least = if !sharing.inferred {
+                ""
+            } else if opts.styling.ascii {
+                ">="
+            } else {
+                "≥"
+            };
+            let cell = Cell::new(format!("timeslice {}{}{}", at_least, times, sharing.replicas));
+            // the GPU looks free to each pod but is not
+            if sharing.pods > 1 {
+                cell.fg(theme.warning)
+            } else {
+                cell.fg(theme.affinity)
+            }
+        }
+        None => Cell::new(""),
+    }]
+}
+
+pub(crate) fn handles_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    vec![match gpu.handles {
+        Some(handles) => {
+            let at_least = if !handles.partial {
+                ""
+            } else if opts.styling.ascii {
+                ">="
+            } else {
+                "≥"
+            };
+            let cell = Cell::new(format!(
+                "handles: {}{} ({} procs)",
+                at_least,
+                figure(opts, u64::from(handles.descriptors)),
+                handles.processes
+            ));
+            if handles.descriptors > opts.thresholds.handles {
+                cell.fg(theme.critical).add_attribute(Attribute::Bold)
+            } else {
+                cell.fg(theme.pcie)
+            }
+        }
+        None => Cell::new(""),
+    }]
+}
+
+pub(crate) fn xid_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    vec![match &gpu.xid {
+        None => Cell::new(""),
+        Some(Ok(None)) => Cell::new("no Xid").fg(theme.pcie),
+        Some(Ok(Some(event))) => {
+            let age = Local::now().signed_duration_since(event.time);
+            let cell = Cell::new(format!("XID {}, {} ago", event.code, short_age(age)));
+            if age < chrono::Duration::hours(XID_RECENT_HOURS) {
+                cell.fg(theme.critical).add_attribute(Attribute::Bold)
+            } else {
+                cell.fg(theme.pcie)
+            }
+        }
+        Some(Err(reason)) => Cell::new(format!("xid: n/a ({})", reason)).add_attribute(Attribute::Dim),
+    }]
+}
+
+pub(crate) fn virtualization_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let Some(label) = gpu.virtualization.as_ref().and_then(virtualization_label) else {
+        return vec![Cell::new("")];
+    };
+    let mut virt = label.to_string();
+    if let Some(GpuVirtualizationMode::HostVgpu) = gpu.virtualization {
+        virt = format!("{} ({} active)", virt, gpu.vgpus.len());
+    }
+    vec![Cell::new(virt).fg(opts.styling.palette.theme().virtualization)]
+}
+
+pub(crate) fn affinity_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let Some(cpus) = &gpu.cpu_affinity else {
+        return vec![Cell::new("")];
+    };
+    let mut affinity = format!("CPUs {}", index_ranges(cpus));
+    if let Some(nodes) = gpu.numa_nodes.as_ref().filter(|nodes| !nodes.is_empty()) {
+        affinity = format!("{} NUMA {}", affinity, index_ranges(nodes));
+    }
+    vec![Cell::new(affinity).fg(opts.styling.palette.theme().affinity)]
+}
+
+pub(crate) fn owner_cells(opts: &RenderOpts, _: &mut Styling, gpu: &GpuStat) -> Vec<Cell> {
+    let owners = opts.styling.palette.theme().owners;
+    vec![match owner(gpu) {
+        Some((user, 0)) => Cell::new(escape_control(&user)).fg(user_color(&user, owners)),
+        Some((user, others)) => {
+            Cell::new(format!("{} (+{})", escape_control(&user), others)).fg(user_color(&user, owners))
+        }
+        None => Cell::new("-").add_attribute(Attribute::Dim),
+    }]
+}
+
+/// The cells of the optional columns that show and go `after_memory` or
+/// before the power, `off` for those whose collector is disabled.
+fn column_cells(
+    opts: &RenderOpts,
+    styling: &mut Styling,
+    gpu: &GpuStat,
+    empty: &[&str],
+    after_memory: bool,
+) -> Vec<Cell> {
+    let mut cells = vec![];
+    for column in COLUMNS.iter().filter(|column| opts.column(column.name)) {
+        let Some(column_cells) = column.cells.as_ref().filter(|cells| cells.after_memory == after_memory) else {
+            continue;
+        };
+        if column.collectors.iter().any(|collector| opts.collector_off(collector)) {
+            cells.extend((0..column_cells.span).map(|_| off_cell()));
+        } else if !empty.contains(&column.name) {
+            cells.extend((column_cells.render)(opts, styling, gpu));
+        }
+    }
+    cells
+}
+
+fn gpu_row(opts: &RenderOpts, styling: &mut Styling, gpu: &GpuStat, empty: &[&str], indent: bool) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    if gpu.reset_required {
+        return vec![
+            Cell::new(index_label(gpu)).fg(theme.critical),
+            Cell::new("RESET REQUIRED")
+                .fg(theme.critical)
+                .add_attribute(Attribute::Bold),
+        ];
+    }
+    if let Some(error) = &gpu.error {
+        return vec![
+            Cell::new(index_label(gpu)).fg(theme.critical),
+            Cell::new(format!("ERROR: {}", error)).fg(theme.critical),
+        ];
+    }
+    if gpu.suspended {
+        // snapshots recorded before names came from the PCI ID database lack it
+        let label = match gpu.name.as_str() {
+            "" => String::from("suspended"),
+            _ => format!("{} (suspended)", display_name(opts, gpu)),
+        };
+        return vec![
+            Cell::new(index_label(gpu)).fg(theme.index),
+            Cell::new(label).add_attribute(Attribute::Dim),
+        ];
+    }
+
+    let mut name = display_name(opts, gpu);
+    if let Some(brand) = gpu.brand {
+        name = format!("{} ({})", name, brand_tag(brand));
+    }
+    let shares_display = gpu.shares_display();
+    if shares_display {
+        name += " [shared w/ display]";
+    }
+    if gpu.power_brake {
+        name += " POWER BRAKE";
+    }
+
+    // the hottest sensor drives the bolding with --show-temps-all
+    let temperature = gpu
+        .temperatures
+        .values()
+        .copied()
+        .max()
+        .unwrap_or(gpu.temperature); // 50
+    let window = gpu.window_max.unwrap_or_default();
+    // the driver's samples since the last refresh catch spikes between frames
+    let util_rates = window.utilization.map_or(gpu.utilization, |max| max.max(gpu.utilization)); // 30
+    let device_memory_rates = gpu.memory_used as f64 / gpu.memory_total as f64; // 50
+
+    let degree = if opts.styling.ascii { "" } else { "°" };
+    let delta = gpu.baseline_delta;
+    let temperature_delta = delta.map(|d| format!(" ({:+})", d.temperature)).unwrap_or_default();
+    let target = gpu.temperature_target.filter(|_| opts.columns.temp_detail);
+    let temperature_delta = match target {
+        Some(target) => format!("{} (target {})", temperature_delta, target),
+        None => temperature_delta,
+    };
+    let temperature_cell = if gpu.temperatures.is_empty() {
+        bold_limit!(styling[(&gpu.uuid, "temperature")], temperature, 50, theme.temperature, "{}{}C{}", temperature, degree, temperature_delta)
+    } else {
+        let sensors: Vec<String> = gpu
+            .temperatures
+            .iter()
+            .map(|(sensor, value)| format!("{} {}", sensor, value))
+            .collect();
+        bold_limit!(styling[(&gpu.uuid, "temperature")], temperature, 50, theme.temperature, "{}{}C{}", sensors.join(" / "), degree, temperature_delta)
+    };
+    // the GPU holds its clocks back to stay below the target
+    let temperature_cell = match target {
+        Some(target) if gpu.temperature + TARGET_MARGIN >= target => temperature_cell.fg(theme.warning),
+        _ => temperature_cell,
+    };
+    let threshold = &opts.thresholds.change;
+    let temperature_cell = regression(temperature_cell, delta.map(|d| d.temperature), u64::from(threshold.temp), theme);
+    let utilization_cell = bold_limit!(styling[(&gpu.uuid, "utilization")], util_rates, 30, theme.utilization, "{} %{}{}", gpu.utilization, clamped(gpu, "utilization"), window_note(window.utilization, gpu.utilization));
+
+    let critical = opts.styling.highlight_rows && gpu.critical();
+    let index = if critical {
+        format!("!{}", index_label(gpu))
+    } else {
+        index_label(gpu)
+    };
+    // members of a --group-by-board group sit under its board row
+    let index = if indent { format!("  {}", index) } else { index };
+
+    // a GPU driving displays stands out even without the displays column
+    let drives_display = gpu.display_active == Some(true) || gpu.displays.is_some_and(|n| n > 0);
+    let inconsistent = |attribute: &str| gpu.inconsistent.iter().any(|a| a == attribute);
+    // attributes without a cell of their own are marked on the name
+    let own_cell = |attribute: &str| {
+        attribute == "power_limit" || (matches!(attribute, "ecc" | "app_clocks") && opts.column(attribute))
+    };
+    let name_cell = match (gpu.power_brake, shares_display) {
+        (true, _) => Cell::new(name).fg(theme.critical).add_attribute(Attribute::Bold),
+        (false, true) => Cell::new(name).fg(theme.warning),
+        (false, false) => Cell::new(name).fg(theme.name),
+    };
+    let mut row = vec![
+        Cell::new(index).fg(if drives_display { theme.display } else { theme.index }), // index
+        consistency_mark(opts, gpu.inconsistent.iter().any(|a| !own_cell(a)), name_cell), // gpu type name
+        temperature_cell,
+        utilization_cell,
+    ];
+
+    row.extend(column_cells(opts, styling, gpu, empty, false));
+
+    // the averaged reading keeps transients from flickering the bold threshold
+    let readings = gpu.power_driver_average.zip(gpu.power_instant);
+    let drawn = readings.map_or(gpu.power_usage, |(average, _)| average);
+    let power_max = window.power.map_or(drawn, |max| max.max(drawn));
+    let pow_rates = power_max as f32 / gpu.power_limit as f32; // 50
+    let watts = |mw: u32| figure(opts, u64::from(mw / 1000));
+    let power_usage = match (readings, gpu.power_average, gpu.power_peak) {
+        (Some((average, instant)), _, peak) => format!(
+            "avg {} W (inst {}{})",
+            watts(average),
+            watts(instant),
+            peak.map(|peak| format!(", peak {}", watts(peak))).unwrap_or_default()
+        ),
+        (None, Some(average), Some(peak)) => format!(
+            "{} W (avg {}, peak {})",
+            watts(gpu.power_usage),
+            watts(average),
+            watts(peak)
+        ),
+        _ => watts(gpu.power_usage),
+    };
+    let power_usage = match gpu.separate_board_power() {
+        Some(board) => format!("module {} / board {}", power_usage, watts(board)),
+        None => power_usage,
+    };
+    let pow_cell = bold_limit!(
+        styling[(&gpu.uuid, "power")],
+        pow_rates,
+        0.5,
+        theme.power,
+        "{} / {} W{}{}",
+        power_usage,
+        figure(opts, u64::from(gpu.power_limit / 1000)),
+        window_note(window.power.map(|mw| mw / 1000), gpu.power_usage / 1000),
+        delta.map(|d| format!(" ({:+})", d.power / 1000)).unwrap_or_default()
+    );
+    let pow_cell = regression(pow_cell, delta.map(|d| d.power), u64::from(threshold.power), theme);
+    let pow_cell = consistency_mark(opts, inconsistent("power_limit"), pow_cell).set_alignment(CellAlignment::Right);
+    let gb = |bytes: u64| gibibytes(opts, bytes);
+    let reserved = gpu.memory_reserved.filter(|_| opts.columns.show_reserved);
+    let memory_delta = delta
+        .map(|d| match opts.columns.mem_display {
+            MemoryDisplay::Used => format!(" ({:+})", d.memory / (1 << 20)),
+            MemoryDisplay::Free | MemoryDisplay::Both => {
+                let sign = if d.memory < 0 { "-" } else { "+" };
+                format!(" ({}{})", sign, gibibytes(opts, d.memory.unsigned_abs()))
+            }
+        })
+        .unwrap_or_default();
+    let memory_cell = match opts.columns.mem_display {
+        MemoryDisplay::Used => bold_limit!(
+            styling[(&gpu.uuid, "memory")],
+            device_memory_rates,
+            0.5,
+            theme.memory,
+            "{}{} / {} MB{}{}",
+            figure(opts, gpu.memory_used >> 20),
+            reserved.map(|r| format!(" + {}r", figure(opts, r >> 20))).unwrap_or_default(),
+            figure(opts, gpu.memory_total >> 20),
+            clamped(gpu, "memory_used"),
+            memory_delta
+        ),
+        // bold once free memory drops below half, the same limit seen from the other side
+        MemoryDisplay::Free => bold_limit!(
+            styling[(&gpu.uuid, "memory")],
+            1.0 - gpu.memory_free as f64 / gpu.memory_total as f64,
+            0.5,
+            theme.memory,
+            "{} / {} GB free{}",
+            gb(gpu.memory_free),
+            gb(gpu.memory_total),
+            memory_delta
+        ),
+        MemoryDisplay::Both => bold_limit!(
+            styling[(&gpu.uuid, "memory")],
+            1.0 - gpu.memory_free as f64 / gpu.memory_total as f64,
+            0.5,
+            theme.memory,
+            "{} used{} / {} free{} / {} GB{}",
+            gb(gpu.memory_used),
+            clamped(gpu, "memory_used"),
+            gb(gpu.memory_free),
+            reserved.map(|r| format!(" / {} reserved", gb(r))).unwrap_or_default(),
+            gb(gpu.memory_total),
+            memory_delta
+        ),
+    };
+    let memory_cell = regression(memory_cell, delta.map(|d| d.memory), threshold.mem, theme);
+
+    row.push(pow_cell);
+    row.push(memory_cell);
+    if let Some(peak) = gpu.memory_peak {
+        let peak = match opts.columns.mem_display {
+            MemoryDisplay::Used => format!("(peak {} MB)", figure(opts, peak >> 20)),
+            MemoryDisplay::Free | MemoryDisplay::Both => format!("(peak {} GB)", gb(peak)),
+        };
+        row.push(
+            Cell::new(peak)
+                .fg(theme.memory)
+                .add_attribute(Attribute::Dim)
+                .set_alignment(CellAlignment::Right),
+        );
+    }
+    if opts.columns.show_uvm {
+        row.push(match gpu.oversubscription() {
+            Some(requested) => Cell::new(format!("oversub {}/{} GB", gb(requested), gb(gpu.memory_total)))
+                .fg(theme.critical)
+                .add_attribute(Attribute::Bold),
+            None => Cell::new(""),
+        });
+    }
+    if opts.columns.show_proc_count {
+        let count = gpu.all_processes.unwrap_or_else(|| ProcessCount::of(&gpu.processes));
+        row.push(match (count.compute, count.graphics) {
+            (0, 0) => Cell::new("idle").add_attribute(Attribute::Dim),
+            (n, 0) | (0, n) => Cell::new(format!("{} {}", n, if n == 1 { "proc" } else { "procs" })).fg(theme.processes),
+            (compute, graphics) => Cell::new(format!("{}C+{}G", compute, graphics)).fg(theme.processes),
+        });
+    }
+    row.extend(column_cells(opts, styling, gpu, empty, true));
+    let many_contexts = opts.columns.show_contexts && gpu.processes.iter().any(|p| p.contexts > MANY_CONTEXTS);
+    let growth = gpu.processes.iter().filter_map(|p| p.memory_growth).max();
+    let foreign = gpu.processes.iter().any(|p| p.foreign);
+    row.push(
+        if many_contexts || foreign || growth.is_some_and(|growth| growth >> 20 >= opts.thresholds.mem_growth) {
+            Cell::new(process_cell(opts, gpu))
+                .fg(theme.critical)
+                .add_attribute(Attribute::Bold)
+        } else if gpu.processes.iter().any(|p| p.matched) {
+            Cell::new(process_cell(opts, gpu))
+                .fg(theme.matched)
+                .add_attribute(Attribute::Bold)
+        } else if growth.is_some() {
+            Cell::new(process_cell(opts, gpu)).fg(theme.warning)
+        } else if let Some(missing) = gpu.missing_processes(opts.thresholds.busy) {
+            match missing {
+                MissingProcesses::Denied => Cell::new("process list denied").add_attribute(Attribute::Dim),
+                MissingProcesses::NotVisible => Cell::new("busy, processes not visible").fg(theme.warning),
+            }
+        } else if let Some(idle) = gpu.idle_seconds.filter(|_| gpu.processes.is_empty()) {
+            Cell::new(format!("idle {}", idle_duration(idle))).add_attribute(Attribute::Dim)
+        } else {
+            Cell::new(process_cell(opts, gpu)).fg(theme.processes)
+        },
+    );
+
+    if critical {
+        row = row
+            .into_iter()
+            .map(|cell| cell.fg(theme.critical))
+            .collect();
+    }
+    row
+}
+
+pub const DEFAULT_ONELINE_FORMAT: &str = "{index}:{util}%/{mem}G";
+
+/// Placeholders understood by `--oneline-format`.
+pub const ONELINE_FIELDS: &[&str] = &["index", "name", "uuid", "bus", "util", "mem", "mem_total", "temp", "power"];
+
+/// Memory in GiB, with one decimal below 10G, e.g. `0.4` or `31`.
+fn gigabytes(bytes: u64) -> String {
+    let gb = bytes as f64 / (1u64 << 30) as f64;
+    if gb < 10.0 {
+        format!("{:.1}", gb)
+    } else {
+        format!("{:.0}", gb)
+    }
+}
+
+fn oneline_entry(opts: &RenderOpts, gpu: &GpuStat) -> String {
+    let index = gpu.order_index.unwrap_or(gpu.index);
+    if gpu.reset_required || gpu.error.is_some() {
+        return format!("{}:ERR", index);
+    }
+    if gpu.suspended {
+        return format!("{}:off", index);
+    }
+    let format = opts.oneline_format.as_deref().unwrap_or(DEFAULT_ONELINE_FORMAT);
+    template::expand(format, |field| match field {
+        "index" => index.to_string(),
+        "name" => display_name(opts, gpu),
+        "uuid" => gpu.uuid.clone(),
+        "bus" => gpu.pci_bus_id.clone().unwrap_or_default(),
+        "util" => format!("{:02}", gpu.utilization),
+        "mem_total" => gigabytes(gpu.memory_total),
+        "mem" => gigabytes(gpu.memory_used),
+        "temp" => gpu.temperature.to_string(),
+        "power" => (gpu.power_usage / 1000).to_string(),
+        _ => String::new(),
+    })
+}
+
+/// Render a snapshot as a single status-bar line, e.g. `0:68%/31G 1:02%/0.4G`.
+pub fn render_oneline(opts: &RenderOpts, snapshot: &Snapshot) -> String {
+    let entries: Vec<String> = snapshot
+        .gpus
+        .iter()
+        .map(|gpu| oneline_entry(opts, gpu))
+        .collect();
+    entries.join(&opts.oneline_separator) + "\n"
+}
+
+/// Options a renderer reads, resolved from the command line once per run:
+/// which columns show, when figures turn bold, how numbers and times are
+/// written and how the output is styled, and what else the output holds.
+/// A third-party renderer gets the same ones the built-in renderers get.
+#[derive(Clone, Debug)]
+pub struct RenderOpts {
+    pub columns: ColumnOpts,
+    pub thresholds: Thresholds,
+    pub units: Units,
+    pub styling: StyleOpts,
+    /// `--procs-only`: one row per process instead of the GPU table.
+    pub procs_only: bool,
+    pub include_idle_marker: bool,
+    /// `--minimal`: the short table of the minimal collection.
+    pub minimal: bool,
+    pub proc_format: Option<String>,
+    pub oneline_format: Option<String>,
+    pub oneline_separator: String,
+    pub csv_fields: Vec<String>,
+    pub csv_delimiter: char,
+    pub csv_level: CsvLevel,
+    /// Whether the output goes to `--output` instead of stdout.
+    pub to_file: bool,
+    pub no_header: bool,
+    pub no_hostname: bool,
+    pub no_timestamp: bool,
+    pub no_driver: bool,
+    pub no_hints: bool,
+    /// Seconds between frames in watch mode.
+    pub interval: Option<f64>,
+    /// `--adaptive-interval`, whose current interval the header shows.
+    pub adaptive_interval: bool,
+    /// `--cache` duration, which a replayed snapshot's age is shown against.
+    pub cache: Option<Duration>,
+    pub verbose: u8,
+    pub group_by_board: bool,
+    pub group_procs: bool,
+    pub procs_by_user: bool,
+    pub collapse_tree: bool,
+    pub expand_users: Vec<String>,
+    pub expand_gpus: Vec<u32>,
+    pub match_only: bool,
+    pub distinguish: Option<Distinguish>,
+}
+
+/// Which optional columns show, and what the columns hold.
+#[derive(Clone, Debug)]
+pub struct ColumnOpts {
+    /// Names of the optional columns shown, see `Opts::column`.
+    pub shown: HashSet<&'static str>,
+    /// Collectors `--disable-collector` switched off, whose cells read `off`.
+    pub disabled_collectors: Vec<String>,
+    /// `--keep-empty-columns`: show optional columns no GPU has a value for.
+    pub keep_empty: bool,
+    pub show_cmd: bool,
+    pub show_contexts: bool,
+    pub show_proc_codec: bool,
+    pub show_proc_count: bool,
+    pub show_reserved: bool,
+    pub show_uvm: bool,
+    pub show_codec_detail: bool,
+    pub temp_detail: bool,
+    pub proc_percent: bool,
+    pub mem_display: MemoryDisplay,
+}
+
+/// Limits past which figures are bold or flagged.
+#[derive(Clone, Debug)]
+pub struct Thresholds {
+    /// Utilization in percent above which a GPU counts as busy.
+    pub busy: u32,
+    /// Clock performance in percent below which a busy GPU's is bold.
+    pub perf: u32,
+    /// Descriptors on one GPU above which the handles cell turns red.
+    pub handles: u32,
+    /// MiB per minute of process memory growth that is flagged.
+    pub mem_growth: u64,
+    /// `--changes-only` margins, below which a change is not highlighted.
+    pub change: ChangeThreshold,
+    /// Percent of its limit a bold cell must drop below it to turn plain
+    /// again in watch mode.
+    pub hysteresis: f64,
+}
+
+/// How numbers and times are written.
+#[derive(Clone, Debug)]
+pub struct Units {
+    pub group_digits: bool,
+    pub digit_separator: char,
+    pub decimal_separator: char,
+    pub time_format: Option<String>,
+    pub utc: bool,
+    pub epoch: bool,
+}
+
+/// How the output is styled.
+#[derive(Clone, Debug)]
+pub struct StyleOpts {
+    /// Whether colors and attributes are written, see `styling_enabled`.
+    pub enabled: bool,
+    /// Whether JSON is indented, see `pretty_json`.
+    pub pretty: bool,
+    pub palette: Palette,
+    pub ascii: bool,
+    pub fixed_width: bool,
+    pub highlight_rows: bool,
+    pub layout: Layout,
+}
+
+impl From<&Opts> for RenderOpts {
+    fn from(opts: &Opts) -> Self {
+        RenderOpts {
+            columns: ColumnOpts {
+                shown: COLUMNS
+                    .iter()
+                    .map(|column| column.name)
+                    .filter(|name| opts.column(name))
+                    .collect(),
+                disabled_collectors: opts.disable_collector.clone(),
+                keep_empty: opts.keep_empty_columns,
+                show_cmd: opts.show_cmd,
+                show_contexts: opts.show_contexts,
+                show_proc_codec: opts.show_proc_codec,
+                show_proc_count: opts.show_proc_count,
+                show_reserved: opts.show_reserved,
+                show_uvm: opts.show_uvm,
+                show_codec_detail: opts.show_codec_detail,
+                temp_detail: opts.temp_detail,
+                proc_percent: opts.proc_percent,
+                mem_display: opts.mem_display,
+            },
+            thresholds: Thresholds {
+                busy: opts.busy_threshold,
+                perf: opts.perf_threshold,
+                handles: opts.handles_threshold,
+                mem_growth: opts.mem_growth_alert,
+                change: opts.change_threshold.clone(),
+                hysteresis: opts.hysteresis,
+            },
+            units: Units {
+                group_digits: opts.group_digits,
+                digit_separator: opts.digit_separator,
+                decimal_separator: opts.decimal_separator,
+                time_format: opts.time_format.clone(),
+                utc: opts.utc,
+                epoch: opts.epoch,
+            },
+            styling: StyleOpts {
+                enabled: styling_enabled(opts),
+                pretty: pretty_json(opts),
+                palette: opts.palette,
+                ascii: opts.ascii,
+                fixed_width: opts.fixed_width,
+                highlight_rows: opts.highlight_rows,
+                layout: opts.layout,
+            },
+            procs_only: opts.procs_only,
+            include_idle_marker: opts.include_idle_marker,
+            minimal: opts.minimal,
+            proc_format: opts.proc_format.clone(),
+            oneline_format: opts.oneline_format.clone(),
+            oneline_separator: opts.oneline_separator.clone(),
+            csv_fields: opts.csv_fields.clone(),
+            csv_delimiter: opts.csv_delimiter,
+            csv_level: opts.csv_level,
+            to_file: opts.output.is_some(),
+            no_header: opts.no_header,
+            no_hostname: opts.no_hostname,
+            no_timestamp: opts.no_timestamp,
+            no_driver: opts.no_driver,
+            no_hints: opts.no_hints,
+            interval: opts.interval,
+            adaptive_interval: opts.adaptive_interval.is_some(),
+            cache: opts.cache,
+            verbose: opts.verbose,
+            group_by_board: opts.group_by_board,
+            group_procs: opts.group_procs,
+            procs_by_user: opts.procs_by_user,
+            collapse_tree: opts.collapse_tree,
+            expand_users: opts.expand_users.clone(),
+            expand_gpus: opts.expand_gpus.clone(),
+            match_only: opts.match_only,
+            distinguish: opts.distinguish,
+        }
+    }
+}
+
+impl RenderOpts {
+    /// Whether the optional column `name` shows.
+    pub fn column(&self, name: &str) -> bool {
+        debug_assert!(
+            COLUMNS.iter().any(|column| column.name == name),
+            "unregistered column {}",
+            name
+        );
+        self.columns.shown.contains(name)
+    }
+
+    /// Whether `--disable-collector` switched off the collector `name`.
+    pub fn collector_off(&self, name: &str) -> bool {
+        self.columns.disabled_collectors.iter().any(|off| off == name)
+    }
+}
+
+/// Turns a snapshot into text. Implement this to reuse collection with your own output.
+pub trait Renderer {
+    fn render(&self, snapshot: &Snapshot, opts: &RenderOpts) -> String;
+}
+
+/// The default table, keeping bold thresholds steady across watch mode frames.
+#[derive(Debug, Default)]
+pub struct TableRenderer {
+    styling: RefCell<Styling>,
+}
+
+impl TableRenderer {
+    pub fn new(hysteresis: f64) -> Self {
+        TableRenderer {
+            styling: RefCell::new(Styling::new(hysteresis)),
+        }
+    }
+}
+
+impl Renderer for TableRenderer {
+    fn render(&self, snapshot: &Snapshot, opts: &RenderOpts) -> String {
+        if opts.procs_only {
+            render_procs(opts, snapshot)
+        } else if opts.minimal {
+            render_minimal(opts, snapshot)
+        } else {
+            render(opts, &mut self.styling.borrow_mut(), snapshot)
+        }
+    }
+}
+
+/// One line of JSON per snapshot, see `Snapshot::from_json` for reading it back.
+#[derive(Debug, Default)]
+pub struct JsonRenderer;
+
+impl Renderer for JsonRenderer {
+    fn render(&self, snapshot: &Snapshot, opts: &RenderOpts) -> String {
+        if opts.procs_only {
+            return render_procs_json(opts, snapshot);
+        }
+        if !opts.styling.pretty {
+            return serde_json::to_string(snapshot).expect("snapshots always serialize") + "\n";
+        }
+        let json = serde_json::to_string_pretty(snapshot).expect("snapshots always serialize");
+        if opts.styling.enabled {
+            colorize_json(&json) + "\n"
+        } else {
+            json + "\n"
+        }
+    }
+}
+
+/// The `--format oneline` status bar line.
+#[derive(Debug, Default)]
+pub struct OnelineRenderer;
+
+impl Renderer for OnelineRenderer {
+    fn render(&self, snapshot: &Snapshot, opts: &RenderOpts) -> String {
+        render_oneline(opts, snapshot)
+    }
+}
+
+/// Names and descriptions of the built-in renderers accepted by `--format`.
+pub const FORMATS: [(&str, &str); 4] = [
+    ("table", "the GPU table with optional summaries (default)"),
+    ("json", "one JSON object per snapshot, one per line in watch mode, see `gpustat schema`"),
+    ("oneline", "one short line for status bars, see --oneline-format"),
+    ("csv", "RFC 4180 rows per GPU or process under a header, see --csv-fields"),
+];
+
+/// Renderers by format name.
+#[derive(Default)]
+pub struct Registry {
+    renderers: BTreeMap<String, Box<dyn Renderer>>,
+}
+
+impl Registry {
+    /// All built-in renderers, registered under the names in `FORMATS`.
+    pub fn builtin(opts: &RenderOpts) -> Self {
+        let mut registry = Registry::default();
+        registry.register("table", TableRenderer::new(opts.thresholds.hysteresis));
+        registry.register("json", JsonRenderer);
+        registry.register("oneline", OnelineRenderer);
+        registry.register("csv", crate::csv::CsvRenderer::default());
+        registry
+    }
+
+    /// Add a renderer, replacing any previous one of the same name.
+    pub fn register(&mut self, name: &str, renderer: impl Renderer + 'static) {
+        self.renderers.insert(name.to_string(), Box::new(renderer));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Renderer> {
+        self.renderers.get(name).map(|renderer| renderer.as_ref())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.renderers.keys().map(String::as_str)
+    }
+}
+
+/// `--procs-only` rows: every process with its GPU, and with `--include-idle-marker`
+/// a `None` for each GPU running none.
+pub(crate) fn process_rows<'a>(
+    opts: &RenderOpts,
+    snapshot: &'a Snapshot,
+) -> Vec<(&'a GpuStat, Option<&'a ProcessStat>)> {
+    let mut rows = vec![];
+    for gpu in &snapshot.gpus {
+        if gpu.processes.is_empty() && opts.include_idle_marker && gpu.error.is_none() {
+            rows.push((gpu, None));
+        }
+        rows.extend(gpu.processes.iter().map(|process| (gpu, Some(process))));
+    }
+    rows
+}
+
+/// Seconds a process has been running at the time of the snapshot, when its start is known.
+pub(crate) fn runtime(snapshot: &Snapshot, process: &ProcessStat) -> Option<u64> {
+    if process.stale || process.start_time == 0 {
+        return None;
+    }
+    (snapshot.timestamp.timestamp().max(0) as u64).checked_sub(process.start_time)
+}
+
+/// Full command line, or the process name when the command line is unknown.
+fn audit_command(process: &ProcessStat) -> &str {
+    if process.full_command.is_empty() {
+        &process.command
+    } else {
+        &process.full_command
+    }
+}
+
+/// Render a snapshot as one table row per process, without any GPU metric.
+pub fn render_procs(opts: &RenderOpts, snapshot: &Snapshot) -> String {
+    let mut table = Table::new();
+    table
+        .load_preset(if opts.styling.ascii {
+            ASCII_TABLE_PRESET
+        } else {
+            TABLE_PRESET
+        })
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(["time", "host", "gpu", "uuid", "pid", "user", "command", "memory", "runtime"]);
+    apply_styling(opts.styling.enabled, &mut table);
+
+    let theme = opts.styling.palette.theme();
+    let timestamp = format_timestamp(opts, snapshot.timestamp);
+    for (gpu, process) in process_rows(opts, snapshot) {
+        let mut row = vec![
+            Cell::new(&timestamp),
+            Cell::new(&snapshot.hostname),
+            Cell::new(gpu.index).fg(theme.index),
+            Cell::new(&gpu.uuid),
+        ];
+        match process {
+            Some(process) => row.extend([
+                Cell::new(process.pid),
+                Cell::new(process.display_user()),
+                if process.matched {
+                    Cell::new(escape_control(audit_command(process))).fg(theme.matched).add_attribute(Attribute::Bold)
+                } else {
+                    Cell::new(escape_control(audit_command(process))).fg(theme.processes)
+                },
+                Cell::new(match process.used_memory {
+                    Some(used) => format!("{} MB", figure(opts, used >> 20)),
+                    None => String::from("N/A"),
+                })
+                .set_alignment(CellAlignment::Right),
+                Cell::new(
+                    runtime(snapshot, process)
+                        .map(|seconds| short_age(chrono::Duration::seconds(seconds as i64)))
+                        .unwrap_or_default(),
+                ),
+            ]),
+            None => row.push(Cell::new("idle").add_attribute(Attribute::Dim)),
+        }
+        table.add_row(row);
+    }
+    table.to_string() + "\n"
+}
+
+/// The `--minimal` table: index, utilization and memory of each GPU, without a header.
+pub fn render_minimal(opts: &RenderOpts, snapshot: &Snapshot) -> String {
+    if let Some(line) = no_devices_line(opts, snapshot) {
+        return line + "\n";
+    }
+    let mut table = Table::new();
+    table
+        .load_preset(if opts.styling.ascii {
+            ASCII_TABLE_PRESET
+        } else {
+            TABLE_PRESET
+        })
+        .set_content_arrangement(ContentArrangement::Dynamic);
+    apply_styling(opts.styling.enabled, &mut table);
+
+    let theme = opts.styling.palette.theme();
+    for gpu in &snapshot.gpus {
+        let mut row = vec![Cell::new(index_label(gpu)).fg(theme.index)];
+        if gpu.reset_required || gpu.error.is_some() {
+            row.push(Cell::new("ERR").fg(theme.critical).add_attribute(Attribute::Bold));
+        } else if gpu.suspended {
+            row.push(Cell::new("suspended").add_attribute(Attribute::Dim));
+        } else {
+            row.extend([
+                Cell::new(format!("{:>3} %", gpu.utilization)).fg(theme.utilization),
+                Cell::new(format!(
+                    "{} / {} MB",
+                    figure(opts, gpu.memory_used >> 20),
+                    figure(opts, gpu.memory_total >> 20)
+                ))
+                .fg(theme.memory)
+                .set_alignment(CellAlignment::Right),
+            ]);
+        }
+        table.add_row(row);
+    }
+    table.to_string() + "\n"
+}
+
+/// Render a snapshot as one JSON object per process and line, for audit logs.
+fn render_procs_json(opts: &RenderOpts, snapshot: &Snapshot) -> String {
+    let mut text = String::new();
+    for (gpu, process) in process_rows(opts, snapshot) {
+        let row = json!({
+            "timestamp": snapshot.collected_at.unwrap_or(snapshot.timestamp),
+            "time_suspect": snapshot.time_suspect,
+            "uptime_ms": snapshot.uptime_ms,
+            "hostname": snapshot.hostname,
+            "gpu": gpu.index,
+            "uuid": gpu.uuid,
+            "idle": process.is_none(),
+            "pid": process.map(|process| process.pid),
+            "user": process.map(|process| process.user.as_str()),
+            "command": process.map(audit_command),
+            "used_memory": process.and_then(|process| process.used_memory),
+            "runtime": process.and_then(|process| runtime(snapshot, process)),
+        });
+        text = text + &row.to_string() + "\n";
+    }
+    text
+}
+
+/// The process cell of `--layout two-line` as a dim line of its own, indented
+/// under the GPU name.
+fn process_line(opts: &RenderOpts, cell: Cell) -> String {
+    let mut line = Table::new();
+    line.load_preset(comfy_table::presets::NOTHING);
+    apply_styling(opts.styling.enabled, &mut line);
+    line.add_row(vec![Cell::new("   "), cell.add_attribute(Attribute::Dim)]);
+    line.to_string()
+}
+
+/// The integrated GPUs of `igpu`, below the GPU table: `[iGPU]`, the card,
+/// utilization and, where the driver reports it, memory.
+fn igpu_table(opts: &RenderOpts, igpus: &[IntegratedGpu]) -> String {
+    let theme = opts.styling.palette.theme();
+    let mut table = Table::new();
+    table.load_preset(if opts.styling.ascii {
+        ASCII_TABLE_PRESET
+    } else {
+        TABLE_PRESET
+    });
+    apply_styling(opts.styling.enabled, &mut table);
+    for igpu in igpus {
+        let name = match &igpu.driver {
+            Some(driver) => format!("{} {} ({})", igpu.vendor, igpu.card, driver),
+            None => format!("{} {}", igpu.vendor, igpu.card),
+        };
+        let utilization = igpu.utilization.map_or_else(|| String::from("?"), |busy| format!("{} %", busy));
+        let mut row = vec![
+            Cell::new("[iGPU]").fg(theme.index),
+            Cell::new(name).fg(theme.name),
+            Cell::new(utilization).fg(theme.utilization),
+        ];
+        if let (Some(used), Some(total)) = (igpu.memory_used, igpu.memory_total) {
+            let memory = format!("{} / {} MB", figure(opts, used >> 20), figure(opts, total >> 20));
+            row.push(Cell::new(memory).fg(theme.memory));
+        }
+        table.add_row(row);
+    }
+    table.to_string()
+}
+
+/// Render a snapshot as the header line, the GPU table and optional summaries.
+pub fn render(opts: &RenderOpts, styling: &mut Styling, snapshot: &Snapshot) -> String {
+    let mut table = Table::new();
+    let two_line = opts.styling.layout == Layout::TwoLine;
+
+    table
+        .load_preset(if opts.styling.ascii {
+            ASCII_TABLE_PRESET
+        } else {
+            TABLE_PRESET
+        })
+        // process lines go below their row, which must stay one line
+        .set_content_arrangement(if two_line {
+            ContentArrangement::Disabled
+        } else {
+            ContentArrangement::Dynamic
+        });
+
+    apply_styling(opts.styling.enabled, &mut table);
+
+    let empty = empty_columns(opts, &snapshot.gpus);
+    let vgpu_branch = if opts.styling.ascii { "`-" } else { "└" };
+    let groups = match board_groups(&snapshot.gpus).filter(|_| opts.group_by_board) {
+        Some(groups) => groups,
+        None => snapshot.gpus.iter().map(|gpu| vec![gpu]).collect(),
+    };
+    // the line printed below each table row with --layout two-line
+    let mut below: Vec<Option<String>> = vec![];
+    for group in groups {
+        let grouped = group.len() > 1;
+        if grouped {
+            table.add_row(board_row(opts, &group));
+            below.push(None);
+        }
+        for gpu in group {
+            let mut row = gpu_row(opts, styling, gpu, &empty, grouped);
+            let failed = !gpu.measured();
+            let processes = if two_line && !failed { row.pop() } else { None };
+            table.add_row(row);
+            let blank = gpu.processes.is_empty()
+                && gpu.idle_seconds.is_none()
+                && gpu.missing_processes(opts.thresholds.busy).is_none();
+            below.push(processes.filter(|_| !blank).map(|cell| process_line(opts, cell)));
+
+            for vgpu in &gpu.vgpus {
+                table.add_row(vec![
+                    Cell::new(""),
+                    Cell::new(format!(" {} {}", vgpu_branch, vgpu_label(vgpu))).fg(opts
+                        .styling
+                        .palette
+                        .theme()
+                        .virtualization),
+                ]);
+                below.push(None);
+            }
+        }
+    }
+    // watch mode keeps the columns still while the values change
+    if opts.styling.fixed_width || opts.interval.is_some() {
+        for (i, width) in styling.widen(stable_widths(opts, &snapshot.gpus, &empty)).iter().enumerate() {
+            if let Some(column) = table.column_mut(i) {
+                let width = *width as u16 + column.padding_width();
+                column.set_constraint(ColumnConstraint::LowerBoundary(Width::Fixed(width)));
+            }
+        }
+    }
+
+    let timestamp = format_timestamp(opts, snapshot.timestamp);
+    let driver_version = snapshot.driver_version.as_deref().unwrap_or("N/A");
+
+    let hostname = match &snapshot.container {
+        Some(container) => format!("{} [{}]", snapshot.hostname, container),
+        None => snapshot.hostname.clone(),
+    };
+
+    let mut text = String::new();
+    if let Some(mut header) = header_line(opts, &hostname, &timestamp, Some(driver_version)) {
+        if let Some(memory) = &snapshot.host_memory {
+            header = header + "\t" + &host_memory_label(opts, memory);
+        }
+        if let Some(uptime) = snapshot.driver_uptime {
+            header = header + "\t" + &driver_uptime_label(opts, uptime);
+        }
+        let dim = |text: String| match opts.styling.enabled {
+            true => format!("\t\x1b[2m{}\x1b[0m", text),
+            false => format!("\t{}", text),
+        };
+        if let Some(interval) = snapshot.interval.filter(|_| opts.adaptive_interval) {
+            header += &dim(format!("every {}s", interval));
+        }
+        if let Some(age) = snapshot.data_age_ms() {
+            header = header + "\t" + &data_age_label(opts, age);
+        }
+        text = text + &header + "\n";
+    }
+    if snapshot.version_mismatch() {
+        text = text + VERSION_MISMATCH_HINT + "\n";
+    }
+    if snapshot.time_suspect {
+        text = text + CLOCK_SUSPECT_HINT + "\n";
+    }
+    if let Some(allocation) = &snapshot.allocation {
+        text = text + &allocation_summary(opts, allocation) + "\n";
+    }
+    if let Some(line) = no_devices_line(opts, snapshot) {
+        text = text + &line + "\n";
+    } else if two_line {
+        for (line, processes) in table.to_string().lines().zip(below) {
+            text = text + line + "\n";
+            if let Some(processes) = processes {
+                text = text + &processes + "\n";
+            }
+        }
+    } else {
+        text = text + &table.to_string() + "\n";
+    }
+    if !snapshot.igpus.is_empty() {
+        text = text + &igpu_table(opts, &snapshot.igpus) + "\n";
+    }
+    if let Some(page) = snapshot.page {
+        text = text + &format!("page {}/{}", page.number, page.count) + "\n";
+    }
+    if let Some(busy) = &snapshot.busy {
+        text = text + &busy_summary(opts, busy) + "\n";
+    }
+    if let Some(chargeback) = &snapshot.chargeback {
+        text += &chargeback_lines(opts, chargeback);
+    }
+    if let Some(differences) = &snapshot.consistency {
+        text += &consistency_lines(opts, differences);
+    }
+    let errors = snapshot.collection_errors.as_deref().filter(|_| opts.verbose > 0);
+    if let Some(summary) = errors.and_then(|counts| collection_errors::summary(counts, opts.styling.ascii)) {
+        text = text + &summary + "\n";
+    }
+    if opts.group_procs {
+        for group in process_groups(&snapshot.gpus) {
+            text = text + &group.summary(opts) + "\n";
+        }
+    }
+    if let Some(limit) = snapshot.permission_limit.as_ref().filter(|_| !opts.no_hints) {
+        text = text + &permission_note(opts, limit) + "\n";
+    }
+    let scanned = snapshot.scanned_gpus();
+    if !scanned.is_empty() {
+        text = text + &scan_note(opts, &scanned) + "\n";
+    }
+    let shared = snapshot.display_shared_gpus();
+    if !shared.is_empty() && !opts.no_hints {
+        text = text + &display_note(opts, &shared, &snapshot.idle_gpus()) + "\n";
+    }
+    let cold = snapshot.cold_start_gpus();
+    if !cold.is_empty() && !opts.no_hints {
+        text = text + &persistence_note(opts, &cold) + "\n";
+    }
+    if !empty.is_empty() && !opts.no_hints {
+        text = text + &dim(opts, &format!("note: no GPU reports {}, hidden (--keep-empty-columns to show)", empty.join(", "))) + "\n";
+    }
+    text
+}
+
+/// GPUs grouped by board in order of their first member, or `None` when no
+/// two GPUs share a board and the table stays flat.
+pub fn board_groups(gpus: &[GpuStat]) -> Option<Vec<Vec<&GpuStat>>> {
+    let mut groups: Vec<Vec<&GpuStat>> = vec![];
+    for gpu in gpus {
+        let group = gpu
+            .board
+            .as_ref()
+            .and_then(|board| groups.iter_mut().find(|group| group[0].board.as_ref() == Some(board)));
+        match group {
+            Some(group) => group.push(gpu),
+            None => groups.push(vec![gpu]),
+        }
+    }
+    Some(groups).filter(|groups| groups.iter().any(|group| group.len() > 1))
+}
+
+/// Bold `board 1652…` row with the totals of its GPUs, e.g. `8 GPUs, max 62°C, 3120 / 5600 W, 512000 / 655360 MB`.
+fn board_row(opts: &RenderOpts, group: &[&GpuStat]) -> Vec<Cell> {
+    let theme = opts.styling.palette.theme();
+    let board = group[0].board.as_deref().unwrap_or_default();
+    let degree = if opts.styling.ascii { "" } else { "°" };
+    let temperature = group.iter().map(|gpu| gpu.temperature).max().unwrap_or_default();
+    let power = stats::total_power(group);
+    let power_limit: u64 = group.iter().map(|gpu| u64::from(gpu.power_limit)).sum();
+    let used: u64 = group.iter().map(|gpu| gpu.memory_used).sum();
+    let total: u64 = group.iter().map(|gpu| gpu.memory_total).sum();
+    let totals = format!(
+        "{} GPUs, max {}{}C, {} / {} W, {} / {} MB",
+        group.len(),
+        temperature,
+        degree,
+        figure(opts, power / 1000),
+        figure(opts, power_limit / 1000),
+        figure(opts, used >> 20),
+        figure(opts, total >> 20)
+    );
+    vec![
+        Cell::new(format!("board {}", escape_control(board)))
+            .fg(theme.index)
+            .add_attribute(Attribute::Bold),
+        Cell::new(totals).add_attribute(Attribute::Bold),
+    ]
+}
+
+/// Requested columns for which no GPU has a value, e.g. the fan of a laptop
+/// GPU with `-a`. A column stays as long as any GPU has a value for it.
+pub fn empty_columns(opts: &RenderOpts, gpus: &[GpuStat]) -> Vec<&'static str> {
+    let collected: Vec<&GpuStat> = gpus.iter().filter(|gpu| gpu.measured()).collect();
+    if opts.columns.keep_empty || collected.is_empty() {
+        return vec![];
+    }
+    COLUMNS
+        .iter()
+        .filter(|column| opts.column(column.name))
+        .filter(|column| !column.collectors.iter().any(|collector| opts.collector_off(collector)))
+        .filter(|column| {
+            let has_value = column.cells.as_ref().map(|cells| cells.has_value);
+            has_value.is_some_and(|has_value| !collected.iter().any(|gpu| has_value(gpu)))
+        })
+        .map(|column| column.name)
+        .collect()
+}
+
+/// `text` dimmed when styling is enabled.
+pub(crate) fn dim(opts: &RenderOpts, text: &str) -> String {
+    if opts.styling.enabled {
+        format!("\x1b[2m{}\x1b[0m", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// `busy: 5.2 GPU-hours since 09:00 (avg occupancy 64%)` for `--show-busy`.
+fn busy_summary(opts: &RenderOpts, busy: &BusyTime) -> String {
+    let since = if busy.since.date_naive() == Local::now().date_naive() {
+        busy.since.format("%H:%M")
+    } else {
+        busy.since.format("%Y-%m-%d %H:%M")
+    };
+    let occupancy = busy
+        .occupancy()
+        .map(|share| format!(" (avg occupancy {:.0}%)", share * 100.0))
+        .unwrap_or_default();
+    let hours = decimal(opts, busy.busy_seconds / 3600.0, 1);
+    format!("busy: {} GPU-hours since {}{}", hours, since, occupancy)
+}
+
+/// `allocation: GPU 0, 1: 12000 / 48000 MB, util 54%, 612 W`, followed by a
+/// bold `, shared with bob` when other users run processes on them.
+fn allocation_summary(opts: &RenderOpts, allocation: &Allocation) -> String {
+    if allocation.gpus.is_empty() {
+        return String::from("allocation: no GPU of CUDA_VISIBLE_DEVICES is shown");
+    }
+    let indices: Vec<String> = allocation.gpus.iter().map(u32::to_string).collect();
+    let mut summary = format!(
+        "allocation: {} {}: {} / {} MB, util {}%, {} W",
+        if allocation.gpus.len() == 1 { "GPU" } else { "GPUs" },
+        indices.join(", "),
+        figure(opts, allocation.memory_used >> 20),
+        figure(opts, allocation.memory_total >> 20),
+        allocation.utilization.round(),
+        figure(opts, allocation.power / 1000)
+    );
+    if !allocation.foreign_users.is_empty() {
+        let shared = format!(", shared with {}", allocation.foreign_users.join(", "));
+        if opts.styling.enabled {
+            summary = summary + "\x1b[1m" + &shared + "\x1b[0m";
+        } else {
+            summary += &shared;
+        }
+    }
+    summary
+}
+
+/// `chargeback of 256.0G installed, 68.0G used:` followed by one line per
+/// user, e.g. `  alice  18.0G   7.03% of installed  26.47% of used`, where
+/// `?` stands for memory NVML did not report.
+fn chargeback_lines(opts: &RenderOpts, chargeback: &Chargeback) -> String {
+    let mut text = format!(
+        "chargeback of {}G installed, {}G used:\n",
+        gibibytes(opts, chargeback.installed),
+        gibibytes(opts, chargeback.used)
+    );
+    let width = chargeback.users.iter().map(|share| share.user.chars().count()).max().unwrap_or_default();
+    let percent = |share: Option<f64>| share.map_or_else(|| String::from("?"), |share| decimal(opts, share, 2) + "%");
+    for share in &chargeback.users {
+        let memory = match share.memory {
+            None => String::from("?"),
+            Some(memory) if share.unavailable => gibibytes(opts, memory) + "G+?",
+            Some(memory) => gibibytes(opts, memory) + "G",
+        };
+        text += &format!(
+            "  {:<width$}  {:>8}  {:>7} of installed  {:>7} of used\n",
+            escape_control(&share.user),
+            memory,
+            percent(share.installed_share),
+            percent(share.used_share),
+            width = width
+        );
+    }
+    text
+}
+
+/// `cell` with a `≠` marker in the outlier color when `--consistency-check`
+/// found the GPU set up differently from its peers in the attributes it shows.
+fn consistency_mark(opts: &RenderOpts, marked: bool, cell: Cell) -> Cell {
+    if !marked {
+        return cell;
+    }
+    let marker = if opts.styling.ascii { "!=" } else { "≠" };
+    Cell::new(format!("{} {}", cell.content(), marker)).fg(opts.styling.palette.theme().inconsistent)
+}
+
+/// `configuration differs between GPUs of the same model:` followed by one
+/// line per differing attribute, e.g. `  NVIDIA H100 80GB HBM3 power limit:
+/// 500 W on GPU 3, 700 W on the others`.
+fn consistency_lines(opts: &RenderOpts, differences: &[Difference]) -> String {
+    if differences.is_empty() {
+        return dim(opts, "configuration consistent between GPUs of the same model") + "\n";
+    }
+    let mut text = String::from("configuration differs between GPUs of the same model:\n");
+    for difference in differences {
+        let mut values: Vec<String> = difference
+            .outliers
+            .iter()
+            .map(|outlier| {
+                let gpus = if outlier.gpus.len() == 1 { "GPU" } else { "GPUs" };
+                format!("{} on {} {}", outlier.value, gpus, index_ranges(&outlier.gpus))
+            })
+            .collect();
+        if let Some(majority) = &difference.majority {
+            values.push(format!("{} on the others", majority));
+        }
+        text += &format!("  {} {}: {}\n", difference.model, difference.label(), values.join(", "));
+    }
+    text
+}
+
+/// Cell of a metric whose collector `--disable-collector` switched off.
+fn off_cell() -> Cell {
+    Cell::new("off").add_attribute(Attribute::Dim)
+}
+
+/// ` (max 93)` when the window maximum of `--window-max` exceeds the latest value.
+fn window_note(max: Option<u32>, latest: u32) -> String {
+    max.filter(|&max| max > latest).map(|max| format!(" (max {})", max)).unwrap_or_default()
+}
+
+/// `note: nvidia-persistenced is not running and GPU 0, 1 have persistence mode off — …`, dim.
+fn persistence_note(opts: &RenderOpts, gpus: &[&GpuStat]) -> String {
+    let indices: Vec<String> = gpus.iter().map(|gpu| gpu.order_index.unwrap_or(gpu.index).to_string()).collect();
+    let (gpu, have) = if gpus.len() == 1 { ("GPU", "has") } else { ("GPUs", "have") };
+    let dash = if opts.styling.ascii { "-" } else { "—" };
+    dim(
+        opts,
+        &format!(
+            "note: nvidia-persistenced is not running and {} {} {} persistence mode off {} the first CUDA call may take seconds; start nvidia-persistenced or run nvidia-smi -pm 1",
+            gpu,
+            indices.join(", "),
+            have,
+            dash
+        ),
+    )
+}
+
+/// `note: GPU 0 runs compute jobs next to the display, which slows both — move them to idle GPU 2`, dim.
+fn display_note(opts: &RenderOpts, shared: &[&GpuStat], idle: &[&GpuStat]) -> String {
+    let indices = |gpus: &[&GpuStat]| -> String {
+        let indices: Vec<String> = gpus.iter().map(|gpu| gpu.order_index.unwrap_or(gpu.index).to_string()).collect();
+        indices.join(", ")
+    };
+    let (gpu, runs) = if shared.len() == 1 { ("GPU", "runs") } else { ("GPUs", "run") };
+    let dash = if opts.styling.ascii { "-" } else { "—" };
+    let advice = match idle.len() {
+        0 => String::new(),
+        1 => format!(" {} move them to idle GPU {}", dash, indices(idle)),
+        _ => format!(" {} move them to an idle GPU: {}", dash, indices(idle)),
+    };
+    dim(
+        opts,
+        &format!(
+            "note: {} {} {} compute jobs next to the display, which slows both{}",
+            gpu,
+            indices(shared),
+            runs,
+            advice
+        ),
+    )
+}
+
+/// `note: GPU 1 processes marked ~ were found in /proc, NVML refused to list them`, dim.
+/// Shown even with `--no-hints` since it explains what the process column holds.
+fn scan_note(opts: &RenderOpts, gpus: &[&GpuStat]) -> String {
+    let indices: Vec<String> = gpus.iter().map(|gpu| gpu.order_index.unwrap_or(gpu.index).to_string()).collect();
+    let gpu = if gpus.len() == 1 { "GPU" } else { "GPUs" };
+    dim(
+        opts,
+        &format!(
+            "note: {} {} processes marked ~ were found in /proc without their memory, NVML refused to list them (--no-proc-scan to skip)",
+            gpu,
+            indices.join(", ")
+        ),
+    )
+}
+
+/// `no NVIDIA GPUs detected — driver 550.54.15 loaded`, followed by what the
+/// device nodes tell, in place of the table of a driver that lists no GPU.
+pub fn no_devices_line(opts: &RenderOpts, snapshot: &Snapshot) -> Option<String> {
+    if snapshot.reason.as_deref() != Some(stats::NO_DEVICES) {
+        return None;
+    }
+    let dash = if opts.styling.ascii { "-" } else { "—" };
+    let mut line = String::from("no NVIDIA GPUs detected");
+    if let Some(driver) = &snapshot.driver_version {
+        line += &format!(" {} driver {} loaded", dash, driver);
+    }
+    if let Some(hint) = &snapshot.reason_hint {
+        line = line + "; " + hint;
+    }
+    Some(line)
+}
+
+/// `note: limited info — run as root for full process details (3 items hidden)`, dim.
+fn permission_note(opts: &RenderOpts, limit: &PermissionLimit) -> String {
+    let items = if limit.hidden == 1 { "item" } else { "items" };
+    let dash = if opts.styling.ascii { "-" } else { "—" };
+    dim(opts, &format!("note: limited info {} {} ({} {} hidden)", dash, limit.hint, limit.hidden, items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::tests::{fixtures, gpu};
+    use clap::Parser;
+    use serde_json::json;
+
+    fn render_opts(args: &[&str]) -> RenderOpts {
+        RenderOpts::from(&Opts::try_parse_from(["gpustat"].iter().chain(args)).unwrap())
+    }
+
+    fn process(pid: u32, user: &str, command: &str, used_mib: u64) -> ProcessStat {
+        ProcessStat {
+            pid,
+            user: user.to_string(),
+            command: command.to_string(),
+            full_command: command.to_string(),
+            used_memory: Some(used_mib << 20),
+            resolved: true,
+            ..Default::default()
+        }
+    }
+
+    fn gpu_with(processes: Vec<ProcessStat>) -> GpuStat {
+        GpuStat {
+            memory_total: 24 << 30,
+            processes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_opts_resolve_the_columns() {
+        let opts = render_opts(&["-a", "--columns", "xid", "--disable-collector", "fan"]);
+        assert!(opts.column("fan") && opts.column("xid"));
+        assert!(opts.collector_off("fan"));
+        // -a leaves out the slow columns not named
+        assert!(!opts.column("handles"));
+        let opts = render_opts(&["--show-fan"]);
+        assert!(opts.column("fan") && !opts.column("brand"));
+        assert!(!opts.collector_off("fan"));
+    }
+
+    #[test]
+    fn render_opts_resolve_thresholds_units_and_styling() {
+        let opts = render_opts(&["--busy-threshold", "50", "--utc", "--color", "--pretty", "--ascii"]);
+        assert_eq!(opts.thresholds.busy, 50);
+        assert!(opts.units.utc && !opts.units.epoch);
+        assert!(opts.styling.enabled && opts.styling.pretty && opts.styling.ascii);
+        let opts = render_opts(&["--no-color", "--compact"]);
+        assert!(!opts.styling.enabled && !opts.styling.pretty);
+        assert_eq!(opts.thresholds.perf, 90);
+    }
+
+    #[test]
+    fn proc_percent_shares_the_gpu_total() {
+        let process = ProcessStat {
+            used_memory: Some(6 << 30),
+            ..Default::default()
+        };
+        assert_eq!(process_memory(&render_opts(&[]), &process, 24 << 30), "6144M");
+        let opts = render_opts(&["--proc-percent"]);
+        assert_eq!(process_memory(&opts, &process, 24 << 30), "6144M, 25%");
+        assert_eq!(process_memory(&opts, &process, 0), "6144M, ?%");
+        // capped at the whole GPU
+        assert_eq!(process_memory(&opts, &process, 4 << 30), "6144M, 100%");
+    }
+
+    #[test]
+    fn procs_by_user_groups_shared_gpus() {
+        let gpu = gpu_with(vec![
+            process(1, "alice", "python", 2048),
+            process(2, "bob", "python", 8192),
+            ProcessStat {
+                used_memory: None,
+                ..process(3, "alice", "python", 0)
+            },
+        ]);
+        let opts = render_opts(&["--procs-by-user"]);
+        assert_eq!(process_cell(&opts, &gpu), "bob(1 proc, 8.0G) alice(2 procs, 2.0G+?)");
+        let opts = render_opts(&["--procs-by-user", "--expand-user", "bob"]);
+        assert_eq!(process_cell(&opts, &gpu), "bob(8192M) alice(2 procs, 2.0G+?)");
+        // a single user keeps the normal cell
+        let gpu = gpu_with(vec![process(1, "alice", "python", 2048)]);
+        assert_eq!(process_cell(&opts, &gpu), process_cell(&render_opts(&[]), &gpu));
+    }
+
+    #[test]
+    fn stale_pids_are_not_attributed() {
+        let stale = ProcessStat {
+            stale: true,
+            ..process(7, "bob", "bash", 512)
+        };
+        let opts = render_opts(&["-cp"]);
+        assert_eq!(process_entry(&opts, &stale, 24 << 30), "<exited pid 7>(512M)");
+        let gpus = [gpu_with(vec![stale.clone()]), gpu_with(vec![stale])];
+        let groups = process_groups(&gpus);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].label, "<exited pid 7>");
+    }
+
+    #[test]
+    fn proc_format_fills_each_entry() {
+        let process = process(42, "alice", "python", 6144);
+        let opts = render_opts(&["--proc-format", "{user}@{pid} {mem} {mem_pct} {{{cmd}}}"]);
+        assert_eq!(process_entry(&opts, &process, 24 << 30), "alice@42 6144M 25% {python}");
+        assert!(Opts::try_parse_from(["gpustat", "--proc-format", "{gpu}"]).is_err());
+    }
+
+    #[test]
+    fn contexts_show_with_their_share() {
+        let process = ProcessStat {
+            contexts: 3,
+            ..process(42, "alice", "python", 3072)
+        };
+        assert_eq!(
+            process_entry(&render_opts(&["--show-contexts"]), &process, 24 << 30),
+            "alice(3×1024M ctxs, 3072M)"
+        );
+        assert_eq!(
+            process_entry(&render_opts(&["--show-contexts", "--ascii"]), &process, 24 << 30),
+            "alice(3x1024M ctxs, 3072M)"
+        );
+        assert_eq!(process_entry(&render_opts(&[]), &process, 24 << 30), "alice(3072M)");
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        assert_eq!(
+            escape_control("a\x1b[31mb\tc\u{7f}\u{9b}"),
+            "a\\x1b[31mb\\x09c\\x7f\\x9b"
+        );
+        assert_eq!(escape_control("naïve \u{fffd} 训练"), "naïve \u{fffd} 训练");
+        let process = process(42, "eve\r", "\x1b]0;owned\x07python", 1024);
+        let entry = process_entry(&render_opts(&["-cp"]), &process, 24 << 30);
+        assert_eq!(entry, "eve\\x0d:\\x1b]0;owned\\x07python/42(1024M)");
+        let groups = process_groups(&[gpu_with(vec![process])]);
+        assert!(!groups[0].label.chars().any(char::is_control));
+    }
+
+    /// The table of `snapshot`, without colors.
+    fn table(snapshot: &Snapshot, args: &[&str]) -> String {
+        let args: Vec<&str> = ["--no-color"].iter().chain(args).copied().collect();
+        let opts = render_opts(&args);
+        TableRenderer::new(opts.thresholds.hysteresis).render(snapshot, &opts)
+    }
+
+    fn snapshot_of(gpus: Vec<serde_json::Value>, args: &[&str]) -> Snapshot {
+        let opts = Opts::try_parse_from(["gpustat"].iter().chain(args)).unwrap();
+        crate::stats::collect(&opts, &fixtures(gpus), "node").unwrap()
+    }
+
+    #[test]
+    fn figures_are_right_aligned() {
+        let mut cool = gpu(1);
+        cool["temperature"] = json!(9);
+        cool["power_usage"] = json!(5000);
+        let table = table(&snapshot_of(vec![gpu(0), cool], &[]), &[]);
+        let rows: Vec<&str> = table.lines().filter(|line| line.contains("°C")).collect();
+        let end = |row: &str, text: &str| row.find(text).unwrap() + text.len();
+        assert_eq!(end(rows[0], "35°C"), end(rows[1], "9°C"));
+        assert_eq!(end(rows[0], "20 / 450 W"), end(rows[1], "5 / 450 W"));
+    }
+
+    #[test]
+    fn fixed_widths_fit_the_busiest_values() {
+        let mut busy = gpu(0);
+        busy["temperature"] = json!(100);
+        busy["utilization_rates"]["gpu"] = json!(100);
+        busy["memory_info"]["used"] = busy["memory_info"]["total"].clone();
+        busy["power_usage"] = json!(449000);
+        // where the memory cell ends, ahead of the processes
+        let end = |device: serde_json::Value, args: &[&str]| {
+            let table = table(&snapshot_of(vec![device], &[]), args);
+            let row = table.lines().find(|line| line.contains("°C")).unwrap().to_string();
+            row[..row.find(" MB").unwrap()].chars().count()
+        };
+        assert!(end(gpu(0), &[]) < end(busy.clone(), &[]));
+        assert_eq!(end(gpu(0), &["--fixed-width"]), end(busy.clone(), &["--fixed-width"]));
+        assert_eq!(end(busy.clone(), &[]), end(busy, &["--fixed-width"]));
+    }
+
+    #[test]
+    fn fixed_widths_only_grow() {
+        let mut styling = Styling::new(0.0);
+        assert_eq!(styling.widen(vec![3, 5]), [3, 5]);
+        assert_eq!(styling.widen(vec![4, 2]), [4, 5]);
+        // another set of columns starts over
+        assert_eq!(styling.widen(vec![1]), [1]);
+    }
+
+    #[test]
+    fn json_is_pretty_and_colored_on_request() {
+        let snapshot = snapshot_of(vec![gpu(0)], &[]);
+        let json = |args: &[&str]| JsonRenderer.render(&snapshot, &render_opts(args));
+        let compact = json(&["--compact", "--color"]);
+        assert_eq!(compact.lines().count(), 1);
+        assert!(!compact.contains('\x1b'));
+        let pretty = json(&["--pretty", "--no-color"]);
+        assert!(pretty.contains("\n  \"hostname\": \"node\""));
+        assert!(!pretty.contains('\x1b'));
+        // the escapes wrap whole tokens, so stripping them gives the plain text back
+        let colored = json(&["--pretty", "--color"]);
+        let mut stripped = String::new();
+        let mut rest = colored.as_str();
+        while let Some(start) = rest.find('\x1b') {
+            stripped += &rest[..start];
+            rest = &rest[start + rest[start..].find('m').unwrap() + 1..];
+        }
+        stripped += rest;
+        assert_eq!(stripped, pretty);
+        let value: serde_json::Value = serde_json::from_str(&stripped).unwrap();
+        assert_eq!(value, serde_json::from_str::<serde_json::Value>(&compact).unwrap());
+    }
+
+    #[test]
+    fn json_tokens_get_their_colors() {
+        assert_eq!(
+            colorize_json(r#"{"a": "x\"y", "b": [-1.5e3, true, null]}"#),
+            "{\x1b[1;34m\"a\"\x1b[0m: \x1b[32m\"x\\\"y\"\x1b[0m, \x1b[1;34m\"b\"\x1b[0m: \
+             [\x1b[33m-1.5e3\x1b[0m, \x1b[35mtrue\x1b[0m, \x1b[35mnull\x1b[0m]}"
+        );
+    }
+
+    #[test]
+    fn distinguished_names() {
+        let gpu = GpuStat {
+            index: 3,
+            name: String::from("NVIDIA A100-SXM4-80GB"),
+            uuid: String::from("GPU-1a2b3c4d-5e6f-7081-92a3-b4c5d6e7f809"),
+            pci_bus_id: Some(String::from("00000000:17:00.0")),
+            ..Default::default()
+        };
+        let name = |args: &[&str], gpu: &GpuStat| display_name(&render_opts(args), gpu);
+        assert_eq!(name(&[], &gpu), "NVIDIA A100-SXM4-80GB");
+        assert_eq!(name(&["--distinguish", "index"], &gpu), "NVIDIA A100-SXM4-80GB #3");
+        let ordered = GpuStat {
+            order_index: Some(0),
+            ..gpu.clone()
+        };
+        assert_eq!(name(&["--distinguish", "index"], &ordered), "NVIDIA A100-SXM4-80GB #0");
+        assert_eq!(
+            name(&["--distinguish", "uuid"], &gpu),
+            "NVIDIA A100-SXM4-80GB #1a2b3c4d"
+        );
+        assert_eq!(
+            name(&["--distinguish", "bus"], &gpu),
+            "NVIDIA A100-SXM4-80GB @00000000:17:00.0"
+        );
+        let remote = GpuStat {
+            pci_bus_id: None,
+            ..gpu.clone()
+        };
+        assert_eq!(name(&["--distinguish", "bus"], &remote), "NVIDIA A100-SXM4-80GB @?");
+        let opts = render_opts(&["--distinguish", "uuid", "--oneline-format", "{name}|{uuid}|{bus}"]);
+        assert_eq!(
+            oneline_entry(&opts, &gpu),
+            "NVIDIA A100-SXM4-80GB #1a2b3c4d|GPU-1a2b3c4d-5e6f-7081-92a3-b4c5d6e7f809|00000000:17:00.0"
+        );
+    }
+
+    #[test]
+    fn process_trees_group_descendants_on_the_gpu() {
+        let child = |pid: u32, parent: u32, command: &str, start_time: u64| ProcessStat {
+            parent: Some(parent),
+            start_time,
+            ..process(pid, "alice", command, 1024)
+        };
+        let processes = [
+            // listed before its parent
+            child(12, 11, "python", 20),
+            child(11, 10, "sh", 20),
+            child(10, 1, "torchrun", 10),
+            child(13, 10, "python", 20),
+            // its parent is not on the GPU, though its grandparent is
+            child(21, 20, "python", 30),
+            // a reused PID 10 cannot have started this one
+            child(30, 10, "python", 5),
+            // parents of each other
+            child(40, 41, "a", 50),
+            child(41, 40, "b", 50),
+            ProcessStat {
+                stale: true,
+                ..child(14, 10, "python", 20)
+            },
+        ];
+        let processes: Vec<&ProcessStat> = processes.iter().collect();
+        let trees: Vec<(u32, Vec<u32>)> = process_trees(&processes)
+            .iter()
+            .map(|tree| (tree.root.pid, tree.descendants.iter().map(|p| p.pid).collect()))
+            .collect();
+        assert_eq!(
+            trees,
+            [
+                (10, vec![12, 11, 13]),
+                (21, vec![]),
+                (30, vec![]),
+                (40, vec![]),
+                (41, vec![]),
+                (14, vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn compute_next_to_the_display_is_flagged() {
+        let kind = |kind| move |process: ProcessStat| ProcessStat { kind, ..process };
+        let (graphics, compute) = (kind(ProcessKind::Graphics), kind(ProcessKind::Compute));
+        let xorg = || graphics(process(1000, "root", "Xorg", 300));
+        let training = || compute(process(2000, "alice", "python", 8192));
+        let gpus = vec![
+            vec![xorg()],
+            vec![training()],
+            vec![xorg(), training()],
+            // one process holding both contexts, as a game does
+            vec![kind(ProcessKind::Mixed)(process(3000, "bob", "game", 2048))],
+            vec![],
+        ];
+        let snapshot = Snapshot {
+            gpus: gpus
+                .into_iter()
+                .enumerate()
+                .map(|(index, processes)| GpuStat {
+                    index: index as u32,
+                    name: String::from("NVIDIA RTX A6000"),
+                    ..gpu_with(processes)
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let shared: Vec<u32> = snapshot.display_shared_gpus().iter().map(|gpu| gpu.index).collect();
+        assert_eq!(shared, [2]);
+        let rendered = table(&snapshot, &["--ascii"]);
+        let badged: Vec<&str> = rendered
+            .lines()
+            .filter(|line| line.contains("[shared w/ display]"))
+            .collect();
+        assert_eq!(badged.len(), 1);
+        assert!(badged[0].contains("[2]"), "{}", badged[0]);
+        assert!(rendered
+            .contains("note: GPU 2 runs compute jobs next to the display, which slows both - move them to idle GPU 4"));
+        // without an idle GPU there is nowhere to move them
+        let busy = Snapshot {
+            gpus: snapshot.gpus[..4].to_vec(),
+            ..Default::default()
+        };
+        assert!(table(&busy, &["--ascii"]).contains("which slows both\n"));
+    }
+
+    #[test]
+    fn digits_are_grouped_by_three() {
+        assert_eq!(group_digits(0, ','), "0");
+        assert_eq!(group_digits(999, ','), "999");
+        assert_eq!(group_digits(1000, ','), "1,000");
+        assert_eq!(group_digits(1234567, ','), "1,234,567");
+        assert_eq!(group_digits(u64::MAX, ','), "18,446,744,073,709,551,615");
+        let grouped = |locale: &str| group_digits(1234567, crate::separators_of(locale).0);
+        assert_eq!(grouped("en_US.UTF-8"), "1,234,567");
+        assert_eq!(grouped("de_DE.UTF-8"), "1.234.567");
+        assert_eq!(grouped("fr_FR@euro"), "1 234 567");
+        // C, POSIX and unset write numbers the C way
+        assert_eq!(grouped("C"), "1,234,567");
+        assert_eq!(grouped(""), "1,234,567");
+        assert_eq!(crate::separators_of("pt_BR").1, ',');
+        assert_eq!(crate::separators_of("POSIX").1, '.');
+    }
+
+    #[test]
+    fn indices_are_compressed_into_ranges() {
+        assert_eq!(index_ranges(&[]), "");
+        assert_eq!(index_ranges(&[7]), "7");
+        assert_eq!(index_ranges(&[0, 1, 2, 3]), "0-3");
+        assert_eq!(index_ranges(&[0, 1, 4]), "0-1,4");
+        assert_eq!(index_ranges(&[0, 2, 4]), "0,2,4");
+        assert_eq!(index_ranges(&[62, 63]), "62-63");
+        assert_eq!(index_ranges(&[63]), "63");
+    }
+
+    #[test]
+    fn oversubscription_needs_more_than_the_physical_memory() {
+        // gpu_with has 24 GiB
+        let cases = [
+            (vec![16 << 10, 16 << 10], Some(32 << 30)),
+            (vec![16 << 10, 8 << 10], None),
+            (vec![8 << 10], None),
+            (vec![], None),
+        ];
+        for (used_mib, expected) in cases {
+            let processes = used_mib
+                .iter()
+                .enumerate()
+                .map(|(i, mib)| process(i as u32, "alice", "python", *mib));
+            let gpu = gpu_with(processes.collect());
+            assert_eq!(gpu.oversubscription(), expected, "{:?} MiB", used_mib);
+        }
+        // a GPU whose memory could not be read is never oversubscribed
+        let unknown = GpuStat {
+            memory_total: 0,
+            ..gpu_with(vec![process(1, "alice", "python", 1)])
+        };
+        assert_eq!(unknown.oversubscription(), None);
+
+        let over = gpu_with(vec![
+            process(1, "alice", "python", 20 << 10),
+            process(2, "bob", "python", 20 << 10),
+        ]);
+        let equal = gpu_with(vec![process(3, "alice", "python", 24 << 10)]);
+        let snapshot = Snapshot {
+            gpus: vec![over, equal],
+            ..Default::default()
+        };
+        let rendered = table(&snapshot, &["--show-uvm"]);
+        assert_eq!(rendered.matches("oversub 40.0/24.0 GB").count(), 1, "{}", rendered);
+        assert!(!table(&snapshot, &[]).contains("oversub"));
+    }
+
+    #[test]
+    fn empty_gpu_lists_are_explained() {
+        let snapshot = Snapshot {
+            driver_version: Some(String::from("550.54.15")),
+            reason: Some(String::from(crate::stats::NO_DEVICES)),
+            reason_hint: Some(String::from("the device cgroup denies /dev/nvidia0")),
+            ..Default::default()
+        };
+        let line = "no NVIDIA GPUs detected — driver 550.54.15 loaded; the device cgroup denies /dev/nvidia0";
+        assert!(table(&snapshot, &[]).contains(line));
+        assert!(table(&snapshot, &["--ascii"]).contains("no NVIDIA GPUs detected - driver 550.54.15 loaded"));
+        let opts = render_opts(&["--ascii"]);
+        assert_eq!(render_minimal(&opts, &snapshot).lines().count(), 1);
+        let json: serde_json::Value =
+            serde_json::from_str(&JsonRenderer.render(&snapshot, &render_opts(&["--no-color"]))).unwrap();
+        assert_eq!(json["gpus"], json!([]));
+        assert_eq!(json["reason"], "no_devices");
+        assert_eq!(json["reason_hint"], "the device cgroup denies /dev/nvidia0");
+        // a listed GPU needs no explanation
+        assert_eq!(no_devices_line(&opts, &Snapshot::default()), None);
+    }
+
+    #[test]
+    fn collapsed_trees_summarize_their_workers() {
+        let worker = |pid: u32| ProcessStat {
+            parent: Some(10),
+            start_time: 20,
+            ..process(pid, "alice", "python", 8192)
+        };
+        let launcher = ProcessStat {
+            start_time: 10,
+            ..process(10, "alice", "torchrun", 2048)
+        };
+        let snapshot = Snapshot {
+            gpus: vec![gpu_with(vec![launcher, worker(11), worker(12)])],
+            ..Default::default()
+        };
+        assert!(table(&snapshot, &["--collapse-tree"]).contains("alice:torchrun→python ×2 (total 18.0G)"));
+        assert!(table(&snapshot, &["--collapse-tree", "--ascii"]).contains("alice:torchrun->python x2 (total 18.0G)"));
+        let expanded = table(&snapshot, &["--collapse-tree", "--expand-gpu", "0"]);
+        assert!(!expanded.contains("total") && expanded.contains("alice(8192M)"));
+    }
+
+    #[test]
+    fn oom_hints_lead_the_process_cell() {
+        let snapshot = Snapshot {
+            gpus: vec![GpuStat {
+                oom_hint: Some(crate::stats::OomHint {
+                    pid: 4242,
+                    before: 68 << 30,
+                    after: 31 << 30,
+                }),
+                ..gpu_with(vec![process(4242, "alice", "python", 31 << 10)])
+            }],
+            ..Default::default()
+        };
+        let plain = table(&snapshot, &[]);
+        assert!(
+            plain.contains("oom? pid 4242 68.0→31.0 GB (advisory) alice(31744M)"),
+            "{}",
+            plain
+        );
+        assert!(table(&snapshot, &["--ascii"]).contains("oom? pid 4242 68.0->31.0 GB (advisory) "));
+    }
+
+    #[test]
+    fn handle_cells_mark_partial_counts() {
+        let snapshot = Snapshot {
+            gpus: vec![GpuStat {
+                handles: Some(crate::handles::DeviceHandles {
+                    descriptors: 412,
+                    processes: 3,
+                    partial: true,
+                }),
+                ..gpu_with(vec![])
+            }],
+            ..Default::default()
+        };
+        assert!(table(&snapshot, &["--show-handles"]).contains("handles: ≥412 (3 procs)"));
+        assert!(table(&snapshot, &["--show-handles", "--ascii"]).contains("handles: >=412 (3 procs)"));
+        assert!(!table(&snapshot, &[]).contains("handles:"));
+    }
+
+    #[test]
+    fn two_line_layout_puts_the_processes_below() {
+        let snapshot = Snapshot {
+            gpus: vec![
+                GpuStat {
+                    name: String::from("busy"),
+                    ..gpu_with(vec![process(7, "alice", "python", 2048)])
+                },
+                GpuStat {
+                    index: 1,
+                    name: String::from("idle"),
+                    ..gpu_with(vec![])
+                },
+            ],
+            ..Default::default()
+        };
+        let single = table(&snapshot, &[]);
+        assert!(single
+            .lines()
+            .any(|line| line.contains("busy") && line.contains("alice")));
+        let two_line = table(&snapshot, &["--layout", "two-line"]);
+        let lines: Vec<&str> = two_line.lines().skip_while(|line| !line.contains("busy")).collect();
+        assert!(!lines[0].contains("alice"));
+        assert!(lines[1].trim_start().starts_with("alice(2048M)"), "{}", two_line);
+        assert!(lines[1].starts_with("    "));
+        assert!(lines[2].contains("idle"));
+        assert_eq!(lines.len(), 3, "{}", two_line);
+    }
+
+    #[test]
+    fn braked_gpus_are_badged() {
+        let mut braked = gpu(1);
+        braked["current_throttle_reasons"] = json!("HW_POWER_BRAKE_SLOWDOWN");
+        let table = table(&snapshot_of(vec![gpu(0), braked], &[]), &[]);
+        let rows: Vec<&str> = table.lines().filter(|line| line.contains("RTX 4090")).collect();
+        assert!(!rows[0].contains("POWER BRAKE"));
+        assert!(rows[1].contains("NVIDIA GeForce RTX 4090 POWER BRAKE"));
+    }
+
+    #[test]
+    fn scanned_processes_are_marked() {
+        let scanned = ProcessStat {
+            used_memory: None,
+            scanned: true,
+            ..process(7, "alice", "python", 0)
+        };
+        let opts = render_opts(&["--no-color"]);
+        assert_eq!(process_memory(&opts, &scanned, 24 << 30), "~");
+        let listed = ProcessStat {
+            scanned: false,
+            ..scanned.clone()
+        };
+        assert_eq!(process_memory(&opts, &listed, 24 << 30), "Unavailable");
+        let snapshot = Snapshot {
+            gpus: vec![
+                gpu_with(vec![]),
+                GpuStat {
+                    index: 1,
+                    ..gpu_with(vec![scanned])
+                },
+            ],
+            ..Default::default()
+        };
+        let scanned = snapshot.scanned_gpus();
+        assert_eq!(scanned.len(), 1);
+        assert!(scan_note(&opts, &scanned).starts_with("note: GPU 1 processes marked ~ were found in /proc"));
+        assert!(table(&snapshot, &["--no-hints"]).contains("note: GPU 1 processes marked ~"));
+    }
+
+    #[test]
+    fn permission_notes_count_the_hidden_items() {
+        let limit = PermissionLimit {
+            hidden: 3,
+            hint: String::from("run as root for full process details"),
+        };
+        assert_eq!(
+            permission_note(&render_opts(&["--no-color"]), &limit),
+            "note: limited info — run as root for full process details (3 items hidden)"
+        );
+        let one = PermissionLimit { hidden: 1, ..limit };
+        assert!(permission_note(&render_opts(&["--no-color", "--ascii"]), &one)
+            .ends_with("- run as root for full process details (1 item hidden)"));
+        assert!(permission_note(&render_opts(&["--color"]), &one).starts_with("\x1b["));
+    }
+
+    #[test]
+    fn window_maxima_show_when_above_the_latest() {
+        assert_eq!(window_note(Some(93), 40), " (max 93)");
+        assert_eq!(window_note(Some(40), 40), "");
+        assert_eq!(window_note(None, 40), "");
+    }
+
+    #[test]
+    fn columns_no_gpu_reports_are_dropped() {
+        let mut fan = gpu(1);
+        fan["fan_speed"] = json!(40);
+        let snapshot = snapshot_of(vec![gpu(0), gpu(1)], &["-F", "--show-ecc"]);
+        let opts = render_opts(&["-F", "--show-ecc"]);
+        assert_eq!(empty_columns(&opts, &snapshot.gpus), ["fan", "ecc"]);
+        assert!(!table(&snapshot, &["-F"]).contains(" F: "));
+        assert!(empty_columns(&render_opts(&["-F", "--keep-empty-columns"]), &snapshot.gpus).is_empty());
+        // one GPU with a fan is enough to keep the column
+        let snapshot = snapshot_of(vec![gpu(0), fan], &["-F", "--show-ecc"]);
+        assert_eq!(empty_columns(&opts, &snapshot.gpus), ["ecc"]);
+        assert!(table(&snapshot, &["-F"]).contains(" F: 40 %"));
+    }
+
+    #[test]
+    fn locale_numbers_only_change_the_table() {
+        let mut opts = render_opts(&["--group-digits"]);
+        assert_eq!(figure(&opts, 1234567), "1,234,567");
+        assert_eq!(
+            (figure(&opts, 999), decimal(&opts, 21.26, 1)),
+            (String::from("999"), String::from("21.3"))
+        );
+        (opts.units.digit_separator, opts.units.decimal_separator) = (' ', ',');
+        assert_eq!(figure(&opts, 24564), "24 564");
+        assert_eq!(gibibytes(&opts, 3 << 29), "1,5");
+        assert_eq!(figure(&render_opts(&[]), 24564), "24564");
+        // machine formats keep C numbers
+        let mut snapshot = snapshot_of(vec![gpu(0)], &[]);
+        snapshot.gpus[0].power_average = Some(20500);
+        let mut localized = render_opts(&["--compact"]);
+        let plain = JsonRenderer.render(&snapshot, &localized);
+        localized.units = opts.units;
+        assert_eq!(JsonRenderer.render(&snapshot, &localized), plain);
+        assert!(plain.contains(r#""power_average":20500"#));
+    }
+
+    #[test]
+    fn vgpu_rows_name_their_type() {
+        let vgpu = VgpuStat {
+            id: 3,
+            type_name: Some(String::from("GRID A100-4C")),
+            framebuffer: Some(4 << 30),
+        };
+        assert_eq!(vgpu_label(&vgpu), "vGPU 3: GRID A100-4C, 4096M");
+        // a type the driver cannot describe
+        assert_eq!(
+            vgpu_label(&VgpuStat {
+                id: 4,
+                ..Default::default()
+            }),
+            "vGPU 4"
+        );
+    }
+
+    #[test]
+    fn perf_is_bold_when_a_busy_gpu_loses_clocks() {
+        use nvml_wrapper::bitmasks::device::ThrottleReasons;
+        let perf = |percent, utilization, reasons| GpuStat {
+            perf: Some(Perf {
+                sm_clock: 2520 * percent / 100,
+                sm_clock_max: 2520,
+                percent,
+            }),
+            utilization,
+            throttle_reasons: reasons,
+            ..Default::default()
+        };
+        // the label, and whether it is bold
+        let cell = |args: &[&str], gpu: &GpuStat| {
+            let cells = perf_cells(&render_opts(args), &mut Styling::new(0.0), gpu);
+            let label = cells[0].content();
+            let mut table = Table::new();
+            table.force_no_tty().enforce_styling().add_row(cells);
+            (label, table.to_string().contains("\u{1b}[1m"))
+        };
+        let capped = perf(70, 95, Some(ThrottleReasons::SW_POWER_CAP));
+        assert_eq!(cell(&[], &capped), (String::from("perf: 70% (SwPower)"), true));
+        assert!(!cell(&["--perf-threshold", "70"], &capped).1);
+        // P8 on an idle GPU is not a loss, nor is P0 with nothing holding it back
+        let idle = perf(8, 0, Some(ThrottleReasons::GPU_IDLE));
+        assert_eq!(cell(&[], &idle), (String::from("perf: 8%"), false));
+        assert_eq!(cell(&[], &perf(100, 95, None)), (String::from("perf: 100%"), false));
+        assert_eq!(cell(&[], &GpuStat::default()).0, "");
+    }
+
+    #[test]
+    fn owner_colors_and_ties_do_not_depend_on_the_call() {
+        let owners = Palette::Default.theme().owners;
+        let colors = || -> Vec<Color> {
+            ["alice", "bob", "carol"]
+                .iter()
+                .map(|user| user_color(user, owners))
+                .collect()
+        };
+        // pinned, so a refresh or another run picks the same
+        assert_eq!(colors(), [rgb(255, 135, 0), Color::DarkCyan, Color::Magenta]);
+        assert_eq!(colors(), colors());
+
+        let alice = || process(1, "alice", "python", 4096);
+        let bob = || process(2, "bob", "python", 4096);
+        let carol = || process(3, "carol", "python", 1024);
+        // whatever order NVML lists them in
+        let orders = |processes: [ProcessStat; 3]| -> Vec<Option<(String, usize)>> {
+            let [a, b, c] = processes;
+            vec![
+                vec![a.clone(), b.clone(), c.clone()],
+                vec![b.clone(), c.clone(), a.clone()],
+                vec![c, b, a],
+            ]
+            .into_iter()
+            .map(|processes| owner(&gpu_with(processes)))
+            .collect()
+        };
+        assert_eq!(orders([alice(), bob(), carol()]), [None, None, None]);
+        // a tie below the top one does not matter
+        let bigger = ProcessStat {
+            used_memory: Some(8192 << 20),
+            ..alice()
+        };
+        let owner = Some((String::from("alice"), 2));
+        assert_eq!(orders([bigger, bob(), carol()]), [owner.clone(), owner.clone(), owner]);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{ffi, require_root, StatusError, EXIT_DEGRADED, EXIT_USAGE};
+use clap::Args;
+use nvml_wrapper::{enum_wrappers::device::TemperatureSensor, Nvml};
+use std::{fs, path::PathBuf};
+use sysinfo::{Pid, PidExt, ProcessExt, System, SystemExt};
+
+#[derive(Args, Clone, Debug)]
+pub struct ResetOpts {
+    #[arg(long, value_name = "INDEX", help = "GPU to reset")]
+    id: u32,
+    #[arg(long, help = "Reset even when processes are still running on the GPU")]
+    force: bool,
+}
+
+/// sysfs directory of a PCI device, e.g. `00000000:3B:00.0` becomes `0000:3b:00.0`.
+pub(crate) fn sysfs_device(bus_id: &str) -> PathBuf {
+    let bus_id = bus_id.to_ascii_lowercase();
+    let address = match bus_id.split_once(':') {
+        Some((domain, rest)) if domain.len() > 4 => {
+            format!("{}:{}", &domain[domain.len() - 4..], rest)
+        }
+        _ => bus_id,
+    };
+    PathBuf::from("/sys/bus/pci/devices").join(address)
+}
+
+/// Reset one idle GPU and report its state once it is back.
+///
+/// NVML has no public reset call, so this goes through the kernel's PCI
+/// function reset, as `nvidia-smi --gpu-reset` does.
+pub fn run(opts: &ResetOpts, nvml: &Nvml) -> Result<u8, StatusError> {
+    require_root("resetting a GPU")?;
+
+    let id = opts.id;
+    let device = nvml.device_by_index(id)?;
+    let name = device.name()?;
+
+    let mut pids: Vec<u32> = device
+        .running_compute_processes()?
+        .into_iter()
+        .chain(device.running_graphics_processes().unwrap_or_default())
+        .map(|info| info.pid)
+        .collect();
+    pids.sort_unstable();
+    pids.dedup();
+    if !pids.is_empty() && !opts.force {
+        let mut system = System::new();
+        eprintln!(
+            "GPU {} ({}) is in use, refusing to reset it (use --force to override):",
+            id, name
+        );
+        for pid in pids {
+            let command = if system.refresh_process(Pid::from_u32(pid)) {
+                system
+                    .process(Pid::from_u32(pid))
+                    .map(|p| p.name().to_string())
+                    .unwrap_or_default()
+            } else {
+                String::from("?")
+            };
+            eprintln!("  {} {}", pid, command);
+        }
+        return Ok(EXIT_USAGE);
+    }
+
+    let reset = sysfs_device(&device.pci_info()?.bus_id).join("reset");
+    if !reset.exists() {
+        eprintln!("GPU {} ({}): reset is not supported", id, name);
+        return Ok(EXIT_USAGE);
+    }
+    fs::write(&reset, "1")?;
+
+    match nvml.device_by_index(id).and_then(|device| {
+        let (memory, _) = ffi::memory_info(&device)?;
+        let temperature = device.temperature(TemperatureSensor::Gpu)?;
+        Ok((temperature, memory))
+    }) {
+        Ok((temperature, memory)) => {
+            println!(
+                "GPU {} ({}) reset: {}°C, {} / {} MB",
+                id,
+                name,
+                temperature,
+                memory.used >> 20,
+                memory.total >> 20
+            );
+            Ok(0)
+        }
+        Err(e) => {
+            eprintln!(
+                "GPU {} ({}) did not come back after the reset: {}",
+                id, name, e
+            );
+            Ok(EXIT_DEGRADED)
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! NVIDIA GPUs suspended by runtime power management (D3cold), as on
+//! desktops and laptops whose second GPU sleeps while unused. Any NVML call
+//! on such a device, even getting its handle, wakes it up and keeps it awake
+//! for seconds with its fans spinning, so unless `--wake` is given it is
+//! reported as suspended from `power/runtime_status` in sysfs instead.
+//!
+//! Before the PCI address of a device is known from NVML, it is found from
+//! its position among the NVIDIA display devices of sysfs in PCI order, the
+//! order NVML enumerates them in, which reads nothing from the devices. The
+//! name of a suspended device it has not seen awake comes from the PCI ID
+//! database when one is installed.
+
+use std::{collections::BTreeMap, fs, path::Path, sync::Mutex};
+
+const NVIDIA: &str = "0x10de";
+
+/// Where distributions install the PCI ID database.
+const PCI_IDS: &[&str] = &["/usr/share/hwdata/pci.ids", "/usr/share/misc/pci.ids", "/usr/share/pci.ids"];
+
+/// Names by PCI device id, so that watch mode reads the database once.
+static NAMES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|text| text.trim().to_string())
+}
+
+/// The sysfs form `0000:01:00.0` of an NVML bus id such as `00000000:01:00.0`.
+pub fn sysfs_bus_id(bus_id: &str) -> String {
+    let trimmed = bus_id.len().saturating_sub("0000:01:00.0".len());
+    bus_id[trimmed..].to_lowercase()
+}
+
+/// The NVML form `00000000:01:00.0` of a sysfs bus id.
+pub fn nvml_bus_id(bus_id: &str) -> String {
+    format!("0000{}", bus_id.to_uppercase())
+}
+
+/// Sysfs bus ids of the NVIDIA display controllers under `sys`, in PCI order.
+fn nvidia_devices(sys: &Path) -> Vec<String> {
+    let mut nvidia: Vec<String> = fs::read_dir(sys.join("bus/pci/devices"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            let vendor = read_trimmed(&entry.path().join("vendor"));
+            // display controllers, not the audio and USB functions of the card
+            let class = read_trimmed(&entry.path().join("class"));
+            vendor.as_deref() == Some(NVIDIA) && class.is_some_and(|class| class.starts_with("0x03"))
+        })
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .collect();
+    nvidia.sort();
+    nvidia
+}
+
+fn runtime_suspended(sys: &Path, bus_id: &str) -> bool {
+    read_trimmed(&sys.join("bus/pci/devices").join(bus_id).join("power/runtime_status"))
+        .is_some_and(|status| status == "suspended")
+}
+
+/// The sysfs bus id of the GPU of NVML `index` under `sys` if it is
+/// suspended, by its NVML `bus_id` once known.
+pub fn suspended_in(sys: &Path, index: u32, bus_id: Option<&str>) -> Option<String> {
+    let bus_id = match bus_id {
+        Some(bus_id) => sysfs_bus_id(bus_id),
+        None => nvidia_devices(sys).into_iter().nth(index as usize)?,
+    };
+    Some(bus_id).filter(|bus_id| runtime_suspended(sys, bus_id))
+}
+
+/// `suspended_in` the running system.
+pub fn suspended(index: u32, bus_id: Option<&str>) -> Option<String> {
+    suspended_in(Path::new("/sys"), index, bus_id)
+}
+
+/// The name of NVIDIA device `device` (e.g. `2704`) in the PCI ID database
+/// `pci_ids`, the marketing name in brackets where there is one, e.g.
+/// `NVIDIA GeForce RTX 4080` for `AD103 [GeForce RTX 4080]`.
+pub fn name_in(pci_ids: &str, device: &str) -> Option<String> {
+    if device.is_empty() {
+        return None;
+    }
+    let mut lines = pci_ids.lines().skip_while(|line| !line.starts_with("10de "));
+    lines.next()?;
+    // the devices of a vendor are indented once, their subsystems twice
+    let entry = lines
+        .take_while(|line| line.starts_with('\t') || line.starts_with('#'))
+        .filter_map(|line| line.strip_prefix('\t'))
+        .find_map(|line| line.strip_prefix(device)?.strip_prefix(char::is_whitespace))?
+        .trim();
+    let name = match (entry.find('['), entry.rfind(']')) {
+        (Some(open), Some(close)) if open < close => &entry[open + 1..close],
+        _ => entry,
+    };
+    Some(format!("NVIDIA {}", name))
+}
+
+/// The name of the suspended GPU at sysfs `bus_id` from its PCI device id,
+/// `NVIDIA [10de:2704]` without a PCI ID database that knows it.
+pub fn device_name(bus_id: &str) -> String {
+    let path = Path::new("/sys/bus/pci/devices").join(bus_id).join("device");
+    let device = read_trimmed(&path).unwrap_or_default();
+    let device = device.trim_start_matches("0x");
+    let mut names = NAMES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let name = names.entry(device.to_string()).or_insert_with(|| {
+        PCI_IDS
+            .iter()
+            .find_map(|path| name_in(&fs::read_to_string(path).ok()?, device))
+            .unwrap_or_else(|| format!("NVIDIA [10de:{}]", device))
+    });
+    name.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A sysfs tree of PCI functions given as (bus id, vendor, class, runtime status).
+    fn sys_tree(name: &str, functions: &[(&str, &str, &str, Option<&str>)]) -> PathBuf {
+        let sys = std::env::temp_dir().join(format!("gpustat-sys-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&sys);
+        for (bus_id, vendor, class, status) in functions {
+            let device = sys.join("bus/pci/devices").join(bus_id);
+            fs::create_dir_all(device.join("power")).unwrap();
+            fs::write(device.join("vendor"), format!("{}\n", vendor)).unwrap();
+            fs::write(device.join("class"), format!("{}\n", class)).unwrap();
+            if let Some(status) = status {
+                fs::write(device.join("power/runtime_status"), format!("{}\n", status)).unwrap();
+            }
+        }
+        sys
+    }
+
+    #[test]
+    fn bus_ids_convert_between_nvml_and_sysfs() {
+        assert_eq!(sysfs_bus_id("00000000:0A:00.0"), "0000:0a:00.0");
+        assert_eq!(nvml_bus_id("0000:0a:00.0"), "00000000:0A:00.0");
+    }
+
+    #[test]
+    fn indices_follow_the_nvidia_display_devices() {
+        let sys = sys_tree(
+            "indices",
+            &[
+                ("0000:00:02.0", "0x8086", "0x030000", Some("active")),
+                ("0000:41:00.0", "0x10de", "0x030200", Some("suspended")),
+                ("0000:41:00.1", "0x10de", "0x040300", Some("suspended")),
+                ("0000:01:00.0", "0x10de", "0x030000", Some("active")),
+                ("0000:02:00.0", "0x10de", "0x030000", None),
+            ],
+        );
+        assert_eq!(nvidia_devices(&sys), ["0000:01:00.0", "0000:02:00.0", "0000:41:00.0"]);
+        assert_eq!(suspended_in(&sys, 0, None), None);
+        // no runtime_status counts as awake
+        assert_eq!(suspended_in(&sys, 1, None), None);
+        assert_eq!(suspended_in(&sys, 2, None).as_deref(), Some("0000:41:00.0"));
+        assert_eq!(suspended_in(&sys, 3, None), None);
+        // a known bus id wins over the position
+        assert_eq!(
+            suspended_in(&sys, 0, Some("00000000:41:00.0")).as_deref(),
+            Some("0000:41:00.0")
+        );
+    }
+
+    #[test]
+    fn names_come_from_the_pci_id_database() {
+        let pci_ids = "\
+# comment
+10b5  PLX Technology, Inc.
+\t2704  Not this one
+10de  NVIDIA Corporation
+\t1eb8  TU104GL [Tesla T4]
+\t\t10de 12a2  T4 16GB
+#\t0000  commented out
+\t2704  AD103 [GeForce RTX 4080]
+\t2330  GH100
+10df  Emulex Corporation
+\t2331  Not NVIDIA
+";
+        assert_eq!(name_in(pci_ids, "2704").as_deref(), Some("NVIDIA GeForce RTX 4080"));
+        assert_eq!(name_in(pci_ids, "1eb8").as_deref(), Some("NVIDIA Tesla T4"));
+        assert_eq!(name_in(pci_ids, "2330").as_deref(), Some("NVIDIA GH100"));
+        assert_eq!(name_in(pci_ids, "2331"), None);
+        assert_eq!(name_in(pci_ids, "12a2"), None);
+        assert_eq!(name_in(pci_ids, ""), None);
+    }
+}
@@ -0,0 +1,167 @@
+//! `--show-sharing`: GPUs that the Kubernetes device plugin hands out to
+//! several pods at once through time-slicing, which leaves no trace in NVML.
+//!
+//! The replica count comes from the device plugin's config file when it is
+//! mounted on this node, and is otherwise inferred from the distinct pods
+//! running on the GPU.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Where the device plugin's ConfigMap is usually mounted.
+pub const CONFIG_PATHS: &[&str] = &["/config/config.yaml", "/etc/nvidia-device-plugin/config.yaml"];
+
+/// How many ways a GPU is time-sliced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sharing {
+    /// Replicas advertised per GPU, a lower bound when `inferred`.
+    pub replicas: u32,
+    /// Counted from the pods on the GPU rather than read from the config.
+    pub inferred: bool,
+    /// Distinct pods running on the GPU.
+    pub pods: u32,
+}
+
+/// The largest `replicas` below `sharing.timeSlicing` of a device plugin
+/// config, e.g.
+///
+/// ```yaml
+/// sharing:
+///   timeSlicing:
+///     resources:
+///     - name: nvidia.com/gpu
+///       replicas: 4
+/// ```
+///
+/// Read line by line, which the flat layout of the config allows.
+pub fn parse_replicas(config: &str) -> Option<u32> {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let mut section: Option<usize> = None;
+    let mut replicas = None;
+    for line in config.lines().filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#')) {
+        let depth = indent(line);
+        let line = line.trim().trim_start_matches("- ");
+        match section {
+            Some(section_depth) if depth <= section_depth => section = None,
+            Some(_) => {
+                if let Some(value) = line.strip_prefix("replicas:") {
+                    let value = value.split('#').next().unwrap_or_default().trim();
+                    replicas = replicas.max(value.parse().ok());
+                }
+                continue;
+            }
+            None => {}
+        }
+        if line == "timeSlicing:" {
+            section = Some(depth);
+        }
+    }
+    replicas.filter(|&replicas| replicas > 1)
+}
+
+/// Time-slicing replicas configured on this node: those of `path`, or of the
+/// first of `CONFIG_PATHS` that exists.
+pub fn configured_replicas(path: Option<&Path>) -> Option<u32> {
+    let config = match path {
+        Some(path) => fs::read_to_string(path)
+            .inspect_err(|e| log::warn!("Cannot read --sharing-config {}: {}", path.display(), e))
+            .ok()?,
+        None => CONFIG_PATHS.iter().find_map(|path| fs::read_to_string(path).ok())?,
+    };
+    parse_replicas(&config)
+}
+
+/// How a GPU running `pods` distinct pods is shared, given the `configured`
+/// replicas. Without a config, two pods or more reveal time-slicing by at
+/// least their number; a single pod, or none, reveals nothing.
+pub fn infer(configured: Option<u32>, pods: u32) -> Option<Sharing> {
+    match configured {
+        Some(replicas) => Some(Sharing {
+            replicas,
+            inferred: false,
+            pods,
+        }),
+        None if pods > 1 => Some(Sharing {
+            replicas: pods,
+            inferred: true,
+            pods,
+        }),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replicas_come_from_the_time_slicing_section_only() {
+        let cases = [
+            (
+                "time-slicing",
+                "version: v1\nsharing:\n  timeSlicing:\n    resources:\n    - name: nvidia.com/gpu\n      replicas: 4\n",
+                Some(4),
+            ),
+            (
+                "the largest of several resources",
+                "sharing:\n  timeSlicing:\n    resources:\n    - name: nvidia.com/gpu\n      replicas: 2 # small jobs\n    - name: nvidia.com/mig-1g.10gb\n      replicas: 8\n",
+                Some(8),
+            ),
+            ("a single replica", "sharing:\n  timeSlicing:\n    resources:\n    - name: nvidia.com/gpu\n      replicas: 1\n", None),
+            // MPS partitions the GPU, and its clients show up in NVML
+            ("MPS", "sharing:\n  mps:\n    resources:\n    - name: nvidia.com/gpu\n      replicas: 4\n", None),
+            (
+                "MPS next to time-slicing",
+                "sharing:\n  mps:\n    resources:\n    - replicas: 6\n  timeSlicing:\n    resources:\n    - replicas: 3\n",
+                Some(3),
+            ),
+            // MIG instances are devices of their own
+            ("MIG", "version: v1\nflags:\n  migStrategy: mixed\n", None),
+            ("commented out", "sharing:\n  timeSlicing:\n    resources:\n    # - replicas: 4\n", None),
+            ("empty", "", None),
+        ];
+        for (case, config, replicas) in cases {
+            assert_eq!(parse_replicas(config), replicas, "{}", case);
+        }
+    }
+
+    #[test]
+    fn pods_on_a_gpu_reveal_time_slicing() {
+        let sharing = |replicas, inferred, pods| {
+            Some(Sharing {
+                replicas,
+                inferred,
+                pods,
+            })
+        };
+        let cases = [
+            // configured replicas show even on an idle GPU
+            (Some(4), 0, sharing(4, false, 0)),
+            (Some(4), 6, sharing(4, false, 6)),
+            // without a config, at least as many replicas as pods
+            (None, 3, sharing(3, true, 3)),
+            (None, 1, None),
+            (None, 0, None),
+        ];
+        for (configured, pods, expected) in cases {
+            assert_eq!(
+                infer(configured, pods),
+                expected,
+                "{:?} replicas, {} pods",
+                configured,
+                pods
+            );
+        }
+    }
+
+    #[test]
+    fn a_given_config_is_read_and_a_missing_one_ignored() {
+        let dir = std::env::temp_dir().join(format!("gpustat-sharing-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("config.yaml");
+        fs::write(&config, "sharing:\n  timeSlicing:\n    resources:\n    - replicas: 4\n").unwrap();
+        assert_eq!(configured_replicas(Some(&config)), Some(4));
+        assert_eq!(configured_replicas(Some(&dir.join("missing.yaml"))), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
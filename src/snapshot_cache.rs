@@ -0,0 +1,431 @@
+//! `--cache DURATION`: one-shot runs of the same user within DURATION of
+//! each other share one collection, for status bars, prompts and cron jobs
+//! that each call gpustat every few seconds.
+//!
+//! Each command line has its own cache, as the options decide what is
+//! collected and the rendering shows whatever the snapshot holds; only the
+//! `--cache` duration itself is left out, so runs asking for different ages
+//! share one. The caches live in `$XDG_RUNTIME_DIR/gpustat`, or
+//! `/tmp/gpustat-UID` without one; a directory or file that is not the
+//! user's own, or that others can write, is never read. Runs that find the
+//! cache stale take a lock and collect one at a time, so the first one
+//! refreshes it and the others read its snapshot.
+//!
+//! With `--allow-stale`, a run that finds the cache stale and cannot collect
+//! a snapshot of its own, because NVML fails, shows the cached one anyway
+//! unless it is older than `--max-age`. Its age in the header turns yellow
+//! past the `--cache` duration and red past three times it. Each GPU and
+//! process carries the time it was queried and its own age, and the oldest
+//! of them is the age of the snapshot.
+
+use crate::{
+    provider::Provider,
+    stats::{self, Snapshot},
+    write_atomic, Opts, StatusError,
+};
+use nix::{
+    errno::Errno,
+    fcntl::{flock, FlockArg},
+    unistd::geteuid,
+};
+use std::{
+    env,
+    ffi::OsString,
+    fs::{self, DirBuilder, File, OpenOptions},
+    io,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{DirBuilderExt, MetadataExt},
+        io::AsRawFd,
+    },
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Longest wait for another run to finish refreshing the cache; a run still
+/// waiting then collects on its own without writing the cache.
+const LOCK_WAIT: Duration = Duration::from_secs(5);
+
+const LOCK_POLL: Duration = Duration::from_millis(10);
+
+/// The cache of this user for one command line.
+#[derive(Debug)]
+pub struct SnapshotCache {
+    path: PathBuf,
+    ttl: Duration,
+}
+
+/// Held while this run refreshes the cache.
+#[derive(Debug)]
+pub struct CacheLock(File);
+
+/// How the age of a replayed snapshot compares with the `--cache` duration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Staleness {
+    Fresh,
+    /// At least the duration old, which only `--allow-stale` shows.
+    Stale,
+    /// More than three times the duration old.
+    VeryStale,
+}
+
+impl Staleness {
+    pub fn of(age_ms: u64, ttl: Duration) -> Self {
+        let (age_ms, ttl) = (u128::from(age_ms), ttl.as_millis());
+        if age_ms > ttl.saturating_mul(3) {
+            Staleness::VeryStale
+        } else if age_ms >= ttl {
+            Staleness::Stale
+        } else {
+            Staleness::Fresh
+        }
+    }
+}
+
+/// Why `path` must not be trusted, if it belongs to another user, can be
+/// written by other users or is a symlink.
+fn untrusted(path: &Path) -> Option<String> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => return Some(format!("{}: {}", path.display(), e)),
+    };
+    if metadata.file_type().is_symlink() {
+        return Some(format!("{} is a symlink", path.display()));
+    }
+    if metadata.uid() != geteuid().as_raw() {
+        return Some(format!("{} belongs to uid {}", path.display(), metadata.uid()));
+    }
+    if metadata.mode() & 0o022 != 0 {
+        return Some(format!("{} is writable by other users", path.display()));
+    }
+    None
+}
+
+/// `$XDG_RUNTIME_DIR/gpustat`, created private to the user.
+fn directory() -> Result<PathBuf, String> {
+    let directory = match env::var_os("XDG_RUNTIME_DIR").filter(|runtime| !runtime.is_empty()) {
+        Some(runtime) => PathBuf::from(runtime).join("gpustat"),
+        None => env::temp_dir().join(format!("gpustat-{}", geteuid())),
+    };
+    match DirBuilder::new().mode(0o700).create(&directory) {
+        Ok(()) => log::debug!("created {}", directory.display()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(format!("{}: {}", directory.display(), e)),
+    }
+    match untrusted(&directory) {
+        Some(reason) => Err(reason),
+        None => Ok(directory),
+    }
+}
+
+/// The name of the cache of the arguments `args`, without the program name:
+/// an FNV-1a hash of them without `--cache`, `--allow-stale`, `--max-age`
+/// and `-v`, and the version that writes it.
+fn file_name(args: &[OsString]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--cache" || arg == "--max-age" {
+            args.next();
+            continue;
+        }
+        let ignored = ["--cache=", "--max-age="].iter().any(|option| arg.as_bytes().starts_with(option.as_bytes()));
+        if ignored || ["-v", "-vv", "--verbose", "--allow-stale"].iter().any(|flag| arg == flag) {
+            continue;
+        }
+        // the terminator keeps `-a b` apart from `-ab`
+        for &byte in arg.as_bytes().iter().chain(&[0]) {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("snapshot-{}-{:016x}.json", env!("CARGO_PKG_VERSION"), hash)
+}
+
+impl SnapshotCache {
+    /// The cache of the command line `args`, keeping snapshots for `ttl`.
+    pub fn new(args: &[OsString], ttl: Duration) -> Result<Self, String> {
+        Ok(SnapshotCache {
+            path: directory()?.join(file_name(args)),
+            ttl,
+        })
+    }
+
+    /// The cached snapshot with its age in milliseconds. The age is measured
+    /// on the boot clock, so a wall clock step neither ages the cache nor
+    /// makes it younger, and a cache from before a reboot has none.
+    fn read(&self) -> Option<(Snapshot, u64)> {
+        if fs::symlink_metadata(&self.path).is_err() {
+            return None;
+        }
+        if let Some(reason) = untrusted(&self.path) {
+            log::warn!("Not reading the cache: {}", reason);
+            return None;
+        }
+        let read = fs::read_to_string(&self.path).map_err(StatusError::from);
+        let snapshot = match read.and_then(|json| Snapshot::from_json(&json)) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                log::info!("cache {} is unreadable, collecting: {}", self.path.display(), e);
+                return None;
+            }
+        };
+        let age = stats::uptime_between(snapshot.uptime_ms?, stats::uptime_ms()?)?;
+        Some((snapshot, age))
+    }
+
+    /// The cached snapshot if its oldest GPU is younger than the duration,
+    /// with its age.
+    fn fresh(&self) -> Option<Snapshot> {
+        let (mut snapshot, age) = self.read()?;
+        snapshot.set_age(age);
+        let age = snapshot.data_age_ms().unwrap_or(age);
+        if Staleness::of(age, self.ttl) != Staleness::Fresh {
+            log::info!("cache {} is {}ms old, collecting", self.path.display(), age);
+            return None;
+        }
+        log::info!("cache {} is {}ms old", self.path.display(), age);
+        Some(snapshot)
+    }
+
+    /// The cached snapshot however stale, unless older than `max_age`, for
+    /// `--allow-stale` runs that could not collect one.
+    pub fn stale(&self, max_age: Option<Duration>) -> Option<Snapshot> {
+        let (mut snapshot, age) = self.read()?;
+        snapshot.set_age(age);
+        let age = snapshot.data_age_ms().unwrap_or(age);
+        if max_age.is_some_and(|max_age| Duration::from_millis(age) > max_age) {
+            log::warn!("Not showing the cached snapshot, it is {:.1}s old, past --max-age", age as f64 / 1000.0);
+            return None;
+        }
+        Some(snapshot)
+    }
+
+    /// The file the cache path names, which changes with each write.
+    fn identity(&self) -> Option<(u64, u64)> {
+        fs::symlink_metadata(&self.path).ok().map(|metadata| (metadata.dev(), metadata.ino()))
+    }
+
+    /// The fresh cached snapshot, or else the lock to refresh the cache with
+    /// once no other run is refreshing it, `None` if that cannot be had.
+    pub fn fresh_or_lock(&self) -> Result<Snapshot, Option<CacheLock>> {
+        let before = self.identity();
+        if let Some(snapshot) = self.fresh() {
+            return Ok(snapshot);
+        }
+        let lock = self.lock();
+        // the run that held the lock may have written a snapshot meanwhile
+        if self.identity() != before {
+            if let Some(snapshot) = self.fresh() {
+                return Ok(snapshot);
+            }
+        }
+        Err(lock)
+    }
+
+    /// Wait for the lock of the refreshing run, `None` if it takes longer
+    /// than `LOCK_WAIT` or the lock file cannot be used.
+    fn lock(&self) -> Option<CacheLock> {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".lock");
+        let path = self.path.with_file_name(name);
+        // the lock file stays, so every run locks the same inode
+        let file = match OpenOptions::new().write(true).create(true).truncate(false).open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                log::warn!("Cannot lock {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if let Some(reason) = untrusted(&path) {
+            log::warn!("Not locking the cache: {}", reason);
+            return None;
+        }
+        let start = Instant::now();
+        loop {
+            match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+                Ok(()) => {
+                    log::debug!("locked {} after {:.0?}", path.display(), start.elapsed());
+                    return Some(CacheLock(file));
+                }
+                Err(Errno::EWOULDBLOCK) if start.elapsed() < LOCK_WAIT => thread::sleep(LOCK_POLL),
+                Err(Errno::EWOULDBLOCK) => {
+                    log::warn!("{} is still locked after {:.0?}, not caching", path.display(), LOCK_WAIT);
+                    return None;
+                }
+                Err(e) => {
+                    log::warn!("Cannot lock {}: {}", path.display(), e);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Collect a snapshot for the cache and, holding `lock`, write it.
+    /// Readers never see a partial file, as it is written to a temporary
+    /// file and renamed.
+    pub fn refresh(
+        &self,
+        opts: &Opts,
+        provider: &Arc<dyn Provider>,
+        hostname: &str,
+        lock: Option<CacheLock>,
+    ) -> Result<Snapshot, StatusError> {
+        let snapshot = match opts.query_timeout {
+            Some(timeout) => {
+                stats::TimedCollector::new(timeout).collect(&Arc::new(opts.clone()), provider, hostname)?
+            }
+            None => stats::collect(opts, provider.as_ref(), hostname)?,
+        };
+        #[cfg(feature = "igpu")]
+        let snapshot = Snapshot {
+            igpus: crate::igpu::Sampler::default().sample(),
+            ..snapshot
+        };
+        if let Some(lock) = lock {
+            if let Err(e) = write_atomic(&self.path, &snapshot.to_json()?) {
+                log::warn!("Cannot cache the snapshot in {}: {}", self.path.display(), e);
+            }
+            drop(lock);
+        }
+        Ok(snapshot)
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        let _ = flock(self.0.as_raw_fd(), FlockArg::Unlock);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixture::tests::{fixtures, gpu},
+        stats::{GpuStat, ProcessStat},
+    };
+    use chrono::prelude::*;
+    use clap::Parser;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    /// The cache `name` in an empty directory of its own.
+    fn scratch(name: &str, ttl: Duration) -> SnapshotCache {
+        let dir = env::temp_dir().join(format!("gpustat-cache-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        DirBuilder::new().mode(0o700).create(&dir).unwrap();
+        SnapshotCache {
+            path: dir.join("snapshot.json"),
+            ttl,
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn command_lines_name_their_cache() {
+        let name = file_name(&args(&["-a", "--cache", "2s"]));
+        assert!(name.starts_with(concat!("snapshot-", env!("CARGO_PKG_VERSION"), "-")));
+        // the age options and verbosity are left out
+        assert_eq!(
+            name,
+            file_name(&args(&["-a", "--cache=5s", "-v", "--allow-stale", "--max-age", "1m"]))
+        );
+        assert_ne!(name, file_name(&args(&["-a", "-p", "--cache", "2s"])));
+        assert_ne!(file_name(&args(&["-a", "b"])), file_name(&args(&["-ab"])));
+    }
+
+    #[test]
+    fn refreshed_snapshots_are_read_back_while_fresh() {
+        let cache = scratch("fresh", Duration::from_secs(60));
+        let lock = match cache.fresh_or_lock() {
+            Err(Some(lock)) => lock,
+            _ => panic!("an empty cache must be refreshed"),
+        };
+        let opts = Opts::try_parse_from(["gpustat"]).unwrap();
+        let provider: Arc<dyn Provider> = Arc::new(fixtures(vec![gpu(0)]));
+        let collected = cache.refresh(&opts, &provider, "node", Some(lock)).unwrap();
+        let cached = cache.fresh_or_lock().unwrap();
+        assert_eq!(cached.gpus[0].uuid, collected.gpus[0].uuid);
+        assert!(cached.cache_age_ms.is_some());
+        // past its duration, the next run collects
+        let expired = SnapshotCache {
+            ttl: Duration::ZERO,
+            ..cache
+        };
+        assert!(expired.fresh_or_lock().is_err());
+        assert!(expired.stale(None).is_some());
+        assert!(expired.stale(Some(Duration::from_secs(60))).is_some());
+    }
+
+    #[test]
+    fn foreign_looking_files_are_not_read() {
+        let cache = scratch("untrusted", Duration::from_secs(60));
+        let provider: Arc<dyn Provider> = Arc::new(fixtures(vec![gpu(0)]));
+        let opts = Opts::try_parse_from(["gpustat"]).unwrap();
+        cache.refresh(&opts, &provider, "node", cache.lock()).unwrap();
+        assert!(cache.read().is_some());
+        fs::set_permissions(&cache.path, fs::Permissions::from_mode(0o666)).unwrap();
+        assert!(untrusted(&cache.path).unwrap().ends_with("is writable by other users"));
+        assert!(cache.read().is_none());
+        let target = cache.path.with_file_name("elsewhere.json");
+        fs::rename(&cache.path, &target).unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+        symlink(&target, &cache.path).unwrap();
+        assert!(untrusted(&cache.path).unwrap().ends_with("is a symlink"));
+        assert!(cache.read().is_none());
+        // garbage is collected over
+        fs::remove_file(&cache.path).unwrap();
+        fs::write(&cache.path, "{\"gpus\": [").unwrap();
+        assert!(cache.read().is_none());
+    }
+
+    #[test]
+    fn staleness_against_the_cache_duration() {
+        let ttl = Duration::from_secs(2);
+        assert_eq!(Staleness::of(1999, ttl), Staleness::Fresh);
+        assert_eq!(Staleness::of(2000, ttl), Staleness::Stale);
+        assert_eq!(Staleness::of(6000, ttl), Staleness::Stale);
+        assert_eq!(Staleness::of(6001, ttl), Staleness::VeryStale);
+    }
+
+    #[test]
+    fn gpus_and_processes_age_from_when_they_were_queried() {
+        let collected = Local.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+        let earlier = collected - chrono::Duration::milliseconds(300);
+        let process = ProcessStat {
+            collected_at: Some(earlier),
+            ..Default::default()
+        };
+        let mut snapshot = Snapshot {
+            timestamp: earlier,
+            collected_at: Some(collected),
+            gpus: vec![
+                GpuStat {
+                    collected_at: Some(earlier),
+                    processes: vec![process],
+                    ..Default::default()
+                },
+                GpuStat {
+                    collected_at: Some(collected),
+                    ..Default::default()
+                },
+                // never queried
+                GpuStat::default(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(snapshot.data_age_ms(), None);
+        snapshot.set_age(1800);
+        assert_eq!(snapshot.cache_age_ms, Some(1800));
+        let ages: Vec<_> = snapshot.gpus.iter().map(|gpu| gpu.age_ms).collect();
+        assert_eq!(ages, [Some(2100), Some(1800), None]);
+        assert_eq!(snapshot.gpus[0].processes[0].age_ms, Some(2100));
+        // the oldest GPU decides, so this fresh cache holds stale data
+        let age = snapshot.data_age_ms().unwrap();
+        assert_eq!(Staleness::of(age, Duration::from_secs(2)), Staleness::Stale);
+    }
+}
@@ -0,0 +1,140 @@
+//! `--sort`: GPUs ordered by a metric, the highest first. Ties are broken by
+//! displayed index, so the same snapshot always gives the same order, and
+//! GPUs that could not be measured follow the others in index order.
+//!
+//! In watch mode each refresh starts from the previous order, and a GPU only
+//! moves ahead of another once it leads it by more than the metric's
+//! `--change-threshold` margin, so rows do not trade places over noise such
+//! as one idle GPU at 1 % and another at 0 %.
+
+use crate::stats::{ChangeThreshold, GpuStat};
+use std::cmp::Ordering;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// GPU utilization
+    Util,
+    /// Used memory
+    Mem,
+    /// Temperature
+    Temp,
+    /// Power draw
+    Power,
+}
+
+fn value(gpu: &GpuStat, key: SortKey) -> Option<i64> {
+    if !gpu.measured() {
+        return None;
+    }
+    Some(match key {
+        SortKey::Util => i64::from(gpu.utilization),
+        SortKey::Mem => gpu.memory_used as i64,
+        SortKey::Temp => i64::from(gpu.temperature),
+        SortKey::Power => i64::from(gpu.power_usage),
+    })
+}
+
+fn margin(key: SortKey, band: &ChangeThreshold) -> i64 {
+    match key {
+        SortKey::Util => i64::from(band.util),
+        SortKey::Mem => band.mem as i64,
+        SortKey::Temp => i64::from(band.temp),
+        SortKey::Power => i64::from(band.power),
+    }
+}
+
+fn index(gpu: &GpuStat) -> u32 {
+    gpu.order_index.unwrap_or(gpu.index)
+}
+
+/// The order of a fresh sort by `key`: the highest value first, then the
+/// lowest displayed index.
+pub fn compare(a: &GpuStat, b: &GpuStat, key: SortKey) -> Ordering {
+    let by_value = match (value(a, key), value(b, key)) {
+        (Some(a), Some(b)) => b.cmp(&a),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    by_value.then(index(a).cmp(&index(b)))
+}
+
+/// Whether `b`, listed after `a`, belongs before it beyond doubt: it leads
+/// `a` by more than the margin, or only `b` was measured.
+fn overtakes(a: &GpuStat, b: &GpuStat, key: SortKey, band: &ChangeThreshold) -> bool {
+    match (value(a, key), value(b, key)) {
+        (Some(a), Some(b)) => b - a > margin(key, band),
+        (None, Some(_)) => true,
+        (Some(_), None) => false,
+        (None, None) => index(b) < index(a),
+    }
+}
+
+/// Sort `gpus` by `key`, starting from `previous`, the NVML indices in the
+/// order of the previous refresh. GPUs missing from it start after the
+/// others in the order of `compare`, and every GPU then moves ahead of those
+/// it overtakes.
+pub fn sort(gpus: &mut [GpuStat], key: SortKey, band: &ChangeThreshold, previous: &[u32]) {
+    let position = |gpu: &GpuStat| {
+        previous.iter().position(|&index| index == gpu.index).unwrap_or(previous.len())
+    };
+    gpus.sort_by(|a, b| position(a).cmp(&position(b)).then_with(|| compare(a, b, key)));
+    // each swap puts one pair in order for good, so the passes end
+    let mut swapped = true;
+    while swapped {
+        swapped = false;
+        for i in 1..gpus.len() {
+            if overtakes(&gpus[i - 1], &gpus[i], key, band) {
+                gpus.swap(i - 1, i);
+                swapped = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gpus(utilization: &[u32]) -> Vec<GpuStat> {
+        utilization
+            .iter()
+            .enumerate()
+            .map(|(index, &utilization)| GpuStat {
+                index: index as u32,
+                utilization,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn order(gpus: &[GpuStat]) -> Vec<u32> {
+        gpus.iter().map(|gpu| gpu.index).collect()
+    }
+
+    #[test]
+    fn fresh_sorts_break_ties_by_index() {
+        let mut sorted = gpus(&[3, 0, 5, 3]);
+        sorted[1].error = Some(String::from("GpuLost"));
+        sorted.sort_by(|a, b| compare(a, b, SortKey::Util));
+        // the failed GPU goes last
+        assert_eq!(order(&sorted), [2, 0, 3, 1]);
+    }
+
+    #[test]
+    fn watch_mode_keeps_the_order_within_the_margin() {
+        let band = ChangeThreshold::default();
+        let refresh = |utilization: &[u32], previous: &[u32]| {
+            let mut sorted = gpus(utilization);
+            sort(&mut sorted, SortKey::Util, &band, previous);
+            order(&sorted)
+        };
+        let first = refresh(&[3, 0, 5], &[]);
+        assert_eq!(first, [2, 0, 1]);
+        assert_eq!(refresh(&[3, 4, 5], &first), [2, 0, 1]);
+        assert_eq!(refresh(&[3, 20, 5], &first), [1, 2, 0]);
+        assert_eq!(refresh(&[0, 0, 0], &[1, 2, 0]), [1, 2, 0]);
+        // a GPU not seen before starts last and passes those it leads by more than 5
+        assert_eq!(refresh(&[3, 0, 5, 9], &first), [2, 3, 0, 1]);
+    }
+}
@@ -0,0 +1,245 @@
+//! `--state-file`: the session peaks, `--show-busy` total, `--bell-on`
+//! cooldowns, GPU idle times and collection error counts of watch mode, saved
+//! periodically and on SIGINT or SIGTERM so a restarted watcher carries on
+//! where the previous one stopped. One-shot runs only read the idle times.
+//!
+//! Watch mode also takes SIGUSR1 as a request to write the collection error
+//! counts to stderr.
+
+use crate::{
+    bell::{Bell, BellState},
+    collection_errors::ErrorCount,
+    stats::{BusyTime, GpuActivity, GpuPeaks, HistoryStore, IdleTracker},
+    write_atomic,
+};
+use chrono::prelude::*;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// How often watch mode writes the state file between shutdowns.
+pub const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Age up to which the GPU activity in a state file is trusted, covering a
+/// restart; older, the GPUs may have been used while nobody watched.
+const ACTIVITY_FRESH_FOR: Duration = Duration::from_secs(300);
+
+/// Granularity at which a sleeping watch loop notices a stop request.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// What a watch session hands on to the next one.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SessionState {
+    pub hostname: String,
+    pub driver_version: Option<String>,
+    pub saved_at: DateTime<Local>,
+    /// Session peaks by GPU UUID.
+    pub gpus: BTreeMap<String, GpuPeaks>,
+    pub busy: Option<BusyTime>,
+    pub bell: BellState,
+    /// When each GPU was last active, by UUID.
+    #[serde(default)]
+    pub activity: BTreeMap<String, GpuActivity>,
+    /// How often each query failed, as `ErrorCounter::counts` lists them.
+    #[serde(default)]
+    pub collection_errors: Vec<ErrorCount>,
+}
+
+impl SessionState {
+    /// Gather the state of a running session.
+    pub fn capture(
+        hostname: &str,
+        driver_version: Option<&str>,
+        history: &HistoryStore,
+        busy: Option<BusyTime>,
+        bell: Option<&Bell>,
+        idle: Option<&IdleTracker>,
+        collection_errors: Vec<ErrorCount>,
+    ) -> Self {
+        SessionState {
+            hostname: hostname.to_string(),
+            driver_version: driver_version.map(str::to_string),
+            saved_at: Local::now(),
+            gpus: history.peaks(),
+            busy,
+            bell: bell.map(Bell::state).unwrap_or_default(),
+            activity: idle.map(IdleTracker::activity).unwrap_or_default(),
+            collection_errors,
+        }
+    }
+
+    /// The GPU activity, if the state was saved recently enough to trust it.
+    pub fn fresh_activity(&self) -> BTreeMap<String, GpuActivity> {
+        let age = (Local::now() - self.saved_at).to_std();
+        if age.is_ok_and(|age| age <= ACTIVITY_FRESH_FOR) {
+            self.activity.clone()
+        } else {
+            log::info!("state saved {} is too old for idle times", self.saved_at.to_rfc3339());
+            BTreeMap::new()
+        }
+    }
+
+    /// The state saved in `path` if it was saved on this host under the same
+    /// driver. A missing file starts a new session; a corrupted or foreign
+    /// one is discarded with a warning.
+    pub fn load(path: &Path, hostname: &str, driver_version: Option<&str>) -> Option<SessionState> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                log::warn!("Cannot read state {}, starting over: {}", path.display(), e);
+                return None;
+            }
+        };
+        let state: SessionState = match serde_json::from_str(&json) {
+            Ok(state) => state,
+            Err(e) => {
+                log::warn!("Ignoring corrupted state {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if state.hostname != hostname || state.driver_version.as_deref() != driver_version {
+            log::warn!(
+                "Discarding state {} saved on {} with driver {}",
+                path.display(),
+                state.hostname,
+                state.driver_version.as_deref().unwrap_or("N/A")
+            );
+            return None;
+        }
+        log::info!("state restored from {}, saved {}", path.display(), state.saved_at.to_rfc3339());
+        Some(state)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(path, &serde_json::to_string(self)?)
+    }
+}
+
+extern "C" fn request_stop(_: nix::libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Turn the first SIGINT or SIGTERM into a stop request, so the state can be
+/// saved; a second one terminates as usual.
+pub fn catch_stop_signals() {
+    let action = SigAction::new(SigHandler::Handler(request_stop), SaFlags::SA_RESETHAND, SigSet::empty());
+    for signal in [Signal::SIGINT, Signal::SIGTERM] {
+        // SAFETY: the handler only stores to an atomic
+        if let Err(e) = unsafe { signal::sigaction(signal, &action) } {
+            log::warn!("Cannot handle {}: {}", signal, e);
+        }
+    }
+}
+
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::SeqCst)
+}
+
+extern "C" fn request_dump(_: nix::libc::c_int) {
+    DUMP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Turn every SIGUSR1 into a request for `sleep_unless_stopped` to dump.
+pub fn catch_dump_signal() {
+    let action = SigAction::new(SigHandler::Handler(request_dump), SaFlags::SA_RESTART, SigSet::empty());
+    // SAFETY: the handler only stores to an atomic
+    if let Err(e) = unsafe { signal::sigaction(Signal::SIGUSR1, &action) } {
+        log::warn!("Cannot handle {}: {}", Signal::SIGUSR1, e);
+    }
+}
+
+/// Sleep for `period`, waking up early on a stop request and calling `dump`
+/// for every SIGUSR1 meanwhile.
+pub fn sleep_unless_stopped(period: Duration, mut dump: impl FnMut()) {
+    let mut left = period;
+    while !left.is_zero() && !stop_requested() {
+        if DUMP_REQUESTED.swap(false, Ordering::SeqCst) {
+            dump();
+        }
+        let nap = left.min(STOP_POLL);
+        thread::sleep(nap);
+        left -= nap;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// An empty directory for one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gpustat-state-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn session() -> SessionState {
+        let mut history = HistoryStore::new(4, None, None);
+        let peaks = GpuPeaks {
+            power: 342_000,
+            memory: 15 << 30,
+        };
+        history.restore_peaks(&BTreeMap::from([(String::from("GPU-0"), peaks)]));
+        SessionState::capture("node", Some("550.54.14"), &history, None, None, None, vec![])
+    }
+
+    #[test]
+    fn states_round_trip_on_the_same_host_and_driver() {
+        let path = scratch("round-trip").join("nested/state.json");
+        session().save(&path).unwrap();
+        let state = SessionState::load(&path, "node", Some("550.54.14")).unwrap();
+        assert_eq!(
+            state.gpus["GPU-0"],
+            GpuPeaks {
+                power: 342_000,
+                memory: 15 << 30
+            }
+        );
+        let mut history = HistoryStore::new(4, None, None);
+        history.restore_peaks(&state.gpus);
+        assert_eq!(history.peaks(), state.gpus);
+    }
+
+    #[test]
+    fn foreign_corrupted_and_missing_states_are_ignored() {
+        let dir = scratch("ignored");
+        let path = dir.join("state.json");
+        assert!(SessionState::load(&path, "node", Some("550.54.14")).is_none());
+        session().save(&path).unwrap();
+        assert!(SessionState::load(&path, "other", Some("550.54.14")).is_none());
+        assert!(SessionState::load(&path, "node", Some("550.90.07")).is_none());
+        fs::write(&path, "{\"hostname\": ").unwrap();
+        assert!(SessionState::load(&path, "node", Some("550.54.14")).is_none());
+    }
+
+    #[test]
+    fn old_activity_is_not_trusted() {
+        let mut state = session();
+        state.activity.insert(
+            String::from("GPU-0"),
+            GpuActivity {
+                since: state.saved_at,
+                last_active: None,
+            },
+        );
+        assert_eq!(state.fresh_activity().len(), 1);
+        state.saved_at = Local::now() - chrono::Duration::minutes(10);
+        assert!(state.fresh_activity().is_empty());
+    }
+}
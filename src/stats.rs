@@ -0,0 +1,4649 @@
+use crate::{
+    allocation::Allocation,
+    cgroup,
+    chargeback::Chargeback,
+    collection_errors::ErrorCount,
+    columns::COLUMNS,
+    consistency::Difference,
+    explain::Legend,
+    ffi,
+    handles::{self, DeviceHandles, HandleScan},
+    igpu::IntegratedGpu,
+    provider::Provider,
+    reset, runtime_pm,
+    sharing::{self, Sharing},
+    xid::{self, XidEvent},
+    Failure, Opts, StatusError,
+};
+use chrono::prelude::*;
+use nix::unistd::{Uid, User};
+use nvml_wrapper::{
+    bitmasks::device::ThrottleReasons,
+    enum_wrappers::device::{
+        Brand, Clock, EccCounter, EncoderType, GpuVirtualizationMode, InfoRom, MemoryError, Sampling,
+        TemperatureSensor, TemperatureThreshold,
+    },
+    enums::device::{FanControlPolicy, SampleValue, UsedGpuMemory},
+    error::NvmlError,
+    struct_wrappers::device::{EncoderSessionInfo, FieldValueSample, ProcessInfo, Sample},
+    structs::device::{ConfidentialComputeGpuCapabilities, EccModeState, FieldId},
+    Device,
+    sys_exports::field_id::{NVML_FI_DEV_MEMORY_TEMP, NVML_FI_DEV_POWER_AVERAGE, NVML_FI_DEV_POWER_INSTANT},
+};
+use regex::RegexBuilder;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, VecDeque},
+    convert::TryFrom,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use sysinfo::{Pid, PidExt, ProcessExt, ProcessRefreshKind, RefreshKind, System, SystemExt};
+
+/// Confidential computing mode of a capable GPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CcMode {
+    Enabled,
+    Disabled,
+    /// Enabled, but open to debugging and not fit for production.
+    DevTools,
+}
+
+impl CcMode {
+    /// The mode of a GPU, `None` when it is not capable of confidential
+    /// computing. NVML reports both per system, not per GPU.
+    pub fn of(device: &Device) -> Result<Option<CcMode>, NvmlError> {
+        let capabilities = device.get_confidential_compute_capabilities()?;
+        if capabilities.gpus_caps != ConfidentialComputeGpuCapabilities::Capable {
+            return Ok(None);
+        }
+        Ok(Some(match ffi::conf_compute_state()? {
+            (false, _) => CcMode::Disabled,
+            (true, false) => CcMode::Enabled,
+            (true, true) => CcMode::DevTools,
+        }))
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CcMode::Enabled => "enabled",
+            CcMode::Disabled => "disabled",
+            CcMode::DevTools => "devtools",
+        }
+    }
+}
+
+/// Which kind of GPU context a process holds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessKind {
+    #[default]
+    Compute,
+    Graphics,
+    /// Listed as both a compute and a graphics process.
+    Mixed,
+    /// Only known from its encoder sessions, with `--show-proc-codec`.
+    Encoder,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessStat {
+    pub pid: u32,
+    pub user: String,
+    /// Process name, as shown by `-c`.
+    pub command: String,
+    /// Full command line, as shown by `-f`.
+    pub full_command: String,
+    /// `--redact-args` reduced `full_command` to the executable's name.
+    pub redacted: bool,
+    /// `full_command` was cut at `--max-cmd-length` bytes.
+    pub command_truncated: bool,
+    /// Used GPU memory in bytes, `None` when NVML reports it as unavailable.
+    pub used_memory: Option<u64>,
+    pub kind: ProcessKind,
+    /// Start time in seconds since the epoch, used to tell reused PIDs apart.
+    pub start_time: u64,
+    /// PID of the parent process, `None` when sysinfo could not see the process.
+    pub parent: Option<u32>,
+    /// `false` when sysinfo could not see the process, so the owner or command may be missing.
+    pub resolved: bool,
+    /// NVML still lists the PID, but it has exited or now belongs to an unrelated process.
+    pub stale: bool,
+    /// Working directory with `--show-cwd`, `None` when it cannot be read.
+    pub cwd: Option<String>,
+    /// Active virtualenv or conda env with `--show-env`.
+    pub env: Option<String>,
+    /// Lifetime peak GPU memory in bytes from accounting stats, with `--show-accounting`.
+    pub max_memory: Option<u64>,
+    /// Lifetime average GPU utilization from accounting stats, with `--show-accounting`.
+    pub average_utilization: Option<u32>,
+    /// Resident host memory in bytes, with `--show-host-mem` and `-f`.
+    pub host_memory: Option<u64>,
+    /// Kubernetes pod of the process, with `--show-allocations`.
+    pub pod: Option<String>,
+    /// Compute contexts NVML lists for this PID on the device, whose memory
+    /// is summed in `used_memory`.
+    pub contexts: u32,
+    /// NVENC sessions of the process, with `--show-proc-codec`.
+    pub encoder: Option<EncoderUse>,
+    /// Found holding the device in /proc because NVML refused to list the
+    /// processes, so no memory is known.
+    pub scanned: bool,
+    /// When the process list of its GPU was queried.
+    pub collected_at: Option<DateTime<Local>>,
+    /// Milliseconds since `collected_at`, when a `--cache` run replays it.
+    pub age_ms: Option<u64>,
+    /// Bytes per minute the used memory grew by over the last
+    /// `MEMORY_GROWTH_WINDOW` ticks of watch mode, when it never shrank.
+    pub memory_growth: Option<u64>,
+    /// Owned by another user on a GPU of this job, with `--allocation-summary`.
+    pub foreign: bool,
+    /// The full command line matches `--match`.
+    pub matched: bool,
+}
+
+/// Encoder sessions one process holds on a GPU.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct EncoderUse {
+    pub sessions: u32,
+    /// Mean of the moving average frame rates of the sessions.
+    pub average_fps: u32,
+}
+
+impl ProcessStat {
+    /// Owner as displayed, marked `?user` (or just `?`) when it could not be fully resolved.
+    pub fn display_user(&self) -> String {
+        if self.resolved {
+            self.user.clone()
+        } else {
+            format!("?{}", self.user)
+        }
+    }
+}
+
+/// Target speed and tachometer reading of one fan, `None` where unsupported.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct FanStat {
+    /// Percent of the maximum speed.
+    pub speed: Option<u32>,
+    pub rpm: Option<u32>,
+}
+
+impl FanStat {
+    /// Driven but not turning, most likely a dead fan.
+    pub fn stalled(&self) -> bool {
+        self.speed.is_some_and(|speed| speed > 0) && self.rpm == Some(0)
+    }
+}
+
+/// An active vGPU of a vGPU host, `--show-virt`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VgpuStat {
+    /// Instance handle, as `nvmlDeviceGetActiveVgpus` lists it.
+    pub id: u32,
+    /// Name of the vGPU type, e.g. `GRID A100-4C`.
+    pub type_name: Option<String>,
+    /// Framebuffer size of the vGPU type in bytes.
+    pub framebuffer: Option<u64>,
+}
+
+/// `--oom-hints`: a process whose used memory fell and has not regrown while
+/// much of its GPU stayed free, as after a CUDA out of memory error caused by
+/// fragmentation. Advisory only, since a job freeing memory on purpose looks
+/// the same.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OomHint {
+    pub pid: u32,
+    /// Used memory in bytes before the fall.
+    pub before: u64,
+    /// Used memory in bytes now.
+    pub after: u64,
+}
+
+/// Maxima of the driver's sample buffers over one refresh period, `None`
+/// where the GPU keeps no samples of that kind.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct WindowMax {
+    /// Power draw in milliwatts.
+    pub power: Option<u32>,
+    pub utilization: Option<u32>,
+}
+
+/// Application clocks in MHz, as set for the GPU and as it ships.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct AppClocks {
+    pub graphics: u32,
+    pub memory: u32,
+    pub default_graphics: u32,
+    pub default_memory: u32,
+}
+
+impl AppClocks {
+    /// Whether either clock is set below its default, capping the GPU.
+    pub fn below_default(&self) -> bool {
+        self.graphics < self.default_graphics || self.memory < self.default_memory
+    }
+}
+
+/// What liquid-cooled boards without fans are watched by, with `--cooling`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cooling {
+    /// HBM temperature in °C, `None` on GPUs without the sensor.
+    pub memory_temperature: Option<u32>,
+    /// Degrees from the GPU temperature to the slowdown threshold, negative
+    /// above it; `None` when the driver reports no threshold.
+    pub slowdown_margin: Option<i64>,
+    /// Milliwatts left below the power limit, `None` without a limit.
+    pub power_headroom: Option<u32>,
+}
+
+impl Cooling {
+    /// Margins of a GPU at `temperature` °C drawing `power_usage` of `power_limit` mW.
+    pub fn of(
+        temperature: u32,
+        memory_temperature: Option<u32>,
+        slowdown_temperature: Option<u32>,
+        power_usage: u32,
+        power_limit: u32,
+    ) -> Cooling {
+        Cooling {
+            memory_temperature,
+            slowdown_margin: slowdown_temperature
+                .filter(|&slowdown| slowdown > 0)
+                .map(|slowdown| i64::from(slowdown) - i64::from(temperature)),
+            power_headroom: Some(power_limit.saturating_sub(power_usage)).filter(|_| power_limit > 0),
+        }
+    }
+}
+
+/// Estimated memory bandwidth in GB/s, with `--show-bandwidth`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bandwidth {
+    /// Theoretical peak of the board.
+    pub peak: f64,
+    /// The peak scaled by `utilization`, an estimate: a busy memory
+    /// controller need not be moving data at full rate.
+    pub used: f64,
+    /// Percent of the sample period the memory controller was busy.
+    pub utilization: u32,
+}
+
+/// Data sheet peak bandwidth in GB/s of boards by their NVML name, used
+/// before the clock-based figure and when the bus width is unknown.
+const KNOWN_BANDWIDTH: &[(&str, f64)] = &[
+    ("NVIDIA H200", 4800.0),
+    ("NVIDIA H100 80GB HBM3", 3352.0),
+    ("NVIDIA H100 PCIe", 2039.0),
+    ("NVIDIA A100-SXM4-80GB", 2039.0),
+    ("NVIDIA A100 80GB PCIe", 1935.0),
+    ("NVIDIA A100-SXM4-40GB", 1555.0),
+    ("NVIDIA A100-PCIE-40GB", 1555.0),
+    ("Tesla V100-SXM2-32GB", 900.0),
+    ("Tesla V100-SXM2-16GB", 900.0),
+    ("Tesla V100-PCIE-32GB", 900.0),
+    ("Tesla V100-PCIE-16GB", 900.0),
+    ("NVIDIA L40S", 864.0),
+    ("NVIDIA A10", 600.0),
+    ("NVIDIA L4", 300.0),
+    ("Tesla T4", 320.0),
+    ("NVIDIA GeForce RTX 4090", 1008.0),
+    ("NVIDIA GeForce RTX 3090", 936.0),
+];
+
+/// Theoretical peak memory bandwidth of a board in GB/s, from
+/// `KNOWN_BANDWIDTH` or else its maximum memory clock in MHz and bus width
+/// in bits. NVML reports the memory clock at half the per-pin data rate of
+/// GDDR6X and HBM2e alike, so both move two bits per pin per cycle.
+pub fn peak_bandwidth(name: &str, memory_clock: Option<u32>, bus_width: Option<u32>) -> Option<f64> {
+    if let Some(&(_, peak)) = KNOWN_BANDWIDTH.iter().find(|(board, _)| *board == name) {
+        return Some(peak);
+    }
+    let bits_per_second = f64::from(memory_clock?) * 1e6 * 2.0 * f64::from(bus_width?);
+    Some(bits_per_second / 8.0 / 1e9).filter(|&peak| peak > 0.0)
+}
+
+/// SM clock against the maximum boost clock, in MHz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Perf {
+    pub sm_clock: u32,
+    pub sm_clock_max: u32,
+    /// SM clock in percent of the maximum, at most 100.
+    pub percent: u32,
+}
+
+impl Perf {
+    /// `None` when the driver reports no maximum clock.
+    pub fn of(sm_clock: u32, sm_clock_max: u32) -> Option<Perf> {
+        let percent = (u64::from(sm_clock) * 100).checked_div(u64::from(sm_clock_max))?.min(100) as u32;
+        Some(Perf {
+            sm_clock,
+            sm_clock_max,
+            percent,
+        })
+    }
+}
+
+/// InfoROM object versions and the outcome of its checksum validation.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InfoRomStat {
+    pub image: String,
+    pub oem: Option<String>,
+    pub ecc: Option<String>,
+    pub power: Option<String>,
+    /// Whether the checksum validates, `None` when the driver cannot check it.
+    pub valid: Option<bool>,
+}
+
+impl InfoRomStat {
+    pub fn corrupted(&self) -> bool {
+        self.valid == Some(false)
+    }
+}
+
+/// Power drawn by `gpus` in mW, counting a board the GPUs share once. The
+/// GPUs of one board serial whose board reading covers at least their own
+/// summed power count it once; a lower one cannot be shared, so it is per
+/// module and summed like the others. GPUs without a board reading count
+/// their own.
+pub fn total_power(gpus: &[&GpuStat]) -> u64 {
+    let mut boards: Vec<(&str, Vec<&GpuStat>)> = vec![];
+    let mut total = 0;
+    for gpu in gpus {
+        match (&gpu.board, gpu.board_power) {
+            (Some(board), Some(_)) => match boards.iter_mut().find(|(serial, _)| serial == board) {
+                Some((_, members)) => members.push(gpu),
+                None => boards.push((board, vec![gpu])),
+            },
+            (_, power) => total += u64::from(power.unwrap_or(gpu.power_usage)),
+        }
+    }
+    for (_, members) in boards {
+        let own: u64 = members.iter().map(|gpu| u64::from(gpu.power_usage)).sum();
+        let board = members.iter().filter_map(|gpu| gpu.board_power).map(u64::from).max().unwrap_or_default();
+        if board >= own {
+            total += board;
+        } else {
+            total += members.iter().filter_map(|gpu| gpu.board_power).map(u64::from).sum::<u64>();
+        }
+    }
+    total
+}
+
+/// Why a GPU shows no process although it should show some.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissingProcesses {
+    /// NVML refused the process list and `/proc` held no trace of one.
+    Denied,
+    /// The GPU is busy but NVML lists no process, usually one in D state
+    /// while it tears down or one in a PID namespace gpustat cannot see.
+    NotVisible,
+}
+
+/// Compute and graphics processes of a GPU; a process holding both counts as each.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessCount {
+    pub compute: u32,
+    pub graphics: u32,
+}
+
+/// How many running processes of a GPU match `--match`, e.g. `2/7`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessMatch {
+    pub matched: u32,
+    pub total: u32,
+}
+
+/// Mark the processes of `gpu` matching `--match` and count them; with
+/// `--match-only` the others are dropped. Stale processes never match.
+pub fn match_processes(opts: &Opts, gpu: &mut GpuStat) {
+    let Some(pattern) = &opts.match_pattern else {
+        return;
+    };
+    let pattern = RegexBuilder::new(pattern.as_str())
+        .case_insensitive(!opts.case_sensitive)
+        .build()
+        .expect("--match was checked at parsing");
+    let mut count = ProcessMatch::default();
+    for process in gpu.processes.iter_mut().filter(|p| !p.stale) {
+        process.matched = pattern.is_match(&process.full_command);
+        count.total += 1;
+        count.matched += u32::from(process.matched);
+    }
+    if opts.match_only {
+        gpu.processes.retain(|p| p.matched);
+    }
+    gpu.process_match = Some(count);
+}
+
+impl ProcessCount {
+    pub fn of(processes: &[ProcessStat]) -> Self {
+        let mut count = ProcessCount::default();
+        for process in processes {
+            if matches!(process.kind, ProcessKind::Compute | ProcessKind::Mixed) {
+                count.compute += 1;
+            }
+            if matches!(process.kind, ProcessKind::Graphics | ProcessKind::Mixed) {
+                count.graphics += 1;
+            }
+        }
+        count
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GpuStat {
+    pub index: u32,
+    /// Position under `--order pci` or `--order cuda`, `None` in NVML order.
+    pub order_index: Option<u32>,
+    /// Stable identity used to match a GPU across watch mode frames.
+    pub uuid: String,
+    pub name: String,
+    /// Board serial with `--group-by-board`. The GPUs of an HGX baseboard or a
+    /// multi-GPU card share it, so it doubles as the grouping key.
+    pub board: Option<String>,
+    /// The device did not answer and needs a reset; no metrics were collected.
+    pub reset_required: bool,
+    /// Runtime power management put the device in D3cold and, without
+    /// `--wake`, it was not woken up to be queried; no metrics were collected.
+    pub suspended: bool,
+    /// NVML refused to list the processes of this device.
+    pub processes_denied: bool,
+    /// Collecting this device failed; no metrics were collected.
+    pub error: Option<String>,
+    /// `error` with its kind, query and NVML error code.
+    pub failure: Option<Failure>,
+    /// NVML error names of the queries that failed in this collection, by
+    /// query, including the optional ones, e.g. `fan_speed: NotSupported`.
+    pub query_failures: BTreeMap<String, String>,
+    /// `Err` holds the raw value of a brand unknown to nvml-wrapper.
+    pub brand: Option<Result<Brand, u32>>,
+    pub temperature: u32,
+    /// Every sensor that could be read with `--show-temps-all`, labelled by name.
+    pub temperatures: BTreeMap<String, u32>,
+    pub utilization: u32,
+    pub memory_used: u64,
+    pub memory_total: u64,
+    /// Free memory as reported by NVML, which excludes memory reserved by the driver.
+    pub memory_free: u64,
+    /// Memory the driver reserves for itself, the gap between used + free and total.
+    pub memory_reserved: Option<u64>,
+    /// Highest used memory in bytes seen this session, only in watch mode.
+    pub memory_peak: Option<u64>,
+    /// Power usage and limit in milliwatts.
+    pub power_usage: u32,
+    pub power_limit: u32,
+    /// Rolling average and session peak of the power draw in mW, with `--power-detail`.
+    pub power_average: Option<u32>,
+    pub power_peak: Option<u32>,
+    /// Instantaneous and 1-second averaged power in mW from drivers that report
+    /// both, such as on Hopper, with `--power-detail`.
+    pub power_instant: Option<u32>,
+    pub power_driver_average: Option<u32>,
+    /// Power of the whole module or board in mW, where the driver reports
+    /// it, with `--power-detail`. It covers both GPUs of an H100 NVL pair
+    /// and the memory next to the GPU, so it can exceed `power_usage`.
+    pub board_power: Option<u32>,
+    /// Highest values the driver sampled since the last refresh, with `--window-max`.
+    pub window_max: Option<WindowMax>,
+    pub fan_speed: Option<u32>,
+    /// `None` on cards that only support the plain speed query.
+    pub fan_policy: Option<FanControlPolicy>,
+    /// Every fan with `--fan-detail`, by fan index.
+    pub fans: Vec<FanStat>,
+    pub encoder_utilization: Option<u32>,
+    pub decoder_utilization: Option<u32>,
+    /// Sampling periods of the codec utilizations in microseconds.
+    pub encoder_sampling_period: Option<u32>,
+    pub decoder_sampling_period: Option<u32>,
+    /// Remaining NVENC capacity in percent, `None` without an encoder.
+    pub h264_capacity: Option<u32>,
+    pub hevc_capacity: Option<u32>,
+    /// PCIe replay counter, and its growth since the previous frame in watch mode.
+    pub pcie_replays: Option<u32>,
+    pub pcie_replays_delta: Option<u32>,
+    /// `None` when the GPU has no ECC support.
+    pub ecc_mode: Option<EccModeState>,
+    /// `None` when the GPU has no MIG support, as before Ampere.
+    pub mig_mode: Option<MigModeState>,
+    pub virtualization: Option<GpuVirtualizationMode>,
+    pub vgpus: Vec<VgpuStat>,
+    /// CPUs with ideal affinity to the GPU, `None` when unsupported.
+    pub cpu_affinity: Option<Vec<u32>>,
+    pub numa_nodes: Option<Vec<u32>>,
+    pub processes: Vec<ProcessStat>,
+    /// Change since the same GPU in the `--baseline` snapshot.
+    pub baseline_delta: Option<BaselineDelta>,
+    /// Every process of the GPU before `--pid` filtering, with `--count-all`.
+    pub all_processes: Option<ProcessCount>,
+    /// Processes matching `--match`, counted before `--match-only` hides the others.
+    pub process_match: Option<ProcessMatch>,
+    /// Health details, only collected for `--highlight-rows`.
+    pub slowdown_temperature: Option<u32>,
+    pub ecc_uncorrected: Option<u64>,
+    pub throttle_reasons: Option<ThrottleReasons>,
+    /// NVML reports the hardware power brake slowdown, as when a loose or
+    /// miswired power cable asserts it. Checked on every GPU.
+    pub power_brake: bool,
+    /// Share of the `--throttle-window` ticks each throttle reason was active, in percent.
+    pub throttle_percent: BTreeMap<String, u32>,
+    /// Application clocks with `--show-app-clocks`, `None` when the GPU has none.
+    pub app_clocks: Option<AppClocks>,
+    /// SM clock against its maximum with `--show-perf`.
+    pub perf: Option<Perf>,
+    /// InfoROM versions and checksum with `--show-inforom`, `None` on GPUs without an InfoROM.
+    pub inforom: Option<InfoRomStat>,
+    /// Memory temperature, slowdown margin and power headroom with `--cooling`.
+    pub cooling: Option<Cooling>,
+    /// Kubernetes time-slicing with `--show-sharing`, `None` when nothing tells.
+    pub sharing: Option<Sharing>,
+    /// Estimated memory bandwidth with `--show-bandwidth`, `None` when the
+    /// peak is unknown.
+    pub bandwidth: Option<Bandwidth>,
+    /// Confidential computing mode with `--show-cc-mode`, `None` on GPUs not capable of it.
+    pub cc_mode: Option<CcMode>,
+    /// Adjustable (acoustic) target temperature with `--temp-detail`, `None`
+    /// on GPUs without one.
+    pub temperature_target: Option<u32>,
+    /// Whether persistence mode is on, not queried with `--no-hints` outside `gpustat ready`.
+    pub persistence_mode: Option<bool>,
+    /// Attributes of `consistency::ATTRIBUTES` set differently from the GPUs
+    /// of the same model, with `--consistency-check`.
+    pub inconsistent: Vec<String>,
+    /// When the queries of this GPU started, with `--timing`.
+    pub collection_started: Option<DateTime<Local>>,
+    /// Whether a display is initialized on this GPU.
+    pub display_active: Option<bool>,
+    /// Connected display connectors from DRM with `--show-display`, when the driver exposes them.
+    pub displays: Option<u32>,
+    /// PCI bus id, only queried for `--show-xid` and `--distinguish bus`.
+    pub pci_bus_id: Option<String>,
+    /// Most recent Xid from the kernel log with `--show-xid`, `Ok(None)` if
+    /// there was none, or why the log could not be read.
+    pub xid: Option<Result<Option<XidEvent>, String>>,
+    /// Minor number of the device node `/dev/nvidia<N>`, with `--show-handles`.
+    pub minor_number: Option<u32>,
+    /// Holders of the device node with `--show-handles`, refreshed every
+    /// `handles::SCAN_EVERY` ticks of watch mode.
+    pub handles: Option<DeviceHandles>,
+    /// With `--oom-hints` in watch mode, the process whose memory fell the most
+    /// without regrowing.
+    pub oom_hint: Option<OomHint>,
+    /// Seconds the GPU has been continuously idle, tracked with `--state-file`;
+    /// `None` while it is active or when nothing is known.
+    pub idle_seconds: Option<u64>,
+    /// Whether accounting mode is on, only queried for `--show-accounting`.
+    pub accounting_enabled: Option<bool>,
+    /// Out of range readings by field name, as NVML returned them before being clamped.
+    pub raw: BTreeMap<String, u64>,
+    /// When querying this GPU completed, `None` if it could not be queried.
+    pub collected_at: Option<DateTime<Local>>,
+    /// Milliseconds since `collected_at`, when a `--cache` run replays it.
+    pub age_ms: Option<u64>,
+}
+
+/// A percentage clamped to 100, `None` when it is in range.
+pub fn clamp_percent(value: u32) -> Option<u32> {
+    Some(100).filter(|_| value > 100)
+}
+
+/// Used memory clamped to the total, `None` when it is in range.
+pub fn clamp_memory(used: u64, total: u64) -> Option<u64> {
+    Some(total).filter(|&total| used > total)
+}
+
+/// Current MIG mode and the one a GPU reset will apply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigModeState {
+    pub current: bool,
+    pub pending: bool,
+}
+
+/// What `--fail-if` compares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FailMetric {
+    /// Current MIG mode, off on GPUs without MIG.
+    Mig,
+    /// MIG mode after the next GPU reset.
+    MigPending,
+    /// Temperature in °C.
+    Temp,
+    /// Utilization in percent.
+    Util,
+    /// Used memory in percent of the total.
+    Mem,
+    /// Power draw in watts.
+    Power,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Operators in the order they are tried, two-character ones first.
+const COMPARISONS: &[(&str, Comparison)] = &[
+    ("==", Comparison::Eq),
+    ("!=", Comparison::Ne),
+    ("<=", Comparison::Le),
+    (">=", Comparison::Ge),
+    ("<", Comparison::Lt),
+    (">", Comparison::Gt),
+    ("=", Comparison::Eq),
+];
+
+/// A `--fail-if` condition such as `mig!=on` or `temp>85`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailCondition {
+    text: String,
+    metric: FailMetric,
+    comparison: Comparison,
+    value: u32,
+}
+
+impl std::str::FromStr for FailCondition {
+    type Err = String;
+
+    /// Parse `<metric><operator><value>`, with `on` or `off` as the value of the MIG metrics.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let start = s
+            .find(['=', '!', '<', '>'])
+            .ok_or_else(|| format!("expected <metric><operator><value>, got '{}'", s))?;
+        let (name, rest) = s.split_at(start);
+        let (operator, comparison) = COMPARISONS
+            .iter()
+            .find(|(operator, _)| rest.starts_with(operator))
+            .ok_or_else(|| format!("unknown operator in '{}', expected ==, !=, <, <=, > or >=", s))?;
+        let value = rest[operator.len()..].trim();
+        let metric = match name.trim() {
+            "mig" => FailMetric::Mig,
+            "mig_pending" => FailMetric::MigPending,
+            "temp" => FailMetric::Temp,
+            "util" => FailMetric::Util,
+            "mem" => FailMetric::Mem,
+            "power" => FailMetric::Power,
+            name => {
+                return Err(format!(
+                    "unknown metric '{}', expected mig, mig_pending, temp, util, mem or power",
+                    name
+                ))
+            }
+        };
+        let value = match metric {
+            FailMetric::Mig | FailMetric::MigPending => {
+                if !matches!(comparison, Comparison::Eq | Comparison::Ne) {
+                    return Err(format!("{} only compares with == or !=", name.trim()));
+                }
+                match value {
+                    "on" => 1,
+                    "off" => 0,
+                    _ => return Err(format!("expected on or off, got '{}'", value)),
+                }
+            }
+            _ => value
+                .trim_end_matches(['%', 'C', 'W'])
+                .parse::<u32>()
+                .map_err(|e| format!("invalid value '{}': {}", value, e))?,
+        };
+        Ok(FailCondition {
+            text: s.trim().to_string(),
+            metric,
+            comparison: *comparison,
+            value,
+        })
+    }
+}
+
+impl std::fmt::Display for FailCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl FailCondition {
+    /// Whether checking the condition needs the MIG mode collected.
+    pub fn needs_mig(&self) -> bool {
+        matches!(self.metric, FailMetric::Mig | FailMetric::MigPending)
+    }
+
+    /// Whether `gpu` matches the condition. GPUs that could not be queried match nothing.
+    pub fn matches(&self, gpu: &GpuStat) -> bool {
+        if gpu.reset_required || gpu.error.is_some() {
+            return false;
+        }
+        let mig = gpu.mig_mode.unwrap_or(MigModeState {
+            current: false,
+            pending: false,
+        });
+        let value = match self.metric {
+            FailMetric::Mig => u32::from(mig.current),
+            FailMetric::MigPending => u32::from(mig.pending),
+            FailMetric::Temp => gpu.temperature,
+            FailMetric::Util => gpu.utilization,
+            FailMetric::Mem if gpu.memory_total == 0 => 0,
+            FailMetric::Mem => (gpu.memory_used * 100 / gpu.memory_total) as u32,
+            FailMetric::Power => gpu.power_usage / 1000,
+        };
+        match self.comparison {
+            Comparison::Eq => value == self.value,
+            Comparison::Ne => value != self.value,
+            Comparison::Lt => value < self.value,
+            Comparison::Le => value <= self.value,
+            Comparison::Gt => value > self.value,
+            Comparison::Ge => value >= self.value,
+        }
+    }
+}
+
+/// Temperature in °C, power in mW and used memory in bytes relative to `--baseline`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BaselineDelta {
+    pub temperature: i64,
+    pub power: i64,
+    pub memory: i64,
+}
+
+/// Margins below which `--changes-only` considers a GPU unchanged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangeThreshold {
+    /// Utilization in percentage points.
+    pub util: u32,
+    /// Used memory in bytes.
+    pub mem: u64,
+    /// Temperature in °C.
+    pub temp: u32,
+    /// Power draw in milliwatts.
+    pub power: u32,
+}
+
+impl Default for ChangeThreshold {
+    fn default() -> Self {
+        ChangeThreshold {
+            util: 5,
+            mem: 100 << 20,
+            temp: 2,
+            power: 10_000,
+        }
+    }
+}
+
+impl std::str::FromStr for ChangeThreshold {
+    type Err = String;
+
+    /// Parse `util=5,mem=100M,temp=2,power=10`, keeping defaults for omitted keys.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut threshold = ChangeThreshold::default();
+        for pair in s.split(',').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", pair))?;
+            let number = |value: &str| {
+                value
+                    .parse::<u32>()
+                    .map_err(|e| format!("invalid {} margin '{}': {}", key, value, e))
+            };
+            match key {
+                "util" => threshold.util = number(value.trim_end_matches('%'))?,
+                "temp" => threshold.temp = number(value.trim_end_matches('C'))?,
+                "power" => threshold.power = number(value.trim_end_matches('W'))? * 1000,
+                "mem" => {
+                    let (digits, shift) = match value.chars().last() {
+                        Some('K') => (&value[..value.len() - 1], 10),
+                        Some('M') => (&value[..value.len() - 1], 20),
+                        Some('G') => (&value[..value.len() - 1], 30),
+                        _ => (value, 20),
+                    };
+                    threshold.mem = u64::from(number(digits)?) << shift;
+                }
+                _ => {
+                    return Err(format!(
+                        "unknown key '{}', expected util, mem, temp or power",
+                        key
+                    ))
+                }
+            }
+        }
+        Ok(threshold)
+    }
+}
+
+/// `NVML_DEVICE_MIG_ENABLE`, an enabled MIG mode.
+const MIG_ENABLE: u32 = 1;
+
+/// Degrees below the slowdown threshold at which the temperature becomes critical.
+const SLOWDOWN_MARGIN: u32 = 5;
+/// Fraction of used memory above which a GPU is critical.
+const CRITICAL_MEMORY: f64 = 0.95;
+
+impl GpuStat {
+    /// Whether the metrics were collected: the device neither failed, needs a
+    /// reset nor was left suspended.
+    pub fn measured(&self) -> bool {
+        self.error.is_none() && !self.reset_required && !self.suspended
+    }
+
+    /// The board power where it differs from the GPU's own reading, which
+    /// then shows next to it.
+    pub fn separate_board_power(&self) -> Option<u32> {
+        self.board_power.filter(|board| board / 1000 != self.power_usage / 1000)
+    }
+
+    /// Why the process list is empty, if it would otherwise look like an
+    /// idle GPU: refused by NVML, or empty while utilization is above
+    /// `threshold` percent. `None` when `--match-only` emptied the list.
+    pub fn missing_processes(&self, threshold: u32) -> Option<MissingProcesses> {
+        let filtered = self.process_match.is_some_and(|count| count.total > 0);
+        if !self.measured() || !self.processes.is_empty() || filtered {
+            None
+        } else if self.processes_denied {
+            Some(MissingProcesses::Denied)
+        } else if self.utilization > threshold {
+            Some(MissingProcesses::NotVisible)
+        } else {
+            None
+        }
+    }
+
+    /// Whether compute jobs run next to a graphics process such as Xorg or a
+    /// compositor, which makes both the desktop and the job stutter. A single
+    /// process holding both contexts does not count.
+    pub fn shares_display(&self) -> bool {
+        let running = || self.processes.iter().filter(|p| !p.stale);
+        running()
+            .filter(|p| matches!(p.kind, ProcessKind::Graphics | ProcessKind::Mixed))
+            .any(|graphics| {
+                running().any(|compute| {
+                    compute.pid != graphics.pid && matches!(compute.kind, ProcessKind::Compute | ProcessKind::Mixed)
+                })
+            })
+    }
+
+    /// Whether the GPU does work: utilization above `floor`, or a compute
+    /// process. Graphics processes such as Xorg sit on idle GPUs too.
+    pub fn active(&self, floor: u32) -> bool {
+        self.utilization > floor
+            || self
+                .processes
+                .iter()
+                .any(|p| !p.stale && matches!(p.kind, ProcessKind::Compute | ProcessKind::Mixed))
+    }
+
+    /// Whether any collected metric on this GPU needs immediate attention.
+    pub fn critical(&self) -> bool {
+        let hot = self
+            .slowdown_temperature
+            .is_some_and(|slowdown| self.temperature + SLOWDOWN_MARGIN >= slowdown);
+        let full = self.memory_total > 0
+            && self.memory_used as f64 / self.memory_total as f64 > CRITICAL_MEMORY;
+        let ecc = self.ecc_uncorrected.is_some_and(|count| count > 0);
+        let throttled = self.throttle_reasons.is_some_and(|reasons| {
+            reasons.intersects(
+                ThrottleReasons::HW_SLOWDOWN
+                    | ThrottleReasons::HW_THERMAL_SLOWDOWN
+                    | ThrottleReasons::SW_THERMAL_SLOWDOWN
+                    | ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN,
+            )
+        });
+        self.reset_required || self.power_brake || hot || full || ecc || throttled
+    }
+
+    /// The throttle reason holding the clocks back the most: the one active
+    /// over most of the watch window, or else the first active one.
+    pub fn dominant_throttle(&self) -> Option<&'static str> {
+        let shares = THROTTLE_REASONS
+            .iter()
+            .filter_map(|(_, name)| Some((*name, *self.throttle_percent.get(*name)?)))
+            .filter(|(_, share)| *share > 0);
+        // the earliest of equal shares wins, as in the throttle column
+        let dominant = shares.fold(None, |best: Option<(&'static str, u32)>, (name, share)| match best {
+            Some((_, most)) if most >= share => best,
+            _ => Some((name, share)),
+        });
+        if let Some((name, _)) = dominant {
+            return Some(name);
+        }
+        let reasons = self.throttle_reasons?;
+        THROTTLE_REASONS
+            .iter()
+            .find(|(reason, _)| reasons.contains(*reason))
+            .map(|(_, name)| *name)
+    }
+
+    /// Memory requested by the processes on this GPU when it exceeds the
+    /// physical memory, a sign of Unified Memory oversubscription.
+    pub fn oversubscription(&self) -> Option<u64> {
+        let requested: u64 = self.processes.iter().filter_map(|p| p.used_memory).sum();
+        Some(requested).filter(|&requested| self.memory_total > 0 && requested > self.memory_total)
+    }
+
+    /// Clamp readings NVML glitched on, keeping the raw values in `raw`.
+    pub fn validate(&mut self) {
+        let mut raw = BTreeMap::new();
+        let mut percent = |field: &str, value: &mut u32| {
+            if let Some(clamped) = clamp_percent(*value) {
+                raw.insert(field.to_string(), u64::from(*value));
+                *value = clamped;
+            }
+        };
+        percent("utilization", &mut self.utilization);
+        for (field, value) in [
+            ("fan_speed", &mut self.fan_speed),
+            ("encoder_utilization", &mut self.encoder_utilization),
+            ("decoder_utilization", &mut self.decoder_utilization),
+        ] {
+            if let Some(value) = value {
+                percent(field, value);
+            }
+        }
+        if let Some(clamped) = clamp_memory(self.memory_used, self.memory_total) {
+            log::info!(
+                "GPU {}: memory used {} exceeds the total {}, showing it as full",
+                self.index, self.memory_used, self.memory_total
+            );
+            raw.insert(String::from("memory_used"), self.memory_used);
+            self.memory_used = clamped;
+        }
+        self.raw = raw;
+    }
+
+    /// Pods holding this GPU through their processes, each listed once.
+    pub fn pods(&self) -> Vec<&str> {
+        let mut pods: Vec<&str> = vec![];
+        for pod in self.processes.iter().filter_map(|p| p.pod.as_deref()) {
+            if !pods.contains(&pod) {
+                pods.push(pod);
+            }
+        }
+        pods
+    }
+
+    /// Whether this GPU moved beyond `threshold` since `before`, or its processes changed.
+    pub fn changed_from(&self, before: &GpuStat, threshold: &ChangeThreshold) -> bool {
+        let pids = |gpu: &GpuStat| {
+            let mut pids: Vec<u32> = gpu.processes.iter().map(|p| p.pid).collect();
+            pids.sort_unstable();
+            pids
+        };
+        self.reset_required != before.reset_required
+            || self.power_brake != before.power_brake
+            || self.error != before.error
+            || self.utilization.abs_diff(before.utilization) >= threshold.util
+            || self.memory_used.abs_diff(before.memory_used) >= threshold.mem
+            || self.temperature.abs_diff(before.temperature) >= threshold.temp
+            || self.power_usage.abs_diff(before.power_usage) >= threshold.power
+            || pids(self) != pids(before)
+    }
+}
+
+/// Version of the JSON snapshot format.
+///
+/// Fields are only ever added, never renamed or removed, so a consumer written
+/// against one version keeps working with every later one. The version is
+/// bumped whenever fields are added.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapshot {
+    pub schema_version: u32,
+    pub hostname: String,
+    /// When collection started, as shown in the table header.
+    pub timestamp: DateTime<Local>,
+    /// When collection completed, the time machine-readable outputs report.
+    pub collected_at: Option<DateTime<Local>>,
+    /// Milliseconds from `timestamp` to `collected_at`.
+    pub collection_duration_ms: Option<u64>,
+    /// `None` when the driver and NVML library versions do not match.
+    pub driver_version: Option<String>,
+    pub gpus: Vec<GpuStat>,
+    /// Host RAM and swap, with `--show-host-mem`.
+    pub host_memory: Option<HostMemory>,
+    /// Seconds the driver has been loaded, with `--show-driver-uptime`; `None`
+    /// without nvidia-persistenced to tell.
+    pub driver_uptime: Option<u64>,
+    /// Seconds until the next snapshot in watch mode, which varies with `--adaptive-interval`.
+    pub interval: Option<f64>,
+    /// The slice of GPUs shown with `--page` and `--page-size`.
+    pub page: Option<Page>,
+    /// GPU time spent busy this watch session, with `--show-busy`.
+    pub busy: Option<BusyTime>,
+    /// What a lack of permission kept out of this snapshot, `None` when nothing was.
+    pub permission_limit: Option<PermissionLimit>,
+    /// Whether nvidia-persistenced runs on this machine.
+    pub persistenced_running: bool,
+    /// Container whose GPUs are shown, with `--container`.
+    pub container: Option<String>,
+    /// Totals of the GPUs of `CUDA_VISIBLE_DEVICES`, with `--allocation-summary`.
+    pub allocation: Option<Allocation>,
+    /// GPU memory of every user across the node, with `--chargeback`.
+    pub chargeback: Option<Chargeback>,
+    /// Configuration differing between GPUs of the same model, with `--consistency-check`.
+    pub consistency: Option<Vec<Difference>>,
+    /// How often each query failed over the watch session, the most
+    /// frequent first.
+    pub collection_errors: Option<Vec<ErrorCount>>,
+    /// Where each column comes from, with `--explain`.
+    pub legend: Option<Legend>,
+    /// Why `gpus` is empty: `NO_DEVICES` when NVML lists no GPU at all.
+    pub reason: Option<String>,
+    /// What the device nodes tell of a `NO_DEVICES` machine, e.g. that the
+    /// device cgroup of a container denies them.
+    pub reason_hint: Option<String>,
+    /// The wall clock reads a time before this binary was built, so it is
+    /// not set yet and `timestamp` cannot be trusted.
+    pub time_suspect: bool,
+    /// Milliseconds since boot when collection completed, which orders
+    /// samples even while the wall clock is wrong.
+    pub uptime_ms: Option<u64>,
+    /// Intel and AMD GPUs read from sysfs, with the `igpu` feature.
+    pub igpus: Vec<IntegratedGpu>,
+    /// Milliseconds since the snapshot was collected, when a `--cache`
+    /// run replays it.
+    pub cache_age_ms: Option<u64>,
+}
+
+/// Processes gpustat could not fully see, and what would reveal them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PermissionLimit {
+    /// Processes without owner or command, plus GPUs whose process list NVML refused.
+    pub hidden: u32,
+    pub hint: String,
+}
+
+/// One page of GPUs, numbered from 1.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Page {
+    pub number: usize,
+    pub count: usize,
+}
+
+/// Host RAM and swap in bytes.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HostMemory {
+    pub used: u64,
+    pub total: u64,
+    pub swap_used: u64,
+    pub swap_total: u64,
+}
+
+impl Snapshot {
+    /// Parse a snapshot written by any version of gpustat, leaving fields
+    /// added after that version empty.
+    pub fn from_json(json: &str) -> Result<Snapshot, StatusError> {
+        let snapshot: Snapshot = serde_json::from_str(json)?;
+        if snapshot.schema_version < 1 {
+            return Err(StatusError::SchemaVersion(snapshot.schema_version));
+        }
+        Ok(snapshot)
+    }
+
+    /// The last snapshot of a `--format json` watch recording, one snapshot per line.
+    pub fn from_jsonl(jsonl: &str) -> Result<Snapshot, StatusError> {
+        let line = jsonl.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or_default();
+        Snapshot::from_json(line)
+    }
+
+    pub fn to_json(&self) -> Result<String, StatusError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Move the GPUs listed in `order` by displayed index to the front, in
+    /// that order, keeping the rest in their current order after them.
+    pub fn reorder(&mut self, order: &[u32]) {
+        self.gpus.sort_by_key(|gpu| {
+            let index = gpu.order_index.unwrap_or(gpu.index);
+            order.iter().position(|&i| i == index).unwrap_or(order.len())
+        });
+    }
+
+    /// Keep only page `number` of `size` GPUs, clamped to the last page. A
+    /// GPU's vGPU rows always stay on its page.
+    pub fn paginate(&mut self, number: usize, size: usize) {
+        let count = self.gpus.len().div_ceil(size).max(1);
+        let number = number.clamp(1, count);
+        self.gpus = self.gpus.drain(..).skip((number - 1) * size).take(size).collect();
+        self.page = Some(Page { number, count });
+    }
+
+    /// Age a snapshot replayed `age_ms` after it was collected, and each of
+    /// its GPUs and processes by how long before that they were queried.
+    pub fn set_age(&mut self, age_ms: u64) {
+        let collected = self.collected_at.unwrap_or(self.timestamp);
+        let age = |at: Option<DateTime<Local>>| {
+            at.map(|at| age_ms + u64::try_from((collected - at).num_milliseconds()).unwrap_or(0))
+        };
+        self.cache_age_ms = Some(age_ms);
+        for gpu in &mut self.gpus {
+            gpu.age_ms = age(gpu.collected_at);
+            for process in &mut gpu.processes {
+                process.age_ms = age(process.collected_at);
+            }
+        }
+    }
+
+    /// Age of the oldest data in a replayed snapshot, as `set_age` set it.
+    pub fn data_age_ms(&self) -> Option<u64> {
+        let gpus = self.gpus.iter().map(|gpu| gpu.age_ms);
+        let processes = self.gpus.iter().flat_map(|gpu| gpu.processes.iter().map(|p| p.age_ms));
+        gpus.chain(processes).flatten().chain(self.cache_age_ms).max()
+    }
+
+    pub fn version_mismatch(&self) -> bool {
+        self.driver_version.is_none()
+    }
+
+    /// GPUs whose processes were found by scanning /proc, with `--no-proc-scan` off.
+    pub fn scanned_gpus(&self) -> Vec<&GpuStat> {
+        self.gpus.iter().filter(|gpu| gpu.processes.iter().any(|p| p.scanned)).collect()
+    }
+
+    /// GPUs without a display whose driver state is torn down whenever no
+    /// client holds them, since neither nvidia-persistenced nor persistence
+    /// mode keeps it, which makes the first CUDA call take seconds.
+    pub fn cold_start_gpus(&self) -> Vec<&GpuStat> {
+        if !cfg!(target_os = "linux") || self.persistenced_running {
+            return vec![];
+        }
+        self.gpus
+            .iter()
+            .filter(|gpu| gpu.persistence_mode == Some(false) && gpu.display_active != Some(true))
+            .collect()
+    }
+
+    /// GPUs whose compute jobs share them with the display.
+    pub fn display_shared_gpus(&self) -> Vec<&GpuStat> {
+        self.gpus.iter().filter(|gpu| gpu.shares_display()).collect()
+    }
+
+    /// GPUs without any process, where a job could move.
+    pub fn idle_gpus(&self) -> Vec<&GpuStat> {
+        self.gpus
+            .iter()
+            .filter(|gpu| gpu.measured() && !gpu.processes_denied && gpu.processes.is_empty())
+            .collect()
+    }
+
+    /// Whether the driver or any GPU needs attention, or some GPU could not be queried.
+    pub fn degraded(&self) -> bool {
+        self.version_mismatch()
+            || self
+                .gpus
+                .iter()
+                .any(|gpu| gpu.reset_required || gpu.error.is_some())
+    }
+
+    /// Fill in the change of every GPU since its namesake in `baseline`; GPUs
+    /// missing from it get none.
+    pub fn compare_with(&mut self, baseline: &Snapshot) {
+        for gpu in &mut self.gpus {
+            gpu.baseline_delta = baseline
+                .gpus
+                .iter()
+                .find(|b| !b.uuid.is_empty() && b.uuid == gpu.uuid && b.error.is_none())
+                .map(|before| BaselineDelta {
+                    temperature: i64::from(gpu.temperature) - i64::from(before.temperature),
+                    power: i64::from(gpu.power_usage) - i64::from(before.power_usage),
+                    memory: gpu.memory_used as i64 - before.memory_used as i64,
+                });
+        }
+    }
+
+    /// Fill in the per-frame deltas of counters against the previous frame.
+    pub fn track_deltas(&mut self, previous: &Snapshot) {
+        for gpu in &mut self.gpus {
+            let before = previous.gpus.iter().find(|p| p.uuid == gpu.uuid);
+            if let (Some(now), Some(before)) =
+                (gpu.pcie_replays, before.and_then(|p| p.pcie_replays))
+            {
+                gpu.pcie_replays_delta = Some(now.saturating_sub(before));
+            }
+        }
+    }
+}
+
+/// Utilization above which `--adaptive-interval` keeps refreshing at its minimum.
+const ADAPTIVE_BUSY_UTILIZATION: u32 = 10;
+
+/// Bounds of `--adaptive-interval` in seconds, parsed from `MIN..MAX`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveInterval {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl std::str::FromStr for AdaptiveInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s
+            .split_once("..")
+            .ok_or_else(|| format!("expected MIN..MAX, got '{}'", s))?;
+        let seconds = |value: &str| match value.parse::<f64>() {
+            Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(seconds),
+            Ok(_) => Err(String::from("interval must be a positive number of seconds")),
+            Err(e) => Err(format!("invalid interval '{}': {}", value, e)),
+        };
+        let (min, max) = (seconds(min)?, seconds(max)?);
+        if min > max {
+            return Err(format!("minimum {} is above the maximum {}", min, max));
+        }
+        Ok(AdaptiveInterval { min, max })
+    }
+}
+
+impl AdaptiveInterval {
+    /// The period after `current`: doubled towards `max` while no GPU moved
+    /// beyond `threshold`, and back to `min` once one does or gets busy.
+    pub fn next(
+        &self,
+        current: f64,
+        snapshot: &Snapshot,
+        previous: Option<&Snapshot>,
+        threshold: &ChangeThreshold,
+    ) -> f64 {
+        let changed = match previous {
+            Some(previous) => {
+                snapshot.gpus.len() != previous.gpus.len()
+                    || snapshot.gpus.iter().any(|gpu| {
+                        match previous.gpus.iter().find(|before| before.uuid == gpu.uuid) {
+                            Some(before) => gpu.changed_from(before, threshold),
+                            None => true,
+                        }
+                    })
+            }
+            None => true,
+        };
+        let busy = snapshot
+            .gpus
+            .iter()
+            .any(|gpu| gpu.utilization > ADAPTIVE_BUSY_UTILIZATION);
+        if changed || busy {
+            self.min
+        } else {
+            (current * 2.0).min(self.max)
+        }
+    }
+}
+
+/// Number of frames in the rolling power average.
+const POWER_WINDOW: usize = 10;
+
+/// Throttle reasons shown by `--show-throttle` and their short names. An idle
+/// GPU also reports a reason, but that is not throttling.
+pub const THROTTLE_REASONS: &[(ThrottleReasons, &str)] = &[
+    (ThrottleReasons::SW_POWER_CAP, "SwPower"),
+    (ThrottleReasons::HW_SLOWDOWN, "HwSlowdown"),
+    (ThrottleReasons::HW_THERMAL_SLOWDOWN, "HwThermal"),
+    (ThrottleReasons::SW_THERMAL_SLOWDOWN, "SwThermal"),
+    (ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN, "HwPowerBrake"),
+    (ThrottleReasons::SYNC_BOOST, "SyncBoost"),
+    (ThrottleReasons::APPLICATIONS_CLOCKS_SETTING, "AppClocks"),
+    (ThrottleReasons::DISPLAY_CLOCK_SETTING, "Display"),
+];
+
+/// GPU time of a watch session, summed over GPUs.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct BusyTime {
+    /// When counting started, carried over by `--resume-state`.
+    pub since: DateTime<Local>,
+    /// Seconds GPUs spent above `--busy-threshold`.
+    pub busy_seconds: f64,
+    /// Seconds GPUs were observed, busy or not.
+    pub observed_seconds: f64,
+}
+
+impl BusyTime {
+    /// Share of the observed time the GPUs were busy.
+    pub fn occupancy(&self) -> Option<f64> {
+        Some(self.busy_seconds / self.observed_seconds).filter(|_| self.observed_seconds > 0.0)
+    }
+}
+
+/// Busy time accumulated across watch mode frames, for `--show-busy`.
+#[derive(Debug)]
+pub struct BusyTracker {
+    threshold: u32,
+    last: Option<DateTime<Local>>,
+    total: Option<BusyTime>,
+}
+
+impl BusyTracker {
+    /// Start counting, or continue from a total recorded by an earlier run.
+    pub fn new(threshold: u32, resumed: Option<BusyTime>) -> Self {
+        BusyTracker {
+            threshold,
+            last: None,
+            total: resumed,
+        }
+    }
+
+    /// Credit the time since the previous frame to every GPU, as busy when it
+    /// is above the threshold now, and fill in the total.
+    pub fn observe(&mut self, snapshot: &mut Snapshot) {
+        let now = snapshot.timestamp;
+        let total = self.total.get_or_insert(BusyTime {
+            since: now,
+            ..Default::default()
+        });
+        // wall-clock time, since frames drift and --adaptive-interval spaces them unevenly;
+        // a recovered driver keeps the count going, unlike the per-driver trackers
+        let elapsed = self.last.map_or(0.0, |last| (now - last).num_milliseconds() as f64 / 1000.0);
+        if elapsed > 0.0 {
+            for gpu in snapshot.gpus.iter().filter(|gpu| gpu.measured()) {
+                total.observed_seconds += elapsed;
+                if gpu.utilization > self.threshold {
+                    total.busy_seconds += elapsed;
+                }
+            }
+        }
+        self.last = Some(now);
+        snapshot.busy = Some(*total);
+    }
+
+    /// The total so far, `None` before the first frame.
+    pub fn total(&self) -> Option<BusyTime> {
+        self.total
+    }
+}
+
+/// Activity shorter than this, such as a health check probing the GPU, does
+/// not end an idle stretch.
+const IDLE_BLIP_SECONDS: i64 = 30;
+
+/// When a GPU was last active, as `--state-file` saves it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GpuActivity {
+    /// When tracking started, from which a GPU never seen active counts as idle.
+    pub since: DateTime<Local>,
+    pub last_active: Option<DateTime<Local>>,
+}
+
+impl GpuActivity {
+    fn idle_seconds(&self, now: DateTime<Local>) -> u64 {
+        (now - self.last_active.unwrap_or(self.since)).num_seconds().max(0) as u64
+    }
+}
+
+/// How long each GPU has been idle, for the `idle 6d4h` of `--state-file`.
+#[derive(Debug)]
+pub struct IdleTracker {
+    floor: u32,
+    gpus: BTreeMap<String, GpuActivity>,
+    /// Start of the current activity of GPUs where it has not yet lasted
+    /// `IDLE_BLIP_SECONDS`.
+    pending: HashMap<String, DateTime<Local>>,
+}
+
+impl IdleTracker {
+    /// Start tracking, or continue from the activity recorded by an earlier run.
+    pub fn new(floor: u32, restored: BTreeMap<String, GpuActivity>) -> Self {
+        IdleTracker {
+            floor,
+            gpus: restored,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Record which GPUs are active in this frame and fill in the idle time
+    /// of the others. A GPU only counts as active once its activity lasted
+    /// `IDLE_BLIP_SECONDS`, and from then on until the last active frame.
+    pub fn observe(&mut self, snapshot: &mut Snapshot) {
+        let now = snapshot.timestamp;
+        // activity is only continuous while the GPU shows up in every frame
+        self.pending
+            .retain(|uuid, _| snapshot.gpus.iter().any(|gpu| gpu.measured() && gpu.uuid == *uuid));
+        for gpu in snapshot.gpus.iter_mut().filter(|gpu| gpu.measured()) {
+            let activity = self.gpus.entry(gpu.uuid.clone()).or_insert(GpuActivity {
+                since: now,
+                last_active: None,
+            });
+            if gpu.active(self.floor) {
+                let started = *self.pending.entry(gpu.uuid.clone()).or_insert(now);
+                if (now - started).num_seconds() >= IDLE_BLIP_SECONDS {
+                    activity.last_active = Some(now);
+                }
+            } else {
+                self.pending.remove(&gpu.uuid);
+            }
+            gpu.idle_seconds = (activity.last_active != Some(now)).then(|| activity.idle_seconds(now));
+        }
+    }
+
+    /// Fill in the idle time of the GPUs idle in a one-shot frame, from the
+    /// activity a watcher recorded.
+    pub fn recall(&self, snapshot: &mut Snapshot) {
+        let now = snapshot.timestamp;
+        for gpu in snapshot.gpus.iter_mut().filter(|gpu| gpu.error.is_none() && !gpu.active(self.floor)) {
+            gpu.idle_seconds = self.gpus.get(&gpu.uuid).map(|activity| activity.idle_seconds(now));
+        }
+    }
+
+    pub fn activity(&self) -> BTreeMap<String, GpuActivity> {
+        self.gpus.clone()
+    }
+}
+
+/// Ticks after which the history of a GPU that stopped showing up, such as a
+/// hot-unplugged one, is dropped.
+const HISTORY_EVICT_TICKS: u64 = 30;
+
+/// Ticks of steady memory growth before a process shows a growth rate.
+const MEMORY_GROWTH_WINDOW: usize = 6;
+
+/// Share of its used memory a process must lose in one tick for `--oom-hints`,
+/// and the least it must lose.
+const OOM_DROP_SHARE: f64 = 0.25;
+const OOM_DROP_MIN: u64 = 1 << 30;
+/// Share of the memory before the fall at which a process counts as regrown.
+const OOM_REGROWN_SHARE: f64 = 0.9;
+/// Share of the GPU memory that must be free for the hint, the "there's room" part.
+const OOM_FREE_SHARE: f64 = 0.1;
+/// Ticks after which a fall without regrowth is forgotten.
+const OOM_HINT_TICKS: u32 = 60;
+
+/// What `--oom-hints` remembers about one process.
+#[derive(Clone, Copy, Debug)]
+struct OomWatch {
+    /// Used memory of the previous tick.
+    last: u64,
+    /// Used memory before the last fall, and the ticks since.
+    fall: Option<(u64, u32)>,
+}
+
+/// Used memory of one process at the time of a frame.
+type MemorySample = (DateTime<Local>, u64);
+
+/// Session peaks of one GPU, as `--state-file` saves them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GpuPeaks {
+    /// Milliwatts.
+    pub power: u32,
+    /// Bytes.
+    pub memory: u64,
+}
+
+/// What watch mode remembers about one GPU between frames.
+#[derive(Debug, Default)]
+struct GpuHistory {
+    /// Tick the GPU was last collected in.
+    last_seen: u64,
+    /// Power draw of the last `POWER_WINDOW` ticks.
+    power: VecDeque<u32>,
+    power_peak: u32,
+    memory_peak: u64,
+    /// Throttle reasons of the last `--throttle-window` ticks.
+    throttle: VecDeque<ThrottleReasons>,
+    /// State last printed by `--changes-only`.
+    printed: Option<GpuStat>,
+    /// Used memory of the last `MEMORY_GROWTH_WINDOW` ticks per process, keyed
+    /// by PID and start time so a reused PID starts over.
+    process_memory: HashMap<(u32, u64), VecDeque<MemorySample>>,
+    /// Memory falls of each process for `--oom-hints`, keyed like `process_memory`.
+    oom: HashMap<(u32, u64), OomWatch>,
+}
+
+/// Every per-GPU history of watch mode, keyed by UUID. The sample buffers are
+/// bounded by their windows and GPUs missing for `HISTORY_EVICT_TICKS` ticks
+/// are forgotten, so a session of any length holds a fixed amount of state.
+#[derive(Debug)]
+pub struct HistoryStore {
+    tick: u64,
+    throttle_window: usize,
+    power_reset: Option<(Duration, Instant)>,
+    peaks_reset: Option<(Duration, Instant)>,
+    driver_version: Option<String>,
+    gpus: HashMap<String, GpuHistory>,
+}
+
+impl HistoryStore {
+    pub fn new(throttle_window: usize, reset_power_every: Option<Duration>, reset_peaks_every: Option<Duration>) -> Self {
+        HistoryStore {
+            tick: 0,
+            throttle_window,
+            power_reset: reset_power_every.map(|every| (every, Instant::now())),
+            peaks_reset: reset_peaks_every.map(|every| (every, Instant::now())),
+            driver_version: None,
+            gpus: HashMap::new(),
+        }
+    }
+
+    /// Start a frame: mark the GPUs of the snapshot as seen, forget the ones
+    /// gone for too long and apply the periodic resets.
+    pub fn tick(&mut self, snapshot: &Snapshot) {
+        self.tick += 1;
+        let tick = self.tick;
+        for gpu in &snapshot.gpus {
+            self.gpus.entry(gpu.uuid.clone()).or_default().last_seen = tick;
+        }
+        self.gpus.retain(|_, history| tick - history.last_seen < HISTORY_EVICT_TICKS);
+
+        // a reloaded driver starts from fresh throttle counters
+        if self.driver_version != snapshot.driver_version {
+            self.gpus.values_mut().for_each(|history| history.throttle.clear());
+            self.driver_version = snapshot.driver_version.clone();
+        }
+        if expired(&mut self.power_reset) {
+            for history in self.gpus.values_mut() {
+                history.power.clear();
+                history.power_peak = 0;
+            }
+        }
+        if expired(&mut self.peaks_reset) {
+            self.gpus.values_mut().for_each(|history| history.memory_peak = 0);
+        }
+        log::debug!("{}", self.footprint());
+    }
+
+    /// `history: 8 GPUs, 80 power and 480 throttle samples, 3 printed states, 5 processes, ~21 KiB`,
+    /// counting the buffers at their capacity.
+    pub fn footprint(&self) -> String {
+        let power: usize = self.gpus.values().map(|history| history.power.len()).sum();
+        let throttle: usize = self.gpus.values().map(|history| history.throttle.len()).sum();
+        let printed = self.gpus.values().filter(|history| history.printed.is_some()).count();
+        let processes: usize = self.gpus.values().map(|history| history.process_memory.len()).sum();
+        let bytes: usize = self
+            .gpus
+            .iter()
+            .map(|(uuid, history)| {
+                uuid.capacity()
+                    + std::mem::size_of::<GpuHistory>()
+                    + history.power.capacity() * std::mem::size_of::<u32>()
+                    + history.throttle.capacity() * std::mem::size_of::<ThrottleReasons>()
+                    + history.printed.as_ref().map_or(0, |_| std::mem::size_of::<GpuStat>())
+                    + history.process_memory.len()
+                        * (std::mem::size_of::<(u32, u64)>()
+                            + MEMORY_GROWTH_WINDOW * std::mem::size_of::<MemorySample>())
+            })
+            .sum();
+        format!(
+            "history: {} GPUs, {} power and {} throttle samples, {} printed states, {} processes, ~{} KiB",
+            self.gpus.len(),
+            power,
+            throttle,
+            printed,
+            processes,
+            bytes.div_ceil(1024)
+        )
+    }
+
+    /// Record the power draw of every GPU and fill in its average and peak.
+    pub fn observe_power(&mut self, snapshot: &mut Snapshot) {
+        for gpu in snapshot.gpus.iter_mut().filter(|gpu| !gpu.uuid.is_empty()) {
+            let history = self.gpus.entry(gpu.uuid.clone()).or_default();
+            push_bounded(&mut history.power, POWER_WINDOW, gpu.power_usage);
+            let sum: u64 = history.power.iter().map(|&mw| u64::from(mw)).sum();
+            gpu.power_average = Some((sum / history.power.len() as u64) as u32);
+
+            // transients only show in the instantaneous reading
+            let drawn = gpu.power_instant.map_or(gpu.power_usage, |instant| instant.max(gpu.power_usage));
+            history.power_peak = history.power_peak.max(drawn);
+            gpu.power_peak = Some(history.power_peak);
+        }
+    }
+
+    /// Record the used memory of every GPU and fill in its session peak.
+    pub fn observe_memory(&mut self, snapshot: &mut Snapshot) {
+        for gpu in snapshot.gpus.iter_mut().filter(|gpu| !gpu.uuid.is_empty()) {
+            let history = self.gpus.entry(gpu.uuid.clone()).or_default();
+            history.memory_peak = history.memory_peak.max(gpu.memory_used);
+            gpu.memory_peak = Some(history.memory_peak);
+        }
+    }
+
+    /// Record the active throttle reasons of every GPU and fill in how often each was active.
+    pub fn observe_throttle(&mut self, snapshot: &mut Snapshot) {
+        for gpu in snapshot.gpus.iter_mut().filter(|gpu| !gpu.uuid.is_empty()) {
+            let Some(reasons) = gpu.throttle_reasons else {
+                continue;
+            };
+            let history = self.gpus.entry(gpu.uuid.clone()).or_default();
+            push_bounded(&mut history.throttle, self.throttle_window, reasons);
+            for &(reason, name) in THROTTLE_REASONS {
+                let active = history.throttle.iter().filter(|r| r.contains(reason)).count();
+                if active > 0 {
+                    let percent = (active * 100 / history.throttle.len()) as u32;
+                    gpu.throttle_percent.insert(name.to_string(), percent);
+                }
+            }
+        }
+    }
+
+    /// Record the used memory of every process and fill in its growth rate
+    /// once it grew over a full window without shrinking. A drop, such as a
+    /// freed cache, starts the window over; unavailable readings are skipped.
+    pub fn observe_process_memory(&mut self, snapshot: &mut Snapshot) {
+        let now = snapshot.timestamp;
+        for gpu in snapshot.gpus.iter_mut().filter(|gpu| !gpu.uuid.is_empty()) {
+            let history = self.gpus.entry(gpu.uuid.clone()).or_default();
+            // exited processes are forgotten
+            history
+                .process_memory
+                .retain(|&(pid, start), _| gpu.processes.iter().any(|p| p.pid == pid && p.start_time == start));
+            for process in gpu.processes.iter_mut() {
+                let samples = history.process_memory.entry((process.pid, process.start_time)).or_default();
+                let Some(used) = process.used_memory else {
+                    continue;
+                };
+                if samples.back().is_some_and(|&(_, last)| used < last) {
+                    samples.clear();
+                }
+                push_bounded(samples, MEMORY_GROWTH_WINDOW, (now, used));
+                let (Some(&(first_at, first)), Some(&(last_at, last))) = (samples.front(), samples.back()) else {
+                    continue;
+                };
+                let seconds = (last_at - first_at).num_milliseconds() as f64 / 1000.0;
+                if samples.len() == MEMORY_GROWTH_WINDOW && last > first && seconds > 0.0 {
+                    process.memory_growth = Some(((last - first) as f64 * 60.0 / seconds) as u64);
+                }
+            }
+        }
+    }
+
+    /// Flag GPUs with much free memory where a process's memory fell by
+    /// `OOM_DROP_SHARE` in one tick and has not regrown since, for `--oom-hints`.
+    /// The fall only counts from the next tick, once it did not regrow at once.
+    pub fn observe_oom(&mut self, snapshot: &mut Snapshot) {
+        for gpu in snapshot.gpus.iter_mut().filter(|gpu| !gpu.uuid.is_empty()) {
+            let history = self.gpus.entry(gpu.uuid.clone()).or_default();
+            history
+                .oom
+                .retain(|&(pid, start), _| gpu.processes.iter().any(|p| p.pid == pid && p.start_time == start));
+            let roomy = gpu.memory_free as f64 >= OOM_FREE_SHARE * gpu.memory_total as f64;
+            let mut hint: Option<OomHint> = None;
+            for process in &gpu.processes {
+                let Some(used) = process.used_memory else {
+                    continue;
+                };
+                let watch = history
+                    .oom
+                    .entry((process.pid, process.start_time))
+                    .or_insert(OomWatch { last: used, fall: None });
+                let fell = watch.last.saturating_sub(used);
+                if fell >= OOM_DROP_MIN && fell as f64 >= OOM_DROP_SHARE * watch.last as f64 {
+                    // a second fall is measured from the level before the first
+                    let before = watch.fall.map_or(watch.last, |(before, _)| before.max(watch.last));
+                    watch.fall = Some((before, 0));
+                } else if let Some((before, ticks)) = watch.fall {
+                    let regrown = used as f64 >= OOM_REGROWN_SHARE * before as f64;
+                    watch.fall = Some((before, ticks + 1)).filter(|_| !regrown && ticks < OOM_HINT_TICKS);
+                }
+                watch.last = used;
+                if let Some((before, ticks)) = watch.fall.filter(|_| roomy) {
+                    let candidate = OomHint {
+                        pid: process.pid,
+                        before,
+                        after: used,
+                    };
+                    let lost = |hint: &OomHint| hint.before.saturating_sub(hint.after);
+                    if ticks > 0 && hint.as_ref().is_none_or(|hint| lost(hint) < lost(&candidate)) {
+                        hint = Some(candidate);
+                    }
+                }
+            }
+            gpu.oom_hint = hint;
+        }
+    }
+
+    /// Session peaks of every GPU that has one.
+    pub fn peaks(&self) -> BTreeMap<String, GpuPeaks> {
+        self.gpus
+            .iter()
+            .filter(|(_, history)| history.power_peak > 0 || history.memory_peak > 0)
+            .map(|(uuid, history)| {
+                let peaks = GpuPeaks {
+                    power: history.power_peak,
+                    memory: history.memory_peak,
+                };
+                (uuid.clone(), peaks)
+            })
+            .collect()
+    }
+
+    /// Continue from the peaks of an earlier session. GPUs that do not show
+    /// up are evicted as usual.
+    pub fn restore_peaks(&mut self, peaks: &BTreeMap<String, GpuPeaks>) {
+        for (uuid, peaks) in peaks {
+            let history = self.gpus.entry(uuid.clone()).or_default();
+            history.last_seen = self.tick;
+            history.power_peak = history.power_peak.max(peaks.power);
+            history.memory_peak = history.memory_peak.max(peaks.memory);
+        }
+    }
+
+    /// Whether a GPU changed since `--changes-only` last printed it, remembering
+    /// it as printed when it did.
+    pub fn changed_since_printed(&mut self, gpu: &GpuStat, threshold: &ChangeThreshold) -> bool {
+        let history = self.gpus.entry(gpu.uuid.clone()).or_default();
+        let changed = history.printed.as_ref().is_none_or(|before| gpu.changed_from(before, threshold));
+        if changed {
+            history.printed = Some(gpu.clone());
+        }
+        changed
+    }
+}
+
+/// Whether a periodic reset is due, restarting its period when it is.
+fn expired(reset: &mut Option<(Duration, Instant)>) -> bool {
+    match reset {
+        Some((every, since)) if since.elapsed() >= *every => {
+            *since = Instant::now();
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Append to a ring buffer of at most `capacity` samples.
+fn push_bounded<T>(samples: &mut VecDeque<T>, capacity: usize, sample: T) {
+    if samples.capacity() < capacity {
+        samples.reserve_exact(capacity - samples.len());
+    }
+    while samples.len() >= capacity.max(1) {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+/// Which memory figures the memory column shows.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryDisplay {
+    /// `used / total MB`
+    #[default]
+    Used,
+    /// `free / total GB free`
+    Free,
+    /// `used / free / total GB`
+    Both,
+}
+
+/// Whose command line arguments `--redact-args` hides.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Redact {
+    /// Other users' processes, unless gpustat runs as root
+    Others,
+    /// Every process, also for root
+    All,
+}
+
+/// How GPUs are enumerated and labelled.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DeviceOrder {
+    /// NVML enumeration order
+    #[default]
+    Nvml,
+    /// PCI bus id order
+    Pci,
+    /// CUDA_DEVICE_ORDER=FASTEST_FIRST: newest compute capability, then most memory
+    Cuda,
+}
+
+/// What `--distinguish` appends to the names of GPUs, which repeat on nodes
+/// of identical cards.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Distinguish {
+    /// Displayed index, e.g. `#3`
+    Index,
+    /// First 8 digits of the UUID, e.g. `#1a2b3c4d`
+    Uuid,
+    /// PCI bus id, e.g. `@00000000:17:00.0`
+    Bus,
+}
+
+/// Map each NVML index to its position in `order`, or `None` for NVML order.
+/// A suspended device is placed by its sysfs bus id unless `wake`.
+fn device_positions(
+    order: DeviceOrder,
+    provider: &dyn Provider,
+    device_num: u32,
+    wake: bool,
+) -> Result<Option<Vec<u32>>, NvmlError> {
+    if order == DeviceOrder::Nvml {
+        return Ok(None);
+    }
+    let mut keys = vec![];
+    for index in 0..device_num {
+        if let Some(bus_id) = (provider.local() && !wake)
+            .then(|| runtime_pm::suspended(index, None))
+            .flatten()
+        {
+            keys.push((Reverse((0, 0)), Reverse(0), runtime_pm::nvml_bus_id(&bus_id), index));
+            continue;
+        }
+        // devices that cannot be queried sort last, in NVML order
+        let queries = DeviceQueries::new(provider, index);
+        let bus_id = match provider.open(index) {
+            Ok(()) => queries.query("pci_info", |device| device.pci_info()).ok(),
+            Err(_) => None,
+        };
+        let bus_id = bus_id.map_or_else(|| String::from("~"), |pci| pci.bus_id);
+        let (capability, memory) = match order {
+            DeviceOrder::Cuda if bus_id != "~" => (
+                queries
+                    .query("cuda_compute_capability", |device| device.cuda_compute_capability())
+                    .map_or((0, 0), |cc| (cc.major, cc.minor)),
+                queries.memory_info().map_or(0, |(m, _)| m.total),
+            ),
+            _ => ((0, 0), 0),
+        };
+        keys.push((Reverse(capability), Reverse(memory), bus_id, index));
+    }
+    keys.sort();
+
+    let mut positions = vec![0; device_num as usize];
+    for (position, (_, _, _, index)) in keys.into_iter().enumerate() {
+        positions[index as usize] = position as u32;
+    }
+    Ok(Some(positions))
+}
+
+/// The NVML queries of one GPU, answered by a `Provider`.
+pub struct DeviceQueries<'a> {
+    provider: &'a dyn Provider,
+    pub index: u32,
+    /// Queries that failed so far, with their NVML error names.
+    failures: RefCell<BTreeMap<String, String>>,
+}
+
+impl<'a> DeviceQueries<'a> {
+    pub fn new(provider: &'a dyn Provider, index: u32) -> Self {
+        DeviceQueries {
+            provider,
+            index,
+            failures: RefCell::default(),
+        }
+    }
+
+    /// The failed queries, for `GpuStat::query_failures`.
+    fn take_failures(&self) -> BTreeMap<String, String> {
+        self.failures.take()
+    }
+
+    /// Framebuffer memory and its reserved amount, through the v1 query
+    /// when the driver has no `nvmlDeviceGetMemoryInfo_v2`, which leaves
+    /// the reserved amount unknown.
+    pub fn memory_info(&self) -> Result<(ffi::Memory, Option<u64>), NvmlError> {
+        match self.query("memory_info", |device| device.memory_info()) {
+            Ok(memory) => Ok((ffi::Memory::from(&memory), Some(memory.reserved))),
+            Err(NvmlError::FunctionNotFound | NvmlError::NotSupported) => {
+                let memory = self.query("memory_info(v1)", ffi::memory_info_v1)?;
+                self.failures.borrow_mut().remove("memory_info");
+                Ok((memory, None))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the GPU is in this machine, see `Provider::local`.
+    pub fn local(&self) -> bool {
+        self.provider.local()
+    }
+
+    /// Run one NVML query, named `name` in the logs and in fixtures, logging
+    /// its duration and outcome at `-v` and noting a failure.
+    pub fn query<T: std::fmt::Debug + Serialize + DeserializeOwned>(
+        &self,
+        name: &str,
+        f: impl Fn(&Device) -> Result<T, NvmlError>,
+    ) -> Result<T, NvmlError> {
+        let start = Instant::now();
+        let live = |device: &Device| f(device).and_then(|value| serde_json::to_value(value).map_err(unencodable));
+        let result = self.provider.query(self.index, name, &live).and_then(|value| {
+            serde_json::from_value(value).map_err(|e| {
+                log::warn!("gpu {}: {} answered with an unexpected value: {}", self.index, name, e);
+                NvmlError::Unknown
+            })
+        });
+        match &result {
+            Ok(value) => log::info!("gpu {}: {} = {:?} ({:.2?})", self.index, name, value, start.elapsed()),
+            Err(e) => {
+                log::info!("gpu {}: {} failed: {} ({:.2?})", self.index, name, e, start.elapsed());
+                self.failures.borrow_mut().insert(name.to_string(), crate::nvml_code(e));
+            }
+        }
+        result
+    }
+}
+
+/// A value serde cannot hold, which no NVML answer is.
+fn unencodable(e: serde_json::Error) -> NvmlError {
+    log::warn!("cannot encode an NVML answer: {}", e);
+    NvmlError::Unknown
+}
+
+/// Number of words requested from the affinity queries, enough for
+/// 1024 CPUs (or NUMA nodes) on 64-bit hosts.
+#[cfg(target_os = "linux")]
+const AFFINITY_MASK_WORDS: usize = 16;
+#[cfg(target_os = "linux")]
+const AFFINITY_SCOPE_NODE: u32 = 0;
+
+/// Indices of the bits set in an NVML affinity bitmask.
+#[cfg(target_os = "linux")]
+fn mask_bits(words: &[std::os::raw::c_ulong]) -> Vec<u32> {
+    let width = std::os::raw::c_ulong::BITS;
+    let mut bits = vec![];
+    for (i, word) in words.iter().enumerate() {
+        for bit in 0..width {
+            if word & (1 << bit) != 0 {
+                bits.push(i as u32 * width + bit);
+            }
+        }
+    }
+    bits
+}
+
+/// Whether `pid` is one of `targets`, or a descendant of one when `children` is set.
+fn pid_matches(system: &System, pid: u32, targets: &[u32], children: bool) -> bool {
+    if targets.contains(&pid) {
+        return true;
+    }
+    if !children {
+        return false;
+    }
+    let mut current = system.process(Pid::from_u32(pid)).and_then(|p| p.parent());
+    while let Some(parent) = current {
+        if targets.contains(&parent.as_u32()) {
+            return true;
+        }
+        current = system.process(parent).and_then(|p| p.parent());
+    }
+    false
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    User::from_uid(Uid::from_raw(uid)).ok().flatten().map(|user| user.name)
+}
+
+/// Owner of a process sysinfo cannot see, from the ownership of `/proc/<pid>`.
+#[cfg(target_os = "linux")]
+pub(crate) fn proc_owner(pid: u32) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::metadata(format!("/proc/{}", pid)).ok()?;
+    user_name(metadata.uid())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn proc_owner(_pid: u32) -> Option<String> {
+    None
+}
+
+/// Whether the process has an NVIDIA device open, `None` when its descriptors are unreadable.
+#[cfg(target_os = "linux")]
+fn holds_gpu(pid: u32) -> Option<bool> {
+    let fds = std::fs::read_dir(format!("/proc/{}/fd", pid)).ok()?;
+    let holds = fds.flatten().any(|fd| {
+        std::fs::read_link(fd.path()).is_ok_and(|target| {
+            let target = target.to_string_lossy();
+            // WSL exposes the GPU as /dev/dxg
+            target.starts_with("/dev/nvidia") || target == "/dev/dxg"
+        })
+    });
+    Some(holds)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn holds_gpu(_pid: u32) -> Option<bool> {
+    None
+}
+
+/// PIDs with `/dev/nvidia<minor>` open, skipping processes whose descriptors
+/// are unreadable. Holders of only /dev/nvidiactl or /dev/nvidia-uvm cannot be
+/// told apart by device and are left out.
+#[cfg(target_os = "linux")]
+fn scan_device_holders(minor: u32) -> Vec<u32> {
+    let node = format!("/dev/nvidia{}", minor);
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return vec![];
+    };
+    let mut pids: Vec<u32> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .filter(|pid| {
+            std::fs::read_dir(format!("/proc/{}/fd", pid)).is_ok_and(|fds| {
+                fds.flatten()
+                    .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|target| target.as_os_str() == node.as_str()))
+            })
+        })
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+
+#[cfg(not(target_os = "linux"))]
+fn scan_device_holders(_minor: u32) -> Vec<u32> {
+    vec![]
+}
+
+/// Name of the virtualenv or conda env a process runs in, from its environment
+/// or else from the prefix of its executable (`<env>/bin/python`).
+fn python_env(process: &sysinfo::Process) -> Option<String> {
+    for var in process.environ() {
+        if let Some(prefix) = var.strip_prefix("VIRTUAL_ENV=") {
+            return env_name(Path::new(prefix));
+        }
+        if let Some(name) = var.strip_prefix("CONDA_DEFAULT_ENV=") {
+            return Some(name.to_string());
+        }
+    }
+    let exe = process
+        .cmd()
+        .first()
+        .map(Path::new)
+        .filter(|exe| exe.is_absolute())
+        .unwrap_or_else(|| process.exe());
+    let bin = exe.parent().filter(|bin| bin.ends_with("bin"))?;
+    let root = bin.parent()?;
+    if root.join("pyvenv.cfg").is_file() {
+        env_name(root)
+    } else if root.join("conda-meta").is_dir() {
+        match root.parent().and_then(Path::file_name) {
+            Some(envs) if envs == "envs" => env_name(root),
+            _ => Some(String::from("base")),
+        }
+    } else {
+        None
+    }
+}
+
+/// The env directory name, or the project it lives in for `.venv`-style names.
+fn env_name(root: &Path) -> Option<String> {
+    let name = root.file_name()?.to_string_lossy();
+    if matches!(name.as_ref(), ".venv" | "venv" | "env" | ".env") {
+        if let Some(project) = root.parent().and_then(Path::file_name) {
+            return Some(project.to_string_lossy().into_owned());
+        }
+    }
+    Some(name.into_owned())
+}
+
+fn process_cwd(process: &sysinfo::Process) -> Option<String> {
+    let cwd = process.cwd();
+    if cwd.as_os_str().is_empty() {
+        // sysinfo leaves it empty when /proc/<pid>/cwd is not readable
+        None
+    } else {
+        Some(cwd.to_string_lossy().into_owned())
+    }
+}
+
+/// Whether pods are looked up, for `--show-allocations`, `--show-sharing` or
+/// a `{container}` process template.
+fn wants_pod(opts: &Opts) -> bool {
+    opts.column("allocations")
+        || opts.column("sharing")
+        || opts
+            .proc_format
+            .as_deref()
+            .is_some_and(|format| format.contains("{container}"))
+}
+
+/// `1.9MB`, `12.0KB` or `512B`, in binary units like the memory columns.
+fn cut_size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{}B", bytes),
+        1024..=1048575 => format!("{:.1}KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1}MB", bytes as f64 / 1048576.0),
+    }
+}
+
+/// The arguments of `cmd` joined by spaces, cut at `limit` bytes and then
+/// followed by `[+1.9MB truncated]`, and whether they were cut. Only the
+/// bytes kept are copied, so a multi-megabyte argument list such as an
+/// inline JSON config is never joined whole.
+pub fn command_line(cmd: &[String], limit: usize) -> (String, bool) {
+    let total = cmd.iter().map(String::len).sum::<usize>() + cmd.len().saturating_sub(1);
+    if total <= limit {
+        return (cmd.join(" "), false);
+    }
+    let mut line = String::with_capacity(limit + " [+1023.9KB truncated]".len());
+    for (i, arg) in cmd.iter().enumerate() {
+        if i > 0 {
+            if line.len() == limit {
+                break;
+            }
+            line.push(' ');
+        }
+        let room = limit - line.len();
+        if arg.len() > room {
+            let mut end = room;
+            while !arg.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.push_str(&arg[..end]);
+            break;
+        }
+        line.push_str(arg);
+    }
+    if line.ends_with(' ') {
+        line.pop();
+    }
+    let cut = total - line.len();
+    line += &format!(" [+{} truncated]", cut_size(cut));
+    (line, true)
+}
+
+/// Replace the arguments of a command line with `[redacted]` and its
+/// executable with the basename, for `--redact-args`.
+pub fn redact_args(opts: &Opts, process: &mut ProcessStat) {
+    let redact = match opts.redact_args {
+        None => false,
+        Some(Redact::All) => true,
+        // an unknown owner counts as someone else
+        Some(Redact::Others) => {
+            let uid = Uid::current();
+            !uid.is_root() && user_name(uid.as_raw()).as_deref() != Some(process.user.as_str())
+        }
+    };
+    if !redact || process.full_command.is_empty() {
+        return;
+    }
+    let (executable, arguments) = process.full_command.split_once(' ').unwrap_or((&process.full_command, ""));
+    let name = executable.rsplit('/').next().unwrap_or(executable);
+    process.full_command = if arguments.trim().is_empty() {
+        name.to_string()
+    } else {
+        format!("{} [redacted]", name)
+    };
+    process.redacted = true;
+}
+
+fn process_stat(
+    opts: &Opts,
+    provider: &dyn Provider,
+    system: &System,
+    info: &ProcessInfo,
+    kind: ProcessKind,
+) -> ProcessStat {
+    let mut stat = unredacted_process_stat(opts, provider, system, info, kind);
+    // before --match and every format, so no output sees the arguments
+    redact_args(opts, &mut stat);
+    stat
+}
+
+fn unredacted_process_stat(
+    opts: &Opts,
+    provider: &dyn Provider,
+    system: &System,
+    info: &ProcessInfo,
+    kind: ProcessKind,
+) -> ProcessStat {
+    let used_memory = match info.used_gpu_memory {
+        UsedGpuMemory::Unavailable => None,
+        UsedGpuMemory::Used(m) => Some(m),
+    };
+    // the processes of a fixture ran on another machine
+    let process = system.process(Pid::from_u32(info.pid)).filter(|_| provider.local());
+    let user = process
+        .and_then(|p| p.user_id())
+        .and_then(|uid| user_name(**uid));
+    let (full_command, command_truncated) = process
+        .map(|process| command_line(process.cmd(), opts.max_cmd_length))
+        .unwrap_or_default();
+    match (process, user) {
+        (Some(process), Some(user)) => ProcessStat {
+            pid: info.pid,
+            user,
+            command: process.name().to_string(),
+            full_command,
+            redacted: false,
+            command_truncated,
+            used_memory,
+            kind,
+            start_time: process.start_time(),
+            parent: process.parent().map(|pid| pid.as_u32()),
+            resolved: true,
+            // a reused PID resolves to a process without any NVIDIA device open
+            stale: holds_gpu(info.pid) == Some(false),
+            cwd: Some(process)
+                .filter(|_| opts.show_cwd)
+                .and_then(process_cwd),
+            env: Some(process)
+                .filter(|_| opts.show_env)
+                .and_then(python_env),
+            max_memory: None,
+            average_utilization: None,
+            host_memory: Some(process.memory())
+                .filter(|_| opts.show_host_mem && opts.column("full_cmd")),
+            pod: Some(info.pid)
+                .filter(|_| wants_pod(opts))
+                .and_then(cgroup::process_pod),
+            contexts: 1,
+            encoder: None,
+            scanned: false,
+            collected_at: None,
+            age_ms: None,
+            memory_growth: None,
+            foreign: false,
+            matched: false,
+        },
+        // hidepid or a container hides the process from sysinfo, keep what is still visible
+        (process, _) => {
+            log::info!("process {}: not visible to sysinfo, using fallbacks", info.pid);
+            let command = match process {
+                Some(process) => Ok(process.name().to_string()),
+                None => provider.sys_process_name(info.pid),
+            };
+            let stale = matches!(command, Err(NvmlError::NotFound));
+            let owner = provider.process_owner(info.pid);
+            // a fixture's process is as resolved as it was when recorded
+            let resolved = stale || (!provider.local() && owner.is_some());
+            ProcessStat {
+                pid: info.pid,
+                user: owner.unwrap_or_default(),
+                command: command.unwrap_or_default(),
+                full_command,
+                redacted: false,
+                command_truncated,
+                used_memory,
+                kind,
+                start_time: process.map_or(0, |p| p.start_time()),
+                parent: process.and_then(|p| p.parent()).map(|pid| pid.as_u32()),
+                resolved,
+                stale,
+                cwd: None,
+                env: None,
+                max_memory: None,
+                average_utilization: None,
+                host_memory: process
+                    .map(|p| p.memory())
+                    .filter(|_| opts.show_host_mem && opts.column("full_cmd")),
+                pod: Some(info.pid)
+                    .filter(|_| wants_pod(opts) && !stale && provider.local())
+                    .and_then(cgroup::process_pod),
+                contexts: 1,
+                encoder: None,
+                scanned: false,
+                collected_at: None,
+                age_ms: None,
+                memory_growth: None,
+                foreign: false,
+                matched: false,
+            }
+        }
+    }
+}
+
+/// Merge the compute and graphics process lists of one device. A PID listed
+/// more than once as a compute process holds one context per entry.
+fn device_processes(
+    opts: &Opts,
+    provider: &dyn Provider,
+    system: &System,
+    compute: Vec<ProcessInfo>,
+    graphics: Vec<ProcessInfo>,
+    sessions: Vec<EncoderSessionInfo>,
+) -> Vec<ProcessStat> {
+    let mut processes: Vec<ProcessStat> = vec![];
+    for info in &compute {
+        match processes.iter_mut().find(|p| p.pid == info.pid) {
+            Some(process) => {
+                process.contexts += 1;
+                process.used_memory = match (process.used_memory, &info.used_gpu_memory) {
+                    (Some(used), UsedGpuMemory::Used(m)) => Some(used + m),
+                    _ => None,
+                };
+            }
+            None => processes.push(process_stat(opts, provider, system, info, ProcessKind::Compute)),
+        }
+    }
+    for info in &graphics {
+        match processes.iter_mut().find(|p| p.pid == info.pid) {
+            Some(process) => process.kind = ProcessKind::Mixed,
+            None => processes.push(process_stat(opts, provider, system, info, ProcessKind::Graphics)),
+        }
+    }
+    let mut encoders: BTreeMap<u32, (u32, u32)> = BTreeMap::new();
+    for session in &sessions {
+        let (count, fps) = encoders.entry(session.pid).or_default();
+        *count += 1;
+        *fps += session.average_fps;
+    }
+    for (pid, (sessions, fps)) in encoders {
+        let index = match processes.iter().position(|p| p.pid == pid) {
+            Some(index) => index,
+            None => {
+                // NVML lists some encoding processes in neither process list
+                let info = ProcessInfo {
+                    pid,
+                    used_gpu_memory: UsedGpuMemory::Unavailable,
+                    gpu_instance_id: None,
+                    compute_instance_id: None,
+                };
+                processes.push(process_stat(opts, provider, system, &info, ProcessKind::Encoder));
+                processes.len() - 1
+            }
+        };
+        processes[index].encoder = Some(EncoderUse {
+            sessions,
+            average_fps: fps / sessions,
+        });
+    }
+    processes
+}
+
+/// Connected DRM connectors (`/sys/class/drm/card0-DP-1/status`) of a PCI
+/// device, `None` when it has none, e.g. without nvidia-drm modesetting.
+fn connected_displays(bus_id: &str) -> Option<u32> {
+    let device = std::fs::canonicalize(reset::sysfs_device(bus_id)).ok()?;
+    let mut connectors = 0;
+    let mut connected = 0;
+    for entry in std::fs::read_dir("/sys/class/drm").ok()?.flatten() {
+        let name = entry.file_name();
+        if !name.to_string_lossy().contains('-') {
+            continue;
+        }
+        match std::fs::canonicalize(entry.path()) {
+            Ok(path) if path.starts_with(&device) => {}
+            _ => continue,
+        }
+        connectors += 1;
+        if std::fs::read_to_string(entry.path().join("status")).is_ok_and(|status| status.trim() == "connected") {
+            connected += 1;
+        }
+    }
+    Some(connected).filter(|_| connectors > 0)
+}
+
+/// The values of a field value query, `None` for fields that failed.
+fn field_values(samples: Vec<Result<FieldValueSample, NvmlError>>) -> Vec<Option<u32>> {
+    samples
+        .into_iter()
+        .map(|sample| sample.ok().and_then(|sample| sample.value.ok()).and_then(sample_u32))
+        .collect()
+}
+
+fn sample_u32(value: SampleValue) -> Option<u32> {
+    match value {
+        SampleValue::U32(v) => Some(v),
+        SampleValue::U64(v) => u32::try_from(v).ok(),
+        SampleValue::I64(v) => u32::try_from(v).ok(),
+        SampleValue::F64(v) => Some(v.round() as u32),
+    }
+}
+
+/// Largest of the buffered samples, skipping glitched ones above `ceiling`;
+/// `None` when no sample is left.
+fn samples_max(samples: &[Sample], ceiling: u32) -> Option<u32> {
+    samples
+        .iter()
+        .filter_map(|sample| sample_u32(sample.value.clone()))
+        .filter(|&value| value <= ceiling)
+        .max()
+}
+
+/// Maxima of the power and utilization samples taken over the last `seconds`,
+/// `None` on drivers without the sampling API.
+fn window_max(queries: &DeviceQueries, seconds: f64) -> Option<WindowMax> {
+    // sample timestamps are CPU time in microseconds since the epoch
+    let since = Utc::now().timestamp_micros() - (seconds * 1e6) as i64;
+    let since = u64::try_from(since).unwrap_or_default();
+    let max = |name, sampling, ceiling| {
+        let samples = queries.query(name, |device| device.samples(sampling, since));
+        samples.ok().and_then(|samples| samples_max(&samples, ceiling))
+    };
+    let window = WindowMax {
+        power: max("power_samples", Sampling::Power, u32::MAX),
+        utilization: max("utilization_samples", Sampling::GpuUtilization, 100),
+    };
+    Some(window).filter(|window| window.power.is_some() || window.utilization.is_some())
+}
+
+/// Optional queries of a device, collected when `wanted` by the options
+/// unless switched off with `--disable-collector`.
+pub struct Collector {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The NVML functions behind it and the units of what it reads, as
+    /// `--explain` lists them.
+    pub explain: &'static str,
+    /// NVML queries it makes on each device, as `--dry-run` lists them.
+    pub calls: &'static [&'static str],
+    /// Options needing it besides the columns of `columns::COLUMNS` it fills.
+    wanted_by: fn(&Opts) -> bool,
+    collect: fn(&Opts, &DeviceQueries, &mut GpuStat) -> Result<(), NvmlError>,
+    /// Only reads properties that stay put while the driver is loaded, so it
+    /// runs once per device and is then served from the `DeviceCache`.
+    pub fixed: bool,
+}
+
+impl Collector {
+    /// Whether the options need it, for a column that shows or otherwise.
+    pub fn wanted(&self, opts: &Opts) -> bool {
+        (self.wanted_by)(opts)
+            || COLUMNS
+                .iter()
+                .any(|column| column.collectors.contains(&self.name) && opts.column(column.name))
+    }
+
+    /// Whether a plain `gpustat` run collects it.
+    pub fn by_default(&self) -> bool {
+        self.wanted(&Opts::default())
+    }
+
+    /// Run the collector alone on a blank GPU, for `gpustat capabilities`:
+    /// whether it succeeded and filled in anything.
+    pub fn probe(&self, opts: &Opts, provider: &dyn Provider, index: u32) -> bool {
+        let blank = GpuStat {
+            index,
+            ..Default::default()
+        };
+        let mut gpu = blank.clone();
+        let collected = (self.collect)(opts, &DeviceQueries::new(provider, index), &mut gpu).is_ok();
+        collected && serde_json::to_value(&gpu).ok() != serde_json::to_value(&blank).ok()
+    }
+
+    /// Run the collector alone on a blank GPU, for `gpustat bench`.
+    pub fn run(&self, opts: &Opts, provider: &dyn Provider, index: u32) -> Result<(), NvmlError> {
+        let mut gpu = GpuStat {
+            index,
+            ..Default::default()
+        };
+        (self.collect)(opts, &DeviceQueries::new(provider, index), &mut gpu)
+    }
+}
+
+pub const COLLECTORS: &[Collector] = &[
+    Collector {
+        name: "brand",
+        description: "board brand next to the name (--show-brand)",
+        explain: "nvmlDeviceGetBrand",
+        calls: &["brand"],
+        wanted_by: |_| false,
+        collect: collect_brand,
+        fixed: true,
+    },
+    Collector {
+        name: "board",
+        description: "board serial to group GPUs by (--group-by-board) and count board power once (--power-detail)",
+        explain: "nvmlDeviceGetSerial",
+        calls: &["serial"],
+        wanted_by: |opts| opts.group_by_board || opts.power_detail,
+        collect: collect_board,
+        fixed: true,
+    },
+    Collector {
+        name: "fan",
+        description: "fan speed and control policy (-F)",
+        explain: "nvmlDeviceGetFanSpeed_v2 in % of the maximum speed, nvmlDeviceGetFanControlPolicy_v2",
+        calls: &["fan_speed", "fan_control_policy"],
+        wanted_by: |_| false,
+        collect: collect_fan,
+        fixed: false,
+    },
+    Collector {
+        name: "cooling",
+        description: "memory temperature and slowdown threshold (--cooling)",
+        explain: "nvmlDeviceGetFieldValues (NVML_FI_DEV_MEMORY_TEMP), nvmlDeviceGetTemperatureThreshold in °C",
+        calls: &["field_values_for", "temperature_threshold"],
+        wanted_by: |_| false,
+        collect: collect_cooling,
+        fixed: false,
+    },
+    Collector {
+        name: "fans",
+        description: "speed and RPM of every fan (--fan-detail)",
+        explain: "nvmlDeviceGetNumFans, nvmlDeviceGetFanSpeed_v2 in % of the maximum, nvmlDeviceGetFanSpeedRPM",
+        calls: &["num_fans", "fan_speed", "fan_speed_rpm"],
+        wanted_by: |opts| opts.fan_detail,
+        collect: collect_fans,
+        fixed: false,
+    },
+    Collector {
+        name: "samples",
+        description: "driver samples of power and utilization since the last refresh (--window-max)",
+        explain: "nvmlDeviceGetSamples of power in mW and utilization in %, the highest since the last refresh",
+        calls: &["samples"],
+        wanted_by: |opts| opts.interval.is_some() && opts.window_max,
+        collect: collect_samples,
+        fixed: false,
+    },
+    Collector {
+        name: "power_readings",
+        description: "instantaneous, averaged and board power where the driver reports them (--power-detail)",
+        explain: "nvmlDeviceGetFieldValues (NVML_FI_DEV_POWER_INSTANT, NVML_FI_DEV_POWER_AVERAGE) in mW, shown in W",
+        calls: &["field_values_for"],
+        wanted_by: |opts| opts.power_detail,
+        collect: collect_power_readings,
+        fixed: false,
+    },
+    Collector {
+        name: "codec",
+        description: "encoder and decoder utilization (-e)",
+        explain: "nvmlDeviceGetEncoderUtilization, nvmlDeviceGetDecoderUtilization in % of the sample period",
+        calls: &["encoder_utilization", "decoder_utilization"],
+        wanted_by: |_| false,
+        collect: collect_codec,
+        fixed: false,
+    },
+    Collector {
+        name: "proc_codec",
+        description: "encoder sessions of every process (--show-proc-codec)",
+        explain: "nvmlDeviceGetEncoderSessions, with the average frame rate in fps and latency in µs",
+        calls: &["encoder_sessions"],
+        wanted_by: |opts| opts.show_proc_codec,
+        // joined with the process list by collect_gpu, before --pid filtering
+        collect: |_, _, _| Ok(()),
+        fixed: false,
+    },
+    Collector {
+        name: "temperatures",
+        description: "memory temperature sensor (--show-temps-all)",
+        explain: "nvmlDeviceGetFieldValues (NVML_FI_DEV_MEMORY_TEMP) in °C",
+        calls: &["field_values_for"],
+        wanted_by: |_| false,
+        collect: collect_temperatures,
+        fixed: false,
+    },
+    Collector {
+        name: "temperature_target",
+        description: "adjustable target temperature (--temp-detail)",
+        explain: "nvmlDeviceGetTemperatureThreshold (NVML_TEMPERATURE_THRESHOLD_ACOUSTIC_CURR) in °C",
+        calls: &["acoustic_target_temperature"],
+        wanted_by: |opts| opts.temp_detail,
+        collect: collect_temperature_target,
+        fixed: false,
+    },
+    Collector {
+        name: "encoder_capacity",
+        description: "remaining NVENC capacity (--show-enc-capacity)",
+        explain: "nvmlDeviceGetEncoderCapacity for H.264 and HEVC in % of the NVENC capacity left",
+        calls: &["encoder_capacity"],
+        wanted_by: |_| false,
+        collect: collect_encoder_capacity,
+        fixed: false,
+    },
+    Collector {
+        name: "pcie_replays",
+        description: "PCIe replay counter (--show-pcie-errors)",
+        explain: "nvmlDeviceGetPcieReplayCounter, replays since the driver loaded",
+        calls: &["pcie_replay_counter"],
+        wanted_by: |_| false,
+        collect: collect_pcie_replays,
+        fixed: false,
+    },
+    Collector {
+        name: "ecc",
+        description: "current and pending ECC mode (--show-ecc, --consistency-check)",
+        explain: "nvmlDeviceGetEccMode, the current and pending mode",
+        calls: &["is_ecc_enabled"],
+        wanted_by: |opts| opts.consistency_check,
+        collect: collect_ecc,
+        fixed: false,
+    },
+    Collector {
+        name: "mig_mode",
+        description: "current and pending MIG mode (--show-mig-mode, --fail-if mig, gpustat ready, --consistency-check)",
+        explain: "nvmlDeviceGetMigMode, the current and pending mode",
+        calls: &["mig_mode"],
+        wanted_by: |opts| {
+            opts.fail_if.iter().any(FailCondition::needs_mig) || opts.ready_check() || opts.consistency_check
+        },
+        collect: collect_mig_mode,
+        fixed: false,
+    },
+    Collector {
+        name: "virtualization",
+        description: "virtualization mode and active vGPUs (--show-virt)",
+        explain: "nvmlDeviceGetVirtualizationMode, nvmlDeviceGetActiveVgpus, nvmlVgpuInstanceGetType, \
+                  nvmlVgpuTypeGetName, nvmlVgpuTypeGetFramebufferSize (bytes)",
+        calls: &["virtualization_mode", "active_vgpus", "vgpu_type"],
+        wanted_by: |_| false,
+        collect: collect_virtualization,
+        fixed: false,
+    },
+    Collector {
+        name: "throttle",
+        description: "throttle reasons (--show-throttle, --highlight-rows)",
+        explain: "nvmlDeviceGetCurrentClocksThrottleReasons, a bit mask of reasons",
+        calls: &["current_throttle_reasons"],
+        wanted_by: |opts| opts.highlight_rows || opts.ready_check(),
+        collect: collect_throttle,
+        fixed: false,
+    },
+    Collector {
+        name: "perf",
+        description: "SM clock and maximum boost clock (--show-perf)",
+        explain: "nvmlDeviceGetClockInfo and nvmlDeviceGetMaxClockInfo (NVML_CLOCK_SM) in MHz",
+        calls: &["clock_info", "max_clock_info"],
+        wanted_by: |_| false,
+        collect: collect_perf,
+        fixed: false,
+    },
+    Collector {
+        name: "bandwidth",
+        description: "memory controller utilization, clock and bus width (--show-bandwidth)",
+        explain: "nvmlDeviceGetUtilizationRates .memory in % of time times the peak bandwidth, in GB/s or TB/s",
+        calls: &["memory_bus_width", "max_clock_info", "utilization_rates"],
+        wanted_by: |_| false,
+        collect: collect_bandwidth,
+        fixed: false,
+    },
+    Collector {
+        name: "app_clocks",
+        description: "current and default application clocks (--show-app-clocks, --consistency-check)",
+        explain: "nvmlDeviceGetApplicationsClock, nvmlDeviceGetDefaultApplicationsClock in MHz",
+        calls: &["applications_clock", "default_applications_clock"],
+        wanted_by: |opts| opts.consistency_check,
+        collect: collect_app_clocks,
+        fixed: false,
+    },
+    Collector {
+        name: "inforom",
+        description: "InfoROM versions and checksum validation (--show-inforom)",
+        explain: "nvmlDeviceGetInforomImageVersion, nvmlDeviceValidateInforom, nvmlDeviceGetInforomVersion",
+        calls: &["info_rom_image_version", "validate_info_rom", "info_rom_version"],
+        // validating reads the InfoROM from flash, so -a leaves it out
+        wanted_by: |_| false,
+        collect: collect_inforom,
+        fixed: false,
+    },
+    Collector {
+        name: "cc_mode",
+        description: "confidential computing mode (--show-cc-mode)",
+        explain: "nvmlSystemGetConfComputeCapabilities, nvmlSystemGetConfComputeState",
+        calls: &["confidential_compute_mode"],
+        wanted_by: |_| false,
+        collect: collect_cc_mode,
+        fixed: false,
+    },
+    Collector {
+        name: "health",
+        description: "slowdown temperature and uncorrected ECC errors (--highlight-rows)",
+        explain: "nvmlDeviceGetTemperatureThreshold (slowdown) in °C, nvmlDeviceGetTotalEccErrors (volatile)",
+        calls: &["temperature_threshold", "total_ecc_errors"],
+        wanted_by: |opts| opts.highlight_rows || opts.ready_check(),
+        collect: collect_health,
+        fixed: false,
+    },
+    Collector {
+        name: "persistence",
+        description: "persistence mode (gpustat ready, the persistence hint, --consistency-check)",
+        explain: "nvmlDeviceGetPersistenceMode",
+        calls: &["is_in_persistent_mode"],
+        wanted_by: |opts| opts.ready_check() || !opts.no_hints || opts.consistency_check,
+        collect: collect_persistence,
+        fixed: false,
+    },
+    Collector {
+        name: "display",
+        description: "attached and connected displays (--show-display)",
+        explain: "nvmlDeviceGetDisplayActive, nvmlDeviceGetPciInfo_v3",
+        calls: &["is_display_active", "pci_info"],
+        // cheap, and colors the index of display GPUs even without --show-display
+        wanted_by: |_| true,
+        collect: collect_display,
+        fixed: false,
+    },
+    Collector {
+        name: "sharing",
+        description: "Kubernetes time-slicing from the device plugin config or the pods (--show-sharing)",
+        explain: "no NVML query; the device plugin config and the pods from the kubelet",
+        calls: &[],
+        wanted_by: |_| false,
+        collect: collect_sharing,
+        fixed: false,
+    },
+    Collector {
+        name: "handles",
+        description: "Device node minor number to count its open descriptors in /proc (--show-handles)",
+        explain: "nvmlDeviceGetMinorNumber, then the descriptors of /dev/nvidiaN in /proc",
+        calls: &["minor_number"],
+        wanted_by: |_| false,
+        collect: collect_handles,
+        fixed: false,
+    },
+    Collector {
+        name: "xid",
+        description: "PCI bus ID to match kernel Xid events (--show-xid, --distinguish bus)",
+        explain: "nvmlDeviceGetPciInfo_v3, then the NVRM Xid lines of the kernel log",
+        calls: &["pci_info"],
+        wanted_by: |opts| {
+            opts.distinguish == Some(Distinguish::Bus)
+                || opts.oneline_format.as_deref().is_some_and(|format| format.contains("{bus}"))
+        },
+        collect: collect_xid,
+        fixed: true,
+    },
+    Collector {
+        name: "accounting",
+        description: "accounting stats of every process (--show-accounting, --oom-hints)",
+        explain: "nvmlDeviceGetAccountingMode, nvmlDeviceGetAccountingStats: peak memory in MiB, utilization in %",
+        calls: &["is_accounting_enabled", "accounting_buffer_size", "accounting_stats_for"],
+        wanted_by: |opts| opts.show_accounting || opts.oom_hints,
+        collect: collect_accounting,
+        fixed: false,
+    },
+    Collector {
+        name: "affinity",
+        description: "CPU and NUMA affinity, Linux only (--show-affinity)",
+        explain: "nvmlDeviceGetCpuAffinity, nvmlDeviceGetMemoryAffinity",
+        calls: &["cpu_affinity", "memory_affinity"],
+        wanted_by: |_| false,
+        collect: collect_affinity,
+        fixed: true,
+    },
+];
+
+fn collect_brand(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.brand = match queries.query("brand", |device| device.brand()) {
+        Ok(brand) => Some(Ok(brand)),
+        Err(NvmlError::UnexpectedVariant(value)) => Some(Err(value)),
+        Err(_) => None,
+    };
+    Ok(())
+}
+
+fn collect_board(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.board = queries
+        .query("serial", |device| device.serial())
+        .ok()
+        .filter(|serial| !serial.is_empty());
+    Ok(())
+}
+
+fn collect_fan(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    // boards cooled by the chassis have no fan, which leaves the cell empty
+    gpu.fan_speed = queries.query("fan_speed", |device| device.fan_speed(0)).ok();
+    gpu.fan_policy = gpu.fan_speed.and_then(|_| {
+        queries
+            .query("fan_control_policy", |device| device.fan_control_policy(0))
+            .ok()
+    });
+    Ok(())
+}
+
+fn collect_fans(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    let fans = queries.query("num_fans", |device| device.num_fans()).unwrap_or(1);
+    gpu.fans = (0..fans)
+        .map(|fan| FanStat {
+            speed: queries
+                .query(&nth("fan_speed", fan), |device| device.fan_speed(fan))
+                .ok(),
+            rpm: queries
+                .query(&nth("fan_speed_rpm", fan), |device| device.fan_speed_rpm(fan))
+                .ok(),
+        })
+        .collect();
+    Ok(())
+}
+
+/// Name of a query of fan, sensor or value `n` of a kind, plain for the first.
+fn nth(call: &str, n: u32) -> String {
+    match n {
+        0 => call.to_string(),
+        n => format!("{}({})", call, n),
+    }
+}
+
+fn collect_samples(opts: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.window_max = opts.interval.and_then(|interval| window_max(queries, interval));
+    Ok(())
+}
+
+fn collect_sharing(opts: &Opts, _: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    let configured = sharing::configured_replicas(opts.sharing_config.as_deref());
+    gpu.sharing = sharing::infer(configured, gpu.pods().len() as u32);
+    Ok(())
+}
+
+fn collect_codec(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    let encoder = queries.query("encoder_utilization", |device| device.encoder_utilization())?;
+    let decoder = queries.query("decoder_utilization", |device| device.decoder_utilization())?;
+    gpu.encoder_utilization = Some(encoder.utilization);
+    gpu.encoder_sampling_period = Some(encoder.sampling_period);
+    gpu.decoder_utilization = Some(decoder.utilization);
+    gpu.decoder_sampling_period = Some(decoder.sampling_period);
+    Ok(())
+}
+
+/// HBM temperature, `None` on GPUs without the sensor. The wrapper only
+/// knows the die sensor, so it is read through a field value.
+fn memory_temperature(queries: &DeviceQueries) -> Option<u32> {
+    let memory = queries.query("field_values_for(MEMORY_TEMP)", |device| {
+        Ok(field_values(
+            device.field_values_for(&[FieldId(NVML_FI_DEV_MEMORY_TEMP)])?,
+        ))
+    });
+    memory.ok().and_then(|values| values.into_iter().next()).flatten()
+}
+
+fn collect_temperatures(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.temperatures.insert(String::from("gpu"), gpu.temperature);
+    if let Some(memory) = memory_temperature(queries) {
+        gpu.temperatures.insert(String::from("mem"), memory);
+    }
+    Ok(())
+}
+
+fn collect_cooling(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    let slowdown = queries
+        .query("temperature_threshold", |device| {
+            device.temperature_threshold(TemperatureThreshold::Slowdown)
+        })
+        .ok();
+    gpu.cooling = Some(Cooling::of(
+        gpu.temperature,
+        memory_temperature(queries),
+        slowdown,
+        gpu.power_usage,
+        gpu.power_limit,
+    ));
+    Ok(())
+}
+
+fn collect_power_readings(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    let readings = queries.query("field_values_for(POWER_AVERAGE, POWER_INSTANT)", |device| {
+        let fields = [FieldId(NVML_FI_DEV_POWER_AVERAGE), FieldId(NVML_FI_DEV_POWER_INSTANT)];
+        Ok(field_values(device.field_values_for(&fields)?))
+    })?;
+    let mut readings = readings.into_iter();
+    gpu.power_driver_average = readings.next().flatten();
+    gpu.power_instant = readings.next().flatten();
+    gpu.board_power = queries
+        .query("field_values_for(POWER_INSTANT, module scope)", |device| {
+            Ok(ffi::module_power(device)?.value.ok().and_then(sample_u32))
+        })
+        .ok()
+        .flatten();
+    Ok(())
+}
+
+fn collect_temperature_target(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.temperature_target = queries.query("acoustic_target_temperature", ffi::acoustic_target).ok();
+    Ok(())
+}
+
+fn collect_encoder_capacity(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.h264_capacity = queries
+        .query("encoder_capacity(H264)", |device| {
+            device.encoder_capacity(EncoderType::H264)
+        })
+        .ok();
+    gpu.hevc_capacity = queries
+        .query("encoder_capacity(HEVC)", |device| {
+            device.encoder_capacity(EncoderType::HEVC)
+        })
+        .ok();
+    Ok(())
+}
+
+fn collect_pcie_replays(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.pcie_replays = queries
+        .query("pcie_replay_counter", |device| device.pcie_replay_counter())
+        .ok();
+    Ok(())
+}
+
+fn collect_ecc(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.ecc_mode = queries.query("is_ecc_enabled", |device| device.is_ecc_enabled()).ok();
+    Ok(())
+}
+
+fn collect_mig_mode(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    // NotSupported before Ampere, which leaves the cell empty
+    gpu.mig_mode = queries
+        .query("mig_mode", |device| device.mig_mode())
+        .ok()
+        .map(|mode| MigModeState {
+            current: mode.current == MIG_ENABLE,
+            pending: mode.pending == MIG_ENABLE,
+        });
+    Ok(())
+}
+
+fn collect_virtualization(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.virtualization = queries
+        .query("virtualization_mode", |device| device.virtualization_mode())
+        .ok();
+    if let Some(GpuVirtualizationMode::HostVgpu) = gpu.virtualization {
+        let ids = queries
+            .query("active_vgpus", |device| device.active_vgpus())
+            .unwrap_or_default();
+        gpu.vgpus = ids
+            .into_iter()
+            .map(|id| {
+                let call = format!("vgpu_type({})", id);
+                let vgpu_type = queries.query(&call, |_| ffi::vgpu_type(id)).ok();
+                VgpuStat {
+                    id,
+                    type_name: vgpu_type.as_ref().map(|(name, _)| name.clone()),
+                    framebuffer: vgpu_type.map(|(_, framebuffer)| framebuffer),
+                }
+            })
+            .collect();
+    }
+    Ok(())
+}
+
+fn collect_throttle(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.throttle_reasons = queries
+        .query("current_throttle_reasons", |device| device.current_throttle_reasons())
+        .ok();
+    Ok(())
+}
+
+fn collect_perf(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    let sm = queries.query("clock_info(SM)", |device| device.clock_info(Clock::SM));
+    let max = queries.query("max_clock_info(SM)", |device| device.max_clock_info(Clock::SM));
+    gpu.perf = match (sm, max) {
+        (Ok(sm), Ok(max)) => Perf::of(sm, max),
+        _ => None,
+    };
+    Ok(())
+}
+
+fn collect_bandwidth(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    let memory_clock = queries
+        .query("max_clock_info(Memory)", |device| device.max_clock_info(Clock::Memory))
+        .ok();
+    let bus_width = queries
+        .query("memory_bus_width", |device| device.memory_bus_width())
+        .ok();
+    let Some(peak) = peak_bandwidth(&gpu.name, memory_clock, bus_width) else {
+        return Ok(());
+    };
+    let utilization = queries
+        .query("utilization_rates", |device| device.utilization_rates())?
+        .memory;
+    gpu.bandwidth = Some(Bandwidth {
+        peak,
+        used: peak * f64::from(utilization) / 100.0,
+        utilization,
+    });
+    Ok(())
+}
+
+fn collect_app_clocks(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    let clocks = || -> Result<AppClocks, NvmlError> {
+        Ok(AppClocks {
+            graphics: queries.query("applications_clock(Graphics)", |device| {
+                device.applications_clock(Clock::Graphics)
+            })?,
+            memory: queries.query("applications_clock(Memory)", |device| {
+                device.applications_clock(Clock::Memory)
+            })?,
+            default_graphics: queries.query("default_applications_clock(Graphics)", |device| {
+                device.default_applications_clock(Clock::Graphics)
+            })?,
+            default_memory: queries.query("default_applications_clock(Memory)", |device| {
+                device.default_applications_clock(Clock::Memory)
+            })?,
+        })
+    };
+    gpu.app_clocks = clocks().ok();
+    Ok(())
+}
+
+fn collect_inforom(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    let image = match queries.query("info_rom_image_version", |device| device.info_rom_image_version()) {
+        Ok(image) => image,
+        // a corrupted InfoROM may not even report its version
+        Err(NvmlError::CorruptedInfoROM) => String::new(),
+        Err(_) => return Ok(()),
+    };
+    let valid = match queries.query("validate_info_rom", |device| device.validate_info_rom()) {
+        Ok(()) => Some(true),
+        Err(NvmlError::CorruptedInfoROM) => Some(false),
+        Err(_) => None,
+    };
+    gpu.inforom = Some(InfoRomStat {
+        image,
+        oem: queries
+            .query("info_rom_version(OEM)", |device| device.info_rom_version(InfoRom::OEM))
+            .ok(),
+        ecc: queries
+            .query("info_rom_version(ECC)", |device| device.info_rom_version(InfoRom::ECC))
+            .ok(),
+        power: queries
+            .query("info_rom_version(Power)", |device| {
+                device.info_rom_version(InfoRom::Power)
+            })
+            .ok(),
+        valid,
+    });
+    Ok(())
+}
+
+fn collect_cc_mode(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.cc_mode = queries
+        .query("confidential_compute_mode", CcMode::of)
+        .unwrap_or_default();
+    Ok(())
+}
+
+fn collect_health(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.slowdown_temperature = queries
+        .query("temperature_threshold", |device| {
+            device.temperature_threshold(TemperatureThreshold::Slowdown)
+        })
+        .ok();
+    gpu.ecc_uncorrected = queries
+        .query("total_ecc_errors", |device| {
+            device.total_ecc_errors(MemoryError::Uncorrected, EccCounter::Volatile)
+        })
+        .ok();
+    Ok(())
+}
+
+fn collect_persistence(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.persistence_mode = queries
+        .query("is_in_persistent_mode", |device| device.is_in_persistent_mode())
+        .ok();
+    Ok(())
+}
+
+fn collect_display(opts: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.display_active = queries
+        .query("is_display_active", |device| device.is_display_active())
+        .ok();
+    if opts.column("display") && queries.local() {
+        gpu.displays = queries
+            .query("pci_info", |device| device.pci_info())
+            .ok()
+            .and_then(|pci| connected_displays(&pci.bus_id));
+    }
+    Ok(())
+}
+
+fn collect_handles(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.minor_number = Some(queries.query("minor_number", |device| device.minor_number())?);
+    Ok(())
+}
+
+fn collect_xid(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.pci_bus_id = queries
+        .query("pci_info", |device| device.pci_info())
+        .ok()
+        .map(|pci| pci.bus_id);
+    Ok(())
+}
+
+fn collect_accounting(opts: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.accounting_enabled = queries
+        .query("is_accounting_enabled", |device| device.is_accounting_enabled())
+        .ok();
+    if gpu.accounting_enabled == Some(true) {
+        // a full circular buffer drops the oldest processes
+        if let Ok(size) = queries.query("accounting_buffer_size", |device| device.accounting_buffer_size()) {
+            log::debug!("gpu {}: accounting buffer holds {} processes", queries.index, size);
+        }
+        for process in &mut gpu.processes {
+            let pid = process.pid;
+            let call = format!("accounting_stats_for({})", pid);
+            if let Ok(stats) = queries.query(&call, |device| device.accounting_stats_for(pid)) {
+                process.max_memory = stats.max_memory_usage;
+                // --oom-hints is after the high-water mark only
+                process.average_utilization = stats.gpu_utilization.filter(|_| opts.show_accounting);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn collect_affinity(_: &Opts, queries: &DeviceQueries, gpu: &mut GpuStat) -> Result<(), NvmlError> {
+    gpu.cpu_affinity = queries
+        .query("cpu_affinity", |device| device.cpu_affinity(AFFINITY_MASK_WORDS))
+        .ok()
+        .map(|mask| mask_bits(&mask));
+    gpu.numa_nodes = queries
+        .query("memory_affinity", |device| {
+            device.memory_affinity(AFFINITY_MASK_WORDS, AFFINITY_SCOPE_NODE)
+        })
+        .ok()
+        .map(|mask| mask_bits(&mask));
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn collect_affinity(_: &Opts, _: &DeviceQueries, _: &mut GpuStat) -> Result<(), NvmlError> {
+    Ok(())
+}
+
+/// Properties of a device that only change with the driver: its identity
+/// and whatever the `fixed` collectors found.
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    uuid: String,
+    name: String,
+    brand: Option<Result<Brand, u32>>,
+    board: Option<String>,
+    pci_bus_id: Option<String>,
+    cpu_affinity: Option<Vec<u32>>,
+    numa_nodes: Option<Vec<u32>>,
+}
+
+impl DeviceInfo {
+    fn of(gpu: &GpuStat) -> Self {
+        DeviceInfo {
+            uuid: gpu.uuid.clone(),
+            name: gpu.name.clone(),
+            brand: gpu.brand,
+            board: gpu.board.clone(),
+            pci_bus_id: gpu.pci_bus_id.clone(),
+            cpu_affinity: gpu.cpu_affinity.clone(),
+            numa_nodes: gpu.numa_nodes.clone(),
+        }
+    }
+
+    fn apply(&self, gpu: &mut GpuStat) {
+        gpu.brand = self.brand;
+        gpu.board = self.board.clone();
+        gpu.pci_bus_id = self.pci_bus_id.clone();
+        gpu.cpu_affinity = self.cpu_affinity.clone();
+        gpu.numa_nodes = self.numa_nodes.clone();
+    }
+}
+
+/// Static device properties between ticks, keyed by UUID. Everything is
+/// dropped when the device count or the driver version changes, and a new
+/// NVML instance starts with an empty cache.
+#[derive(Debug, Default)]
+pub struct DeviceCache {
+    /// UUID of each NVML index seen so far.
+    uuids: HashMap<u32, String>,
+    devices: HashMap<String, DeviceInfo>,
+    /// `--order` positions, `Some(None)` for NVML order.
+    positions: Option<Option<Vec<u32>>>,
+    device_count: u32,
+    driver_version: Option<String>,
+    /// The last walk of /proc for `--show-handles` and the ticks since.
+    handles: Option<(u32, HandleScan)>,
+}
+
+impl DeviceCache {
+    /// Static properties of the device at `index`, if already known.
+    pub fn get(&self, index: u32) -> Option<&DeviceInfo> {
+        self.devices.get(self.uuids.get(&index)?)
+    }
+
+    /// Forget everything when the devices or the driver differ from the
+    /// previous tick.
+    fn validate(&mut self, device_count: u32, driver_version: &Option<String>) {
+        if self.device_count == device_count && self.driver_version == *driver_version {
+            return;
+        }
+        if !self.uuids.is_empty() {
+            log::info!(
+                "device cache invalidated: {} devices, driver {}",
+                device_count,
+                driver_version.as_deref().unwrap_or("unknown")
+            );
+        }
+        *self = DeviceCache {
+            device_count,
+            driver_version: driver_version.clone(),
+            ..Default::default()
+        };
+    }
+
+    /// Device node holders, walking /proc again every `handles::SCAN_EVERY` calls.
+    fn handle_scan(&mut self) -> &HandleScan {
+        match &mut self.handles {
+            Some((age, _)) if *age + 1 < handles::SCAN_EVERY => *age += 1,
+            _ => self.handles = Some((0, handles::scan(Path::new("/proc")))),
+        }
+        &self.handles.as_ref().expect("scanned above").1
+    }
+
+    fn store(&mut self, gpus: &[GpuStat]) {
+        for gpu in gpus.iter().filter(|gpu| gpu.measured()) {
+            self.uuids.insert(gpu.index, gpu.uuid.clone());
+            self.devices
+                .entry(gpu.uuid.clone())
+                .or_insert_with(|| DeviceInfo::of(gpu));
+        }
+    }
+}
+
+/// A GPU that needs a reset before NVML can query it again.
+fn lost_gpu(index: u32) -> GpuStat {
+    GpuStat {
+        index,
+        reset_required: true,
+        ..Default::default()
+    }
+}
+
+/// A GPU left in D3cold at sysfs `bus_id`, with what the cache knows about it.
+fn suspended_gpu(index: u32, cached: Option<&DeviceInfo>, bus_id: &str) -> GpuStat {
+    GpuStat {
+        index,
+        suspended: true,
+        uuid: cached.map(|cached| cached.uuid.clone()).unwrap_or_default(),
+        name: cached.map_or_else(|| runtime_pm::device_name(bus_id), |cached| cached.name.clone()),
+        pci_bus_id: cached
+            .and_then(|cached| cached.pci_bus_id.clone())
+            .or_else(|| Some(runtime_pm::nvml_bus_id(bus_id))),
+        ..Default::default()
+    }
+}
+
+/// Query one device, or `None` when it is filtered out by `--pid`. With the
+/// `cached` properties of the device, only the changing ones are queried.
+fn collect_gpu(
+    opts: &Opts,
+    provider: &dyn Provider,
+    system: &System,
+    index: u32,
+    cached: Option<&DeviceInfo>,
+) -> Result<Option<GpuStat>, StatusError> {
+    let started = Some(Local::now()).filter(|_| opts.timing);
+    let failed = |query: &'static str| {
+        move |source| StatusError::NvmlQuery {
+            gpu: index,
+            query: query.to_string(),
+            source,
+        }
+    };
+    // even getting the handle would wake the device up
+    let bus_id = cached.and_then(|cached| cached.pci_bus_id.as_deref());
+    let suspended = (provider.local() && !opts.wake)
+        .then(|| runtime_pm::suspended(index, bus_id))
+        .flatten();
+    if let Some(bus_id) = suspended {
+        log::info!("gpu {}: runtime suspended, not queried", index);
+        return Ok(Some(suspended_gpu(index, cached, &bus_id)));
+    }
+    match provider.open(index) {
+        Err(NvmlError::ResetRequired) | Err(NvmlError::GpuLost) => return Ok(Some(lost_gpu(index))),
+        opened => opened.map_err(failed("device_by_index"))?,
+    }
+    let queries = DeviceQueries::new(provider, index);
+    let name = match cached {
+        Some(cached) => cached.name.clone(),
+        None => match queries.query("name", |device| device.name()) {
+            Err(NvmlError::ResetRequired) | Err(NvmlError::GpuLost) => return Ok(Some(lost_gpu(index))),
+            name => name.map_err(failed("name"))?,
+        },
+    };
+
+    let (compute, processes_denied) =
+        match queries.query("running_compute_processes", |device| device.running_compute_processes()) {
+            Err(NvmlError::ResetRequired) | Err(NvmlError::GpuLost) => return Ok(Some(lost_gpu(index))),
+            Err(NvmlError::NoPermission) => (vec![], true),
+            compute => (compute.map_err(failed("running_compute_processes"))?, false),
+        };
+    let graphics = queries
+        .query("running_graphics_processes", |device| {
+            device.running_graphics_processes()
+        })
+        .unwrap_or_default();
+    // joined with the process list before --pid filtering, unlike the other collectors
+    let sessions = if opts.show_proc_codec && !opts.collector_off("proc_codec") {
+        queries
+            .query("encoder_sessions", |device| device.encoder_sessions())
+            .unwrap_or_default()
+    } else {
+        vec![]
+    };
+    let mut processes = device_processes(opts, provider, system, compute, graphics, sessions);
+    if processes_denied && !opts.no_proc_scan && provider.local() {
+        match queries.query("minor_number", |device| device.minor_number()) {
+            Ok(minor) => {
+                for pid in scan_device_holders(minor) {
+                    if processes.iter().any(|p| p.pid == pid) {
+                        continue;
+                    }
+                    let info = ProcessInfo {
+                        pid,
+                        used_gpu_memory: UsedGpuMemory::Unavailable,
+                        gpu_instance_id: None,
+                        compute_instance_id: None,
+                    };
+                    let mut process = process_stat(opts, provider, system, &info, ProcessKind::Compute);
+                    process.scanned = true;
+                    processes.push(process);
+                }
+            }
+            Err(e) => log::info!("gpu {}: no /proc scan without the minor number: {}", index, e),
+        }
+    }
+    let all_processes = Some(ProcessCount::of(&processes)).filter(|_| opts.count_all);
+
+    if !opts.pids.is_empty() {
+        processes.retain(|p| pid_matches(system, p.pid, &opts.pids, opts.pid_children));
+        if processes.is_empty() {
+            return Ok(None);
+        }
+    }
+
+    let (memory, reserved) = queries.memory_info().map_err(failed("memory_info"))?;
+    let uuid = match cached {
+        Some(cached) => cached.uuid.clone(),
+        None => queries.query("uuid", |device| device.uuid()).map_err(failed("uuid"))?,
+    };
+    let mut gpu = GpuStat {
+        index,
+        uuid,
+        name,
+        processes_denied,
+        temperature: queries
+            .query("temperature", |device| device.temperature(TemperatureSensor::Gpu))
+            .map_err(failed("temperature"))?,
+        utilization: queries
+            .query("utilization_rates", |device| device.utilization_rates())
+            .map_err(failed("utilization_rates"))?
+            .gpu,
+        memory_used: memory.used,
+        memory_total: memory.total,
+        memory_free: memory.free,
+        memory_reserved: reserved,
+        power_usage: queries
+            .query("power_usage", |device| device.power_usage())
+            .map_err(failed("power_usage"))?,
+        power_limit: queries
+            .query("power_management_limit", |device| device.power_management_limit())
+            .map_err(failed("power_management_limit"))?,
+        processes,
+        all_processes,
+        collection_started: started,
+        ..Default::default()
+    };
+
+    if let Some(cached) = cached {
+        cached.apply(&mut gpu);
+    }
+    for collector in COLLECTORS.iter().filter(|collector| collector.wanted(opts)) {
+        if opts.collector_off(collector.name) {
+            log::info!("gpu {}: collector {} disabled", index, collector.name);
+        } else if collector.fixed && cached.is_some() {
+            log::trace!("gpu {}: collector {} cached", index, collector.name);
+        } else {
+            (collector.collect)(opts, &queries, &mut gpu).map_err(failed(collector.name))?;
+        }
+    }
+    // the brake halves the clocks with no other sign, so it is checked even without the throttle column
+    if !opts.collector_off("throttle") {
+        let reasons = match gpu.throttle_reasons {
+            Some(reasons) => Ok(reasons),
+            None => queries.query("current_throttle_reasons", |device| device.current_throttle_reasons()),
+        };
+        gpu.power_brake = reasons.is_ok_and(|reasons| reasons.contains(ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN));
+    }
+
+    match_processes(opts, &mut gpu);
+    gpu.validate();
+    gpu.query_failures = queries.take_failures();
+    gpu.collected_at = Some(Local::now());
+    for process in &mut gpu.processes {
+        process.collected_at = gpu.collected_at;
+    }
+    Ok(Some(gpu))
+}
+
+/// Query every device. Optional metrics are only collected when requested.
+pub fn collect(opts: &Opts, provider: &dyn Provider, hostname: &str) -> Result<Snapshot, StatusError> {
+    collect_cached(opts, provider, &mut DeviceCache::default(), hostname)
+}
+
+/// Like `collect`, but static device properties come from `cache` after the
+/// first call, unless `--no-cache`. Keep one cache per NVML instance.
+pub fn collect_cached(
+    opts: &Opts,
+    provider: &dyn Provider,
+    cache: &mut DeviceCache,
+    hostname: &str,
+) -> Result<Snapshot, StatusError> {
+    collect_with(opts, provider, cache, hostname, |system, indices, cache| {
+        indices
+            .iter()
+            .map(|&index| {
+                let gpu = collect_gpu(opts, provider, &system, index, cache.get(index));
+                (index, gpu.map_err(|e| e.failure()))
+            })
+            .collect()
+    })
+}
+
+/// Per-device results of one collection, in any order.
+type DeviceResults = Vec<(u32, Result<Option<GpuStat>, Failure>)>;
+
+/// One collection, asking `provider` for the device count and the driver
+/// and `devices` for each GPU.
+pub fn collect_with(
+    opts: &Opts,
+    provider: &dyn Provider,
+    cache: &mut DeviceCache,
+    hostname: &str,
+    devices: impl FnOnce(System, &[u32], &DeviceCache) -> DeviceResults,
+) -> Result<Snapshot, StatusError> {
+    let (timestamp, start) = (Local::now(), Instant::now());
+    let device_num = provider.device_count()?;
+    let driver_version = match provider.sys_driver_version() {
+        Err(NvmlError::LibRmVersionMismatch) => None,
+        version => Some(version?),
+    };
+    if opts.no_cache {
+        *cache = DeviceCache::default();
+    }
+    cache.validate(device_num, &driver_version);
+
+    let refresh_start = Instant::now();
+    let mut refresh = RefreshKind::new()
+        .with_processes(ProcessRefreshKind::new().with_user())
+        .with_users_list();
+    if opts.show_host_mem {
+        refresh = refresh.with_memory();
+    }
+    let system = System::new_with_specifics(refresh);
+    let host_memory = Some(HostMemory {
+        used: system.used_memory(),
+        total: system.total_memory(),
+        swap_used: system.used_swap(),
+        swap_total: system.total_swap(),
+    })
+    .filter(|_| opts.show_host_mem);
+    log::debug!(
+        "sysinfo refresh: {} processes ({:.2?})",
+        system.processes().len(),
+        refresh_start.elapsed()
+    );
+    // a fixture's daemon and driver are not this machine's
+    let persistenced_running = !provider.local()
+        || system
+            .processes()
+            .values()
+            .any(|process| process.name() == PERSISTENCED_COMM);
+    let driver_uptime = if opts.show_driver_uptime && provider.local() {
+        driver_uptime(&system)
+    } else {
+        None
+    };
+
+    let positions = match cache.positions.clone() {
+        Some(positions) => positions,
+        None => device_positions(opts.order, provider, device_num, opts.wake)?,
+    };
+    cache.positions = Some(positions.clone());
+
+    let mut results = devices(system, &device_indices(opts, device_num), cache);
+    results.sort_by_key(|(index, _)| *index);
+    let mut gpus = vec![];
+    for (index, result) in results {
+        match result {
+            Ok(Some(gpu)) => gpus.push(gpu),
+            Ok(None) => {}
+            Err(mut failure) => {
+                match failure.gpu {
+                    Some(_) => log::warn!("{}", failure.message),
+                    None => log::warn!("gpu {}: {}", index, failure.message),
+                }
+                failure.gpu = Some(index);
+                failure.uuid = cache.uuids.get(&index).cloned();
+                gpus.push(GpuStat {
+                    index,
+                    error: Some(failure.message.clone()),
+                    failure: Some(failure),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    if opts.column("xid") && provider.local() {
+        let xids = xid::last_xids();
+        for gpu in &mut gpus {
+            gpu.xid = match (&xids, &gpu.pci_bus_id) {
+                (Ok(xids), Some(bus_id)) => Some(Ok(xids.get(&xid::bus_key(bus_id)).cloned())),
+                (Err(e), _) => Some(Err(e.clone())),
+                (Ok(_), None) => None,
+            };
+        }
+    }
+    if gpus.iter().any(|gpu| gpu.minor_number.is_some()) && opts.column("handles") && provider.local() {
+        let scan = cache.handle_scan();
+        for gpu in &mut gpus {
+            gpu.handles = gpu.minor_number.map(|minor| scan.get(minor));
+        }
+    }
+    if let Some(positions) = positions {
+        for gpu in &mut gpus {
+            gpu.order_index = Some(positions[gpu.index as usize]);
+        }
+        gpus.sort_by_key(|gpu| gpu.order_index);
+    }
+    if gpus.iter().any(|gpu| gpu.suspended) {
+        // a suspended GPU has no compute capability or memory to sort by yet
+        cache.positions = None;
+    }
+    cache.store(&gpus);
+    let collection_duration_ms = start.elapsed().as_millis() as u64;
+    log::info!("collected {} GPUs in {} ms", gpus.len(), collection_duration_ms);
+
+    Ok(Snapshot {
+        schema_version: SCHEMA_VERSION,
+        hostname: hostname.to_string(),
+        timestamp,
+        collected_at: Some(Local::now()),
+        collection_duration_ms: Some(collection_duration_ms),
+        driver_version,
+        host_memory,
+        driver_uptime,
+        interval: None,
+        page: None,
+        busy: None,
+        permission_limit: permission_limit(&gpus),
+        persistenced_running,
+        container: None,
+        allocation: None,
+        chargeback: None,
+        consistency: None,
+        collection_errors: None,
+        legend: None,
+        reason: (device_num == 0).then(|| String::from(NO_DEVICES)),
+        reason_hint: Some(device_num).filter(|&count| count == 0).and_then(|_| no_devices_hint()),
+        time_suspect: clock_suspect(timestamp),
+        uptime_ms: uptime_ms(),
+        igpus: vec![],
+        cache_age_ms: None,
+        gpus,
+    })
+}
+
+/// NVML indices of the devices to query: those of `--id` that exist, or all.
+fn device_indices(opts: &Opts, device_num: u32) -> Vec<u32> {
+    if opts.ids.is_empty() {
+        return (0..device_num).collect();
+    }
+    for index in opts.ids.iter().filter(|&&index| index >= device_num) {
+        log::warn!("--id {}: no such GPU, found {}", index, device_num);
+    }
+    let mut indices: Vec<u32> = opts.ids.iter().copied().filter(|&index| index < device_num).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+}
+
+/// `--minimal`: only the utilization and memory of each device, without the
+/// process list, the sysinfo refresh, the driver version or the collectors.
+pub fn collect_minimal(opts: &Opts, provider: &dyn Provider) -> Result<Snapshot, StatusError> {
+    let (timestamp, start) = (Local::now(), Instant::now());
+    let mut gpus = vec![];
+    let device_num = provider.device_count()?;
+    for index in device_indices(opts, device_num) {
+        // the minimal rows show no name to look up
+        if provider.local() && runtime_pm::suspended(index, None).is_some() && !opts.wake {
+            gpus.push(GpuStat {
+                index,
+                suspended: true,
+                ..Default::default()
+            });
+            continue;
+        }
+        let failed = |query: &'static str| {
+            move |source| StatusError::NvmlQuery {
+                gpu: index,
+                query: query.to_string(),
+                source,
+            }
+        };
+        let queries = DeviceQueries::new(provider, index);
+        let gpu = provider.open(index).map_err(failed("device_by_index")).and_then(|()| {
+            let utilization = queries
+                .query("utilization_rates", |device| device.utilization_rates())
+                .map_err(failed("utilization_rates"))?;
+            let (memory, _) = queries.memory_info().map_err(failed("memory_info"))?;
+            Ok(GpuStat {
+                index,
+                utilization: utilization.gpu,
+                memory_used: memory.used,
+                memory_total: memory.total,
+                memory_free: memory.free,
+                ..Default::default()
+            })
+        });
+        gpus.push(match gpu {
+            Ok(gpu) => gpu,
+            Err(StatusError::NvmlQuery {
+                source: NvmlError::ResetRequired | NvmlError::GpuLost,
+                ..
+            }) => lost_gpu(index),
+            Err(e) => {
+                log::warn!("gpu {}: {}", index, e);
+                GpuStat {
+                    index,
+                    error: Some(e.to_string()),
+                    failure: Some(e.failure()),
+                    ..Default::default()
+                }
+            }
+        });
+    }
+    let collection_duration_ms = start.elapsed().as_millis() as u64;
+    log::info!("collected {} GPUs in {} ms", gpus.len(), collection_duration_ms);
+    Ok(Snapshot {
+        schema_version: SCHEMA_VERSION,
+        timestamp,
+        collected_at: Some(Local::now()),
+        collection_duration_ms: Some(collection_duration_ms),
+        time_suspect: clock_suspect(timestamp),
+        uptime_ms: uptime_ms(),
+        gpus,
+        reason: (device_num == 0).then(|| String::from(NO_DEVICES)),
+        reason_hint: Some(device_num).filter(|&count| count == 0).and_then(|_| no_devices_hint()),
+        ..Default::default()
+    })
+}
+
+/// How far before the build time the wall clock may be before it counts as
+/// unset, for builds on a machine whose clock ran ahead.
+const CLOCK_MARGIN: i64 = 24 * 60 * 60;
+
+/// Whether `now` predates this binary's build, as on diskless nodes that boot
+/// with the clock at the epoch until NTP syncs.
+pub fn clock_suspect(now: DateTime<Local>) -> bool {
+    let built: i64 = env!("GPUSTAT_BUILD_EPOCH").parse().unwrap_or_default();
+    before_build(now.timestamp(), built)
+}
+
+/// Whether the Unix time `now` lies more than `CLOCK_MARGIN` before `built`.
+fn before_build(now: i64, built: i64) -> bool {
+    now < built - CLOCK_MARGIN
+}
+
+/// `Snapshot::reason` of a driver that lists no GPU, as on a machine without
+/// one or in a container started without any.
+pub const NO_DEVICES: &str = "no_devices";
+
+/// What the `/dev/nvidiaN` nodes tell of a driver that lists no GPU.
+fn no_devices_hint() -> Option<String> {
+    no_devices_hint_in(Path::new("/dev"), crate::doctor::container_runtime())
+}
+
+/// The hint of the `nvidiaN` nodes under `dev` in a `runtime` container:
+/// none at all in a container means it was started without GPUs, and nodes
+/// that cannot be opened mean the device cgroup denies them.
+fn no_devices_hint_in(dev: &Path, runtime: Option<&str>) -> Option<String> {
+    let mut nodes: Vec<std::path::PathBuf> = std::fs::read_dir(dev)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let minor = name.to_str().and_then(|name| name.strip_prefix("nvidia"));
+            minor.is_some_and(|minor| !minor.is_empty() && minor.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .map(|entry| entry.path())
+        .collect();
+    nodes.sort();
+    if nodes.is_empty() {
+        return Some(match runtime {
+            Some(runtime) => format!(
+                "this {} container has no /dev/nvidiaN device, start it with the GPUs, e.g. docker run --gpus all",
+                runtime
+            ),
+            None => String::from("there is no /dev/nvidiaN device, check that the GPU shows in lspci -d 10de:"),
+        });
+    }
+    let denied = nodes.iter().all(|node| {
+        let open = std::fs::File::open(node);
+        matches!(open, Err(e) if e.raw_os_error() == Some(nix::errno::Errno::EPERM as i32))
+    });
+    let node = nodes[0].display();
+    match runtime {
+        Some(runtime) if denied => Some(format!("the device cgroup of this {} container denies {}", runtime, node)),
+        None if denied => Some(format!("the device cgroup denies {}", node)),
+        _ => None,
+    }
+}
+
+/// Milliseconds on `CLOCK_BOOTTIME`, which NTP never steps back.
+pub fn uptime_ms() -> Option<u64> {
+    let now = nix::time::clock_gettime(nix::time::ClockId::CLOCK_BOOTTIME).ok()?;
+    Some(now.tv_sec() as u64 * 1000 + now.tv_nsec() as u64 / 1_000_000)
+}
+
+/// Milliseconds between two `uptime_ms` readings whatever the wall clock
+/// did meanwhile; `None` when `later` is smaller, as across a reboot.
+pub fn uptime_between(earlier: u64, later: u64) -> Option<u64> {
+    later.checked_sub(earlier)
+}
+
+/// Command name of nvidia-persistenced, which the kernel truncates to 15 characters.
+const PERSISTENCED_COMM: &str = "nvidia-persiste";
+
+/// Uptime of nvidia-persistenced. It keeps the devices open, so the kernel
+/// module cannot have been reloaded since it started; the driver itself was
+/// loaded at most moments before it.
+fn driver_uptime(system: &System) -> Option<u64> {
+    let daemon = system
+        .processes()
+        .values()
+        .filter(|process| process.name() == PERSISTENCED_COMM)
+        .map(|process| process.start_time())
+        .min()?;
+    let now = u64::try_from(Utc::now().timestamp()).ok()?;
+    Some(now.saturating_sub(daemon))
+}
+
+/// `hidepid` mount option of /proc, which hides other users' processes from non-root users.
+pub fn proc_hidepid() -> Option<String> {
+    std::fs::read_to_string("/proc/mounts")
+        .ok()?
+        .lines()
+        .filter(|line| line.split(' ').nth(1) == Some("/proc"))
+        .find_map(|line| {
+            line.split(' ')
+                .nth(3)?
+                .split(',')
+                .find(|option| option.starts_with("hidepid=") && *option != "hidepid=0" && *option != "hidepid=off")
+                .map(str::to_string)
+        })
+}
+
+/// Count what permissions kept hidden, with a hint matching the likely cause.
+fn permission_limit(gpus: &[GpuStat]) -> Option<PermissionLimit> {
+    let unresolved = gpus.iter().flat_map(|gpu| &gpu.processes).filter(|p| !p.resolved).count();
+    let denied = gpus.iter().filter(|gpu| gpu.processes_denied).count();
+    let hidden = (unresolved + denied) as u32;
+    if hidden == 0 {
+        return None;
+    }
+    let hint = if nix::unistd::Uid::effective().is_root() {
+        // root still cannot see into other PID namespaces
+        "run on the host for full process details"
+    } else if let Some(option) = proc_hidepid() {
+        log::info!("/proc is mounted with {}", option);
+        "run as root or remount /proc without hidepid for full process details"
+    } else {
+        "run as root for full process details"
+    };
+    Some(PermissionLimit {
+        hidden,
+        hint: hint.to_string(),
+    })
+}
+
+/// Error of a device that did not answer within `--query-timeout`.
+pub const QUERY_TIMEOUT: &str = "timeout";
+
+/// Collection for `--query-timeout`: every device is queried on its own worker
+/// thread, and devices that miss the deadline are reported as timed out while
+/// their worker is left to finish in the background.
+#[derive(Debug)]
+pub struct TimedCollector {
+    timeout: Duration,
+    /// Set while a device's worker is running, so a hung device is not queried again.
+    busy: HashMap<u32, Arc<AtomicBool>>,
+    /// Timed out queries per device, logged with `--verbose`.
+    slow: HashMap<u32, u64>,
+    cache: DeviceCache,
+}
+
+impl TimedCollector {
+    pub fn new(timeout: Duration) -> Self {
+        TimedCollector {
+            timeout,
+            busy: HashMap::new(),
+            slow: HashMap::new(),
+            cache: DeviceCache::default(),
+        }
+    }
+
+    pub fn collect(
+        &mut self,
+        opts: &Arc<Opts>,
+        provider: &Arc<dyn Provider>,
+        hostname: &str,
+    ) -> Result<Snapshot, StatusError> {
+        let TimedCollector {
+            timeout,
+            busy: running,
+            slow: timeouts,
+            cache,
+        } = self;
+        collect_with(opts, provider.as_ref(), cache, hostname, |system, indices, cache| {
+            let system = Arc::new(system);
+            let deadline = Instant::now() + *timeout;
+            let (sender, receiver) = mpsc::channel();
+            let mut results = DeviceResults::new();
+            let mut pending = 0;
+            for &index in indices {
+                let busy = running.entry(index).or_default();
+                if busy.swap(true, Ordering::SeqCst) {
+                    continue;
+                }
+                let busy = Arc::clone(busy);
+                let (opts, provider, system, sender) = (
+                    Arc::clone(opts),
+                    Arc::clone(provider),
+                    Arc::clone(&system),
+                    sender.clone(),
+                );
+                let cached = cache.get(index).cloned();
+                pending += 1;
+                thread::spawn(move || {
+                    let result =
+                        collect_gpu(&opts, provider.as_ref(), &system, index, cached.as_ref()).map_err(|e| e.failure());
+                    busy.store(false, Ordering::SeqCst);
+                    let _ = sender.send((index, result));
+                });
+            }
+            while pending > 0 {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match receiver.recv_timeout(remaining) {
+                    Ok(result) => {
+                        results.push(result);
+                        pending -= 1;
+                    }
+                    Err(_) => break,
+                }
+            }
+            for &index in indices {
+                if results.iter().all(|(i, _)| *i != index) {
+                    let slow = timeouts.entry(index).or_default();
+                    *slow += 1;
+                    log::info!(
+                        "gpu {}: no answer within {:.2?}, {} slow queries so far",
+                        index,
+                        *timeout,
+                        slow
+                    );
+                    let failure = Failure {
+                        kind: String::from("Timeout"),
+                        message: String::from(QUERY_TIMEOUT),
+                        ..Default::default()
+                    };
+                    results.push((index, Err(failure)));
+                }
+            }
+            results
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{
+        tests::{fixtures, gpu},
+        FixtureProvider,
+    };
+    use clap::Parser;
+    use serde_json::json;
+
+    fn opts(args: &[&str]) -> Opts {
+        Opts::try_parse_from(["gpustat"].iter().chain(args)).unwrap()
+    }
+
+    fn fan_failure(gpu: &GpuStat) -> Option<&str> {
+        gpu.query_failures.get("fan_speed").map(String::as_str)
+    }
+
+    fn consumer() -> FixtureProvider {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/consumer.json");
+        FixtureProvider::load(&path).unwrap()
+    }
+
+    #[test]
+    fn collection_types_gpus_and_processes() {
+        let snapshot = collect(&opts(&[]), &consumer(), "workstation").unwrap();
+        assert_eq!(snapshot.driver_version.as_deref(), Some("550.54.14"));
+        let gpu = &snapshot.gpus[0];
+        assert_eq!(
+            (gpu.name.as_str(), gpu.temperature, gpu.utilization),
+            ("NVIDIA GeForce RTX 4090", 61, 87)
+        );
+        assert_eq!((gpu.power_usage, gpu.power_limit), (342000, 450000));
+        let processes: Vec<_> = gpu
+            .processes
+            .iter()
+            .map(|p| (p.pid, p.user.as_str(), p.command.as_str(), p.kind, p.used_memory))
+            .collect();
+        assert_eq!(
+            processes,
+            [
+                (4194305, "alice", "python", ProcessKind::Compute, Some(15032385536)),
+                (4194306, "root", "Xorg", ProcessKind::Graphics, Some(419430400)),
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn holders_are_told_by_their_descriptors() {
+        // the test runs without any NVIDIA device open
+        assert_eq!(holds_gpu(std::process::id()), Some(false));
+        assert_eq!(holds_gpu(u32::MAX), None);
+    }
+
+    #[test]
+    fn snapshots_round_trip_through_json() {
+        let snapshot = collect(&opts(&["-a"]), &consumer(), "workstation").unwrap();
+        let json = snapshot.to_json().unwrap();
+        let parsed = Snapshot::from_json(&json).unwrap();
+        assert_eq!(parsed.to_json().unwrap(), json);
+        // the last line of a recording, and what an older version wrote
+        let older = r#"{"schema_version":1,"hostname":"old","timestamp":"2026-01-02T03:04:05+00:00","gpus":[]}"#;
+        assert_eq!(
+            Snapshot::from_jsonl(&format!("{}\n{}\n\n", json, older))
+                .unwrap()
+                .hostname,
+            "old"
+        );
+        let unversioned = older.replace(r#""schema_version":1"#, r#""schema_version":0"#);
+        assert!(matches!(
+            Snapshot::from_json(&unversioned),
+            Err(StatusError::SchemaVersion(0))
+        ));
+    }
+
+    #[test]
+    fn the_schema_describes_every_field() {
+        let schema: serde_json::Value = serde_json::from_str(crate::SNAPSHOT_SCHEMA).unwrap();
+        let snapshot = collect(&opts(&["-a"]), &consumer(), "workstation").unwrap();
+        let json = serde_json::to_value(&snapshot).unwrap();
+        let undescribed = |value: &serde_json::Value, properties: &serde_json::Value| -> Vec<String> {
+            let fields = value.as_object().unwrap().keys();
+            fields
+                .filter(|field| properties.get(field.as_str()).is_none())
+                .cloned()
+                .collect()
+        };
+        assert_eq!(undescribed(&json, &schema["properties"]), Vec::<String>::new());
+        let gpu = &json["gpus"][0];
+        assert_eq!(
+            undescribed(gpu, &schema["$defs"]["gpu"]["properties"]),
+            Vec::<String>::new()
+        );
+        let process = &gpu["processes"][0];
+        assert_eq!(
+            undescribed(process, &schema["$defs"]["process"]["properties"]),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn throttle_shares_cover_the_window() {
+        let mut history = HistoryStore::new(4, None, None);
+        let snapshot = |reasons, driver: &str| Snapshot {
+            driver_version: Some(driver.to_string()),
+            gpus: vec![GpuStat {
+                uuid: String::from("GPU-0"),
+                throttle_reasons: Some(reasons),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let power = ThrottleReasons::SW_POWER_CAP;
+        let mut last = Snapshot::default();
+        for reasons in [
+            power,
+            power | ThrottleReasons::HW_SLOWDOWN,
+            ThrottleReasons::GPU_IDLE,
+            power,
+            power,
+        ] {
+            last = snapshot(reasons, "550.54.14");
+            history.tick(&last);
+            history.observe_throttle(&mut last);
+        }
+        // the first sample left the window of four
+        let shares = &last.gpus[0].throttle_percent;
+        assert_eq!(shares.get("SwPower"), Some(&75));
+        assert_eq!(shares.get("HwSlowdown"), Some(&25));
+        assert_eq!(shares.len(), 2);
+        // a reloaded driver starts over
+        let mut reloaded = snapshot(ThrottleReasons::HW_SLOWDOWN, "550.90.07");
+        history.tick(&reloaded);
+        history.observe_throttle(&mut reloaded);
+        assert_eq!(reloaded.gpus[0].throttle_percent.get("HwSlowdown"), Some(&100));
+        assert_eq!(reloaded.gpus[0].throttle_percent.get("SwPower"), None);
+    }
+
+    #[test]
+    fn history_is_bounded_and_forgets_unplugged_gpus() {
+        let mut history = HistoryStore::new(4, None, None);
+        let snapshot = |uuids: &[&str], power: u32| Snapshot {
+            gpus: uuids
+                .iter()
+                .map(|uuid| GpuStat {
+                    uuid: uuid.to_string(),
+                    power_usage: power,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        for tick in 0..POWER_WINDOW as u32 * 3 {
+            let mut frame = snapshot(&["GPU-0", "GPU-1"], tick * 1000);
+            history.tick(&frame);
+            history.observe_power(&mut frame);
+        }
+        assert!(history
+            .footprint()
+            .starts_with("history: 2 GPUs, 20 power and 0 throttle samples"));
+        // the average only covers the window, the peak the whole session
+        let mut frame = snapshot(&["GPU-0"], 0);
+        history.tick(&frame);
+        history.observe_power(&mut frame);
+        assert_eq!(frame.gpus[0].power_average, Some(22_500));
+        assert_eq!(frame.gpus[0].power_peak, Some(29_000));
+
+        for _ in 1..HISTORY_EVICT_TICKS {
+            history.tick(&snapshot(&["GPU-0"], 0));
+        }
+        assert!(
+            history.footprint().starts_with("history: 1 GPUs"),
+            "{}",
+            history.footprint()
+        );
+        assert_eq!(history.peaks().keys().collect::<Vec<_>>(), ["GPU-0"]);
+    }
+
+    #[test]
+    fn contexts_of_one_pid_are_merged() {
+        let context = |used: u64| json!({ "pid": 10, "used_gpu_memory": { "Used": used }, "gpu_instance_id": null, "compute_instance_id": null });
+        let mut device = gpu(0);
+        device["running_compute_processes"] = json!([context(1 << 30), context(2 << 30)]);
+        device["running_graphics_processes"] = json!([context(1 << 20)]);
+        let snapshot = collect(&opts(&["--show-contexts"]), &fixtures(vec![device]), "node").unwrap();
+        let processes = &snapshot.gpus[0].processes;
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].contexts, 2);
+        assert_eq!(processes[0].used_memory, Some(3 << 30));
+        assert_eq!(processes[0].kind, ProcessKind::Mixed);
+    }
+
+    #[test]
+    fn empty_process_lists_of_busy_gpus_are_explained() {
+        let process = json!({ "pid": 10, "used_gpu_memory": { "Used": 1 << 30 }, "gpu_instance_id": null, "compute_instance_id": null });
+        let cases = [
+            (95, json!([process]), None),
+            (0, json!([process]), None),
+            (95, json!([]), Some(MissingProcesses::NotVisible)),
+            (0, json!([]), None),
+            (95, json!({ "error": "NoPermission" }), Some(MissingProcesses::Denied)),
+            (0, json!({ "error": "NoPermission" }), Some(MissingProcesses::Denied)),
+        ];
+        for (utilization, processes, expected) in cases {
+            let mut device = gpu(0);
+            device["utilization_rates"]["gpu"] = json!(utilization);
+            device["running_compute_processes"] = processes.clone();
+            let snapshot = collect(&opts(&[]), &fixtures(vec![device]), "node").unwrap();
+            let missing = snapshot.gpus[0].missing_processes(10);
+            assert_eq!(missing, expected, "{}% with {}", utilization, processes);
+        }
+    }
+
+    #[test]
+    fn denied_process_lists_count_as_hidden() {
+        let mut denied = gpu(1);
+        denied["running_compute_processes"] = json!({ "error": "NoPermission" });
+        let snapshot = collect(&opts(&[]), &fixtures(vec![gpu(0), denied]), "node").unwrap();
+        assert!(!snapshot.gpus[0].processes_denied && snapshot.gpus[1].processes_denied);
+        assert!(snapshot.gpus[1].error.is_none());
+        let limit = snapshot.permission_limit.unwrap();
+        assert_eq!(limit.hidden, 1);
+        assert!(limit.hint.ends_with(" for full process details"));
+        let snapshot = collect(&opts(&[]), &fixtures(vec![gpu(0)]), "node").unwrap();
+        assert!(snapshot.permission_limit.is_none());
+    }
+
+    #[test]
+    fn window_maxima_skip_glitched_samples() {
+        let sample = |timestamp: u64, value: u32| json!({ "timestamp": timestamp, "value": { "U32": value } });
+        let mut sampled = gpu(0);
+        sampled["power_samples"] = json!([sample(1, 300000), sample(2, 310000)]);
+        sampled["utilization_samples"] = json!([sample(1, 95), sample(2, 4000)]);
+        let provider = fixtures(vec![sampled, gpu(1)]);
+        let snapshot = collect(&opts(&["-i", "1", "--window-max"]), &provider, "node").unwrap();
+        let window = snapshot.gpus[0].window_max.unwrap();
+        assert_eq!((window.power, window.utilization), (Some(310000), Some(95)));
+        // no samples at all
+        assert!(snapshot.gpus[1].window_max.is_none());
+        assert!(collect(&opts(&["-i", "1"]), &provider, "node").unwrap().gpus[0]
+            .window_max
+            .is_none());
+    }
+
+    #[test]
+    fn empty_gpu_lists_carry_a_reason() {
+        let snapshot = collect(&opts(&[]), &fixtures(vec![]), "node").unwrap();
+        assert!(snapshot.gpus.is_empty());
+        assert_eq!(snapshot.reason.as_deref(), Some(NO_DEVICES));
+        let snapshot = collect(&opts(&[]), &fixtures(vec![gpu(0)]), "node").unwrap();
+        assert_eq!((snapshot.reason, snapshot.reason_hint), (None, None));
+    }
+
+    #[test]
+    fn device_nodes_hint_why_no_gpu_shows() {
+        let dev = std::env::temp_dir().join(format!("gpustat-dev-{}", std::process::id()));
+        std::fs::create_dir_all(&dev).unwrap();
+        // the control nodes are no GPU
+        std::fs::write(dev.join("nvidiactl"), "").unwrap();
+        std::fs::write(dev.join("nvidia-uvm"), "").unwrap();
+        let hint = no_devices_hint_in(&dev, Some("docker")).unwrap();
+        assert!(
+            hint.starts_with("this docker container has no /dev/nvidiaN device"),
+            "{}",
+            hint
+        );
+        let hint = no_devices_hint_in(&dev, None).unwrap();
+        assert!(hint.contains("lspci -d 10de:"), "{}", hint);
+        // nodes that open tell nothing more
+        std::fs::write(dev.join("nvidia0"), "").unwrap();
+        assert_eq!(no_devices_hint_in(&dev, Some("docker")), None);
+        std::fs::remove_dir_all(&dev).unwrap();
+    }
+
+    #[test]
+    fn long_command_lines_are_cut_with_a_mark() {
+        let cmd = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let python = cmd(&["python", "-c", "pass"]);
+        assert_eq!(command_line(&python, 14), (String::from("python -c pass"), false));
+        assert_eq!(
+            command_line(&python, 9),
+            (String::from("python -c [+5B truncated]"), true)
+        );
+        assert_eq!(
+            command_line(&python, 8),
+            (String::from("python - [+6B truncated]"), true)
+        );
+        // the cut backs off to a character boundary
+        let accented = cmd(&["echo", "ééé"]);
+        assert_eq!(
+            command_line(&accented, 8),
+            (String::from("echo é [+4B truncated]"), true)
+        );
+        let config = cmd(&["python", "-c", &"x".repeat(2 << 20)]);
+        let (line, cut) = command_line(&config, 4096);
+        assert!(cut && line.ends_with(" [+2.0MB truncated]"), "{}", &line[4000..]);
+        assert_eq!(line.len(), 4096 + " [+2.0MB truncated]".len());
+        assert_eq!(opts(&["--max-cmd-length", "3M"]).max_cmd_length, 3 << 20);
+        assert!(Opts::try_parse_from(["gpustat", "--max-cmd-length", "0"]).is_err());
+    }
+
+    #[test]
+    fn oom_hints_follow_unregrown_falls() {
+        const GIB: u64 = 1 << 30;
+        /// The hint of each tick for a process using `used` GiB in turn, on an 80 GiB GPU.
+        fn hints(used: &[u64], free: u64) -> Vec<Option<(u64, u64)>> {
+            let mut history = HistoryStore::new(4, None, None);
+            used.iter()
+                .map(|&used| {
+                    let mut snapshot = Snapshot {
+                        gpus: vec![GpuStat {
+                            uuid: String::from("GPU-0"),
+                            memory_total: 80 * GIB,
+                            memory_free: free * GIB,
+                            processes: vec![ProcessStat {
+                                pid: 4242,
+                                used_memory: Some(used * GIB),
+                                ..Default::default()
+                            }],
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    };
+                    history.tick(&snapshot);
+                    history.observe_oom(&mut snapshot);
+                    snapshot.gpus[0]
+                        .oom_hint
+                        .map(|hint| (hint.before / GIB, hint.after / GIB))
+                })
+                .collect()
+        }
+        // flagged from the tick after the fall
+        assert_eq!(hints(&[68, 31, 31], 40), [None, None, Some((68, 31))]);
+        // regrown, a small dip, no room, and a growing job
+        assert_eq!(hints(&[68, 31, 66, 66], 40), [None; 4]);
+        assert_eq!(hints(&[68, 60, 60], 40), [None; 3]);
+        assert_eq!(hints(&[68, 31, 31], 4), [None; 3]);
+        assert_eq!(hints(&[10, 20, 40], 40), [None; 3]);
+        // a second fall is measured from the first level
+        assert_eq!(hints(&[68, 40, 20, 20], 40), [None, None, None, Some((68, 20))]);
+    }
+
+    #[test]
+    fn redacted_commands_keep_the_executable_name() {
+        let redacted = |args: &[&str], user: &str, command: &str| {
+            let mut process = ProcessStat {
+                user: user.to_string(),
+                full_command: command.to_string(),
+                ..Default::default()
+            };
+            redact_args(&opts(args), &mut process);
+            (process.full_command, process.redacted)
+        };
+        let all = ["--redact-args=all"];
+        assert_eq!(
+            redacted(&all, "root", "/usr/bin/python train.py --token s3cret"),
+            (String::from("python [redacted]"), true)
+        );
+        assert_eq!(
+            redacted(&all, "root", "/usr/bin/python"),
+            (String::from("python"), true)
+        );
+        assert_eq!(redacted(&all, "root", ""), (String::new(), false));
+        let command = "python train.py --token s3cret";
+        assert_eq!(redacted(&[], "nobody", command), (command.to_string(), false));
+        // root sees every command, anyone else only their own
+        let others = redacted(&["--redact-args=others"], "someone-unknown", command);
+        assert_eq!(others.1, !Uid::current().is_root());
+    }
+
+    #[test]
+    fn the_power_brake_is_checked_without_the_throttle_column() {
+        let mut braked = gpu(1);
+        braked["current_throttle_reasons"] = json!("SW_POWER_CAP | HW_POWER_BRAKE_SLOWDOWN");
+        let provider = fixtures(vec![gpu(0), braked]);
+        let snapshot = collect(&opts(&[]), &provider, "node").unwrap();
+        assert!(!snapshot.gpus[0].power_brake);
+        assert!(snapshot.gpus[1].power_brake && snapshot.gpus[1].critical());
+        assert!(snapshot.gpus[1].throttle_reasons.is_none());
+        let released = GpuStat {
+            power_brake: false,
+            ..snapshot.gpus[1].clone()
+        };
+        assert!(snapshot.gpus[1].changed_from(&released, &ChangeThreshold::default()));
+        assert!(!released.changed_from(&released, &ChangeThreshold::default()));
+        let snapshot = collect(&opts(&["--show-throttle"]), &provider, "node").unwrap();
+        assert!(snapshot.gpus[1].power_brake);
+        let snapshot = collect(&opts(&["--disable-collector", "throttle"]), &provider, "node").unwrap();
+        assert!(!snapshot.gpus[1].power_brake);
+    }
+
+    #[test]
+    fn failed_gpus_carry_a_structured_failure() {
+        let mut hot = gpu(1);
+        hot["temperature"] = json!({ "error": "Unknown" });
+        let snapshot = collect(&opts(&[]), &fixtures(vec![gpu(0), hot]), "node").unwrap();
+        assert!(snapshot.gpus[0].failure.is_none());
+        let failure = snapshot.gpus[1].failure.as_ref().unwrap();
+        assert_eq!(failure.kind, "NvmlQuery");
+        assert_eq!(failure.gpu, Some(1));
+        assert_eq!(failure.query.as_deref(), Some("temperature"));
+        assert_eq!(failure.code.as_deref(), Some("Unknown"));
+        assert!(
+            failure.message.starts_with("Querying temperature of GPU 1 failed: "),
+            "{}",
+            failure.message
+        );
+        assert_eq!(snapshot.gpus[1].error.as_ref(), Some(&failure.message));
+    }
+
+    #[test]
+    fn errors_map_to_their_kind_and_exit_code() {
+        let query = StatusError::NvmlQuery {
+            gpu: 3,
+            query: String::from("fan"),
+            source: NvmlError::NotSupported,
+        };
+        let failure = query.failure();
+        assert_eq!(
+            (failure.kind.as_str(), failure.code.as_deref()),
+            ("NvmlQuery", Some("NotSupported"))
+        );
+        assert_eq!(crate::exit_code(&query), crate::EXIT_DEGRADED);
+        let init = StatusError::NvmlInitError(NvmlError::DriverNotLoaded);
+        assert_eq!(init.failure().code.as_deref(), Some("DriverNotLoaded"));
+        assert_eq!(crate::exit_code(&init), crate::EXIT_NVML_INIT);
+        let json = StatusError::SchemaVersion(9);
+        assert_eq!((json.kind(), json.failure().code), ("SchemaVersion", None));
+        assert_eq!(crate::exit_code(&json), crate::EXIT_USAGE);
+    }
+
+    #[test]
+    fn query_failures_stay_with_their_gpu() {
+        let mut fan = gpu(0);
+        fan["fan_speed"] = json!(40);
+        let provider = fixtures(vec![fan, gpu(1)]);
+        let snapshot = collect(&opts(&["-F"]), &provider, "node").unwrap();
+        assert_eq!(fan_failure(&snapshot.gpus[0]), None);
+        assert_eq!(fan_failure(&snapshot.gpus[1]), Some("NotSupported"));
+        // the next collection starts without them
+        let snapshot = collect(&opts(&[]), &provider, "node").unwrap();
+        assert!(snapshot.gpus.iter().all(|gpu| fan_failure(gpu).is_none()));
+    }
+
+    #[test]
+    fn memory_falls_back_to_v1() {
+        let mut old = gpu(0);
+        old["memory_info"] = json!({ "error": "FunctionNotFound" });
+        old["memory_info(v1)"] = json!({ "total": 25769803776_u64, "free": 25000000000_u64, "used": 769803776 });
+        let mut lost = gpu(1);
+        lost["memory_info"] = json!({ "error": "NotSupported" });
+        let snapshot = collect(&opts(&[]), &fixtures(vec![old, gpu(1), lost]), "node").unwrap();
+        let old = &snapshot.gpus[0];
+        assert_eq!(
+            (old.memory_used, old.memory_total, old.memory_reserved),
+            (769803776, 25769803776, None)
+        );
+        assert!(!old.query_failures.contains_key("memory_info"));
+        assert_eq!(snapshot.gpus[1].memory_reserved, Some(0));
+        // without the v1 answer either the GPU cannot be collected
+        assert!(snapshot.gpus[2].error.is_some());
+    }
+
+    #[test]
+    fn vgpus_carry_their_type() {
+        let mut host = gpu(0);
+        host["virtualization_mode"] = json!("HostVgpu");
+        host["active_vgpus"] = json!([3, 4]);
+        host["vgpu_type(3)"] = json!(["GRID A100-4C", 4294967296_u64]);
+        let snapshot = collect(&opts(&["--show-virt"]), &fixtures(vec![host]), "node").unwrap();
+        let vgpus = &snapshot.gpus[0].vgpus;
+        assert_eq!(vgpus[0].type_name.as_deref(), Some("GRID A100-4C"));
+        assert_eq!(vgpus[0].framebuffer, Some(4294967296));
+        assert_eq!(
+            vgpus[1],
+            VgpuStat {
+                id: 4,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn matches_are_counted_before_match_only() {
+        let process = |pid, command: &str| ProcessStat {
+            pid,
+            full_command: command.to_string(),
+            ..Default::default()
+        };
+        let mut gpu = GpuStat {
+            processes: vec![
+                process(1, "python train.py"),
+                process(2, "jupyter lab"),
+                process(3, "python Finetune.py"),
+            ],
+            ..Default::default()
+        };
+        gpu.processes.push(ProcessStat {
+            stale: true,
+            ..process(4, "python train.py")
+        });
+        match_processes(&opts(&["--match", r"train\.py|finetune", "--match-only"]), &mut gpu);
+        assert_eq!(gpu.process_match, Some(ProcessMatch { matched: 2, total: 3 }));
+        assert_eq!(gpu.processes.iter().map(|p| p.pid).collect::<Vec<_>>(), [1, 3]);
+        match_processes(&opts(&["--match", "finetune", "--case-sensitive"]), &mut gpu);
+        assert_eq!(gpu.process_match, Some(ProcessMatch { matched: 0, total: 2 }));
+    }
+
+    #[test]
+    fn matching_takes_linear_time() {
+        let mut gpu = GpuStat {
+            processes: vec![
+                ProcessStat {
+                    full_command: "a".repeat(24),
+                    ..Default::default()
+                },
+                ProcessStat {
+                    full_command: format!("python -c {}", "x".repeat(100_000)),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        // a backtracker tries every split of the a's between the two stars
+        let started = Instant::now();
+        match_processes(&opts(&["--match", "(a*)*b"]), &mut gpu);
+        assert_eq!(gpu.process_match, Some(ProcessMatch { matched: 0, total: 2 }));
+        // a matcher recursing per character overflows the stack here
+        match_processes(&opts(&["--match", "^python -c (x|y)+$"]), &mut gpu);
+        assert_eq!(gpu.process_match, Some(ProcessMatch { matched: 1, total: 2 }));
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+    }
+
+    #[test]
+    fn timed_workers_keep_their_failures() {
+        let provider: Arc<dyn Provider> = Arc::new(fixtures(vec![gpu(0), gpu(1)]));
+        let mut timed = TimedCollector::new(Duration::from_secs(10));
+        let snapshot = timed.collect(&Arc::new(opts(&["-F"])), &provider, "node").unwrap();
+        assert!(snapshot.gpus.iter().all(|gpu| fan_failure(gpu) == Some("NotSupported")));
+        let snapshot = timed.collect(&Arc::new(opts(&[])), &provider, "node").unwrap();
+        assert!(snapshot.gpus.iter().all(|gpu| fan_failure(gpu).is_none()));
+    }
+
+    #[test]
+    fn clocks_before_the_build_are_suspect() {
+        let built = 1_760_000_000;
+        // booted at the epoch
+        assert!(before_build(0, built));
+        assert!(before_build(built - CLOCK_MARGIN - 1, built));
+        // built on a machine whose clock ran ahead by up to a day
+        assert!(!before_build(built - CLOCK_MARGIN, built));
+        assert!(!before_build(built + 60, built));
+        assert!(!clock_suspect(Local::now()));
+    }
+
+    #[test]
+    fn uptime_orders_samples_whatever_the_wall_clock_does() {
+        use crate::snapshot_cache::Staleness;
+        let ttl = Duration::from_secs(2);
+        let sample = |timestamp: DateTime<Local>, uptime_ms: u64| Snapshot {
+            timestamp,
+            uptime_ms: Some(uptime_ms),
+            ..Snapshot::default()
+        };
+        let age = |earlier: &Snapshot, later: &Snapshot| uptime_between(earlier.uptime_ms?, later.uptime_ms?);
+        let start = sample(Local::now(), 60_000);
+        // a normal tick
+        let tick = sample(start.timestamp + chrono::Duration::seconds(1), 61_000);
+        assert_eq!(age(&start, &tick), Some(1_000));
+        assert_eq!(Staleness::of(1_000, ttl), Staleness::Fresh);
+        // NTP stepping the wall clock back by an hour
+        let back = sample(start.timestamp - chrono::Duration::hours(1), 61_000);
+        assert_eq!(age(&start, &back), Some(1_000));
+        // the wall clock leaping from the epoch to the time of day
+        let forward = sample(start.timestamp, 61_000);
+        assert_eq!(
+            age(&sample(DateTime::<Local>::from(DateTime::UNIX_EPOCH), 60_000), &forward),
+            Some(1_000)
+        );
+        // the uptime itself jumping far beyond the interval, as after a suspend
+        let woken = sample(start.timestamp, 60_000 + 3_600_000);
+        assert_eq!(age(&start, &woken), Some(3_600_000));
+        assert_eq!(Staleness::of(3_600_000, ttl), Staleness::VeryStale);
+        // a sample from before a reboot has no age
+        assert_eq!(age(&start, &sample(start.timestamp, 500)), None);
+    }
+
+    #[test]
+    fn idle_time_ignores_blips_and_survives_missing_frames() {
+        let start = Local::now();
+        let mut tracker = IdleTracker::new(5, BTreeMap::new());
+        // idle seconds of each GPU in a frame `at` seconds in, given as (uuid, utilization)
+        let mut frame = |at: i64, gpus: &[(&str, u32)]| {
+            let mut snapshot = Snapshot {
+                timestamp: start + chrono::Duration::seconds(at),
+                gpus: gpus
+                    .iter()
+                    .map(|(uuid, utilization)| GpuStat {
+                        uuid: uuid.to_string(),
+                        utilization: *utilization,
+                        ..GpuStat::default()
+                    })
+                    .collect(),
+                ..Snapshot::default()
+            };
+            tracker.observe(&mut snapshot);
+            snapshot.gpus.iter().map(|gpu| gpu.idle_seconds).collect::<Vec<_>>()
+        };
+        // an idle streak counts from the start of tracking, and a busy GPU
+        // only stops counting once busy for IDLE_BLIP_SECONDS
+        assert_eq!(frame(0, &[("A", 0), ("B", 90), ("C", 90)]), [Some(0), Some(0), Some(0)]);
+        assert_eq!(frame(10, &[("A", 0), ("B", 90)]), [Some(10), Some(10)]);
+        assert_eq!(frame(30, &[("A", 0), ("B", 90)]), [Some(30), None]);
+        // a single busy sample is a blip
+        assert_eq!(frame(40, &[("A", 90), ("B", 90)]), [Some(40), None]);
+        assert_eq!(frame(50, &[("A", 0), ("B", 0)]), [Some(50), Some(10)]);
+        // B drops out and comes back: its idle time runs on from its last activity
+        assert_eq!(frame(100, &[("A", 0)]), [Some(100)]);
+        assert_eq!(frame(110, &[("A", 0), ("B", 0)]), [Some(110), Some(70)]);
+        // C's activity before it dropped out does not join up with its new one
+        assert_eq!(frame(120, &[("C", 90)]), [Some(120)]);
+        assert_eq!(frame(140, &[("C", 90)]), [Some(140)]);
+        assert_eq!(frame(150, &[("C", 90)]), [None]);
+        assert_eq!(tracker.activity().keys().collect::<Vec<_>>(), ["A", "B", "C"]);
+    }
+
+    #[test]
+    fn glitched_readings_are_clamped() {
+        assert_eq!(clamp_percent(0), None);
+        assert_eq!(clamp_percent(100), None);
+        assert_eq!(clamp_percent(101), Some(100));
+        assert_eq!(clamp_percent(u32::MAX), Some(100));
+        assert_eq!(clamp_memory(8 << 30, 8 << 30), None);
+        assert_eq!(clamp_memory(0, 8 << 30), None);
+        assert_eq!(clamp_memory((8 << 30) + 1, 8 << 30), Some(8 << 30));
+        assert_eq!(clamp_memory(u64::MAX, 8 << 30), Some(8 << 30));
+
+        let mut gpu = GpuStat {
+            utilization: u32::MAX,
+            fan_speed: Some(101),
+            encoder_utilization: Some(100),
+            memory_used: 9 << 30,
+            memory_total: 8 << 30,
+            ..GpuStat::default()
+        };
+        gpu.validate();
+        assert_eq!(
+            (gpu.utilization, gpu.fan_speed, gpu.encoder_utilization),
+            (100, Some(100), Some(100))
+        );
+        assert_eq!(gpu.memory_used, 8 << 30);
+        // the raw values stay for the JSON, in-range ones are left out
+        let raw: Vec<(&str, u64)> = gpu.raw.iter().map(|(field, value)| (field.as_str(), *value)).collect();
+        assert_eq!(
+            raw,
+            [
+                ("fan_speed", 101),
+                ("memory_used", 9 << 30),
+                ("utilization", u64::from(u32::MAX))
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn affinity_masks_list_their_bits() {
+        assert!(mask_bits(&[]).is_empty());
+        assert!(mask_bits(&[0, 0]).is_empty());
+        assert_eq!(mask_bits(&[1 << 5]), [5]);
+        assert_eq!(mask_bits(&[0b1111]), [0, 1, 2, 3]);
+        assert_eq!(mask_bits(&[0b10011]), [0, 1, 4]);
+        let width = std::os::raw::c_ulong::BITS;
+        let top = 1 << (width - 1);
+        assert_eq!(mask_bits(&[top]), [width - 1]);
+        // the second word continues the numbering
+        assert_eq!(mask_bits(&[top, 1]), [width - 1, width]);
+    }
+
+    #[test]
+    fn peak_bandwidth_prefers_the_datasheet() {
+        // the table wins over the clocks, which NVML may report for a lower bin
+        assert_eq!(
+            peak_bandwidth("NVIDIA A100-SXM4-80GB", Some(1593), Some(5120)),
+            Some(2039.0)
+        );
+        assert_eq!(peak_bandwidth("NVIDIA GeForce RTX 4090", None, None), Some(1008.0));
+        // an unknown board: 10501 MHz × 2 × 384 bits
+        let peak = peak_bandwidth("NVIDIA GeForce RTX 5090 Prototype", Some(10501), Some(384)).unwrap();
+        assert!((peak - 1008.1).abs() < 0.1, "{}", peak);
+        // unknown without either reading, or with nonsense ones
+        assert_eq!(peak_bandwidth("NVIDIA Unknown", None, Some(384)), None);
+        assert_eq!(peak_bandwidth("NVIDIA Unknown", Some(10501), None), None);
+        assert_eq!(peak_bandwidth("NVIDIA Unknown", Some(0), Some(384)), None);
+        // names match exactly
+        assert_eq!(peak_bandwidth("NVIDIA A100", None, None), None);
+    }
+
+    #[test]
+    fn cooling_uses_the_sensors_a_board_has() {
+        // an HGX board: no fan, HBM sensor and a slowdown threshold
+        let mut liquid = gpu(0);
+        liquid["fan_speed"] = json!({ "error": "NotSupported" });
+        liquid["temperature_threshold"] = json!(87);
+        liquid["field_values_for(MEMORY_TEMP)"] = json!([71]);
+        // an air-cooled card idling with its fans stopped
+        let mut stopped = gpu(1);
+        stopped["fan_speed"] = json!(0);
+        stopped["temperature_threshold"] = json!(90);
+        // neither a threshold nor a power limit to compare with
+        let mut bare = gpu(2);
+        bare["power_management_limit"] = json!(0);
+        let snapshot = collect(
+            &opts(&["--cooling", "--show-fan"]),
+            &fixtures(vec![liquid, stopped, bare]),
+            "node",
+        )
+        .unwrap();
+        let cooling: Vec<Cooling> = snapshot.gpus.iter().map(|gpu| gpu.cooling.unwrap()).collect();
+        assert_eq!(
+            cooling,
+            [
+                Cooling {
+                    memory_temperature: Some(71),
+                    slowdown_margin: Some(52),
+                    power_headroom: Some(430_000)
+                },
+                Cooling {
+                    memory_temperature: None,
+                    slowdown_margin: Some(55),
+                    power_headroom: Some(430_000)
+                },
+                Cooling {
+                    memory_temperature: None,
+                    slowdown_margin: None,
+                    power_headroom: None
+                },
+            ]
+        );
+        let fans: Vec<Option<u32>> = snapshot.gpus.iter().map(|gpu| gpu.fan_speed).collect();
+        assert_eq!(fans, [None, Some(0), None]);
+        assert_eq!(fan_failure(&snapshot.gpus[0]), Some("NotSupported"));
+    }
+
+    #[test]
+    fn cooling_margins_go_negative_above_slowdown() {
+        let hot = Cooling::of(92, None, Some(87), 460_000, 450_000);
+        assert_eq!((hot.slowdown_margin, hot.power_headroom), (Some(-5), Some(0)));
+        // drivers report a missing threshold as 0
+        assert_eq!(Cooling::of(40, None, Some(0), 0, 450_000).slowdown_margin, None);
+    }
+
+    #[test]
+    fn perf_follows_the_performance_state() {
+        let percent = |sm, max| Perf::of(sm, max).map(|perf| perf.percent);
+        // P0 runs at the boost clock, P8 and P12 idle down to a few percent
+        assert_eq!(percent(2520, 2520), Some(100));
+        assert_eq!(percent(210, 2520), Some(8));
+        assert_eq!(percent(180, 2520), Some(7));
+        // boost reported a step above the maximum is not more than 100 %
+        assert_eq!(percent(2535, 2520), Some(100));
+        // a performance state the driver does not know reports no maximum
+        assert_eq!(percent(1410, 0), None);
+
+        let mut capped = gpu(0);
+        capped["clock_info(SM)"] = json!(1785);
+        capped["max_clock_info(SM)"] = json!(2520);
+        capped["current_throttle_reasons"] = json!("SW_POWER_CAP");
+        let mut unknown = gpu(1);
+        unknown["clock_info(SM)"] = json!(1410);
+        unknown["max_clock_info(SM)"] = json!(0);
+        let provider = fixtures(vec![capped, unknown, gpu(2)]);
+        let snapshot = collect(&opts(&["--show-perf", "--show-throttle"]), &provider, "node").unwrap();
+        let perf = snapshot.gpus[0].perf.unwrap();
+        assert_eq!((perf.sm_clock, perf.sm_clock_max, perf.percent), (1785, 2520, 70));
+        assert_eq!(snapshot.gpus[0].dominant_throttle(), Some("SwPower"));
+        // neither an unknown maximum nor missing clocks make up a value
+        assert!(snapshot.gpus[1].perf.is_none() && snapshot.gpus[2].perf.is_none());
+        assert_eq!(serde_json::to_value(&snapshot.gpus[0]).unwrap()["perf"]["percent"], 70);
+    }
+}
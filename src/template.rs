@@ -0,0 +1,94 @@
+//! `{field}` templates shared by `--oneline-format` and `--proc-format`.
+//! `{{` and `}}` stand for literal braces.
+
+enum Piece<'a> {
+    Literal(&'a str),
+    Field(&'a str),
+}
+
+fn pieces(template: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = vec![];
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        if i > 0 {
+            pieces.push(Piece::Literal(&rest[..i]));
+        }
+        let brace = &rest[i..i + 1];
+        if rest[i + 1..].starts_with(brace) {
+            pieces.push(Piece::Literal(brace));
+            rest = &rest[i + 2..];
+        } else if brace == "}" {
+            return Err(String::from("unmatched '}' in template, write '}}' for a literal brace"));
+        } else {
+            let end = rest[i..]
+                .find('}')
+                .ok_or_else(|| String::from("unclosed '{' in template"))?;
+            pieces.push(Piece::Field(&rest[i + 1..i + end]));
+            rest = &rest[i + end + 1..];
+        }
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Literal(rest));
+    }
+    Ok(pieces)
+}
+
+/// Check that `template` is well formed and only uses `fields`.
+pub fn validate(template: &str, fields: &[&str]) -> Result<String, String> {
+    for piece in pieces(template)? {
+        if let Piece::Field(field) = piece {
+            if !fields.contains(&field) {
+                return Err(format!(
+                    "unknown field '{{{}}}', expected one of {}",
+                    field,
+                    fields.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(template.to_string())
+}
+
+/// Fill in a template checked by `validate`, looking up each field with `value`.
+pub fn expand(template: &str, value: impl Fn(&str) -> String) -> String {
+    let mut s = String::new();
+    for piece in pieces(template).unwrap_or_default() {
+        match piece {
+            Piece::Literal(literal) => s.push_str(literal),
+            Piece::Field(field) => s.push_str(&value(field)),
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[&str] = &["user", "pid"];
+
+    fn fill(template: &str) -> String {
+        expand(template, |field| match field {
+            "user" => String::from("alice"),
+            _ => String::from("42"),
+        })
+    }
+
+    #[test]
+    fn fields_and_literal_braces() {
+        assert_eq!(fill("{user}:{pid}"), "alice:42");
+        assert_eq!(fill("{{{user}}} {pid}}}"), "{alice} 42}");
+        assert_eq!(fill(""), "");
+    }
+
+    #[test]
+    fn malformed_templates_are_refused() {
+        assert!(validate("{user}/{pid}", FIELDS).is_ok());
+        assert_eq!(
+            validate("{cmd}", FIELDS).unwrap_err(),
+            "unknown field '{cmd}', expected one of user, pid"
+        );
+        assert!(validate("{user", FIELDS).unwrap_err().starts_with("unclosed '{'"));
+        assert!(validate("user}", FIELDS).unwrap_err().starts_with("unmatched '}'"));
+    }
+}
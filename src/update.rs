@@ -0,0 +1,169 @@
+//! `gpustat check-update`: compare the build with the latest GitHub release.
+//! Only this subcommand touches the network, and only builds with the opt-in
+//! `check-update` feature have it, together with the HTTP client it uses.
+
+use crate::StatusError;
+use clap::Args;
+use serde::Deserialize;
+use std::{cmp::Ordering, time::Duration};
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/AlongWY/gpustat/releases/latest";
+
+/// Exit code of `--quiet` when a newer release exists.
+pub const EXIT_UPDATE_AVAILABLE: u8 = 10;
+/// Exit code when GitHub cannot be reached or answers nonsense.
+pub const EXIT_UPDATE_UNREACHABLE: u8 = 11;
+
+#[derive(Args, Clone, Debug)]
+pub struct CheckUpdateOpts {
+    #[arg(long, help = "Print nothing, exit 0 when up to date and 10 when an update exists")]
+    quiet: bool,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+}
+
+/// `1.2.3-rc.1`, with or without a leading `v`. Build metadata after `+` is ignored.
+#[derive(Debug, Eq)]
+pub struct Version {
+    numbers: Vec<u64>,
+    pre: Vec<String>,
+}
+
+impl Version {
+    pub fn parse(version: &str) -> Option<Version> {
+        let version = version.trim().trim_start_matches('v');
+        let version = version.split('+').next()?;
+        let (release, pre) = match version.split_once('-') {
+            Some((release, pre)) => (release, pre.split('.').map(str::to_string).collect()),
+            None => (version, vec![]),
+        };
+        let numbers = release.split('.').map(|n| n.parse().ok()).collect::<Option<Vec<u64>>>()?;
+        Some(Version { numbers, pre })
+    }
+}
+
+/// One pre-release identifier against another: numeric ones by value and
+/// below alphanumeric ones, which compare as text.
+fn compare_identifier(a: &str, b: &str) -> Ordering {
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+impl Ord for Version {
+    /// Semver precedence: `1.2` equals `1.2.0`, and a pre-release sorts below its release.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.numbers.len().max(other.numbers.len());
+        let number = |version: &Version, i: usize| version.numbers.get(i).copied().unwrap_or_default();
+        (0..len)
+            .map(|i| number(self, i).cmp(&number(other, i)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => self
+                    .pre
+                    .iter()
+                    .zip(&other.pre)
+                    .map(|(a, b)| compare_identifier(a, b))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or_else(|| self.pre.len().cmp(&other.pre.len())),
+            })
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The latest release, or why it could not be fetched.
+fn latest_release() -> Result<Release, String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(10))
+        // the GitHub API refuses requests without one
+        .user_agent(concat!("gpustat/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let response = agent
+        .get(LATEST_RELEASE)
+        .set("Accept", "application/vnd.github+json")
+        .call()
+        .map_err(|e| e.to_string())?;
+    serde_json::from_reader(response.into_reader()).map_err(|e| format!("unexpected answer from GitHub: {}", e))
+}
+
+/// Tell whether a newer release than this build exists.
+pub fn run(opts: &CheckUpdateOpts) -> Result<u8, StatusError> {
+    let installed = env!("CARGO_PKG_VERSION");
+    let release = match latest_release() {
+        Ok(release) => release,
+        Err(e) => {
+            if !opts.quiet {
+                eprintln!("Cannot check for updates, GitHub is unreachable: {}", e);
+            }
+            return Ok(EXIT_UPDATE_UNREACHABLE);
+        }
+    };
+    let (Some(current), Some(latest)) = (Version::parse(installed), Version::parse(&release.tag_name)) else {
+        if !opts.quiet {
+            eprintln!("Cannot compare version {} with release tag {}", installed, release.tag_name);
+        }
+        return Ok(EXIT_UPDATE_UNREACHABLE);
+    };
+    if latest > current {
+        if !opts.quiet {
+            println!("gpustat {} is available (installed {}): {}", release.tag_name, installed, release.html_url);
+        }
+        return Ok(if opts.quiet { EXIT_UPDATE_AVAILABLE } else { 0 });
+    }
+    if !opts.quiet {
+        println!("gpustat {} is up to date", installed);
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn pre_releases_sort_below_their_release() {
+        assert!(version("1.2.0-rc1") < version("1.2.0"));
+        assert!(version("1.2.0-rc.2") < version("1.2.0-rc.10"));
+        assert!(version("1.2.0-alpha") < version("1.2.0-alpha.1"));
+        assert!(version("1.2.0-1") < version("1.2.0-beta"));
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert!(version("1.10.0") > version("1.9.0"));
+        assert!(version("v0.1.9") > version("0.1.8"));
+        assert_eq!(version("1.2"), version("1.2.0"));
+        assert_eq!(version("1.2.0+build.5"), version("1.2.0"));
+    }
+
+    #[test]
+    fn garbled_tags_are_refused() {
+        assert_eq!(Version::parse("latest"), None);
+        assert_eq!(Version::parse("1..2"), None);
+    }
+}
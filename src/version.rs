@@ -0,0 +1,97 @@
+//! `--version-full`: build metadata and the NVML runtime, for bug reports.
+
+use chrono::prelude::*;
+use nvml_wrapper::{cuda_driver_version_major, cuda_driver_version_minor, error::NvmlError, Nvml};
+
+/// Shown for build metadata missing from the build, e.g. the commit of a crates.io package.
+const UNKNOWN: &str = "unknown";
+
+fn or_unknown(value: &str) -> &str {
+    if value.is_empty() {
+        UNKNOWN
+    } else {
+        value
+    }
+}
+
+/// `1.2.3 (abc123def456, built 2026-10-14 05:13 UTC)`, tolerating missing build metadata.
+pub fn build_line(version: &str, commit: &str, built: &str) -> String {
+    let built = built
+        .parse()
+        .ok()
+        .and_then(|epoch| Utc.timestamp_opt(epoch, 0).single())
+        .filter(|time| time.timestamp() > 0)
+        .map_or_else(|| String::from(UNKNOWN), |time| time.format("%Y-%m-%d %H:%M UTC").to_string());
+    format!("{} ({}, built {})", version, or_unknown(commit), built)
+}
+
+/// Runtime versions, each `unavailable` with the error when it cannot be read.
+fn runtime_lines(nvml: Result<&Nvml, &NvmlError>) -> Vec<String> {
+    let unavailable = |e: &NvmlError| format!("unavailable ({})", e);
+    let nvml = match nvml {
+        Ok(nvml) => nvml,
+        Err(e) => {
+            return ["NVML", "driver", "CUDA driver"]
+                .iter()
+                .map(|name| format!("{}: {}", name, unavailable(e)))
+                .collect()
+        }
+    };
+    let cuda = nvml.sys_cuda_driver_version().map(|version| {
+        format!(
+            "{}.{}",
+            cuda_driver_version_major(version),
+            cuda_driver_version_minor(version)
+        )
+    });
+    vec![
+        format!("NVML: {}", nvml.sys_nvml_version().unwrap_or_else(|e| unavailable(&e))),
+        format!("driver: {}", nvml.sys_driver_version().unwrap_or_else(|e| unavailable(&e))),
+        format!("CUDA driver: {}", cuda.unwrap_or_else(|e| unavailable(&e))),
+    ]
+}
+
+/// The `--version-full` block, one `name: value` line each.
+pub fn report(nvml: Result<&Nvml, &NvmlError>) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "gpustat {}",
+            build_line(
+                env!("CARGO_PKG_VERSION"),
+                env!("GPUSTAT_GIT_COMMIT"),
+                env!("GPUSTAT_BUILD_EPOCH")
+            )
+        ),
+        format!("rustc: {}", or_unknown(env!("GPUSTAT_RUSTC_VERSION"))),
+        format!("target: {}-{}", std::env::consts::ARCH, std::env::consts::OS),
+    ];
+    lines.extend(runtime_lines(nvml));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_build_metadata_is_unknown() {
+        assert_eq!(
+            build_line("1.2.3", "abc123def456", "1791695580"),
+            "1.2.3 (abc123def456, built 2026-10-11 05:13 UTC)"
+        );
+        assert_eq!(build_line("1.2.3", "", ""), "1.2.3 (unknown, built unknown)");
+        assert_eq!(build_line("1.2.3", "abc", "0"), "1.2.3 (abc, built unknown)");
+        assert_eq!(build_line("1.2.3", "abc", "soon"), "1.2.3 (abc, built unknown)");
+    }
+
+    #[test]
+    fn runtime_versions_without_nvml() {
+        let lines = report(Err(&NvmlError::LibraryNotFound));
+        assert!(lines[0].starts_with(concat!("gpustat ", env!("CARGO_PKG_VERSION"), " (")));
+        let runtime: Vec<&str> = lines[3..]
+            .iter()
+            .map(|line| line.split_once(": unavailable (").unwrap().0)
+            .collect();
+        assert_eq!(runtime, ["NVML", "driver", "CUDA driver"]);
+    }
+}
@@ -0,0 +1,121 @@
+//! Xid errors reported by the NVIDIA kernel driver, read from the kernel log.
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Read},
+    os::unix::fs::OpenOptionsExt,
+    process::Command,
+};
+
+/// The most recent Xid of one GPU.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct XidEvent {
+    pub code: u32,
+    pub time: DateTime<Local>,
+}
+
+/// `0000:3b:00` from either an NVML bus id (`00000000:3B:00.0`) or the
+/// address in an Xid line (`PCI:0000:3b:00`).
+pub fn bus_key(bus_id: &str) -> String {
+    let bus_id = bus_id.trim_start_matches("PCI:").to_ascii_lowercase();
+    let bus_id = bus_id.split('.').next().unwrap_or_default();
+    match bus_id.split_once(':') {
+        Some((domain, rest)) if domain.len() > 4 => format!("{}:{}", &domain[domain.len() - 4..], rest),
+        _ => bus_id.to_string(),
+    }
+}
+
+/// Bus key and code of a line like `NVRM: Xid (PCI:0000:3b:00): 31, pid=1234, ...`.
+fn parse_xid(message: &str) -> Option<(String, u32)> {
+    let rest = &message[message.find("NVRM: Xid (")? + "NVRM: Xid (".len()..];
+    let (address, rest) = rest.split_once("):")?;
+    let code = rest.trim_start().split(|c: char| !c.is_ascii_digit()).next()?;
+    Some((bus_key(address), code.parse().ok()?))
+}
+
+fn uptime() -> Option<f64> {
+    fs::read_to_string("/proc/uptime")
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Records of /dev/kmsg look like `6,1234,5066312345,-;NVRM: Xid ...`, stamped in microseconds since boot.
+fn read_kmsg() -> io::Result<Vec<(String, u32, DateTime<Local>)>> {
+    let mut kmsg = OpenOptions::new()
+        .read(true)
+        .custom_flags(nix::fcntl::OFlag::O_NONBLOCK.bits())
+        .open("/dev/kmsg")?;
+    let boot = Local::now() - chrono::Duration::milliseconds((uptime().unwrap_or_default() * 1000.0) as i64);
+    let mut events = vec![];
+    let mut record = [0u8; 8192];
+    loop {
+        let n = match kmsg.read(&mut record) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            // the oldest records were overwritten while reading
+            Err(e) if e.raw_os_error() == Some(nix::libc::EPIPE) => continue,
+            Err(e) => return Err(e),
+        };
+        let record = String::from_utf8_lossy(&record[..n]);
+        let Some((prefix, message)) = record.split_once(';') else {
+            continue;
+        };
+        let Some((address, code)) = parse_xid(message) else {
+            continue;
+        };
+        let usec: i64 = prefix.split(',').nth(2).and_then(|ts| ts.parse().ok()).unwrap_or_default();
+        events.push((address, code, boot + chrono::Duration::microseconds(usec)));
+    }
+    Ok(events)
+}
+
+/// `journalctl -k -o short-unix` lines start with the time in seconds since the epoch.
+fn read_journal() -> Result<Vec<(String, u32, DateTime<Local>)>, String> {
+    let output = Command::new("journalctl")
+        .args(["-k", "--no-pager", "-o", "short-unix", "-g", "NVRM: Xid"])
+        .output()
+        .map_err(|e| e.to_string())?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("insufficient permissions") || stderr.contains("Permission denied") {
+        return Err(String::from("permission"));
+    }
+    if stderr.contains("No journal files") {
+        return Err(String::from("no kernel log"));
+    }
+    let events = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (time, message) = line.split_once(' ')?;
+            let (address, code) = parse_xid(message)?;
+            let time = Local.timestamp_opt(time.split('.').next()?.parse().ok()?, 0).single()?;
+            Some((address, code, time))
+        })
+        .collect();
+    Ok(events)
+}
+
+/// The most recent Xid per bus key, or why the kernel log could not be read.
+pub fn last_xids() -> Result<HashMap<String, XidEvent>, String> {
+    let events = match read_kmsg() {
+        Ok(events) => events,
+        Err(kmsg) => read_journal().map_err(|journal| {
+            if kmsg.kind() == io::ErrorKind::PermissionDenied {
+                String::from("permission")
+            } else {
+                journal
+            }
+        })?,
+    };
+    let mut last = HashMap::new();
+    for (address, code, time) in events {
+        last.insert(address, XidEvent { code, time });
+    }
+    Ok(last)
+}
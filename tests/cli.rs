@@ -0,0 +1,100 @@
+//! Command lines run through the gpustat binary against the fixtures in
+//! tests/fixtures, so that a bare invocation keeps working next to the
+//! subcommands without a GPU.
+
+use assert_cmd::Command;
+use std::path::Path;
+
+/// `gpustat ARGS` replaying `fixture`.
+fn gpustat(fixture: &str, args: &[&str]) -> Command {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(format!("{}.json", fixture));
+    let mut command = Command::cargo_bin("gpustat").unwrap();
+    command.env("GPUSTAT_FIXTURE", path).env_remove("NO_COLOR").args(args);
+    command
+}
+
+/// What a successful `gpustat ARGS` prints, without the timestamp that
+/// changes between runs.
+fn stdout(fixture: &str, args: &[&str]) -> String {
+    let args: Vec<&str> = ["--no-timestamp"].iter().chain(args).copied().collect();
+    let output = gpustat(fixture, &args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "gpustat {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn bare_invocation_prints_the_status_table() {
+    let table = stdout("consumer", &[]);
+    assert!(table.starts_with("workstation\t550.54.14\n"));
+    assert!(table.contains("NVIDIA GeForce RTX 4090"));
+    assert_eq!(stdout("consumer", &["-a"]), stdout("consumer", &["--show-all"]));
+}
+
+#[test]
+fn short_flags_combine() {
+    let combined = stdout("consumer", &["-cp"]);
+    assert_eq!(combined, stdout("consumer", &["-c", "-p"]));
+    assert_eq!(combined, stdout("consumer", &["--show-cmd", "--show-pid"]));
+    assert!(combined.contains("alice:python/4194305(14336M)"));
+}
+
+#[test]
+fn unknown_short_flags_are_usage_errors() {
+    let output = gpustat("consumer", &["-cpu"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unexpected argument '-u'"));
+}
+
+#[test]
+fn color_follows_the_flags() {
+    assert!(stdout("consumer", &["--color"]).contains("\x1b["));
+    assert!(!stdout("consumer", &["--no-color"]).contains("\x1b["));
+    // piped output is plain unless asked otherwise
+    assert!(!stdout("consumer", &[]).contains("\x1b["));
+}
+
+#[test]
+fn help_lists_the_status_options_and_commands() {
+    let output = gpustat("consumer", &["--help"]).output().unwrap();
+    assert!(output.status.success());
+    let help = String::from_utf8(output.stdout).unwrap();
+    assert!(help.contains("gpustat [OPTIONS]            print the GPU status table"));
+    assert!(help.contains("Commands:\n"));
+    assert!(help.contains("  doctor "));
+    assert!(help.contains("--show-all"));
+}
+
+#[test]
+fn subcommands_do_not_collect() {
+    let schema = stdout("consumer", &["schema"]);
+    assert!(serde_json::from_str::<serde_json::Value>(&schema).is_ok());
+    // status options before a subcommand are still accepted
+    let output = gpustat("consumer", &["-a", "man"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with(".ie"));
+}
+
+#[test]
+fn fixtures_take_every_option() {
+    let json: serde_json::Value = serde_json::from_str(&stdout("a100-mig", &["--format", "json"])).unwrap();
+    assert_eq!(json["hostname"], "dgx-07");
+    let one = stdout("a100-mig", &["--id", "0"]);
+    assert!(one.contains(" [0] ") && !one.contains(" [1] "));
+    let output = gpustat("a100-mig", &["--id", "9"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--id 9: no such GPU, found 2"));
+}
+
+#[test]
+fn invalid_match_patterns_fail_at_parsing() {
+    let output = gpustat("consumer", &["--match", "(train"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'(train' for '--match <REGEX>': regex parse error"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unclosed group"));
+}
@@ -0,0 +1,88 @@
+//! Outputs of the hand-written fixtures in tests/fixtures, collected through
+//! `FixtureProvider` and compared with tests/golden. Run with
+//! `GPUSTAT_BLESS=1` to rewrite the expected files after an intended change.
+
+use chrono::prelude::*;
+use clap::Parser;
+use gpustat::{fixture::FixtureProvider, render, stats, Opts};
+use std::{env, fs, path::Path};
+
+/// What `gpustat ARGS` prints for `fixture`, at a fixed time.
+fn output(fixture: &str, args: &[&str]) -> String {
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let provider = FixtureProvider::load(&tests.join("fixtures").join(format!("{}.json", fixture))).unwrap();
+    let opts = Opts::try_parse_from(["gpustat"].iter().chain(args)).unwrap();
+    let mut snapshot = stats::collect(&opts, &provider, &provider.hostname).unwrap();
+    // the rest comes from the machine running the test
+    let timestamp = Local.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+    snapshot.timestamp = timestamp;
+    snapshot.collected_at = Some(timestamp);
+    snapshot.collection_duration_ms = Some(12);
+    snapshot.uptime_ms = None;
+    snapshot.time_suspect = false;
+    for gpu in &mut snapshot.gpus {
+        gpu.collected_at = Some(timestamp);
+        gpu.processes.iter_mut().for_each(|process| process.collected_at = Some(timestamp));
+    }
+    let render_opts = render::RenderOpts::from(&opts);
+    let registry = render::Registry::builtin(&render_opts);
+    let rendered = registry.get(opts.format_name()).unwrap().render(&snapshot, &render_opts);
+    // the offset differs between time zones
+    rendered
+        .replace(&serde_json::to_string(&timestamp).unwrap(), "\"2026-01-02T03:04:05\"")
+        .replace(&timestamp.to_rfc3339(), "2026-01-02T03:04:05")
+}
+
+fn check(fixture: &str, name: &str, args: &[&str]) {
+    let actual = output(fixture, args);
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.{}", fixture, name));
+    if env::var_os("GPUSTAT_BLESS").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    assert_eq!(
+        actual,
+        expected,
+        "{} differs, rerun with GPUSTAT_BLESS=1 if intended",
+        path.display()
+    );
+}
+
+fn check_all(fixture: &str) {
+    check(fixture, "table", &["--color", "-a", "--show-mig-mode"]);
+    check(fixture, "plain", &["--no-color"]);
+    check(fixture, "json", &["--format", "json", "-a", "--show-mig-mode"]);
+    check(fixture, "oneline", &["--format", "oneline"]);
+    check(fixture, "csv", &["--format", "csv", "-a"]);
+}
+
+#[test]
+fn every_format_has_goldens() {
+    let opts = Opts::try_parse_from(["gpustat"]).unwrap();
+    let registry = render::Registry::builtin(&render::RenderOpts::from(&opts));
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    for fixture in ["consumer", "a100-mig", "wsl"] {
+        for name in registry.names() {
+            let path = golden.join(format!("{}.{}", fixture, name));
+            assert!(path.exists(), "{} has no golden, add it to check_all", path.display());
+        }
+    }
+}
+
+#[test]
+fn consumer() {
+    check_all("consumer");
+}
+
+#[test]
+fn a100_mig() {
+    check_all("a100-mig");
+}
+
+#[test]
+fn wsl() {
+    check_all("wsl");
+}